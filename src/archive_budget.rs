@@ -0,0 +1,359 @@
+//! Archive size budgeting
+//!
+//! Large worldspace and city mods can produce precombine/previs archives that the game or
+//! Bethesda.net cannot use, and the workflow only finds out when the archive is written,
+//! hours into a run. This module checks sizes against the limits for the build mode:
+//!
+//! | Build mode | Limit | Reason |
+//! |------------|-------|--------|
+//! | Clean, Filtered | 4 GB | BA2 readers treat file offsets as 32-bit |
+//! | Xbox | 2 GB | Bethesda.net mod space for all Xbox One mods |
+//!
+//! Before an archive step the *projected* size is checked (existing archive plus the
+//! uncompressed source files, an upper bound), which only warns. Once the archive is
+//! written and verified, and before the loose source files are deleted, the *actual*
+//! archive size is checked (see
+//! [`ArchiveManager::with_size_budget`](crate::tools::ArchiveManager::with_size_budget)).
+//! Exceeding the limit is an error: the previous archive is restored, the source files
+//! are kept and the run stops before previs generation. Either way, a per-cell report
+//! shows which cells contribute most of the size.
+//!
+//! Cells are identified by the form ID that precombined meshes (`0001F4A2_2A6B3C5D_OC.nif`)
+//! and previs files (`0001F4A2.uvd`) are named after.
+
+use anyhow::Result;
+use log::{info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use walkdir::WalkDir;
+
+use crate::config::BuildMode;
+use crate::tools::ba2_reader::Ba2Archive;
+
+const MIB: u64 = 1024 * 1024;
+const GIB: u64 = 1024 * MIB;
+
+/// Largest archive the game can read (32-bit file offsets)
+pub const BA2_SIZE_LIMIT: u64 = 4 * GIB;
+
+/// Bethesda.net space for all mods on Xbox One; a larger archive can never be installed
+pub const XBOX_MOD_SPACE_LIMIT: u64 = 2 * GIB;
+
+/// Number of cells listed in size reports
+const REPORT_CELLS: usize = 10;
+
+/// Size limit that applies to an archive
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeBudget {
+    /// Maximum archive size in bytes
+    pub limit: u64,
+    /// What the limit is, for messages
+    pub description: &'static str,
+}
+
+/// Result of checking a size against a [`SizeBudget`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetStatus {
+    /// Below 90% of the limit
+    WithinBudget,
+    /// Between 90% and 100% of the limit
+    NearLimit,
+    /// Above the limit
+    OverLimit,
+}
+
+impl SizeBudget {
+    /// The budget for a build mode (Xbox builds use the smaller Bethesda.net limit)
+    pub fn for_build_mode(build_mode: BuildMode) -> Self {
+        match build_mode {
+            BuildMode::Clean | BuildMode::Filtered => Self {
+                limit: BA2_SIZE_LIMIT,
+                description: "BA2 4 GB offset limit",
+            },
+            BuildMode::Xbox => Self {
+                limit: XBOX_MOD_SPACE_LIMIT,
+                description: "Bethesda.net 2 GB Xbox mod limit",
+            },
+        }
+    }
+
+    /// Check a size in bytes against the budget
+    pub fn check(&self, size: u64) -> BudgetStatus {
+        if size > self.limit {
+            BudgetStatus::OverLimit
+        } else if size > self.limit / 10 * 9 {
+            BudgetStatus::NearLimit
+        } else {
+            BudgetStatus::WithinBudget
+        }
+    }
+}
+
+/// Total size of the files belonging to one cell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellContribution {
+    /// Cell form ID (8 hex digits, uppercase)
+    pub cell: String,
+    /// Total bytes
+    pub bytes: u64,
+    /// Number of files
+    pub files: usize,
+}
+
+/// The cell form ID a precombine or previs file belongs to, from its file name
+///
+/// Returns `None` for files that are not named after a cell.
+fn cell_key(path: &str) -> Option<String> {
+    let file_name = path.rsplit(['\\', '/']).next()?;
+    let prefix = file_name.get(..8)?;
+    let terminated = matches!(file_name.as_bytes().get(8), Some(b'_' | b'.'));
+
+    (terminated && prefix.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| prefix.to_ascii_uppercase())
+}
+
+/// Group file sizes by cell, largest cell first
+fn group_by_cell<'a>(files: impl IntoIterator<Item = (&'a str, u64)>) -> Vec<CellContribution> {
+    let mut cells: HashMap<String, CellContribution> = HashMap::new();
+    for (name, bytes) in files {
+        let cell = cell_key(name).unwrap_or_else(|| "(other)".to_string());
+        let entry = cells
+            .entry(cell.clone())
+            .or_insert_with(|| CellContribution {
+                cell,
+                bytes: 0,
+                files: 0,
+            });
+        entry.bytes += bytes;
+        entry.files += 1;
+    }
+
+    let mut cells: Vec<_> = cells.into_values().collect();
+    cells.sort_by(|a, b| b.bytes.cmp(&a.bytes).then_with(|| a.cell.cmp(&b.cell)));
+    cells
+}
+
+/// Per-cell sizes of the loose files under `dir` (uncompressed)
+pub fn cell_contributions_in_dir(dir: &Path) -> Vec<CellContribution> {
+    let files: Vec<(String, u64)> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .filter_map(|e| {
+            let size = e.metadata().ok()?.len();
+            Some((e.file_name().to_string_lossy().into_owned(), size))
+        })
+        .collect();
+
+    group_by_cell(files.iter().map(|(name, size)| (name.as_str(), *size)))
+}
+
+/// Per-cell sizes of the entries in an archive (as stored, i.e. compressed)
+pub fn cell_contributions_in_archive(archive: &Ba2Archive) -> Vec<CellContribution> {
+    group_by_cell(
+        archive
+            .entries()
+            .iter()
+            .map(|entry| (entry.name.as_str(), u64::from(entry.stored_size()))),
+    )
+}
+
+/// Format a byte count for reports (e.g., `512.0 MB`, `3.75 GB`)
+pub fn format_size(bytes: u64) -> String {
+    if bytes >= GIB {
+        format!("{}.{:02} GB", bytes / GIB, (bytes % GIB) * 100 / GIB)
+    } else {
+        format!("{}.{} MB", bytes / MIB, (bytes % MIB) * 10 / MIB)
+    }
+}
+
+/// Log the cells that contribute most to `total` bytes
+pub fn log_cell_report(cells: &[CellContribution], total: u64) {
+    if cells.is_empty() {
+        return;
+    }
+
+    warn!("Largest contributors by cell:");
+    for cell in cells.iter().take(REPORT_CELLS) {
+        let percent = (cell.bytes * 100).checked_div(total).unwrap_or(0);
+        warn!(
+            "  {:<10} {:>12} ({percent:>2}%, {} files)",
+            cell.cell,
+            format_size(cell.bytes),
+            cell.files
+        );
+    }
+    if cells.len() > REPORT_CELLS {
+        warn!("  ... and {} more cells", cells.len() - REPORT_CELLS);
+    }
+}
+
+/// Warn if archiving `source_dir` into `archive_path` may exceed the budget
+///
+/// The projection is the current archive size (if any) plus the uncompressed size of
+/// `source_dir` (see [`filesystem::get_directory_size`](crate::filesystem::get_directory_size)).
+/// Compression usually keeps the real archive well below this, so this only warns.
+///
+/// Returns the projected size in bytes.
+pub fn check_projected(budget: SizeBudget, archive_path: &Path, source_dir: &Path) -> u64 {
+    let existing = fs::metadata(archive_path).map_or(0, |m| m.len());
+    let projected = existing + crate::filesystem::get_directory_size(source_dir);
+
+    info!(
+        "Projected archive size: up to {} (limit {}, {})",
+        format_size(projected),
+        format_size(budget.limit),
+        budget.description
+    );
+
+    if budget.check(projected) != BudgetStatus::WithinBudget {
+        warn!(
+            "{} may exceed the {} before compression ({} of uncompressed data)",
+            archive_path.display(),
+            budget.description,
+            format_size(projected)
+        );
+        log_cell_report(&cell_contributions_in_dir(source_dir), projected);
+    }
+
+    projected
+}
+
+/// Check the size of a written archive against the budget
+///
+/// Logs a per-cell report when the archive is near or over the limit.
+///
+/// Returns the archive size in bytes.
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be read, or if it exceeds the budget
+pub fn check_actual(budget: SizeBudget, archive_path: &Path) -> Result<u64> {
+    let size = fs::metadata(archive_path)?.len();
+    let status = budget.check(size);
+
+    info!(
+        "Archive size: {} (limit {}, {})",
+        format_size(size),
+        format_size(budget.limit),
+        budget.description
+    );
+
+    if status == BudgetStatus::WithinBudget {
+        return Ok(size);
+    }
+
+    if let Ok(archive) = Ba2Archive::open(archive_path) {
+        log_cell_report(&cell_contributions_in_archive(&archive), size);
+    }
+
+    if status == BudgetStatus::OverLimit {
+        anyhow::bail!(
+            "{} is {}, which exceeds the {} ({}).\n\
+            Split the worldspace across several plugins or reduce the largest cells listed in the log.",
+            archive_path.display(),
+            format_size(size),
+            budget.description,
+            format_size(budget.limit)
+        );
+    }
+
+    warn!(
+        "{} is within 10% of the {}",
+        archive_path.display(),
+        budget.description
+    );
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_budget_for_build_mode() {
+        assert_eq!(
+            SizeBudget::for_build_mode(BuildMode::Clean).limit,
+            BA2_SIZE_LIMIT
+        );
+        assert_eq!(
+            SizeBudget::for_build_mode(BuildMode::Xbox).limit,
+            XBOX_MOD_SPACE_LIMIT
+        );
+    }
+
+    #[test]
+    fn test_budget_check() {
+        let budget = SizeBudget {
+            limit: 1000,
+            description: "test",
+        };
+        assert_eq!(budget.check(500), BudgetStatus::WithinBudget);
+        assert_eq!(budget.check(950), BudgetStatus::NearLimit);
+        assert_eq!(budget.check(1000), BudgetStatus::NearLimit);
+        assert_eq!(budget.check(1001), BudgetStatus::OverLimit);
+    }
+
+    #[test]
+    fn test_cell_key() {
+        assert_eq!(
+            cell_key("meshes\\precombined\\0001f4a2_2A6B3C5D_OC.nif"),
+            Some("0001F4A2".to_string())
+        );
+        assert_eq!(cell_key("vis/0000003C.uvd"), Some("0000003C".to_string()));
+        assert_eq!(cell_key("scripts\\MyQuest.pex"), None);
+        assert_eq!(cell_key("0001F4A2X.nif"), None);
+    }
+
+    #[test]
+    fn test_cell_contributions_in_dir() {
+        let temp = TempDir::new().unwrap();
+        fs::write(temp.path().join("0001F4A2_AAAAAAAA_OC.nif"), [0u8; 30]).unwrap();
+        fs::write(temp.path().join("0001F4A2_BBBBBBBB_OC.nif"), [0u8; 30]).unwrap();
+        fs::write(temp.path().join("0000003C_CCCCCCCC_OC.nif"), [0u8; 40]).unwrap();
+
+        let cells = cell_contributions_in_dir(temp.path());
+        assert_eq!(
+            cells,
+            [
+                CellContribution {
+                    cell: "0001F4A2".to_string(),
+                    bytes: 60,
+                    files: 2
+                },
+                CellContribution {
+                    cell: "0000003C".to_string(),
+                    bytes: 40,
+                    files: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_check_actual_fails_over_limit() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("Test - Main.ba2");
+        fs::write(&archive, [0u8; 64]).unwrap();
+
+        let tight = SizeBudget {
+            limit: 32,
+            description: "test limit",
+        };
+        assert!(check_actual(tight, &archive).is_err());
+
+        let roomy = SizeBudget {
+            limit: 1024,
+            description: "test limit",
+        };
+        assert_eq!(check_actual(roomy, &archive).unwrap(), 64);
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(512 * MIB), "512.0 MB");
+        assert_eq!(format_size(3 * GIB + GIB / 4 * 3), "3.75 GB");
+    }
+}
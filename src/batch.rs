@@ -0,0 +1,737 @@
+//! Batch builds
+//!
+//! Runs the full workflow for several plugins in sequence from a manifest, so a set of
+//! patches can be rebuilt unattended. The manifest is TOML (or JSON with a `.json`
+//! extension); `[defaults]` applies to every `[[plugins]]` entry that does not override it:
+//!
+//! ```toml
+//! # Optional: overrides the registry lookup (--FO4 overrides this)
+//! fo4_dir = 'C:\Games\Fallout 4'
+//! # Keep going after a plugin fails (also --continue-on-error)
+//! continue_on_error = true
+//!
+//! [defaults]
+//! build_mode = "clean"        # clean | filtered | xbox
+//! archive_tool = "archive2"   # archive2 | bsarch | native
+//! merge_tool = "native"       # native | fo4edit
+//!
+//! [[plugins]]
+//! name = "RegionNorth.esp"
+//!
+//! [[plugins]]
+//! name = "RegionSouth.esp"
+//! archive_tool = "bsarch"
+//! seed = "xPrevisPatch.esp"
+//! uncompressed = true
+//!
+//! [[plugins]]
+//! name = "RegionEast.esp"
+//! build_mode = "filtered"
+//! loose_files = true
+//! output = 'D:\Builds\RegionEast'
+//! ```
+//!
+//! Other keys are `mo2_path` (runs the tools through Mod Organizer 2), `mo2_data_dir` and
+//! `hooks` (a [hooks file](crate::hooks), relative to the manifest).
+//! Tools are looked up once for the whole batch. Between plugins the working files
+//! (`CombinedObjects.esp`, `Previs.esp`) are deleted and `meshes\precombined` and `vis`
+//! are emptied, since the previous plugin's output has been archived or exported by then.
+//! The results are printed as one report at the end and written as JSON to
+//! `<FO4>\GeneratePrevisibines\batch\<Manifest>.json`.
+
+use anyhow::{Context, Result, bail};
+use chrono::Local;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::ckpe_config;
+use crate::config::{ArchiveTool, BuildMode, Config, MergeTool};
+use crate::filesystem;
+use crate::hooks::Hooks;
+use crate::journal::JournalConfig;
+use crate::registry;
+use crate::validation;
+use crate::workflow::{WorkflowExecutor, WorkflowStep};
+
+/// Timestamp format of the report (local time)
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A batch manifest as written by the user
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchManifest {
+    /// Fallout 4 directory (default: found via the registry)
+    pub fo4_dir: Option<PathBuf>,
+
+    /// Keep going with the next plugin after one fails
+    #[serde(default)]
+    pub continue_on_error: bool,
+
+    /// Settings used by every plugin that does not set its own
+    #[serde(default)]
+    pub defaults: PluginOptions,
+
+    /// Plugins to build, in order
+    #[serde(default)]
+    pub plugins: Vec<PluginOptions>,
+}
+
+/// Settings of one manifest entry (or of `[defaults]`); unset keys fall back to the
+/// defaults, then to the command line defaults
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginOptions {
+    /// Plugin name (e.g., "MyMod.esp"); required for plugins, not allowed in defaults
+    pub name: Option<String>,
+    pub build_mode: Option<BuildMode>,
+    pub archive_tool: Option<ArchiveTool>,
+    pub merge_tool: Option<MergeTool>,
+    /// Path to ModOrganizer.exe; setting it runs the tools through MO2
+    pub mo2_path: Option<PathBuf>,
+    /// MO2's VFS staging directory (e.g., overwrite folder)
+    pub mo2_data_dir: Option<PathBuf>,
+    pub loose_files: Option<bool>,
+    /// Store archive entries uncompressed; not allowed with `loose_files`
+    pub uncompressed: Option<bool>,
+    /// Output folder in loose-files mode; not allowed in defaults
+    pub output: Option<PathBuf>,
+    /// Plugin in `Data` to copy when the plugin does not exist yet
+    pub seed: Option<String>,
+    /// Hooks file (see [`hooks`](crate::hooks)), relative to the manifest's folder
+    pub hooks: Option<PathBuf>,
+}
+
+/// A manifest entry with the defaults applied
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchEntry {
+    pub plugin: String,
+    pub build_mode: BuildMode,
+    pub archive_tool: ArchiveTool,
+    pub merge_tool: MergeTool,
+    pub mo2_path: Option<PathBuf>,
+    pub mo2_data_dir: Option<PathBuf>,
+    pub loose_files: bool,
+    pub uncompressed: bool,
+    pub output: Option<PathBuf>,
+    pub seed: Option<String>,
+    pub hooks: Option<PathBuf>,
+}
+
+impl BatchManifest {
+    /// Read a manifest: JSON if the file name ends in `.json`, TOML otherwise
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid manifest
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read batch manifest: {}", path.display()))?;
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        Self::parse(&text, is_json)
+            .with_context(|| format!("Invalid batch manifest: {}", path.display()))
+    }
+
+    /// Parse a manifest from TOML or JSON text
+    fn parse(text: &str, is_json: bool) -> Result<Self> {
+        if is_json {
+            Ok(serde_json::from_str(text)?)
+        } else {
+            Ok(toml::from_str(text)?)
+        }
+    }
+
+    /// The plugins to build, in order, with `[defaults]` applied
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if there are no plugins, an entry has no name, a plugin is
+    /// listed twice, `[defaults]` sets `name` or `output`, or `output` is set without
+    /// loose files
+    pub fn entries(&self) -> Result<Vec<BatchEntry>> {
+        if self.defaults.name.is_some() {
+            bail!("[defaults] cannot set a plugin name");
+        }
+        if self.defaults.output.is_some() {
+            bail!("[defaults] cannot set output; each plugin needs its own output folder");
+        }
+        if self.plugins.is_empty() {
+            bail!("The manifest lists no plugins");
+        }
+
+        let defaults = &self.defaults;
+        let mut seen = HashSet::new();
+        let mut entries = Vec::with_capacity(self.plugins.len());
+        for (index, options) in self.plugins.iter().enumerate() {
+            let Some(ref plugin) = options.name else {
+                bail!("Plugin entry {} has no name", index + 1);
+            };
+            if !seen.insert(plugin.to_ascii_lowercase()) {
+                bail!("{plugin} is listed more than once");
+            }
+
+            let entry = BatchEntry {
+                plugin: plugin.clone(),
+                build_mode: options
+                    .build_mode
+                    .or(defaults.build_mode)
+                    .unwrap_or(BuildMode::Clean),
+                archive_tool: options
+                    .archive_tool
+                    .or(defaults.archive_tool)
+                    .unwrap_or(ArchiveTool::Archive2),
+                merge_tool: options
+                    .merge_tool
+                    .or(defaults.merge_tool)
+                    .unwrap_or(MergeTool::FO4Edit),
+                mo2_path: options.mo2_path.clone().or(defaults.mo2_path.clone()),
+                mo2_data_dir: options
+                    .mo2_data_dir
+                    .clone()
+                    .or(defaults.mo2_data_dir.clone()),
+                loose_files: options
+                    .loose_files
+                    .or(defaults.loose_files)
+                    .unwrap_or(false),
+                uncompressed: options
+                    .uncompressed
+                    .or(defaults.uncompressed)
+                    .unwrap_or(false),
+                output: options.output.clone(),
+                seed: options.seed.clone().or(defaults.seed.clone()),
+                hooks: options.hooks.clone().or(defaults.hooks.clone()),
+            };
+            if entry.output.is_some() && !entry.loose_files {
+                bail!("{plugin}: output is only used with loose_files = true");
+            }
+            if entry.uncompressed && entry.loose_files {
+                bail!("{plugin}: uncompressed is not used with loose_files = true");
+            }
+            entries.push(entry);
+        }
+        Ok(entries)
+    }
+}
+
+/// Outcome of one plugin in the batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PluginStatus {
+    Succeeded,
+    Failed,
+    /// An earlier plugin failed and the batch stopped
+    NotRun,
+}
+
+impl PluginStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Succeeded => "succeeded",
+            Self::Failed => "failed",
+            Self::NotRun => "not run",
+        }
+    }
+}
+
+/// One plugin's line in the batch report
+#[derive(Debug, Serialize)]
+pub struct PluginResult {
+    pub plugin: String,
+    pub settings: JournalConfig,
+    pub status: PluginStatus,
+    /// Step that failed, if the failure happened inside a step
+    pub failed_step: Option<u8>,
+    pub error: Option<String>,
+    /// Wall-clock time spent on the plugin
+    pub seconds: u64,
+}
+
+/// Combined report of a batch run
+#[derive(Debug, Serialize)]
+pub struct BatchReport {
+    pub manifest: PathBuf,
+    pub started: String,
+    pub finished: String,
+    pub plugins: Vec<PluginResult>,
+}
+
+impl BatchReport {
+    /// Number of plugins with the given status
+    pub fn count(&self, status: PluginStatus) -> usize {
+        self.plugins.iter().filter(|p| p.status == status).count()
+    }
+
+    /// Print the report as a table
+    pub fn print(&self) {
+        println!();
+        println!("======================================");
+        println!("  Batch Report");
+        println!("======================================");
+        for result in &self.plugins {
+            let time = format!("{}m {}s", result.seconds / 60, result.seconds % 60);
+            println!(
+                "{:<10} {:<9} {}  ({})",
+                result.status.as_str(),
+                time,
+                result.plugin,
+                result.settings
+            );
+            if let Some(ref error) = result.error {
+                match result.failed_step {
+                    Some(step) => println!("           Step {step}: {error}"),
+                    None => println!("           {error}"),
+                }
+            }
+        }
+        println!();
+        println!(
+            "{} succeeded, {} failed, {} not run",
+            self.count(PluginStatus::Succeeded),
+            self.count(PluginStatus::Failed),
+            self.count(PluginStatus::NotRun)
+        );
+    }
+
+    /// Write the report as JSON, creating its folder if needed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create report folder: {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write batch report: {}", path.display()))
+    }
+}
+
+/// Default report path: `<FO4>\GeneratePrevisibines\batch\<Manifest>.json`
+pub fn default_report_path(fo4_dir: &Path, manifest: &Path) -> PathBuf {
+    let stem = manifest
+        .file_stem()
+        .map_or_else(|| "batch".into(), |stem| stem.to_string_lossy());
+    fo4_dir
+        .join("GeneratePrevisibines")
+        .join("batch")
+        .join(format!("{stem}.json"))
+}
+
+/// Tool paths shared by every plugin in the batch
+#[derive(Debug, Default)]
+struct BatchTools {
+    creation_kit: PathBuf,
+    ckpe_config: Option<PathBuf>,
+    ck_log: Option<PathBuf>,
+    fo4edit: Option<PathBuf>,
+    archive2: Option<PathBuf>,
+    bsarch: Option<PathBuf>,
+}
+
+impl BatchTools {
+    /// Find the Creation Kit, the CKPE log and the tools any entry needs
+    fn discover(fo4_dir: &Path, entries: &[BatchEntry]) -> Result<Self> {
+        let mut tools = Self {
+            creation_kit: registry::find_creation_kit(fo4_dir)
+                .context("Failed to find Creation Kit in FO4 directory")?,
+            ..Self::default()
+        };
+        println!("Creation Kit:   {}", tools.creation_kit.display());
+
+        if let Some(config_path) = registry::find_ckpe_config(fo4_dir) {
+            let ckpe_cfg = ckpe_config::CKPEConfig::parse(&config_path)
+                .context("Failed to parse CKPE configuration")?;
+            ckpe_cfg
+                .validate()
+                .context("CKPE configuration validation failed")?;
+            println!("CKPE config:    {}", config_path.display());
+            tools.ck_log = ckpe_cfg.log_file_path;
+            tools.ckpe_config = Some(config_path);
+        } else {
+            println!("Warning: No CKPE configuration file found.");
+        }
+
+        if entries.iter().any(|e| e.merge_tool.requires_executable()) {
+            let path = registry::find_fo4edit_path().context(
+                "Failed to find FO4Edit. Make sure it's in the current directory or properly installed.",
+            )?;
+            println!("FO4Edit:        {}", path.display());
+            tools.fo4edit = Some(path);
+        }
+        if entries
+            .iter()
+            .any(|e| e.archive_tool == ArchiveTool::Archive2)
+        {
+            let path = registry::find_archive2(fo4_dir)
+                .context("Failed to find Archive2.exe in FO4 Tools directory")?;
+            println!("Archive2:       {}", path.display());
+            tools.archive2 = Some(path);
+        }
+        if entries
+            .iter()
+            .any(|e| e.archive_tool == ArchiveTool::BSArch)
+        {
+            let path = registry::find_bsarch(fo4_dir)
+                .context("Failed to find BSArch.exe in FO4 directory")?;
+            println!("BSArch:         {}", path.display());
+            tools.bsarch = Some(path);
+        }
+        Ok(tools)
+    }
+
+    /// The workflow configuration for one entry
+    fn config_for(&self, fo4_dir: &Path, entry: &BatchEntry, hooks: Hooks) -> Config {
+        let mut config = Config::new(entry.build_mode, entry.archive_tool);
+        config.merge_tool = entry.merge_tool;
+        config.fo4_dir = fo4_dir.to_path_buf();
+        config.fo4edit_path = self.fo4edit.clone().unwrap_or_default();
+        config.creation_kit_path.clone_from(&self.creation_kit);
+        config.archive_exe_path = match entry.archive_tool {
+            ArchiveTool::Archive2 => self.archive2.clone().unwrap_or_default(),
+            ArchiveTool::BSArch => self.bsarch.clone().unwrap_or_default(),
+            ArchiveTool::Native => PathBuf::new(),
+        };
+        config.ckpe_config_path.clone_from(&self.ckpe_config);
+        config.ck_log_path.clone_from(&self.ck_log);
+        config.plugin_name = Some(entry.plugin.clone());
+        config.mo2_mode = entry.mo2_path.is_some();
+        config.mo2_path.clone_from(&entry.mo2_path);
+        config.mo2_data_dir.clone_from(&entry.mo2_data_dir);
+        config.loose_files = entry.loose_files;
+        config.uncompressed = entry.uncompressed;
+        config.loose_output_dir.clone_from(&entry.output);
+        config.seed_plugin.clone_from(&entry.seed);
+        config.hooks = hooks;
+        config
+    }
+}
+
+/// Directories the workflow writes precombines and previs data to: `Data` and any MO2
+/// staging directories of the entries
+fn output_roots(fo4_dir: &Path, entries: &[BatchEntry]) -> Vec<PathBuf> {
+    let mut roots = vec![fo4_dir.join("Data")];
+    for dir in entries.iter().filter_map(|e| e.mo2_data_dir.as_ref()) {
+        if !roots.contains(dir) {
+            roots.push(dir.clone());
+        }
+    }
+    roots
+}
+
+/// Fail unless `meshes\precombined` and `vis` are empty under each root
+///
+/// # Errors
+///
+/// Returns an error naming the first directory that is not empty
+fn ensure_outputs_empty(roots: &[PathBuf]) -> Result<()> {
+    for root in roots {
+        for dir in [root.join("meshes").join("precombined"), root.join("vis")] {
+            if dir.exists() && !filesystem::is_directory_empty(&dir)? {
+                bail!(
+                    "{} is not empty. Clean it before starting a batch.",
+                    dir.display()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete the working plugins and empty `meshes\precombined` and `vis` under each root
+///
+/// # Errors
+///
+/// Returns an error if a file or directory cannot be deleted
+fn clean_between_plugins(roots: &[PathBuf]) -> Result<()> {
+    for root in roots {
+        if !root.exists() {
+            continue;
+        }
+        for file_name in filesystem::find_working_files(root)? {
+            let path = root.join(&file_name);
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to delete working file: {}", path.display()))?;
+            info!("Deleted: {}", path.display());
+        }
+        for dir in [root.join("meshes").join("precombined"), root.join("vis")] {
+            if dir.exists() && !filesystem::is_directory_empty(&dir)? {
+                let files = filesystem::count_all_files(&dir);
+                fs::remove_dir_all(&dir)
+                    .with_context(|| format!("Failed to empty {}", dir.display()))?;
+                fs::create_dir_all(&dir)
+                    .with_context(|| format!("Failed to recreate {}", dir.display()))?;
+                info!("Emptied {} ({files} files)", dir.display());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run one entry to completion
+///
+/// Returns the failed step (if the error came from a step) with the error.
+fn run_entry(config: &Config, entry: &BatchEntry) -> Result<(), (Option<u8>, anyhow::Error)> {
+    config
+        .validate()
+        .context("Configuration validation failed")
+        .map_err(|e| (None, e))?;
+    validation::validate_plugin_name(&entry.plugin, entry.build_mode == BuildMode::Clean)
+        .context("Plugin name validation failed")
+        .map_err(|e| (None, e))?;
+
+    let mut executor = WorkflowExecutor::new(config, entry.plugin.clone(), false);
+    executor
+        .run_all()
+        .map_err(|e| (executor.failed_step().map(WorkflowStep::number), e))
+}
+
+/// Build every plugin in a manifest, one after another
+///
+/// `fo4_dir` overrides the manifest's `fo4_dir`; `continue_on_error` overrides its
+/// `continue_on_error` when set. The report is written to `report_path`, or to
+/// [`default_report_path`].
+///
+/// # Errors
+///
+/// Returns an error if the manifest is invalid, a tool cannot be found,
+/// `meshes\precombined` or `vis` is not empty before the first plugin, or any plugin
+/// failed
+pub fn run(
+    manifest_path: &Path,
+    fo4_dir: Option<PathBuf>,
+    continue_on_error: bool,
+    report_path: Option<&Path>,
+) -> Result<()> {
+    let manifest = BatchManifest::load(manifest_path)?;
+    let entries = manifest.entries()?;
+    let continue_on_error = continue_on_error || manifest.continue_on_error;
+
+    // Hooks files are relative to the manifest
+    let manifest_dir = manifest_path.parent().unwrap_or(Path::new(""));
+    let hooks = entries
+        .iter()
+        .map(|entry| match entry.hooks {
+            Some(ref path) => Hooks::load(&manifest_dir.join(path)),
+            None => Ok(Hooks::default()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let fo4_dir = match fo4_dir.or(manifest.fo4_dir) {
+        Some(dir) => dir,
+        None => registry::find_fo4_directory()
+            .context("Failed to find Fallout 4 installation. Use --FO4 to specify manually.")?,
+    };
+    filesystem::validate_fo4_directories(&fo4_dir).context("Invalid Fallout 4 installation")?;
+
+    println!("======================================");
+    println!("  Batch: {} plugins", entries.len());
+    println!("======================================");
+    println!("Fallout 4:      {}", fo4_dir.display());
+    let tools = BatchTools::discover(&fo4_dir, &entries)?;
+
+    // Only output from this batch is deleted between plugins
+    let roots = output_roots(&fo4_dir, &entries);
+    ensure_outputs_empty(&roots)?;
+
+    let mut report = BatchReport {
+        manifest: manifest_path.to_path_buf(),
+        started: Local::now().format(TIMESTAMP_FORMAT).to_string(),
+        finished: String::new(),
+        plugins: Vec::with_capacity(entries.len()),
+    };
+    let mut stopped = false;
+    for (index, (entry, hooks)) in entries.iter().zip(hooks).enumerate() {
+        let config = tools.config_for(&fo4_dir, entry, hooks);
+        let settings = JournalConfig::of(&config);
+        if stopped {
+            report.plugins.push(PluginResult {
+                plugin: entry.plugin.clone(),
+                settings,
+                status: PluginStatus::NotRun,
+                failed_step: None,
+                error: None,
+                seconds: 0,
+            });
+            continue;
+        }
+
+        if index > 0 {
+            clean_between_plugins(&roots)
+                .context("Failed to clean up after the previous plugin")?;
+        }
+
+        println!();
+        println!("======================================");
+        println!("  [{}/{}] {}", index + 1, entries.len(), entry.plugin);
+        println!("======================================");
+        println!("Settings:       {settings}");
+        info!("Batch: starting {} ({settings})", entry.plugin);
+
+        let started = Instant::now();
+        let outcome = run_entry(&config, entry);
+        let seconds = started.elapsed().as_secs();
+        let result = match outcome {
+            Ok(()) => PluginResult {
+                plugin: entry.plugin.clone(),
+                settings,
+                status: PluginStatus::Succeeded,
+                failed_step: None,
+                error: None,
+                seconds,
+            },
+            Err((failed_step, e)) => {
+                warn!("Batch: {} failed: {e:#}", entry.plugin);
+                stopped = !continue_on_error;
+                PluginResult {
+                    plugin: entry.plugin.clone(),
+                    settings,
+                    status: PluginStatus::Failed,
+                    failed_step,
+                    error: Some(format!("{e:#}")),
+                    seconds,
+                }
+            }
+        };
+        report.plugins.push(result);
+    }
+    report.finished = Local::now().format(TIMESTAMP_FORMAT).to_string();
+
+    report.print();
+    let report_path = report_path.map_or_else(
+        || default_report_path(&fo4_dir, manifest_path),
+        Path::to_path_buf,
+    );
+    match report.write(&report_path) {
+        Ok(()) => println!("Report written to {}", report_path.display()),
+        Err(e) => warn!("{e:#}"),
+    }
+
+    let failed = report.count(PluginStatus::Failed);
+    if failed > 0 {
+        bail!("{failed} of {} plugins failed", report.plugins.len());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const TOML_MANIFEST: &str = r#"
+continue_on_error = true
+
+[defaults]
+build_mode = "filtered"
+archive_tool = "bsarch"
+seed = "xPrevisPatch.esp"
+
+[[plugins]]
+name = "RegionNorth.esp"
+uncompressed = true
+
+[[plugins]]
+name = "RegionSouth.esp"
+build_mode = "clean"
+archive_tool = "native"
+merge_tool = "native"
+loose_files = true
+output = "D:/Builds/South"
+"#;
+
+    #[test]
+    fn test_toml_entries_apply_defaults() {
+        let manifest = BatchManifest::parse(TOML_MANIFEST, false).unwrap();
+        assert!(manifest.continue_on_error);
+        assert!(manifest.fo4_dir.is_none());
+
+        let entries = manifest.entries().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].plugin, "RegionNorth.esp");
+        assert_eq!(entries[0].build_mode, BuildMode::Filtered);
+        assert_eq!(entries[0].archive_tool, ArchiveTool::BSArch);
+        assert_eq!(entries[0].merge_tool, MergeTool::FO4Edit);
+        assert_eq!(entries[0].seed.as_deref(), Some("xPrevisPatch.esp"));
+        assert!(!entries[0].loose_files);
+        assert!(entries[0].uncompressed);
+
+        assert_eq!(entries[1].build_mode, BuildMode::Clean);
+        assert_eq!(entries[1].archive_tool, ArchiveTool::Native);
+        assert_eq!(entries[1].merge_tool, MergeTool::Native);
+        assert!(entries[1].loose_files);
+        assert!(!entries[1].uncompressed);
+        assert_eq!(entries[1].output, Some(PathBuf::from("D:/Builds/South")));
+    }
+
+    #[test]
+    fn test_json_manifest() {
+        let json = r#"{
+            "fo4_dir": "C:/Games/Fallout 4",
+            "plugins": [{ "name": "MyMod.esp", "archive_tool": "archive2" }]
+        }"#;
+        let manifest = BatchManifest::parse(json, true).unwrap();
+        assert!(!manifest.continue_on_error);
+        assert_eq!(manifest.fo4_dir, Some(PathBuf::from("C:/Games/Fallout 4")));
+
+        let entries = manifest.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].build_mode, BuildMode::Clean);
+        assert_eq!(entries[0].archive_tool, ArchiveTool::Archive2);
+        assert!(entries[0].mo2_path.is_none());
+    }
+
+    #[test]
+    fn test_invalid_manifests() {
+        let entries = |text: &str| BatchManifest::parse(text, false).and_then(|m| m.entries());
+
+        // Typos are reported instead of ignored
+        assert!(entries("[[plugins]]\nname = \"A.esp\"\nbuild = \"clean\"\n").is_err());
+        assert!(entries("[[plugins]]\nname = \"A.esp\"\nbuild_mode = \"fast\"\n").is_err());
+        assert!(entries("continue_on_error = true\n").is_err());
+        assert!(entries("[[plugins]]\nbuild_mode = \"clean\"\n").is_err());
+        assert!(entries("[[plugins]]\nname = \"A.esp\"\n[[plugins]]\nname = \"a.ESP\"\n").is_err());
+        assert!(entries("[defaults]\nname = \"A.esp\"\n[[plugins]]\nname = \"B.esp\"\n").is_err());
+        assert!(entries("[[plugins]]\nname = \"A.esp\"\noutput = \"out\"\n").is_err());
+    }
+
+    #[test]
+    fn test_clean_between_plugins() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        fs::create_dir_all(data.join("meshes").join("precombined")).unwrap();
+        fs::create_dir_all(data.join("vis")).unwrap();
+        fs::write(
+            data.join("meshes/precombined/0001F4A2_A1B2C3D4_OC.nif"),
+            b"nif",
+        )
+        .unwrap();
+        fs::write(data.join("vis/0000003c.uvd"), b"uvd").unwrap();
+        fs::write(data.join("Previs.esp"), b"esp").unwrap();
+        fs::write(data.join("CombinedObjects.esp"), b"esp").unwrap();
+        fs::write(data.join("MyMod.esp"), b"esp").unwrap();
+
+        clean_between_plugins(&[data.clone(), temp.path().join("missing")]).unwrap();
+
+        assert!(filesystem::is_directory_empty(&data.join("meshes").join("precombined")).unwrap());
+        assert!(filesystem::is_directory_empty(&data.join("vis")).unwrap());
+        assert!(!data.join("Previs.esp").exists());
+        assert!(!data.join("CombinedObjects.esp").exists());
+        assert!(data.join("MyMod.esp").exists());
+    }
+
+    #[test]
+    fn test_default_report_path() {
+        let path = default_report_path(Path::new("C:/FO4"), Path::new("D:/regions.toml"));
+        assert_eq!(
+            path,
+            Path::new("C:/FO4/GeneratePrevisibines/batch/regions.json")
+        );
+    }
+}
@@ -0,0 +1,567 @@
+use anyhow::{Context, Result, bail};
+use clap::Subcommand;
+use log::info;
+use std::path::{Path, PathBuf};
+
+use crate::batch;
+use crate::mo2_helper;
+use crate::plugin::load_order::LoadOrderFormIds;
+use crate::plugin::seed::{self, CellSelector};
+use crate::plugin::{breakers, load_order, scanner};
+use crate::precombine_check;
+use crate::registry;
+use crate::snapshots::{self, SnapshotStore};
+use crate::tools::ArchiveManager;
+use crate::tools::archive_backend::NativeBackend;
+use crate::tools::ba2_reader::Ba2Archive;
+use crate::validation;
+
+/// Standalone subcommands that run instead of the precombine/previs workflow
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Inspect BA2 archives without Archive2 or `BSArch`
+    Archive {
+        #[command(subcommand)]
+        action: ArchiveCommand,
+    },
+
+    /// Report which cells of a plugin have current, stale or missing precombine/previs data
+    Analyze {
+        /// Path to the plugin (e.g., Data\MyMod.esp)
+        #[arg(value_name = "PLUGIN")]
+        plugin: PathBuf,
+
+        /// Only list cells whose previs data is stale or missing
+        #[arg(long = "problems")]
+        problems: bool,
+    },
+
+    /// Check that every precombined mesh a plugin references exists, and list orphaned meshes
+    CheckPrecombines {
+        /// Path to the plugin (e.g., Data\MyMod.esp)
+        #[arg(value_name = "PLUGIN")]
+        plugin: PathBuf,
+
+        /// Data folder holding `meshes\precombined` (default: the plugin's folder)
+        #[arg(long = "data", value_name = "PATH")]
+        data: Option<PathBuf>,
+
+        /// Archive to check as well (default: `<Plugin> - Main.ba2` in the data folder, if present)
+        #[arg(long = "archive", value_name = "ARCHIVE")]
+        archive: Option<PathBuf>,
+    },
+
+    /// List plugins later in the load order that override a plugin's precombined cells
+    FindBreakers {
+        /// Path to the plugin (e.g., Data\MyMod.esp)
+        #[arg(value_name = "PLUGIN")]
+        plugin: PathBuf,
+
+        /// Load order to check (default: `%LOCALAPPDATA%\Fallout4\plugins.txt`, or the
+        /// profile's with --mo2)
+        #[arg(long = "plugins-txt", value_name = "PATH")]
+        plugins_txt: Option<PathBuf>,
+
+        /// Data folder with the plugins (default: the plugin's folder)
+        #[arg(long = "data", value_name = "PATH")]
+        data: Option<PathBuf>,
+
+        /// Path to ModOrganizer.exe; uses the selected profile's load order and mods
+        #[arg(long = "mo2", value_name = "PATH")]
+        mo2: Option<PathBuf>,
+    },
+
+    /// Write a new plugin that overrides chosen cells of a master, to use as a seed
+    CreateSeed {
+        /// Plugin to take the cells from (e.g., Data\Fallout4.esm)
+        #[arg(value_name = "SOURCE")]
+        source: PathBuf,
+
+        /// Seed plugin to write; a bare file name is written next to SOURCE
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Cells to include: a form ID as stored in the source (0001F4A2), a plugin and
+        /// object ID (Fallout4.esm:01F4A2), an editor ID, a grid position (Commonwealth:-3,22)
+        /// or a grid rectangle (Commonwealth:-5,20:0,25); repeatable
+        #[arg(long = "cell", value_name = "SELECTOR", required = true)]
+        cells: Vec<CellSelector>,
+    },
+
+    /// List and restore the plugin snapshots taken before each run and merge step
+    Snapshots {
+        #[command(subcommand)]
+        action: SnapshotCommand,
+    },
+
+    /// Run the full workflow for every plugin in a TOML or JSON manifest, one after another
+    Batch {
+        /// Path to the manifest (.toml, or .json)
+        #[arg(value_name = "MANIFEST")]
+        manifest: PathBuf,
+
+        /// Fallout 4 directory (default: the manifest's `fo4_dir`, else found via the registry)
+        #[arg(long = "FO4", value_name = "PATH")]
+        fo4_dir: Option<PathBuf>,
+
+        /// Build the remaining plugins after one fails (as `continue_on_error = true`)
+        #[arg(long = "continue-on-error")]
+        continue_on_error: bool,
+
+        /// Where to write the JSON report
+        /// (default: `<FO4>\GeneratePrevisibines\batch\<Manifest>.json`)
+        #[arg(long = "report", value_name = "PATH")]
+        report: Option<PathBuf>,
+    },
+}
+
+/// Actions for the `snapshots` subcommand
+#[derive(Subcommand, Debug)]
+pub enum SnapshotCommand {
+    /// List the snapshots of a plugin, oldest first
+    List {
+        /// Plugin name (e.g., MyMod.esp)
+        #[arg(value_name = "PLUGIN")]
+        plugin: String,
+
+        /// Fallout 4 directory (default: found via the registry)
+        #[arg(long = "FO4", value_name = "PATH")]
+        fo4_dir: Option<PathBuf>,
+    },
+
+    /// Replace a plugin with one of its snapshots
+    Restore {
+        /// Plugin name (e.g., MyMod.esp)
+        #[arg(value_name = "PLUGIN")]
+        plugin: String,
+
+        /// Number shown by `snapshots list`, or the snapshot's file name (default: the newest)
+        #[arg(value_name = "SNAPSHOT")]
+        snapshot: Option<String>,
+
+        /// Fallout 4 directory (default: found via the registry)
+        #[arg(long = "FO4", value_name = "PATH")]
+        fo4_dir: Option<PathBuf>,
+
+        /// Plugin file to replace (default: `<FO4>\Data\<PLUGIN>`)
+        #[arg(long = "target", value_name = "PATH")]
+        target: Option<PathBuf>,
+    },
+}
+
+/// Actions for the `archive` subcommand
+#[derive(Subcommand, Debug)]
+pub enum ArchiveCommand {
+    /// List the files in a BA2 archive
+    List {
+        /// Path to the BA2 archive
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+    },
+
+    /// Extract files from a BA2 archive
+    Extract {
+        /// Path to the BA2 archive
+        #[arg(value_name = "ARCHIVE")]
+        archive: PathBuf,
+
+        /// Directory to extract into (or destination file when --file is given)
+        #[arg(value_name = "OUTPUT")]
+        output: PathBuf,
+
+        /// Extract only this file (path inside the archive, e.g. vis\0000003c.uvd)
+        #[arg(long = "file", value_name = "NAME")]
+        file: Option<String>,
+    },
+}
+
+/// Run a standalone subcommand
+///
+/// # Errors
+///
+/// Returns an error if the subcommand fails (see the individual handlers).
+pub fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Archive { action } => run_archive(action),
+        Command::Analyze { plugin, problems } => run_analyze(&plugin, problems),
+        Command::CheckPrecombines {
+            plugin,
+            data,
+            archive,
+        } => run_check_precombines(&plugin, data.as_deref(), archive.as_deref()),
+        Command::FindBreakers {
+            plugin,
+            plugins_txt,
+            data,
+            mo2,
+        } => run_find_breakers(
+            &plugin,
+            plugins_txt.as_deref(),
+            data.as_deref(),
+            mo2.as_deref(),
+        ),
+        Command::CreateSeed {
+            source,
+            output,
+            cells,
+        } => run_create_seed(&source, &output, &cells),
+        Command::Snapshots { action } => run_snapshots(action),
+        Command::Batch {
+            manifest,
+            fo4_dir,
+            continue_on_error,
+            report,
+        } => batch::run(&manifest, fo4_dir, continue_on_error, report.as_deref()),
+    }
+}
+
+/// Handle `analyze`
+///
+/// # Errors
+///
+/// Returns an error if the plugin cannot be read or parsed.
+fn run_analyze(plugin: &Path, problems_only: bool) -> Result<()> {
+    let scan = scanner::scan(plugin)?;
+    info!(
+        "Analyzed plugin: {} ({} cells)",
+        plugin.display(),
+        scan.cells.len()
+    );
+
+    println!(
+        "{:<10}  {:<16}  {:>7}  {:>6}  {:>10}  {:>10}  Cell",
+        "Form ID", "Status", "Meshes", "Refs", "PCMB", "VISI"
+    );
+    for cell in scan
+        .cells
+        .iter()
+        .filter(|cell| !problems_only || cell.status().needs_attention())
+    {
+        let timestamp = |t: Option<u16>| t.map_or_else(|| "-".to_string(), |t| t.to_string());
+        println!(
+            "{:08X}    {:<16}  {:>7}  {:>6}  {:>10}  {:>10}  {}",
+            cell.form_id,
+            cell.status().as_str(),
+            cell.precombined_meshes,
+            cell.precombined_refs,
+            timestamp(cell.precombine_timestamp),
+            timestamp(cell.previs_timestamp),
+            cell.location()
+        );
+    }
+    println!();
+    println!("{}: {}", plugin.display(), scan.summary());
+
+    Ok(())
+}
+
+/// Handle `check-precombines`
+///
+/// # Errors
+///
+/// Returns an error if the plugin, one of its installed masters, the mesh folder or the
+/// archive cannot be read, or if any referenced mesh is missing.
+fn run_check_precombines(
+    plugin: &Path,
+    data_dir: Option<&Path>,
+    archive: Option<&Path>,
+) -> Result<()> {
+    let scan = scanner::scan(plugin)?;
+    let data_dir = data_dir
+        .or_else(|| plugin.parent())
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut files = precombine_check::loose_meshes(&[data_dir])?;
+    let default_archive = plugin.file_name().map(|name| {
+        data_dir.join(format!(
+            "{} - Main.ba2",
+            validation::get_plugin_base_name(&name.to_string_lossy())
+        ))
+    });
+    let archive = archive
+        .map(Path::to_path_buf)
+        .or_else(|| default_archive.filter(|path| path.is_file()));
+    if let Some(ref archive) = archive {
+        let ba2 = Ba2Archive::open(archive)?;
+        files.extend(precombine_check::archive_meshes(&ba2));
+    }
+    info!(
+        "Checking precombines: {} against {} meshes ({}{})",
+        plugin.display(),
+        files.len(),
+        data_dir.join("meshes").join("precombined").display(),
+        archive
+            .as_ref()
+            .map(|path| format!(", {}", path.display()))
+            .unwrap_or_default()
+    );
+
+    // The masters decide how the Creation Kit numbered the cells in the mesh names
+    let master_dirs: Vec<PathBuf> = [Some(data_dir), plugin.parent()]
+        .into_iter()
+        .flatten()
+        .map(Path::to_path_buf)
+        .collect();
+    let ids = LoadOrderFormIds::read(plugin, &master_dirs)?;
+    let report = precombine_check::check(&scan, &ids, &files);
+    for missing in &report.missing {
+        println!("Missing:  {}  ({})", missing.file, missing.location);
+    }
+    for orphan in &report.orphaned {
+        println!("Orphaned: {orphan}");
+    }
+    if !report.missing.is_empty() || !report.orphaned.is_empty() {
+        println!();
+    }
+    println!("{}: {}", plugin.display(), report.summary());
+
+    if !report.is_ok() {
+        bail!(
+            "{} precombined meshes referenced by {} are missing",
+            report.missing.len(),
+            plugin.display()
+        );
+    }
+    Ok(())
+}
+
+/// Handle `find-breakers`
+///
+/// # Errors
+///
+/// Returns an error if the load order or a plugin cannot be read, the plugin is not active,
+/// or a later plugin overrides one of its precombined cells.
+fn run_find_breakers(
+    plugin: &Path,
+    plugins_txt: Option<&Path>,
+    data_dir: Option<&Path>,
+    mo2: Option<&Path>,
+) -> Result<()> {
+    let plugins_txt = match (plugins_txt, mo2) {
+        (Some(path), _) => path.to_path_buf(),
+        (None, Some(mo2)) => mo2_helper::profile_plugins_txt(mo2)?,
+        (None, None) => load_order::default_plugins_txt()
+            .context("LOCALAPPDATA is not set; pass the load order with --plugins-txt")?,
+    };
+    let data_dir = data_dir
+        .or_else(|| plugin.parent())
+        .unwrap_or_else(|| Path::new("."));
+    let mut search_dirs = match mo2 {
+        Some(mo2) => mo2_helper::virtual_data_dirs(mo2)?,
+        None => Vec::new(),
+    };
+    search_dirs.push(data_dir.to_path_buf());
+
+    let plugin_name = plugin
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let load_order = load_order::read_plugins_txt(&plugins_txt)?;
+    let later = load_order::plugins_after(&load_order, &plugin_name)
+        .with_context(|| format!("Load order: {}", plugins_txt.display()))?;
+    let later = breakers::locate_plugins(later, &search_dirs);
+
+    let report = breakers::find_breakers(plugin, &later)?;
+    for cell in &report.conflicts {
+        println!("{:08X}  {}", cell.form_id, cell.location);
+        for conflict in &cell.plugins {
+            let mut parts = Vec::new();
+            if conflict.cell_override {
+                parts.push("overrides the cell without its precombine/previs data".to_string());
+            }
+            if !conflict.references.is_empty() {
+                let references: Vec<String> = conflict
+                    .references
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect();
+                parts.push(format!(
+                    "overrides {} references ({})",
+                    references.len(),
+                    references.join(", ")
+                ));
+            }
+            println!("    {}: {}", conflict.plugin, parts.join("; "));
+        }
+    }
+    if !report.is_clean() {
+        println!();
+    }
+    println!("{}: {}", plugin.display(), report.summary());
+
+    if !report.is_clean() {
+        bail!(
+            "{} breaks the precombines/previs of {}; load it before {} or make a patch",
+            report.breaking_plugins().join(", "),
+            plugin_name,
+            plugin_name
+        );
+    }
+    Ok(())
+}
+
+/// Handle `create-seed`
+///
+/// # Errors
+///
+/// Returns an error if the output already exists, the source cannot be read, a selector
+/// matches no cell, or the seed cannot be written.
+fn run_create_seed(source: &Path, output: &Path, cells: &[CellSelector]) -> Result<()> {
+    let output = match (output.parent(), source.parent()) {
+        (Some(dir), Some(source_dir)) if dir.as_os_str().is_empty() => source_dir.join(output),
+        _ => output.to_path_buf(),
+    };
+    let name = output.file_name().unwrap_or_default().to_string_lossy();
+    if !validation::has_plugin_extension(&name) {
+        bail!("Seed plugin name must end with .esp, .esm or .esl: {name}");
+    }
+
+    let report = seed::create_seed(source, &output, cells)?;
+    println!("Created {}", output.display());
+    println!("  {report}");
+    println!();
+    println!("Run the workflow with this plugin as PLUGIN, or pass --seed {name} to copy it.");
+    Ok(())
+}
+
+/// Handle `snapshots list` and `snapshots restore`
+///
+/// # Errors
+///
+/// Returns an error if the Fallout 4 directory cannot be found, the snapshots cannot be
+/// read, the requested snapshot does not exist, or the plugin cannot be replaced.
+fn run_snapshots(action: SnapshotCommand) -> Result<()> {
+    match action {
+        SnapshotCommand::List { plugin, fo4_dir } => {
+            let store = SnapshotStore::new(&resolve_fo4_dir(fo4_dir)?, &plugin);
+            let snapshots = store.list()?;
+            if snapshots.is_empty() {
+                println!("No snapshots of {plugin} in {}", store.dir().display());
+                return Ok(());
+            }
+
+            println!("{:>3}  {:<19}  {:>12}  Taken", "#", "Created", "Size");
+            for (number, snapshot) in snapshots.iter().enumerate() {
+                println!(
+                    "{:>3}  {}  {:>12}  {}",
+                    number + 1,
+                    snapshot.created.format("%Y-%m-%d %H:%M:%S"),
+                    snapshot.size,
+                    snapshot.label.replace('-', " ")
+                );
+            }
+            println!();
+            println!(
+                "{} snapshots of {plugin} in {}",
+                snapshots.len(),
+                store.dir().display()
+            );
+        }
+        SnapshotCommand::Restore {
+            plugin,
+            snapshot,
+            fo4_dir,
+            target,
+        } => {
+            let fo4_dir = resolve_fo4_dir(fo4_dir)?;
+            let store = SnapshotStore::new(&fo4_dir, &plugin);
+            let snapshots = store.list()?;
+            let chosen = match snapshot {
+                None => snapshots.last(),
+                Some(ref wanted) => match wanted.parse::<usize>() {
+                    Ok(number) => number.checked_sub(1).and_then(|i| snapshots.get(i)),
+                    Err(_) => snapshots.iter().find(|s| {
+                        s.path
+                            .file_name()
+                            .is_some_and(|name| name.eq_ignore_ascii_case(wanted.as_str()))
+                    }),
+                },
+            };
+            let Some(chosen) = chosen.cloned() else {
+                bail!(
+                    "Snapshot {} of {plugin} not found; see `snapshots list {plugin}`",
+                    snapshot.as_deref().unwrap_or("(newest)")
+                );
+            };
+
+            let target = target.unwrap_or_else(|| fo4_dir.join("Data").join(&plugin));
+            if target.is_file() {
+                let current = store.take(&target, "before-restore")?;
+                println!("Current {plugin} saved as {}", current.path.display());
+            }
+            snapshots::restore(&chosen, &target)?;
+            println!(
+                "Restored {} from the snapshot taken {} ({})",
+                target.display(),
+                chosen.created.format("%Y-%m-%d %H:%M:%S"),
+                chosen.label.replace('-', " ")
+            );
+        }
+    }
+    Ok(())
+}
+
+/// The Fallout 4 directory from `--FO4`, or from the registry
+fn resolve_fo4_dir(fo4_dir: Option<PathBuf>) -> Result<PathBuf> {
+    match fo4_dir {
+        Some(dir) => Ok(dir),
+        None => registry::find_fo4_directory()
+            .context("Failed to find Fallout 4 installation. Use --FO4 to specify manually."),
+    }
+}
+
+/// Handle `archive list` and `archive extract`
+///
+/// # Errors
+///
+/// Returns an error if the archive cannot be parsed, a requested file is not in the
+/// archive, or the output cannot be written.
+fn run_archive(action: ArchiveCommand) -> Result<()> {
+    // The built-in reader handles archives from every tool, so no executable is needed.
+    // Absolute paths make the manager ignore its Fallout 4 directory.
+    let manager = ArchiveManager::with_backend(Box::new(NativeBackend), "");
+
+    match action {
+        ArchiveCommand::List { archive } => {
+            let archive = std::path::absolute(&archive)?;
+            let entries = manager.list_archive(&archive)?;
+            info!(
+                "Listing archive: {} ({} files)",
+                archive.display(),
+                entries.len()
+            );
+
+            println!("{:>12}  {:>12}  Name", "Size", "Packed");
+            for entry in &entries {
+                let packed = if entry.is_compressed() {
+                    entry.packed_size.to_string()
+                } else {
+                    "-".to_string()
+                };
+                println!(
+                    "{:>12}  {:>12}  {}",
+                    entry.unpacked_size, packed, entry.name
+                );
+            }
+            println!();
+            println!("{} files in {}", entries.len(), archive.display());
+        }
+        ArchiveCommand::Extract {
+            archive,
+            output,
+            file,
+        } => {
+            if let Some(name) = file {
+                Ba2Archive::open(&archive)?.extract_file(&name, &output)?;
+                println!("Extracted {name} to {}", output.display());
+            } else {
+                manager
+                    .extract_archive(std::path::absolute(&archive)?, &output)
+                    .with_context(|| format!("Failed to extract archive: {}", archive.display()))?;
+                println!("Extracted {} to {}", archive.display(), output.display());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,8 +1,13 @@
 use anyhow::Result;
+use serde::Deserialize;
 use std::path::PathBuf;
 
+use crate::hooks::Hooks;
+use crate::tools::archive_backend::CompressionProfile;
+
 /// Build mode for the precombine/previs generation
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum BuildMode {
     Clean,
     Filtered,
@@ -20,10 +25,52 @@ impl BuildMode {
 }
 
 /// Archive tool to use
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ArchiveTool {
     Archive2,
     BSArch,
+    /// Built-in BA2 writer (no external executable required)
+    Native,
+}
+
+impl ArchiveTool {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ArchiveTool::Archive2 => "Archive2",
+            ArchiveTool::BSArch => "BSArch",
+            ArchiveTool::Native => "Native",
+        }
+    }
+
+    /// Whether this tool requires an external executable
+    pub fn requires_executable(self) -> bool {
+        !matches!(self, ArchiveTool::Native)
+    }
+}
+
+/// Tool used to merge the Creation Kit's output plugins into the target plugin
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeTool {
+    /// Built-in plugin merge (no external tool required)
+    Native,
+    /// `FO4Edit` batch scripts
+    FO4Edit,
+}
+
+impl MergeTool {
+    pub fn as_str(&self) -> &str {
+        match self {
+            MergeTool::Native => "Native",
+            MergeTool::FO4Edit => "FO4Edit",
+        }
+    }
+
+    /// Whether this tool requires the `FO4Edit` executable
+    pub fn requires_executable(self) -> bool {
+        matches!(self, MergeTool::FO4Edit)
+    }
 }
 
 /// Configuration for the tool, including paths to external programs
@@ -35,19 +82,22 @@ pub struct Config {
     /// Archive tool to use
     pub archive_tool: ArchiveTool,
 
+    /// Tool used to merge `CombinedObjects.esp` and `Previs.esp` into the plugin (steps 2 and 7)
+    pub merge_tool: MergeTool,
+
     /// Plugin name (e.g., "MyMod.esp")
     pub plugin_name: Option<String>,
 
     /// Fallout 4 installation directory
     pub fo4_dir: PathBuf,
 
-    /// `FO4Edit` executable path
+    /// `FO4Edit` executable path (unused for [`MergeTool::Native`])
     pub fo4edit_path: PathBuf,
 
     /// Creation Kit executable path
     pub creation_kit_path: PathBuf,
 
-    /// Archive2 or `BSArch` executable path
+    /// Archive2 or `BSArch` executable path (unused for [`ArchiveTool::Native`])
     pub archive_exe_path: PathBuf,
 
     /// CKPE configuration file path
@@ -65,6 +115,23 @@ pub struct Config {
     /// Path to MO2's VFS staging directory (e.g., overwrite folder)
     /// Required when `mo2_mode` is true for archiving operations
     pub mo2_data_dir: Option<PathBuf>,
+
+    /// Keep precombines and previs data loose instead of archiving them (skips steps 3 and 8)
+    pub loose_files: bool,
+
+    /// Store archive entries uncompressed instead of using the build mode's compression
+    pub uncompressed: bool,
+
+    /// Folder the loose-files build is copied to (only used if `loose_files` is true);
+    /// defaults to `<FO4>\GeneratePrevisibines\loose\<PluginBase>`
+    pub loose_output_dir: Option<PathBuf>,
+
+    /// Plugin in `Data` to copy when the target plugin does not exist; defaults to the
+    /// only `xPrevisPatch` plugin in `Data`
+    pub seed_plugin: Option<String>,
+
+    /// Commands to run before and after steps or the whole run (from `--hooks`)
+    pub hooks: Hooks,
 }
 
 impl Config {
@@ -73,6 +140,7 @@ impl Config {
         Self {
             build_mode,
             archive_tool,
+            merge_tool: MergeTool::FO4Edit,
             plugin_name: None,
             fo4_dir: PathBuf::new(),
             fo4edit_path: PathBuf::new(),
@@ -83,6 +151,11 @@ impl Config {
             mo2_mode: false,
             mo2_path: None,
             mo2_data_dir: None,
+            loose_files: false,
+            uncompressed: false,
+            loose_output_dir: None,
+            seed_plugin: None,
+            hooks: Hooks::default(),
         }
     }
 
@@ -98,6 +171,16 @@ impl Config {
         self.fo4_dir.join("Data")
     }
 
+    /// Compression for the entries steps 3 and 8 archive: none with `uncompressed`,
+    /// otherwise the build mode's profile
+    pub fn compression_profile(&self) -> CompressionProfile {
+        if self.uncompressed {
+            CompressionProfile::None
+        } else {
+            CompressionProfile::for_build_mode(self.build_mode)
+        }
+    }
+
     /// Get the meshes\\precombined directory
     #[allow(dead_code)]
     pub fn precombined_dir(&self) -> PathBuf {
@@ -119,7 +202,7 @@ impl Config {
             );
         }
 
-        if !self.fo4edit_path.exists() {
+        if self.merge_tool.requires_executable() && !self.fo4edit_path.exists() {
             anyhow::bail!("FO4Edit not found at: {}", self.fo4edit_path.display());
         }
 
@@ -130,14 +213,10 @@ impl Config {
             );
         }
 
-        if !self.archive_exe_path.exists() {
-            let tool_name = match self.archive_tool {
-                ArchiveTool::Archive2 => "Archive2",
-                ArchiveTool::BSArch => "BSArch",
-            };
+        if self.archive_tool.requires_executable() && !self.archive_exe_path.exists() {
             anyhow::bail!(
                 "{} not found at: {}",
-                tool_name,
+                self.archive_tool.as_str(),
                 self.archive_exe_path.display()
             );
         }
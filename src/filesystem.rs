@@ -153,6 +153,15 @@ pub fn count_files(dir: &Path, extension: &str) -> usize {
         .count()
 }
 
+/// Count every file in a directory and its subdirectories
+pub fn count_all_files(dir: &Path) -> usize {
+    WalkDir::new(dir)
+        .into_iter()
+        .filter_map(std::result::Result::ok)
+        .filter(|e| e.file_type().is_file())
+        .count()
+}
+
 /// Check if a directory is empty
 ///
 /// Determines whether a directory contains any entries (files or subdirectories).
@@ -282,7 +291,6 @@ pub fn delete_matching_files(dir: &Path, extension: &str) -> Result<usize> {
 }
 
 /// Get the size of a directory in bytes
-#[allow(dead_code)] // Part of public filesystem utility API; available for external use
 pub fn get_directory_size(dir: &Path) -> u64 {
     if !dir.exists() {
         return 0;
@@ -297,6 +305,43 @@ pub fn get_directory_size(dir: &Path) -> u64 {
         .sum()
 }
 
+/// Recursively copy a directory
+///
+/// Creates `dst` (and any missing parents) and copies every file under `src` to the same
+/// relative path under `dst`, overwriting existing files.
+///
+/// Returns the number of files copied.
+///
+/// # Errors
+///
+/// This function will return an error if:
+/// - `src` cannot be read
+/// - A directory cannot be created or a file cannot be copied
+pub fn copy_directory(src: &Path, dst: &Path) -> Result<usize> {
+    let mut count = 0;
+
+    for entry in WalkDir::new(src) {
+        let entry =
+            entry.with_context(|| format!("Failed to read directory: {}", src.display()))?;
+        let relative = entry
+            .path()
+            .strip_prefix(src)
+            .context("Directory entry outside source directory")?;
+        let target = dst.join(relative);
+
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target)
+                .with_context(|| format!("Failed to create directory: {}", target.display()))?;
+        } else if entry.file_type().is_file() {
+            fs::copy(entry.path(), &target)
+                .with_context(|| format!("Failed to copy file: {}", entry.path().display()))?;
+            count += 1;
+        }
+    }
+
+    Ok(count)
+}
+
 /// Find xPrevisPatch plugin in the Data directory
 ///
 /// This function scans for plugin files (.esp or .esm) containing "xprevis" in their name
@@ -308,8 +353,8 @@ pub fn get_directory_size(dir: &Path) -> u64 {
 ///
 /// # Returns
 ///
-/// Returns a vector of plugin filenames (not full paths) that contain "xprevis".
-/// Returns an empty vector if the directory doesn't exist or contains no matching plugins.
+/// Returns a vector of plugin filenames (not full paths) that contain "xprevis", sorted
+/// case-insensitively. Returns an empty vector if the directory doesn't exist or contains no matching plugins.
 ///
 /// # Errors
 ///
@@ -372,6 +417,7 @@ pub fn find_xprevis_patch_plugins(data_dir: &Path) -> Result<Vec<String>> {
         }
     }
 
+    xprevis_plugins.sort_by_key(|name| name.to_lowercase());
     Ok(xprevis_plugins)
 }
 
@@ -518,6 +564,21 @@ mod tests {
         assert!(!is_directory_empty(temp_dir.path()).unwrap());
     }
 
+    #[test]
+    fn test_copy_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let src = temp_dir.path().join("src");
+        fs::create_dir_all(src.join("sub")).unwrap();
+        File::create(src.join("a.uvd")).unwrap();
+        File::create(src.join("sub").join("b.uvd")).unwrap();
+
+        let dst = temp_dir.path().join("out").join("vis");
+        assert_eq!(copy_directory(&src, &dst).unwrap(), 2);
+        assert!(dst.join("a.uvd").exists());
+        assert!(dst.join("sub").join("b.uvd").exists());
+        assert!(src.join("a.uvd").exists());
+    }
+
     #[test]
     fn test_is_directory_empty_nonexistent() {
         let nonexistent = std::path::Path::new("nonexistent_dir_12345");
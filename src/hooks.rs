@@ -0,0 +1,374 @@
+//! User-defined hooks
+//!
+//! Shell commands run before or after a workflow step, or before or after the whole run,
+//! so cleaning scripts, packaging and comparisons can be wired in without changing the
+//! tool. Hooks are read from a TOML file (`--hooks`, or `hooks` in a batch manifest):
+//!
+//! ```toml
+//! # Clean the plugin before the Creation Kit sees it
+//! [[hooks]]
+//! when = "before"
+//! step = 1
+//! command = 'powershell -NoProfile -File Scripts\CleanPlugin.ps1'
+//!
+//! # Keep this build's previs data for comparison
+//! [[hooks]]
+//! when = "after"
+//! step = 6
+//! command = 'xcopy /E /I /Y "%PREVIS_DATA_DIR%\vis" "D:\Compare\%PREVIS_PLUGIN%\vis"'
+//!
+//! # No step: after the whole run
+//! [[hooks]]
+//! when = "after"
+//! command = 'copy /Y "%PREVIS_ARCHIVE%" D:\Packaging\'
+//! ```
+//!
+//! Commands run through `cmd /C` in the hooks file's folder, in file order, with these
+//! environment variables:
+//!
+//! | Variable | Value |
+//! |----------|-------|
+//! | `PREVIS_HOOK` | `before` or `after` |
+//! | `PREVIS_STEP` | Step number (1-8), `0` for whole-run hooks |
+//! | `PREVIS_STEP_NAME` | Step name (step hooks only) |
+//! | `PREVIS_PLUGIN` | Plugin name (e.g., `MyMod.esp`) |
+//! | `PREVIS_BUILD_MODE` | `clean`, `filtered` or `xbox` |
+//! | `PREVIS_FO4_DIR` | Fallout 4 directory |
+//! | `PREVIS_DATA_DIR` | `<FO4>\Data` |
+//! | `PREVIS_ARCHIVE` | `<FO4>\Data\<Plugin> - Main.ba2` (not set in loose-files mode) |
+//! | `PREVIS_LOOSE_OUTPUT` | Loose-files output folder (loose-files mode only) |
+//! | `PREVIS_MO2_DATA_DIR` | MO2 data directory (when set) |
+//!
+//! A failing before hook (non-zero exit code) stops the workflow before the step or run
+//! starts. After hooks only run when the step or run succeeded, and a failing after hook
+//! is logged as a warning, since the work it follows is already done. Hooks of skipped
+//! steps do not run.
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::Config;
+use crate::loose_files;
+use crate::validation;
+use crate::workflow::WorkflowStep;
+
+/// When a hook runs relative to its step (or the run)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookTiming {
+    Before,
+    After,
+}
+
+impl HookTiming {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Before => "before",
+            Self::After => "after",
+        }
+    }
+}
+
+/// One command from the hooks file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub when: HookTiming,
+    /// Step number (1-8); `None` runs the hook before or after the whole run
+    pub step: Option<u8>,
+    /// Command line passed to the shell
+    pub command: String,
+}
+
+impl Hook {
+    /// What the hook is attached to, e.g. "before-step-6" or "after-run"
+    fn label(&self) -> String {
+        match self.step {
+            Some(step) => format!("{}-step-{step}", self.when.as_str()),
+            None => format!("{}-run", self.when.as_str()),
+        }
+    }
+}
+
+/// Layout of the hooks file
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HooksFile {
+    #[serde(default)]
+    hooks: Vec<Hook>,
+}
+
+/// The hooks of a run; empty unless a hooks file was given
+#[derive(Debug, Clone, Default)]
+pub struct Hooks {
+    /// Folder the commands run in (the hooks file's folder)
+    pub dir: PathBuf,
+    pub hooks: Vec<Hook>,
+}
+
+impl Hooks {
+    /// Read a hooks file
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read, is not valid TOML, has unknown keys
+    /// or names a step outside 1-8
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read hooks file: {}", path.display()))?;
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .map_or_else(|| PathBuf::from("."), Path::to_path_buf);
+        Self::parse(&text, dir).with_context(|| format!("Invalid hooks file: {}", path.display()))
+    }
+
+    /// Parse hooks from TOML text
+    fn parse(text: &str, dir: PathBuf) -> Result<Self> {
+        let file: HooksFile = toml::from_str(text)?;
+        for hook in &file.hooks {
+            if let Some(step) = hook.step
+                && WorkflowStep::from_number(step).is_none()
+            {
+                bail!("Hook step must be 1-8, got {step}: {}", hook.command);
+            }
+        }
+        Ok(Self {
+            dir,
+            hooks: file.hooks,
+        })
+    }
+
+    /// Hooks for a step (or, with `None`, for the whole run), in file order
+    pub fn matching(
+        &self,
+        timing: HookTiming,
+        step: Option<WorkflowStep>,
+    ) -> impl Iterator<Item = &Hook> {
+        let step = step.map(WorkflowStep::number);
+        self.hooks
+            .iter()
+            .filter(move |hook| hook.when == timing && hook.step == step)
+    }
+
+    /// Run the matching hooks in order, stopping at the first that fails
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a hook cannot be started or exits with a non-zero code
+    pub fn run(
+        &self,
+        timing: HookTiming,
+        step: Option<WorkflowStep>,
+        env: &[(&'static str, String)],
+    ) -> Result<()> {
+        for hook in self.matching(timing, step) {
+            let label = hook.label();
+            info!("Running {label} hook: {}", hook.command);
+            let status = shell(&hook.command)
+                .current_dir(&self.dir)
+                .envs(env.iter().map(|(key, value)| (*key, value)))
+                .status()
+                .with_context(|| format!("Failed to start {label} hook: {}", hook.command))?;
+            if !status.success() {
+                let code = status
+                    .code()
+                    .map_or_else(|| "none".to_string(), |code| code.to_string());
+                bail!(
+                    "The {label} hook failed (exit code {code}): {}",
+                    hook.command
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Describe the matching hooks for `--dry-run`
+    pub fn plan(&self, timing: HookTiming, step: Option<WorkflowStep>) -> Vec<String> {
+        self.matching(timing, step)
+            .map(|hook| {
+                format!(
+                    "Run {} hook: {} (in {})",
+                    hook.label(),
+                    hook.command,
+                    self.dir.display()
+                )
+            })
+            .collect()
+    }
+}
+
+/// Environment variables passed to a hook
+pub fn environment(
+    config: &Config,
+    plugin_name: &str,
+    timing: HookTiming,
+    step: Option<WorkflowStep>,
+) -> Vec<(&'static str, String)> {
+    let data_dir = config.data_dir();
+    let mut env = vec![
+        ("PREVIS_HOOK", timing.as_str().to_string()),
+        (
+            "PREVIS_STEP",
+            step.map_or(0, WorkflowStep::number).to_string(),
+        ),
+        ("PREVIS_PLUGIN", plugin_name.to_string()),
+        ("PREVIS_BUILD_MODE", config.build_mode.as_str().to_string()),
+        ("PREVIS_FO4_DIR", config.fo4_dir.display().to_string()),
+        ("PREVIS_DATA_DIR", data_dir.display().to_string()),
+    ];
+    if let Some(step) = step {
+        env.push(("PREVIS_STEP_NAME", step.name().to_string()));
+    }
+    if config.loose_files {
+        let output_dir = config
+            .loose_output_dir
+            .clone()
+            .unwrap_or_else(|| loose_files::default_output_dir(&config.fo4_dir, plugin_name));
+        env.push(("PREVIS_LOOSE_OUTPUT", output_dir.display().to_string()));
+    } else {
+        let plugin_base = validation::get_plugin_base_name(plugin_name);
+        let archive = data_dir.join(format!("{plugin_base} - Main.ba2"));
+        env.push(("PREVIS_ARCHIVE", archive.display().to_string()));
+    }
+    if let Some(ref mo2_data_dir) = config.mo2_data_dir {
+        env.push(("PREVIS_MO2_DATA_DIR", mo2_data_dir.display().to_string()));
+    }
+    env
+}
+
+/// Shell command running `command`
+///
+/// On Windows the command line is passed to `cmd /C` verbatim, so quoting works as it
+/// does in a batch file.
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").raw_arg(command);
+    cmd
+}
+
+/// Shell command running `command`
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveTool, BuildMode};
+    use tempfile::TempDir;
+
+    const HOOKS: &str = r#"
+[[hooks]]
+when = "before"
+step = 1
+command = "clean.cmd"
+
+[[hooks]]
+when = "after"
+step = 6
+command = "compare.cmd"
+
+[[hooks]]
+when = "after"
+command = "package.cmd"
+"#;
+
+    #[test]
+    fn test_parse_and_match() {
+        let hooks = Hooks::parse(HOOKS, PathBuf::from("scripts")).unwrap();
+        assert_eq!(hooks.hooks.len(), 3);
+
+        let commands = |timing, step| {
+            hooks
+                .matching(timing, step)
+                .map(|hook| hook.command.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            commands(HookTiming::Before, Some(WorkflowStep::GeneratePrecombined)),
+            ["clean.cmd"]
+        );
+        assert_eq!(
+            commands(HookTiming::After, Some(WorkflowStep::GeneratePrevis)),
+            ["compare.cmd"]
+        );
+        assert_eq!(commands(HookTiming::After, None), ["package.cmd"]);
+        assert!(commands(HookTiming::Before, None).is_empty());
+        assert!(commands(HookTiming::After, Some(WorkflowStep::GeneratePrecombined)).is_empty());
+
+        assert_eq!(
+            hooks.plan(HookTiming::After, Some(WorkflowStep::GeneratePrevis)),
+            ["Run after-step-6 hook: compare.cmd (in scripts)"]
+        );
+    }
+
+    #[test]
+    fn test_invalid_hooks() {
+        let parse = |text: &str| Hooks::parse(text, PathBuf::new());
+        assert!(parse("[[hooks]]\nwhen = \"before\"\nstep = 9\ncommand = \"x\"\n").is_err());
+        assert!(parse("[[hooks]]\nwhen = \"during\"\ncommand = \"x\"\n").is_err());
+        assert!(parse("[[hooks]]\nwhen = \"before\"\ncmd = \"x\"\n").is_err());
+        assert!(parse("").unwrap().hooks.is_empty());
+    }
+
+    #[test]
+    fn test_environment() {
+        let mut config = Config::new(BuildMode::Xbox, ArchiveTool::Native);
+        config.fo4_dir = PathBuf::from("FO4");
+        let env = environment(
+            &config,
+            "MyMod.esp",
+            HookTiming::After,
+            Some(WorkflowStep::AddPrevisToArchive),
+        );
+        let get = |key: &str| {
+            env.iter()
+                .find(|(name, _)| *name == key)
+                .map(|(_, value)| value.clone())
+        };
+        assert_eq!(get("PREVIS_HOOK").as_deref(), Some("after"));
+        assert_eq!(get("PREVIS_STEP").as_deref(), Some("8"));
+        assert_eq!(get("PREVIS_BUILD_MODE").as_deref(), Some("xbox"));
+        let archive = Path::new("FO4").join("Data").join("MyMod - Main.ba2");
+        assert_eq!(get("PREVIS_ARCHIVE"), Some(archive.display().to_string()));
+        assert!(get("PREVIS_LOOSE_OUTPUT").is_none());
+
+        let env = environment(&config, "MyMod.esp", HookTiming::Before, None);
+        assert!(env.contains(&("PREVIS_STEP", "0".to_string())));
+        assert!(!env.iter().any(|(name, _)| *name == "PREVIS_STEP_NAME"));
+    }
+
+    #[test]
+    fn test_run_passes_environment_and_fails_on_exit_code() {
+        let temp = TempDir::new().unwrap();
+        let command = if cfg!(windows) {
+            "echo %PREVIS_PLUGIN%> hook.txt"
+        } else {
+            "echo $PREVIS_PLUGIN> hook.txt"
+        };
+        let text = format!(
+            "[[hooks]]\nwhen = \"before\"\ncommand = '{command}'\n\n\
+             [[hooks]]\nwhen = \"after\"\ncommand = 'exit 3'\n"
+        );
+        let hooks = Hooks::parse(&text, temp.path().to_path_buf()).unwrap();
+        let env = [("PREVIS_PLUGIN", "MyMod.esp".to_string())];
+
+        hooks.run(HookTiming::Before, None, &env).unwrap();
+        let written = fs::read_to_string(temp.path().join("hook.txt")).unwrap();
+        assert_eq!(written.trim(), "MyMod.esp");
+
+        let error = hooks.run(HookTiming::After, None, &env).unwrap_err();
+        assert!(format!("{error:#}").contains("after-run hook failed (exit code 3)"));
+    }
+}
@@ -0,0 +1,349 @@
+//! Run journal
+//!
+//! The Creation Kit steps can run for hours, and when one crashes the console output is
+//! often gone by the time anyone looks. The workflow records every step it starts,
+//! finishes, fails or skips in a journal per plugin, together with the settings of the
+//! run, so `--resume` can continue from the first step that did not complete:
+//!
+//! ```text
+//! <FO4>\GeneratePrevisibines\journal\MyMod.esp.json
+//! ```
+//!
+//! ```json
+//! {
+//!   "plugin": "MyMod.esp",
+//!   "config": {
+//!     "build_mode": "clean",
+//!     "archive_tool": "Archive2",
+//!     "merge_tool": "Native",
+//!     "mo2_mode": false,
+//!     "loose_files": false,
+//!     "uncompressed": false
+//!   },
+//!   "steps": [
+//!     {
+//!       "step": 1,
+//!       "name": "Generate Precombines Via CK",
+//!       "status": "completed",
+//!       "started": "2026-10-16 14:22:33",
+//!       "finished": "2026-10-16 15:40:12",
+//!       "detail": null
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! A run starting at step 1 replaces the journal; a run starting later appends to it.
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+use crate::workflow::WorkflowStep;
+
+/// Timestamp format of journal entries (local time)
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Outcome of a step
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StepStatus {
+    /// The step started and has not finished (or the process died during it)
+    Started,
+    Completed,
+    Failed,
+    /// Not run in this build mode or output mode
+    Skipped,
+}
+
+impl StepStatus {
+    /// Whether the step does not need to run again
+    pub fn is_done(self) -> bool {
+        matches!(self, Self::Completed | Self::Skipped)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Started => "started",
+            Self::Completed => "completed",
+            Self::Failed => "failed",
+            Self::Skipped => "skipped",
+        }
+    }
+}
+
+/// Settings of the run that wrote the journal
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JournalConfig {
+    pub build_mode: String,
+    pub archive_tool: String,
+    pub merge_tool: String,
+    pub mo2_mode: bool,
+    pub loose_files: bool,
+    #[serde(default)]
+    pub uncompressed: bool,
+}
+
+impl JournalConfig {
+    /// The journal settings of a configuration
+    pub fn of(config: &Config) -> Self {
+        Self {
+            build_mode: config.build_mode.as_str().to_string(),
+            archive_tool: config.archive_tool.as_str().to_string(),
+            merge_tool: config.merge_tool.as_str().to_string(),
+            mo2_mode: config.mo2_mode,
+            loose_files: config.loose_files,
+            uncompressed: config.uncompressed,
+        }
+    }
+}
+
+impl fmt::Display for JournalConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} mode, {}, {} merge",
+            self.build_mode, self.archive_tool, self.merge_tool
+        )?;
+        if self.mo2_mode {
+            f.write_str(", MO2")?;
+        }
+        if self.loose_files {
+            f.write_str(", loose files")?;
+        }
+        if self.uncompressed {
+            f.write_str(", uncompressed")?;
+        }
+        Ok(())
+    }
+}
+
+/// One attempt at a step
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepEntry {
+    /// Step number (1-8)
+    pub step: u8,
+    /// Step name, for people reading the file
+    pub name: String,
+    pub status: StepStatus,
+    /// When the step started (local time)
+    pub started: String,
+    /// When the step completed, failed or was skipped
+    pub finished: Option<String>,
+    /// Error of a failed step, or why a step was skipped
+    pub detail: Option<String>,
+}
+
+/// Journal of the steps run for one plugin
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunJournal {
+    /// Plugin the workflow ran for
+    pub plugin: String,
+    /// Settings of the latest run
+    pub config: JournalConfig,
+    /// Every step attempt, oldest first
+    pub steps: Vec<StepEntry>,
+}
+
+impl RunJournal {
+    /// Path of a plugin's journal: `<FO4>\GeneratePrevisibines\journal\<Plugin>.json`
+    pub fn path_for(fo4_dir: &Path, plugin_name: &str) -> PathBuf {
+        fo4_dir
+            .join("GeneratePrevisibines")
+            .join("journal")
+            .join(format!("{plugin_name}.json"))
+    }
+
+    /// An empty journal
+    pub fn new(plugin_name: &str, config: JournalConfig) -> Self {
+        Self {
+            plugin: plugin_name.to_string(),
+            config,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Read a journal, if it exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the journal exists but cannot be read or parsed
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read journal: {}", path.display()))?;
+        let journal = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse journal: {}", path.display()))?;
+        Ok(Some(journal))
+    }
+
+    /// Write the journal, creating its folder if needed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create journal folder: {}", dir.display()))?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n")
+            .with_context(|| format!("Failed to write journal: {}", path.display()))
+    }
+
+    /// Record that a step started
+    pub fn step_started(&mut self, step: WorkflowStep) {
+        self.steps.push(StepEntry {
+            step: step.number(),
+            name: step.name().to_string(),
+            status: StepStatus::Started,
+            started: now(),
+            finished: None,
+            detail: None,
+        });
+    }
+
+    /// Record how the last attempt at a step ended
+    pub fn step_finished(
+        &mut self,
+        step: WorkflowStep,
+        status: StepStatus,
+        detail: Option<String>,
+    ) {
+        let entry = self
+            .steps
+            .iter_mut()
+            .rev()
+            .find(|entry| entry.step == step.number() && entry.status == StepStatus::Started);
+        if let Some(entry) = entry {
+            entry.status = status;
+            entry.finished = Some(now());
+            entry.detail = detail;
+        }
+    }
+
+    /// Record that a step was skipped
+    pub fn step_skipped(&mut self, step: WorkflowStep, reason: &str) {
+        let now = now();
+        self.steps.push(StepEntry {
+            step: step.number(),
+            name: step.name().to_string(),
+            status: StepStatus::Skipped,
+            started: now.clone(),
+            finished: Some(now),
+            detail: Some(reason.to_string()),
+        });
+    }
+
+    /// Latest attempt at a step
+    pub fn last_entry(&self, step: WorkflowStep) -> Option<&StepEntry> {
+        self.steps
+            .iter()
+            .rev()
+            .find(|entry| entry.step == step.number())
+    }
+
+    /// First step that has not completed, or `None` if the last run finished
+    ///
+    /// Steps before the first one in the journal (a run that started later than step 1)
+    /// count as done.
+    pub fn resume_step(&self) -> Option<WorkflowStep> {
+        let first = self.steps.iter().map(|entry| entry.step).min().unwrap_or(1);
+        let mut step = WorkflowStep::from_number(first);
+        while let Some(current) = step {
+            if !self
+                .last_entry(current)
+                .is_some_and(|entry| entry.status.is_done())
+            {
+                return Some(current);
+            }
+            step = current.next();
+        }
+        None
+    }
+}
+
+/// Current local time in the journal's format
+fn now() -> String {
+    Local::now().format(TIMESTAMP_FORMAT).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ArchiveTool, BuildMode};
+    use tempfile::TempDir;
+
+    fn journal() -> RunJournal {
+        let config = Config::new(BuildMode::Filtered, ArchiveTool::Native);
+        RunJournal::new("MyMod.esp", JournalConfig::of(&config))
+    }
+
+    #[test]
+    fn test_resume_step() {
+        let mut journal = journal();
+        assert_eq!(
+            journal.resume_step(),
+            Some(WorkflowStep::GeneratePrecombined)
+        );
+
+        for step in [
+            WorkflowStep::GeneratePrecombined,
+            WorkflowStep::MergeCombinedObjects,
+            WorkflowStep::CreatePrecombinedArchive,
+        ] {
+            journal.step_started(step);
+            journal.step_finished(step, StepStatus::Completed, None);
+        }
+        journal.step_skipped(WorkflowStep::CompressPSG, "clean mode only");
+        journal.step_skipped(WorkflowStep::BuildCDX, "clean mode only");
+        journal.step_started(WorkflowStep::GeneratePrevis);
+        journal.step_finished(
+            WorkflowStep::GeneratePrevis,
+            StepStatus::Failed,
+            Some("Creation Kit crashed".to_string()),
+        );
+        assert_eq!(journal.resume_step(), Some(WorkflowStep::GeneratePrevis));
+
+        // A crash during the step leaves it started
+        journal.step_started(WorkflowStep::GeneratePrevis);
+        assert_eq!(journal.resume_step(), Some(WorkflowStep::GeneratePrevis));
+        journal.step_finished(WorkflowStep::GeneratePrevis, StepStatus::Completed, None);
+
+        for step in [WorkflowStep::MergePrevis, WorkflowStep::AddPrevisToArchive] {
+            journal.step_started(step);
+            journal.step_finished(step, StepStatus::Completed, None);
+        }
+        assert_eq!(journal.resume_step(), None);
+    }
+
+    #[test]
+    fn test_round_trip_and_late_start() {
+        let temp = TempDir::new().unwrap();
+        let path = RunJournal::path_for(temp.path(), "MyMod.esp");
+        assert!(RunJournal::load(&path).unwrap().is_none());
+
+        let mut journal = journal();
+        journal.step_started(WorkflowStep::MergePrevis);
+        journal.step_finished(WorkflowStep::MergePrevis, StepStatus::Completed, None);
+        journal.write(&path).unwrap();
+
+        let loaded = RunJournal::load(&path).unwrap().unwrap();
+        assert_eq!(loaded, journal);
+        assert_eq!(loaded.config.build_mode, "filtered");
+        assert_eq!(loaded.resume_step(), Some(WorkflowStep::AddPrevisToArchive));
+        assert!(
+            fs::read_to_string(&path)
+                .unwrap()
+                .contains("\"status\": \"completed\"")
+        );
+    }
+}
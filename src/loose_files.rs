@@ -0,0 +1,270 @@
+//! Loose-files output mode
+//!
+//! During development a mod is usually iterated on with loose precombines and previs
+//! files and only archived for release. In loose-files mode the workflow skips both
+//! archive steps (3 and 8), so nothing is packed and Archive2 never deletes the loose
+//! sources, and then [`export`] collects the build into a clean output folder:
+//!
+//! ```text
+//! <output>/
+//! ├── MyMod.esp
+//! ├── MyMod - Geometry.csg   (clean mode)
+//! ├── MyMod.cdx              (clean mode)
+//! ├── meshes/precombined/...
+//! └── vis/...
+//! ```
+//!
+//! Files are copied, not moved, so the game still sees the loose files in `Data`. The
+//! output folder is only emptied if an earlier export created it (it holds
+//! [`OUTPUT_MARKER`]); any other non-empty folder is refused.
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::filesystem;
+use crate::validation;
+
+/// What [`export`] copied to the output folder
+#[derive(Debug, Default)]
+pub struct LooseExport {
+    /// Folder the files were copied to
+    pub output_dir: PathBuf,
+    /// Number of files copied
+    pub files: usize,
+    /// Expected items that were not found in any source directory
+    pub missing: Vec<String>,
+}
+
+/// File written to every output folder so a later export knows it may empty it
+pub const OUTPUT_MARKER: &str = ".generateprevisibines-loose";
+
+/// Default output folder: `<FO4>\GeneratePrevisibines\loose\<PluginBase>`
+pub fn default_output_dir(fo4_dir: &Path, plugin_name: &str) -> PathBuf {
+    fo4_dir
+        .join("GeneratePrevisibines")
+        .join("loose")
+        .join(validation::get_plugin_base_name(plugin_name))
+}
+
+/// Copy the plugin, precombines, previs data and clean-mode artifacts to `output_dir`
+///
+/// `sources` are searched in order for each item, so an MO2 staging directory can be
+/// listed before `Data`. If `output_dir` holds [`OUTPUT_MARKER`] from an earlier export
+/// it is deleted and recreated first so it only ever contains the current build.
+///
+/// # Errors
+///
+/// Returns an error if `output_dir` is (or contains) one of the source directories, if
+/// it is a non-empty folder not created by an earlier export, if the plugin is not
+/// found, or if copying fails
+pub fn export(
+    plugin_name: &str,
+    sources: &[&Path],
+    output_dir: &Path,
+    include_clean_artifacts: bool,
+) -> Result<LooseExport> {
+    let canonical_output = canonical(output_dir);
+    for source in sources {
+        if canonical(source).starts_with(&canonical_output) {
+            bail!(
+                "Loose-files output folder {} contains the source directory {}; choose another folder",
+                output_dir.display(),
+                source.display()
+            );
+        }
+    }
+
+    let locate = |relative: &Path| {
+        sources
+            .iter()
+            .map(|source| source.join(relative))
+            .find(|path| path.exists())
+    };
+
+    let Some(plugin) = locate(Path::new(plugin_name)) else {
+        bail!("Plugin not found for loose-files output: {plugin_name}");
+    };
+
+    // Start from an empty folder so files from earlier builds never linger, but only
+    // delete a folder an earlier export created
+    if output_dir.join(OUTPUT_MARKER).is_file() {
+        info!(
+            "Cleaning loose-files output folder: {}",
+            output_dir.display()
+        );
+        fs::remove_dir_all(output_dir)
+            .with_context(|| format!("Failed to clean output folder: {}", output_dir.display()))?;
+    } else if !filesystem::is_directory_empty(output_dir)? {
+        bail!(
+            "Loose-files output folder {} is not empty and was not created by a previous export; choose an empty or new folder",
+            output_dir.display()
+        );
+    }
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create output folder: {}", output_dir.display()))?;
+    fs::write(output_dir.join(OUTPUT_MARKER), b"")
+        .with_context(|| format!("Failed to write marker in: {}", output_dir.display()))?;
+
+    let mut export = LooseExport {
+        output_dir: output_dir.to_path_buf(),
+        ..LooseExport::default()
+    };
+
+    fs::copy(&plugin, output_dir.join(plugin_name))
+        .with_context(|| format!("Failed to copy plugin: {}", plugin.display()))?;
+    export.files += 1;
+
+    for tree in [
+        Path::new("meshes").join("precombined"),
+        PathBuf::from("vis"),
+    ] {
+        match locate(&tree) {
+            Some(dir) => export.files += filesystem::copy_directory(&dir, &output_dir.join(&tree))?,
+            None => export.missing.push(tree.display().to_string()),
+        }
+    }
+
+    if include_clean_artifacts {
+        let plugin_base = validation::get_plugin_base_name(plugin_name);
+        for artifact in [
+            format!("{plugin_base} - Geometry.csg"),
+            format!("{plugin_base}.cdx"),
+        ] {
+            if let Some(file) = locate(Path::new(&artifact)) {
+                fs::copy(&file, output_dir.join(&artifact))
+                    .with_context(|| format!("Failed to copy {}", file.display()))?;
+                export.files += 1;
+            } else {
+                export.missing.push(artifact);
+            }
+        }
+    }
+
+    for item in &export.missing {
+        warn!("Not found for loose-files output: {item}");
+    }
+    info!(
+        "Copied {} files to loose-files output folder: {}",
+        export.files,
+        output_dir.display()
+    );
+
+    Ok(export)
+}
+
+/// Resolve symlinks and `..` so paths can be compared, keeping `path` as is if it does
+/// not exist yet
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write(path: &Path, data: &[u8]) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_export_collects_build() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        write(&data.join("MyMod.esp"), b"plugin");
+        write(&data.join("MyMod - Geometry.csg"), b"csg");
+        write(&data.join("MyMod.cdx"), b"cdx");
+        write(&data.join("meshes/precombined/0001F4A2_OC.nif"), b"mesh");
+        write(&data.join("vis/0001F4A2.uvd"), b"previs");
+        write(&data.join("Other.esp"), b"unrelated");
+
+        let output = temp.path().join("out");
+        export("MyMod.esp", &[&data], &output, true).unwrap();
+        assert!(output.join(OUTPUT_MARKER).exists());
+        write(&output.join("stale.nif"), b"old build");
+
+        let export = export("MyMod.esp", &[&data], &output, true).unwrap();
+        assert_eq!(export.files, 5);
+        assert!(export.missing.is_empty());
+
+        assert!(output.join("MyMod.esp").exists());
+        assert!(output.join("MyMod - Geometry.csg").exists());
+        assert!(output.join("MyMod.cdx").exists());
+        assert!(output.join("meshes/precombined/0001F4A2_OC.nif").exists());
+        assert!(output.join("vis/0001F4A2.uvd").exists());
+        assert!(!output.join("Other.esp").exists());
+        assert!(!output.join("stale.nif").exists());
+
+        // Copied, not moved
+        assert!(data.join("vis/0001F4A2.uvd").exists());
+    }
+
+    #[test]
+    fn test_export_prefers_earlier_sources() {
+        let temp = TempDir::new().unwrap();
+        let staging = temp.path().join("overwrite");
+        let data = temp.path().join("Data");
+        write(&data.join("MyMod.esp"), b"plugin");
+        write(&staging.join("vis/0001F4A2.uvd"), b"staged previs");
+        write(&data.join("vis/0001F4A2.uvd"), b"old previs");
+
+        let output = temp.path().join("out");
+        let export = export("MyMod.esp", &[&staging, &data], &output, false).unwrap();
+
+        assert_eq!(
+            fs::read(output.join("vis/0001F4A2.uvd")).unwrap(),
+            b"staged previs"
+        );
+        assert_eq!(
+            export.missing,
+            [Path::new("meshes")
+                .join("precombined")
+                .display()
+                .to_string()]
+        );
+    }
+
+    #[test]
+    fn test_export_refuses_source_inside_output() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        write(&data.join("MyMod.esp"), b"plugin");
+
+        assert!(export("MyMod.esp", &[&data], temp.path(), false).is_err());
+        assert!(data.join("MyMod.esp").exists());
+    }
+
+    #[test]
+    fn test_export_refuses_foreign_folder() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        write(&data.join("MyMod.esp"), b"plugin");
+
+        let output = temp.path().join("Mods");
+        write(&output.join("keep.txt"), b"user file");
+
+        assert!(export("MyMod.esp", &[&data], &output, false).is_err());
+        assert!(output.join("keep.txt").exists());
+        assert!(!output.join(OUTPUT_MARKER).exists());
+
+        // An existing empty folder is fine
+        let empty = temp.path().join("empty");
+        fs::create_dir(&empty).unwrap();
+        export("MyMod.esp", &[&data], &empty, false).unwrap();
+        assert!(empty.join("MyMod.esp").exists());
+    }
+
+    #[test]
+    fn test_export_refuses_source_inside_output_via_dot_dot() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        write(&data.join("MyMod.esp"), b"plugin");
+
+        let output = data.join("..");
+        assert!(export("MyMod.esp", &[&data], &output, false).is_err());
+        assert!(data.join("MyMod.esp").exists());
+    }
+}
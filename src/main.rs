@@ -3,24 +3,39 @@ use clap::Parser;
 use log::info;
 use std::path::PathBuf;
 
+mod archive_budget;
+mod batch;
 mod ckpe_config;
+mod commands;
 mod config;
 mod filesystem;
+mod hooks;
+mod journal;
+mod loose_files;
 mod mo2_helper;
+mod plugin;
+mod precombine_check;
+mod previs_check;
 mod prompts;
 mod registry;
+mod snapshots;
 mod tools;
 mod utils;
 mod validation;
 mod workflow;
 
-use config::{ArchiveTool, BuildMode, Config};
+use config::{ArchiveTool, BuildMode, Config, MergeTool};
+use journal::{JournalConfig, RunJournal};
 
 #[derive(Parser, Debug)]
 #[command(name = "generateprevisibines")]
 #[command(about = "Automate Fallout 4 precombine and previs generation", long_about = None)]
 #[allow(clippy::struct_excessive_bools)]
 struct Args {
+    /// Standalone command to run instead of the workflow
+    #[command(subcommand)]
+    command: Option<commands::Command>,
+
     /// Plugin name (e.g., MyMod.esp)
     #[arg(value_name = "PLUGIN")]
     plugin: Option<String>,
@@ -38,9 +53,22 @@ struct Args {
     xbox: bool,
 
     /// Use `BSArch` instead of Archive2
-    #[arg(long = "bsarch")]
+    #[arg(long = "bsarch", conflicts_with = "native_archive")]
     bsarch: bool,
 
+    /// Use the built-in BA2 writer instead of Archive2 (no external tool required)
+    #[arg(long = "native-archive", conflicts_with = "bsarch")]
+    native_archive: bool,
+
+    /// Merge CombinedObjects.esp and Previs.esp with `FO4Edit`'s batch scripts (default)
+    #[arg(long = "xedit-merge", conflicts_with = "native_merge")]
+    xedit_merge: bool,
+
+    /// Merge CombinedObjects.esp and Previs.esp with the built-in merge instead of `FO4Edit`
+    /// (experimental: not yet checked against `FO4Edit`'s output)
+    #[arg(long = "native-merge", conflicts_with = "xedit_merge")]
+    native_merge: bool,
+
     /// Override Fallout 4 directory
     #[arg(long = "FO4", value_name = "PATH")]
     fo4_dir: Option<PathBuf>,
@@ -58,6 +86,67 @@ struct Args {
     /// Required when using --mo2 for archiving operations
     #[arg(long = "mo2-data-dir", value_name = "PATH")]
     mo2_data_dir: Option<PathBuf>,
+
+    /// Keep precombines and previs files loose instead of archiving them (skips steps 3 and 8)
+    /// and copy the build to a clean output folder
+    #[arg(long = "loose-files")]
+    loose_files: bool,
+
+    /// Store files in the archive uncompressed instead of using the build mode's compression
+    #[arg(long = "uncompressed", conflicts_with = "loose_files")]
+    uncompressed: bool,
+
+    /// Output folder for --loose-files
+    /// (default: `<FO4>\GeneratePrevisibines\loose\<PluginBase>`)
+    #[arg(long = "output", value_name = "PATH", requires = "loose_files")]
+    output: Option<PathBuf>,
+
+    /// Plugin in Data to copy when PLUGIN does not exist yet
+    /// (default: the xPrevisPatch plugin in Data)
+    #[arg(long = "seed", value_name = "PLUGIN")]
+    seed: Option<String>,
+
+    /// TOML file of commands to run before or after steps or the whole run
+    #[arg(long = "hooks", value_name = "FILE")]
+    hooks: Option<PathBuf>,
+
+    /// Continue from the first step the last run for PLUGIN did not complete
+    /// (read from its run journal)
+    #[arg(long = "resume", requires = "plugin", conflicts_with_all = ["from", "only"])]
+    resume: bool,
+
+    /// First step to run (1-8; default: 1)
+    #[arg(
+        long = "from",
+        value_name = "STEP",
+        requires = "plugin",
+        value_parser = clap::value_parser!(u8).range(1..=8)
+    )]
+    from: Option<u8>,
+
+    /// Last step to run (1-8; default: 8)
+    #[arg(
+        long = "to",
+        value_name = "STEP",
+        requires = "plugin",
+        value_parser = clap::value_parser!(u8).range(1..=8)
+    )]
+    to: Option<u8>,
+
+    /// Run only this step (1-8)
+    #[arg(
+        long = "only",
+        value_name = "STEP",
+        requires = "plugin",
+        conflicts_with_all = ["from", "to"],
+        value_parser = clap::value_parser!(u8).range(1..=8)
+    )]
+    only: Option<u8>,
+
+    /// Print every command line, the directories that would be wiped and the DLLs that
+    /// would be renamed for each step, without running or changing anything
+    #[arg(long = "dry-run", requires = "plugin")]
+    dry_run: bool,
 }
 
 impl Args {
@@ -76,10 +165,51 @@ impl Args {
     fn get_archive_tool(&self) -> ArchiveTool {
         if self.bsarch {
             ArchiveTool::BSArch
+        } else if self.native_archive {
+            ArchiveTool::Native
         } else {
             ArchiveTool::Archive2
         }
     }
+
+    /// Steps to run in non-interactive mode, starting at `resume_step` with `--resume`
+    fn get_step_range(
+        &self,
+        config: &Config,
+        resume_step: Option<workflow::WorkflowStep>,
+    ) -> Result<workflow::StepRange> {
+        let step = |number: Option<u8>, default: workflow::WorkflowStep| {
+            number
+                .and_then(workflow::WorkflowStep::from_number)
+                .unwrap_or(default)
+        };
+        let start = resume_step.unwrap_or_else(|| {
+            step(
+                self.only.or(self.from),
+                workflow::WorkflowStep::GeneratePrecombined,
+            )
+        });
+        let end = step(
+            self.only.or(self.to),
+            workflow::WorkflowStep::AddPrevisToArchive,
+        );
+
+        let named: Vec<workflow::WorkflowStep> = [self.only, self.from, self.to]
+            .into_iter()
+            .flatten()
+            .filter_map(workflow::WorkflowStep::from_number)
+            .collect();
+        workflow::StepRange::new(start, end, &named, config)
+    }
+
+    /// Get the merge tool
+    fn get_merge_tool(&self) -> MergeTool {
+        if self.native_merge {
+            MergeTool::Native
+        } else {
+            MergeTool::FO4Edit
+        }
+    }
 }
 
 #[allow(clippy::too_many_lines)]
@@ -91,6 +221,10 @@ fn main() -> Result<()> {
 
     let args = Args::parse();
 
+    if let Some(command) = args.command {
+        return commands::run(command);
+    }
+
     println!("======================================");
     println!("  GeneratePrevisibines - Rust Edition");
     println!("======================================");
@@ -108,13 +242,20 @@ fn main() -> Result<()> {
         dir
     };
 
-    // Find FO4Edit
+    // Find FO4Edit (not needed for --native-merge)
     println!();
-    println!("Finding FO4Edit...");
-    let fo4edit_path = registry::find_fo4edit_path().context(
-        "Failed to find FO4Edit. Make sure it's in the current directory or properly installed.",
-    )?;
-    println!("Found FO4Edit at: {}", fo4edit_path.display());
+    let merge_tool = args.get_merge_tool();
+    let fo4edit_path = if merge_tool.requires_executable() {
+        println!("Finding FO4Edit...");
+        let path = registry::find_fo4edit_path().context(
+            "Failed to find FO4Edit. Make sure it's in the current directory or properly installed.",
+        )?;
+        println!("Found FO4Edit at: {}", path.display());
+        path
+    } else {
+        println!("Using built-in plugin merge (FO4Edit not required)");
+        PathBuf::new()
+    };
 
     // Find Creation Kit
     println!();
@@ -136,15 +277,17 @@ fn main() -> Result<()> {
             println!("Finding BSArch...");
             registry::find_bsarch(&fo4_dir).context("Failed to find BSArch.exe in FO4 directory")?
         }
+        ArchiveTool::Native => PathBuf::new(),
     };
-    println!(
-        "Found {} at: {}",
-        match archive_tool {
-            ArchiveTool::Archive2 => "Archive2",
-            ArchiveTool::BSArch => "BSArch",
-        },
-        archive_path.display()
-    );
+    if archive_tool.requires_executable() {
+        println!(
+            "Found {} at: {}",
+            archive_tool.as_str(),
+            archive_path.display()
+        );
+    } else {
+        println!("Using built-in BA2 writer (no external archive tool required)");
+    }
 
     // Validate FO4 directories
     println!();
@@ -198,19 +341,22 @@ fn main() -> Result<()> {
         println!("Fallout 4:      {version}");
     }
 
-    let fo4edit_version = utils::get_simple_version(&fo4edit_path);
-    println!("FO4Edit:        {fo4edit_version}");
+    if merge_tool.requires_executable() {
+        let fo4edit_version = utils::get_simple_version(&fo4edit_path);
+        println!("FO4Edit:        {fo4edit_version}");
+    }
 
     let ck_version = utils::get_simple_version(&ck_path);
     println!("Creation Kit:   {ck_version}");
 
-    let archive_version = utils::get_simple_version(&archive_path);
+    let archive_version = if archive_tool.requires_executable() {
+        utils::get_simple_version(&archive_path)
+    } else {
+        env!("CARGO_PKG_VERSION").to_string()
+    };
     println!(
-        "{}: {}",
-        match archive_tool {
-            ArchiveTool::Archive2 => "Archive2   ",
-            ArchiveTool::BSArch => "BSArch     ",
-        },
+        "{:<15} {}",
+        format!("{}:", archive_tool.as_str()),
         archive_version
     );
 
@@ -251,13 +397,8 @@ fn main() -> Result<()> {
     println!("  Configuration");
     println!("======================================");
     println!("Build mode:     {}", args.get_build_mode().as_str());
-    println!(
-        "Archive tool:   {}",
-        match archive_tool {
-            ArchiveTool::Archive2 => "Archive2",
-            ArchiveTool::BSArch => "BSArch",
-        }
-    );
+    println!("Archive tool:   {}", archive_tool.as_str());
+    println!("Merge tool:     {}", merge_tool.as_str());
     if args.mo2_mode {
         println!("MO2 mode:       Enabled");
         if let Some(ref mo2_path) = mo2_config {
@@ -266,6 +407,18 @@ fn main() -> Result<()> {
     } else {
         println!("MO2 mode:       Disabled");
     }
+    let hooks = match args.hooks {
+        Some(ref hooks_path) => {
+            let hooks = hooks::Hooks::load(hooks_path)?;
+            println!(
+                "Hooks:          {} from {}",
+                hooks.hooks.len(),
+                hooks_path.display()
+            );
+            hooks
+        }
+        None => hooks::Hooks::default(),
+    };
     if let Some(ref plugin) = args.plugin {
         println!("Plugin:         {plugin}");
     }
@@ -273,6 +426,7 @@ fn main() -> Result<()> {
 
     // Create configuration
     let mut config = Config::new(args.get_build_mode(), archive_tool);
+    config.merge_tool = merge_tool;
     config.fo4_dir.clone_from(&fo4_dir);
     config.fo4edit_path = fo4edit_path;
     config.creation_kit_path = ck_path;
@@ -283,6 +437,11 @@ fn main() -> Result<()> {
     config.mo2_mode = args.mo2_mode;
     config.mo2_path = mo2_config;
     config.mo2_data_dir = mo2_data_dir_config;
+    config.loose_files = args.loose_files;
+    config.uncompressed = args.uncompressed;
+    config.loose_output_dir.clone_from(&args.output);
+    config.seed_plugin.clone_from(&args.seed);
+    config.hooks = hooks;
 
     // Validate configuration
     config
@@ -323,10 +482,18 @@ fn main() -> Result<()> {
     println!("  Directory Setup");
     println!("======================================");
     let data_dir = fo4_dir.join("Data");
-    let (precombined_dir, vis_dir) = filesystem::ensure_output_directories(&data_dir)
-        .context("Failed to create output directories")?;
-
-    println!("✓ Created/verified output directories:");
+    let (precombined_dir, vis_dir) = if args.dry_run {
+        println!("Dry run: output directories are not created");
+        (
+            data_dir.join("meshes").join("precombined"),
+            data_dir.join("vis"),
+        )
+    } else {
+        let dirs = filesystem::ensure_output_directories(&data_dir)
+            .context("Failed to create output directories")?;
+        println!("✓ Created/verified output directories:");
+        dirs
+    };
     println!("  Precombined: {}", precombined_dir.display());
     println!("  Vis:         {}", vis_dir.display());
 
@@ -359,8 +526,8 @@ fn main() -> Result<()> {
 
     // Get plugin name (prompt if not provided)
     let interactive = args.plugin.is_none();
-    let plugin_name = if let Some(plugin) = args.plugin {
-        plugin
+    let plugin_name = if let Some(ref plugin) = args.plugin {
+        plugin.clone()
     } else {
         println!("======================================");
         println!("  Plugin Selection");
@@ -375,14 +542,34 @@ fn main() -> Result<()> {
     let data_dir = fo4_dir.join("Data");
     let plugin_path = data_dir.join(&plugin_name);
 
-    if validation::plugin_exists(&data_dir, &plugin_name) {
-        println!("✓ Plugin file found: {}", plugin_path.display());
+    let plugin_found = validation::plugin_exists(&data_dir, &plugin_name);
+    // Non-interactive runs without the plugin create it from a seed plugin in step 1
+    // (--seed or an xPrevisPatch plugin); dry runs show that copy in the plan
+    let from_seed = !plugin_found
+        && !interactive
+        && (args.dry_run
+            || workflow::WorkflowExecutor::new(&config, plugin_name.clone(), false).has_seed());
+
+    if plugin_found || from_seed {
+        if plugin_found {
+            println!("✓ Plugin file found: {}", plugin_path.display());
+        } else {
+            println!(
+                "Plugin file not found at: {}; step 1 creates it from a seed plugin",
+                plugin_path.display()
+            );
+        }
 
         // In interactive mode, ask if user wants to use existing or restart
         if interactive {
             match prompts::prompt_use_existing_plugin(&plugin_path)? {
                 Some(true) => {
                     println!("Using existing plugin");
+                    if let Ok(Some(journal)) =
+                        RunJournal::load(&RunJournal::path_for(&fo4_dir, &plugin_name))
+                    {
+                        print_resume_point(&journal);
+                    }
                     // Ask which step to resume from
                     if let Some(step_number) = prompts::prompt_restart_step()? {
                         let start_step = workflow::WorkflowStep::from_number(step_number)
@@ -395,7 +582,7 @@ fn main() -> Result<()> {
                             start_step.name()
                         );
                         println!();
-                        let executor =
+                        let mut executor =
                             workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
                         executor.run_from_step(start_step)?;
                     } else {
@@ -406,7 +593,7 @@ fn main() -> Result<()> {
                 Some(false) => {
                     println!("Starting fresh workflow from step 1");
                     println!();
-                    let executor =
+                    let mut executor =
                         workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
                     executor.run_all()?;
                 }
@@ -416,10 +603,27 @@ fn main() -> Result<()> {
                 }
             }
         } else {
-            // Non-interactive: just run from step 1
+            // Non-interactive: run --from/--to/--only (all steps by default), or continue
+            // from the first incomplete step in the journal with --resume
+            let resume_step = if args.resume {
+                let Some(step) = resume_point(&config, &plugin_name)? else {
+                    println!("The last run for {plugin_name} completed; nothing to resume.");
+                    return Ok(());
+                };
+                Some(step)
+            } else {
+                None
+            };
+            let range = args
+                .get_step_range(&config, resume_step)
+                .context("Invalid step selection")?;
             println!();
-            let executor = workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
-            executor.run_all()?;
+            let mut executor = workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
+            if args.dry_run {
+                executor.print_plan(range);
+                return Ok(());
+            }
+            executor.run_steps(range)?;
         }
     } else {
         println!(
@@ -433,7 +637,8 @@ fn main() -> Result<()> {
                 false,
             )? {
                 println!();
-                let executor = workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
+                let mut executor =
+                    workflow::WorkflowExecutor::new(&config, plugin_name, interactive);
                 executor.run_all()?;
             } else {
                 println!("Workflow cancelled by user");
@@ -442,7 +647,8 @@ fn main() -> Result<()> {
         } else {
             anyhow::bail!(
                 "Plugin file not found: {}\n\
-                Make sure the plugin exists in the Data directory or run interactively.",
+                Make sure the plugin exists in the Data directory, pass --seed <PLUGIN> to \
+                create it from a seed plugin, or run interactively.",
                 plugin_path.display()
             );
         }
@@ -454,3 +660,57 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+/// Step to continue from with `--resume`, read from the plugin's run journal
+///
+/// Returns `None` if the last run completed every step.
+///
+/// # Errors
+///
+/// Returns an error if the plugin has no journal or it cannot be read
+fn resume_point(config: &Config, plugin_name: &str) -> Result<Option<workflow::WorkflowStep>> {
+    let path = RunJournal::path_for(&config.fo4_dir, plugin_name);
+    let Some(journal) = RunJournal::load(&path)? else {
+        anyhow::bail!(
+            "No run journal for {plugin_name} at {}; run without --resume",
+            path.display()
+        );
+    };
+
+    let current = JournalConfig::of(config);
+    if journal.config != current {
+        println!(
+            "Warning: The last run used different settings ({}); resuming with {current}",
+            journal.config
+        );
+    }
+    print_resume_point(&journal);
+    Ok(journal.resume_step())
+}
+
+/// Print where the last run in a journal stopped
+fn print_resume_point(journal: &RunJournal) {
+    let Some(step) = journal.resume_step() else {
+        println!("Last run: all steps completed");
+        return;
+    };
+    match journal.last_entry(step) {
+        Some(entry) => {
+            println!(
+                "Last run: Step {} - {} {} at {}",
+                step.number(),
+                step.name(),
+                entry.status.as_str(),
+                entry.finished.as_deref().unwrap_or(&entry.started)
+            );
+            if let Some(ref detail) = entry.detail {
+                println!("  {detail}");
+            }
+        }
+        None => println!(
+            "Last run: stopped before Step {} - {}",
+            step.number(),
+            step.name()
+        ),
+    }
+}
@@ -155,6 +155,93 @@ impl Mo2Helper {
     }
 }
 
+/// Directories that MO2's VFS layers over `Data` for the selected profile
+///
+/// Reads `ModOrganizer.ini` next to `ModOrganizer.exe` (portable instance) for the
+/// selected profile and the mods, profiles and overwrite directories, then the profile's
+/// `modlist.txt` for the enabled mods. Returns the overwrite folder followed by the
+/// enabled mod folders, highest priority first. The real `Data` directory is not included.
+///
+/// # Errors
+///
+/// Returns an error if `ModOrganizer.ini` or the profile's `modlist.txt` cannot be read
+pub fn virtual_data_dirs(mo2_exe: &Path) -> Result<Vec<PathBuf>> {
+    let instance = Instance::read(mo2_exe)?;
+    let modlist_path = instance.profile.join("modlist.txt");
+    let modlist = fs::read_to_string(&modlist_path)
+        .with_context(|| format!("Failed to read MO2 mod list: {}", modlist_path.display()))?;
+
+    // modlist.txt lists mods highest priority first; '+' marks enabled mods
+    let mut dirs = vec![instance.overwrite];
+    dirs.extend(
+        modlist
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('+'))
+            .map(|name| instance.mods.join(name)),
+    );
+    Ok(dirs)
+}
+
+/// The selected profile's `plugins.txt` (the load order MO2 gives the game)
+///
+/// # Errors
+///
+/// Returns an error if `ModOrganizer.ini` cannot be read
+pub fn profile_plugins_txt(mo2_exe: &Path) -> Result<PathBuf> {
+    Ok(Instance::read(mo2_exe)?.profile.join("plugins.txt"))
+}
+
+/// Directories of an MO2 instance, from `ModOrganizer.ini`
+struct Instance {
+    mods: PathBuf,
+    overwrite: PathBuf,
+    /// Folder of the selected profile
+    profile: PathBuf,
+}
+
+impl Instance {
+    fn read(mo2_exe: &Path) -> Result<Self> {
+        let instance_dir = mo2_exe.parent().unwrap_or_else(|| Path::new("."));
+        let ini_path = instance_dir.join("ModOrganizer.ini");
+        let ini = fs::read_to_string(&ini_path)
+            .with_context(|| format!("Failed to read MO2 settings: {}", ini_path.display()))?;
+
+        let base_dir = ini_value(&ini, "base_directory").map_or_else(
+            || instance_dir.to_path_buf(),
+            |dir| PathBuf::from(dir.replace("%BASE_DIR%", &instance_dir.to_string_lossy())),
+        );
+        let directory = |key: &str, default: &str| {
+            ini_value(&ini, key).map_or_else(
+                || base_dir.join(default),
+                |dir| PathBuf::from(dir.replace("%BASE_DIR%", &base_dir.to_string_lossy())),
+            )
+        };
+        let profile = ini_value(&ini, "selected_profile").unwrap_or_else(|| "Default".to_string());
+
+        Ok(Self {
+            mods: directory("mod_directory", "mods"),
+            overwrite: directory("overwrite_directory", "overwrite"),
+            profile: directory("profiles_directory", "profiles").join(profile),
+        })
+    }
+}
+
+/// Value of `key` in an MO2 INI file, with Qt's `@ByteArray(...)` wrapper removed
+fn ini_value(ini: &str, key: &str) -> Option<String> {
+    ini.lines().find_map(|line| {
+        let (name, value) = line.split_once('=')?;
+        if !name.trim().eq_ignore_ascii_case(key) {
+            return None;
+        }
+        let value = value.trim();
+        let value = value
+            .strip_prefix("@ByteArray(")
+            .and_then(|v| v.strip_suffix(')'))
+            .unwrap_or(value);
+        (!value.is_empty()).then(|| value.replace("\\\\", "\\"))
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -172,6 +259,44 @@ mod tests {
         assert!(helper.is_err());
     }
 
+    #[test]
+    fn test_virtual_data_dirs() {
+        let temp = TempDir::new().unwrap();
+        fs::write(
+            temp.path().join("ModOrganizer.ini"),
+            "[General]\nselected_profile=@ByteArray(Building)\n\n[Settings]\n",
+        )
+        .unwrap();
+        let profile = temp.path().join("profiles").join("Building");
+        fs::create_dir_all(&profile).unwrap();
+        fs::write(
+            profile.join("modlist.txt"),
+            "# This file was automatically generated by Mod Organizer.\n\
+             +Patch\n-Disabled Mod\n*DLC: Far Harbor\n+Base Mod\n",
+        )
+        .unwrap();
+
+        let dirs = virtual_data_dirs(&temp.path().join("ModOrganizer.exe")).unwrap();
+        assert_eq!(
+            dirs,
+            [
+                temp.path().join("overwrite"),
+                temp.path().join("mods").join("Patch"),
+                temp.path().join("mods").join("Base Mod"),
+            ]
+        );
+        assert_eq!(
+            profile_plugins_txt(&temp.path().join("ModOrganizer.exe")).unwrap(),
+            profile.join("plugins.txt")
+        );
+    }
+
+    #[test]
+    fn test_virtual_data_dirs_without_ini() {
+        let temp = TempDir::new().unwrap();
+        assert!(virtual_data_dirs(&temp.path().join("ModOrganizer.exe")).is_err());
+    }
+
     #[test]
     fn test_collect_files_empty_directory() {
         let staging = TempDir::new().unwrap();
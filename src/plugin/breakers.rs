@@ -0,0 +1,624 @@
+//! Previs-breaker detection
+//!
+//! Precombined meshes and previs data are baked from the cell and its references as the
+//! plugin saw them. A plugin that loads later and overrides the cell, or one of the
+//! references in it, replaces what the game loads, and the engine drops the cell's
+//! precombines and previs in-game ("previs breakers"). [`find_breakers`] scans every
+//! plugin that loads after the target and reports, per precombined cell of the target:
+//!
+//! - **Cell overrides** that do not carry the target's precombine and previs data
+//!   (`PCMB` and `VISI` timestamps); a patch that forwards the data is not a breaker
+//! - **Reference overrides** of persistent and temporary `REFR`s in the cell. Persistent
+//!   exterior references are stored in the worldspace's persistent cell, so they are
+//!   matched to the cell by their position
+//!
+//! Plugins number form IDs by their own master lists, so records are compared by the
+//! plugin that defines them and their object ID (a [`FormKey`]), not by raw form ID.
+//! The later plugins are read as a stream; only the cells in question and the references
+//! inside them are read.
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use super::header::PluginHeader;
+use super::record::{
+    FLAG_COMPRESSED, GROUP_CELL_CHILDREN, GROUP_CELL_PERSISTENT, GROUP_CELL_TEMPORARY,
+    GROUP_CELL_VISIBLE_DISTANT, GROUP_HEADER_SIZE, GROUP_WORLD_CHILDREN, RECORD_HEADER_SIZE,
+    RecordHeader, Subrecords, decompress, read_u32,
+};
+use super::scanner::{self, CellInfo};
+
+/// Width of an exterior cell in game units
+const CELL_SIZE: f32 = 4096.0;
+
+/// A record identified by the plugin that defines it, independent of master order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormKey {
+    /// Object ID (form ID without the master index)
+    pub object_id: u32,
+    /// Plugin that defines the record
+    pub plugin: String,
+}
+
+impl fmt::Display for FormKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:06X}:{}", self.object_id, self.plugin)
+    }
+}
+
+/// What one later plugin overrides in a precombined cell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginConflict {
+    /// File name of the later plugin
+    pub plugin: String,
+    /// Whether the plugin overrides the cell without its precombine/previs data
+    pub cell_override: bool,
+    /// References in the cell the plugin overrides
+    pub references: Vec<FormKey>,
+}
+
+/// The later plugins that override a precombined cell
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellConflicts {
+    /// Form ID of the cell, as the target plugin stores it
+    pub form_id: u32,
+    /// Cell description (editor ID, worldspace and grid), for messages
+    pub location: String,
+    /// Conflicting plugins, in load order
+    pub plugins: Vec<PluginConflict>,
+}
+
+/// Result of [`find_breakers`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BreakerReport {
+    /// Number of precombined cells in the target plugin
+    pub cells_checked: usize,
+    /// Number of later plugins scanned
+    pub plugins_checked: usize,
+    /// Precombined cells with conflicts, in the target plugin's order
+    pub conflicts: Vec<CellConflicts>,
+}
+
+impl BreakerReport {
+    /// Whether no later plugin breaks a precombined cell
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    /// Later plugins that break at least one cell, in load order
+    pub fn breaking_plugins(&self) -> Vec<&str> {
+        let mut plugins: Vec<&str> = Vec::new();
+        for conflict in self.conflicts.iter().flat_map(|cell| &cell.plugins) {
+            if !plugins.contains(&conflict.plugin.as_str()) {
+                plugins.push(&conflict.plugin);
+            }
+        }
+        plugins
+    }
+
+    /// One-line summary (e.g., `3 of 212 precombined cells overridden by 2 of 40 later plugins`)
+    pub fn summary(&self) -> String {
+        format!(
+            "{} of {} precombined cells overridden by {} of {} later plugins",
+            self.conflicts.len(),
+            self.cells_checked,
+            self.breaking_plugins().len(),
+            self.plugins_checked
+        )
+    }
+}
+
+/// Load-order independent key: lowercase plugin name and object ID
+type Key = (String, u32);
+
+/// Translates between a plugin's form IDs and [`Key`]s
+struct Numbering<'a> {
+    masters: &'a [String],
+    name: &'a str,
+}
+
+impl Numbering<'_> {
+    fn key(&self, form_id: u32) -> Key {
+        let plugin = self
+            .masters
+            .get((form_id >> 24) as usize)
+            .map_or(self.name, String::as_str);
+        (plugin.to_ascii_lowercase(), form_id & 0x00FF_FFFF)
+    }
+
+    fn form_key(&self, form_id: u32) -> FormKey {
+        FormKey {
+            object_id: form_id & 0x00FF_FFFF,
+            plugin: self
+                .masters
+                .get((form_id >> 24) as usize)
+                .map_or(self.name, String::as_str)
+                .to_string(),
+        }
+    }
+
+    /// Form ID of a record from one of the masters, `None` if it is not a master
+    fn form_id(&self, key: &Key) -> Option<u32> {
+        let index = self
+            .masters
+            .iter()
+            .position(|master| master.eq_ignore_ascii_case(&key.0))?;
+        Some(u32::try_from(index).ok()? << 24 | key.1)
+    }
+
+    fn own_index(&self) -> u32 {
+        u32::try_from(self.masters.len()).unwrap_or(u32::MAX)
+    }
+}
+
+/// Find the plugins in `later_plugins` that override precombined cells of `target`
+///
+/// `later_plugins` are the plugins that load after the target, in load order.
+///
+/// # Errors
+///
+/// Returns an error if the target or one of the later plugins cannot be read or parsed
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::{Path, PathBuf};
+/// use generateprevisibines::plugin::breakers;
+///
+/// # fn main() -> anyhow::Result<()> {
+/// let report = breakers::find_breakers(
+///     Path::new("Data/MyMod.esp"),
+///     &[PathBuf::from("Data/LaterMod.esp")],
+/// )?;
+/// println!("{}", report.summary());
+/// # Ok(())
+/// # }
+/// ```
+pub fn find_breakers(target: &Path, later_plugins: &[PathBuf]) -> Result<BreakerReport> {
+    let target_cells = PrecombinedCells::read(target)?;
+    info!(
+        "Checking {} precombined cells of {} against {} later plugins",
+        target_cells.cells.len(),
+        file_name(target),
+        later_plugins.len()
+    );
+
+    let mut conflicts: Vec<Vec<PluginConflict>> = vec![Vec::new(); target_cells.cells.len()];
+    for path in later_plugins {
+        for (index, conflict) in target_cells.conflicts(path)? {
+            conflicts[index].push(conflict);
+        }
+    }
+
+    let conflicts = target_cells
+        .cells
+        .iter()
+        .zip(conflicts)
+        .filter(|(_, plugins)| !plugins.is_empty())
+        .map(|(cell, plugins)| CellConflicts {
+            form_id: cell.form_id,
+            location: cell.location(),
+            plugins,
+        })
+        .collect();
+    Ok(BreakerReport {
+        cells_checked: target_cells.cells.len(),
+        plugins_checked: later_plugins.len(),
+        conflicts,
+    })
+}
+
+/// Exterior grid position of a cell
+type Grid = (i32, i32);
+
+/// The target's precombined cells, indexed by [`Key`] and by worldspace and grid
+struct PrecombinedCells {
+    cells: Vec<CellInfo>,
+    by_key: HashMap<Key, usize>,
+    by_grid: HashMap<(Key, Grid), usize>,
+}
+
+impl PrecombinedCells {
+    fn read(target: &Path) -> Result<Self> {
+        let name = file_name(target);
+        let header = PluginHeader::read(target)?;
+        let numbering = Numbering {
+            masters: &header.masters,
+            name: &name,
+        };
+
+        let cells: Vec<CellInfo> = scanner::scan(target)?
+            .cells
+            .into_iter()
+            .filter(CellInfo::has_precombines)
+            .collect();
+        let by_key = cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| (numbering.key(cell.form_id), index))
+            .collect();
+        let by_grid = cells
+            .iter()
+            .enumerate()
+            .filter_map(|(index, cell)| {
+                let world = numbering.key(cell.worldspace_form_id?);
+                Some(((world, cell.grid?), index))
+            })
+            .collect();
+
+        Ok(Self {
+            cells,
+            by_key,
+            by_grid,
+        })
+    }
+
+    /// What a later plugin overrides, by index into `cells`
+    fn conflicts(&self, path: &Path) -> Result<HashMap<usize, PluginConflict>> {
+        let name = file_name(path);
+        let header = PluginHeader::read(path)?;
+        let numbering = Numbering {
+            masters: &header.masters,
+            name: &name,
+        };
+
+        let wanted = Wanted {
+            cells: self
+                .by_key
+                .keys()
+                .filter_map(|key| numbering.form_id(key))
+                .collect(),
+            worlds: self
+                .by_grid
+                .keys()
+                .filter_map(|(world, _)| numbering.form_id(world))
+                .collect(),
+            own_index: numbering.own_index(),
+        };
+        let mut found = HashMap::new();
+        if wanted.cells.is_empty() && wanted.worlds.is_empty() {
+            return Ok(found);
+        }
+
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open plugin: {}", path.display()))?;
+        let len = file.metadata()?.len();
+        let overrides = read_overrides(BufReader::new(file), len, &wanted)
+            .with_context(|| format!("Failed to scan plugin: {}", path.display()))?;
+
+        for cell in &overrides.cells {
+            let Some(&index) = self.by_key.get(&numbering.key(cell.form_id)) else {
+                continue;
+            };
+            let original = &self.cells[index];
+            let forwards_data = cell.precombine_timestamp == original.precombine_timestamp
+                && cell.previs_timestamp == original.previs_timestamp;
+            if !forwards_data {
+                entry(&mut found, index, &name).cell_override = true;
+            }
+        }
+        for &(cell, reference) in &overrides.references {
+            if let Some(&index) = self.by_key.get(&numbering.key(cell)) {
+                entry(&mut found, index, &name)
+                    .references
+                    .push(numbering.form_key(reference));
+            }
+        }
+        for &((world, grid), reference) in &overrides.world_references {
+            if let Some(&index) = self.by_grid.get(&(numbering.key(world), grid)) {
+                entry(&mut found, index, &name)
+                    .references
+                    .push(numbering.form_key(reference));
+            }
+        }
+        Ok(found)
+    }
+}
+
+/// Resolve plugin names to files in the first of `search_dirs` that has them
+///
+/// Plugins that are not found are logged and left out.
+pub fn locate_plugins(names: &[String], search_dirs: &[PathBuf]) -> Vec<PathBuf> {
+    names
+        .iter()
+        .filter_map(|name| {
+            let path = search_dirs
+                .iter()
+                .map(|dir| dir.join(name))
+                .find(|path| path.is_file());
+            if path.is_none() {
+                warn!("{name} is active in the load order but was not found; skipping it");
+            }
+            path
+        })
+        .collect()
+}
+
+/// The conflict for a cell, created on first use
+fn entry<'a>(
+    found: &'a mut HashMap<usize, PluginConflict>,
+    index: usize,
+    plugin: &str,
+) -> &'a mut PluginConflict {
+    found.entry(index).or_insert_with(|| PluginConflict {
+        plugin: plugin.to_string(),
+        cell_override: false,
+        references: Vec::new(),
+    })
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Cells and worldspaces to look for in one later plugin, in its numbering
+struct Wanted {
+    cells: HashSet<u32>,
+    worlds: HashSet<u32>,
+    /// Master index of the plugin's own records; lower indices are overrides
+    own_index: u32,
+}
+
+/// What one later plugin overrides, in its numbering
+#[derive(Default)]
+struct Overrides {
+    cells: Vec<CellInfo>,
+    /// (cell, reference)
+    references: Vec<(u32, u32)>,
+    /// ((worldspace, grid), reference) for persistent references of a worldspace
+    world_references: Vec<((u32, Grid), u32)>,
+}
+
+/// Read the overrides of the wanted cells (and persistent references of the wanted
+/// worldspaces) from a plugin
+fn read_overrides<R: Read + Seek>(mut reader: R, len: u64, wanted: &Wanted) -> Result<Overrides> {
+    let mut overrides = Overrides::default();
+    // Open groups: (end offset, group type, label)
+    let mut groups: Vec<(u64, u32, u32)> = Vec::new();
+    let mut header = [0u8; RECORD_HEADER_SIZE];
+    let mut pos = 0u64;
+
+    while pos < len {
+        groups.retain(|&(end, _, _)| end > pos);
+
+        reader
+            .read_exact(&mut header)
+            .with_context(|| format!("Truncated record header at offset {pos}"))?;
+        let record = RecordHeader::parse(&header)?;
+        if pos == 0 && &record.record_type != b"TES4" {
+            bail!("Not a plugin (first record is {:?})", record.type_name());
+        }
+
+        if &record.record_type == b"GRUP" {
+            let group_size = u64::from(record.data_size);
+            let group_type = read_u32(&header, 12);
+            let label = record.flags;
+            if group_size < GROUP_HEADER_SIZE as u64 {
+                bail!("Invalid group size {group_size} at offset {pos}");
+            }
+
+            let enter = match groups.last() {
+                None => matches!(&header[8..12], b"CELL" | b"WRLD"),
+                // A cell's references; directly under the worldspace is its persistent cell
+                Some(&(_, parent_type, world)) if group_type == GROUP_CELL_CHILDREN => {
+                    wanted.cells.contains(&label)
+                        || (parent_type == GROUP_WORLD_CHILDREN && wanted.worlds.contains(&world))
+                }
+                Some(_) => group_type != GROUP_CELL_VISIBLE_DISTANT,
+            };
+            if enter {
+                groups.push((pos + group_size, group_type, label));
+                pos += GROUP_HEADER_SIZE as u64;
+            } else {
+                pos += group_size;
+                reader.seek(SeekFrom::Start(pos))?;
+            }
+            continue;
+        }
+
+        let next = pos + RECORD_HEADER_SIZE as u64 + u64::from(record.data_size);
+        let innermost = groups
+            .last()
+            .map(|&(_, group_type, label)| (group_type, label));
+        let is_override = record.form_id >> 24 < wanted.own_index;
+
+        match (&record.record_type, innermost) {
+            (b"CELL", Some(_)) if wanted.cells.contains(&record.form_id) => {
+                let data = read_data(&mut reader, &record)?;
+                overrides
+                    .cells
+                    .push(scanner::parse_cell(record.form_id, None, &data)?);
+            }
+            (b"REFR", Some((GROUP_CELL_PERSISTENT | GROUP_CELL_TEMPORARY, cell)))
+                if is_override && wanted.cells.contains(&cell) =>
+            {
+                overrides.references.push((cell, record.form_id));
+                reader.seek(SeekFrom::Start(next))?;
+            }
+            (b"REFR", Some((GROUP_CELL_PERSISTENT, _))) if is_override => {
+                // Persistent cell of a worldspace: place the reference by its position
+                let world = groups
+                    .iter()
+                    .rev()
+                    .find(|&&(_, group_type, _)| group_type == GROUP_WORLD_CHILDREN)
+                    .map(|&(_, _, world)| world);
+                let data = read_data(&mut reader, &record)?;
+                if let Some(world) = world
+                    && let Some(grid) = reference_grid(&data)?
+                {
+                    overrides
+                        .world_references
+                        .push(((world, grid), record.form_id));
+                }
+            }
+            _ => reader.seek(SeekFrom::Start(next)).map(|_| ())?,
+        }
+        pos = next;
+    }
+
+    Ok(overrides)
+}
+
+/// Read (and decompress) the data of the record whose header was just read
+fn read_data<R: Read>(reader: &mut R, record: &RecordHeader) -> Result<Vec<u8>> {
+    let mut data = vec![0u8; record.data_size as usize];
+    reader.read_exact(&mut data).with_context(|| {
+        format!(
+            "Truncated {} record {:08X}",
+            record.type_name(),
+            record.form_id
+        )
+    })?;
+    if record.flags & FLAG_COMPRESSED != 0 {
+        data = decompress(&data).with_context(|| {
+            format!(
+                "Failed to decompress {} record {:08X}",
+                record.type_name(),
+                record.form_id
+            )
+        })?;
+    }
+    Ok(data)
+}
+
+/// Exterior grid cell of a reference, from its position (`DATA`)
+fn reference_grid(data: &[u8]) -> Result<Option<Grid>> {
+    for subrecord in Subrecords::new(data) {
+        let subrecord = subrecord?;
+        if &subrecord.subrecord_type == b"DATA" && subrecord.data.len() >= 8 {
+            let x = f32::from_bits(read_u32(subrecord.data, 0));
+            let y = f32::from_bits(read_u32(subrecord.data, 4));
+            return Ok(Some((grid_coordinate(x), grid_coordinate(y))));
+        }
+    }
+    Ok(None)
+}
+
+#[allow(clippy::cast_possible_truncation)]
+fn grid_coordinate(position: f32) -> i32 {
+    (position / CELL_SIZE).floor() as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn cell(form_id: u32, grid: Option<(i32, i32)>, pcmb: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        if let Some((x, y)) = grid {
+            subrecord(
+                &mut data,
+                *b"XCLC",
+                &[x.to_le_bytes(), y.to_le_bytes()].concat(),
+            );
+        }
+        subrecord(&mut data, *b"XCRI", &[1u32.to_le_bytes(), [0; 4]].concat());
+        subrecord(&mut data, *b"PCMB", &pcmb.to_le_bytes());
+        subrecord(&mut data, *b"VISI", &pcmb.to_le_bytes());
+        record(*b"CELL", 0, form_id, &data)
+    }
+
+    fn reference(form_id: u32, position: (f32, f32)) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut pos = position.0.to_le_bytes().to_vec();
+        pos.extend_from_slice(&position.1.to_le_bytes());
+        pos.extend_from_slice(&[0; 16]);
+        subrecord(&mut data, *b"DATA", &pos);
+        record(*b"REFR", 0, form_id, &data)
+    }
+
+    /// A worldspace with a persistent cell holding `persistent` and a grid cell
+    fn world(world_id: u32, persistent: &[u8], grid_cell: &[u8], temporary: &[u8]) -> Vec<u8> {
+        let mut children = record(*b"CELL", 0, world_id + 1, b"");
+        children.extend(group(
+            (world_id + 1).to_le_bytes(),
+            GROUP_CELL_CHILDREN,
+            &group([0; 4], GROUP_CELL_PERSISTENT, persistent),
+        ));
+        let cell_id = read_u32(grid_cell, 12);
+        let mut block = grid_cell.to_vec();
+        if !temporary.is_empty() {
+            block.extend(group(
+                cell_id.to_le_bytes(),
+                GROUP_CELL_CHILDREN,
+                &group(cell_id.to_le_bytes(), GROUP_CELL_TEMPORARY, temporary),
+            ));
+        }
+        children.extend(group([0; 4], 4, &group([0; 4], 5, &block)));
+
+        let mut out = record(*b"WRLD", 0, world_id, b"");
+        out.extend(group(
+            world_id.to_le_bytes(),
+            GROUP_WORLD_CHILDREN,
+            &children,
+        ));
+        group(*b"WRLD", 0, &out)
+    }
+
+    #[test]
+    fn test_find_breakers() {
+        let temp = TempDir::new().unwrap();
+        // The target overrides Fallout4.esm's cell 0000E2F3 at (1, 2)
+        let mut target = tes4(0, "", &["Fallout4.esm"]);
+        target.extend(world(0x3C, b"", &cell(0xE2F3, Some((1, 2)), 100), b""));
+        let target_path = temp.path().join("MyMod.esp");
+        fs::write(&target_path, target).unwrap();
+
+        // Overrides the cell without previs, a temporary reference in it, and a
+        // persistent reference placed inside it
+        let mut breaker = tes4(0, "", &["Fallout4.esm"]);
+        breaker.extend(world(
+            0x3C,
+            &[
+                reference(0x0000_1000, (4096.0 + 10.0, 2.0 * 4096.0 + 10.0)),
+                reference(0x0000_1001, (-10.0, 0.0)),
+                reference(0x0100_0800, (4100.0, 8200.0)),
+            ]
+            .concat(),
+            &cell(0xE2F3, Some((1, 2)), 50),
+            &reference(0x0000_2000, (4100.0, 8200.0)),
+        ));
+        let breaker_path = temp.path().join("Breaker.esp");
+        fs::write(&breaker_path, breaker).unwrap();
+
+        // Forwards the target's precombines, numbering Fallout4.esm differently
+        let mut patch = tes4(0, "", &["DLCRobot.esm", "Fallout4.esm"]);
+        patch.extend(world(
+            0x0100_003C,
+            b"",
+            &cell(0x0100_E2F3, Some((1, 2)), 100),
+            b"",
+        ));
+        let patch_path = temp.path().join("Patch.esp");
+        fs::write(&patch_path, patch).unwrap();
+
+        let report = find_breakers(&target_path, &[patch_path, breaker_path]).unwrap();
+        assert_eq!(report.cells_checked, 1);
+        assert_eq!(report.plugins_checked, 2);
+        assert!(!report.is_clean());
+        assert_eq!(report.breaking_plugins(), ["Breaker.esp"]);
+        assert_eq!(
+            report.summary(),
+            "1 of 1 precombined cells overridden by 1 of 2 later plugins"
+        );
+
+        let conflict = &report.conflicts[0].plugins[0];
+        assert!(conflict.cell_override);
+        let references: Vec<String> = conflict
+            .references
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(references, ["002000:Fallout4.esm", "001000:Fallout4.esm"]);
+    }
+}
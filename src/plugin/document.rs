@@ -0,0 +1,796 @@
+//! In-memory plugin model for editing and writing plugins
+//!
+//! [`PluginFile`] holds the whole group/record tree of a plugin with each record's data
+//! kept as raw bytes, so records the workflow does not touch are written back unchanged.
+//! Edited records are re-serialized (and recompressed if they were compressed), group
+//! sizes are recomputed on write, and the record count in `HEDR` is updated.
+//!
+//! Cells are stored in a fixed hierarchy, which [`PluginFile::insert_cell`] creates as
+//! needed:
+//!
+//! ```text
+//! GRUP CELL (top)                      GRUP WRLD (top)
+//! └── GRUP block (2)                   ├── WRLD
+//!     └── GRUP sub-block (3)           └── GRUP world children (1)
+//!         ├── CELL                         └── GRUP block (4)
+//!         └── GRUP cell children (6)           └── GRUP sub-block (5)
+//!                                                  └── CELL ...
+//! ```
+
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::write::ZlibEncoder;
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use super::header::PluginHeader;
+use super::record::{
+    FLAG_COMPRESSED, GROUP_CELL_CHILDREN, GROUP_EXTERIOR_BLOCK, GROUP_EXTERIOR_SUB_BLOCK,
+    GROUP_HEADER_SIZE, GROUP_INTERIOR_BLOCK, GROUP_INTERIOR_SUB_BLOCK, GROUP_TOP,
+    GROUP_WORLD_CHILDREN, RECORD_HEADER_SIZE, RecordHeader, Subrecords, decompress, read_u32,
+};
+
+/// Order of the top-level groups in Fallout 4 plugins
+///
+/// Groups with other labels sort after these.
+const TOP_GROUP_ORDER: [[u8; 4]; 128] = [
+    *b"GMST", *b"KYWD", *b"LCRT", *b"AACT", *b"TRNS", *b"CMPO", *b"TXST", *b"GLOB", *b"DMGT",
+    *b"CLAS", *b"FACT", *b"HDPT", *b"RACE", *b"SOUN", *b"ASPC", *b"MGEF", *b"LTEX", *b"ENCH",
+    *b"SPEL", *b"ACTI", *b"TACT", *b"ARMO", *b"BOOK", *b"CONT", *b"DOOR", *b"INGR", *b"LIGH",
+    *b"MISC", *b"STAT", *b"SCOL", *b"MSTT", *b"GRAS", *b"TREE", *b"FLOR", *b"FURN", *b"WEAP",
+    *b"AMMO", *b"NPC_", *b"LVLN", *b"KEYM", *b"ALCH", *b"IDLM", *b"NOTE", *b"PROJ", *b"HAZD",
+    *b"BNDS", *b"TERM", *b"LVLI", *b"WTHR", *b"CLMT", *b"SPGD", *b"RFCT", *b"REGN", *b"NAVI",
+    *b"CELL", *b"WRLD", *b"QUST", *b"IDLE", *b"PACK", *b"CSTY", *b"LSCR", *b"ANIO", *b"WATR",
+    *b"EFSH", *b"EXPL", *b"DEBR", *b"IMGS", *b"IMAD", *b"FLST", *b"PERK", *b"BPTD", *b"ADDN",
+    *b"AVIF", *b"CAMS", *b"CPTH", *b"VTYP", *b"MATT", *b"IPCT", *b"IPDS", *b"ARMA", *b"ECZN",
+    *b"LCTN", *b"MESG", *b"DOBJ", *b"DFOB", *b"LGTM", *b"MUSC", *b"FSTP", *b"FSTS", *b"SMBN",
+    *b"SMQN", *b"SMEN", *b"DLBR", *b"MUST", *b"DLVW", *b"EQUP", *b"RELA", *b"SCEN", *b"ASTP",
+    *b"OTFT", *b"ARTO", *b"MATO", *b"MOVT", *b"SNDR", *b"SNCT", *b"SOPM", *b"COLL", *b"CLFM",
+    *b"REVB", *b"PKIN", *b"RFGP", *b"AMDL", *b"LAYR", *b"COBJ", *b"OMOD", *b"MSWP", *b"ZOOM",
+    *b"INNR", *b"KSSM", *b"AECH", *b"SCCO", *b"AORU", *b"SCSN", *b"STAG", *b"NOCM", *b"LENS",
+    *b"GDRY", *b"OVIS",
+];
+
+/// A record with its raw header and data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    header: [u8; RECORD_HEADER_SIZE],
+    data: Vec<u8>,
+}
+
+impl Record {
+    /// Build an uncompressed record from subrecords
+    pub fn new(record_type: [u8; 4], form_id: u32, subrecords: &[([u8; 4], Vec<u8>)]) -> Self {
+        let mut header = [0u8; RECORD_HEADER_SIZE];
+        header[..4].copy_from_slice(&record_type);
+        header[12..16].copy_from_slice(&form_id.to_le_bytes());
+        header[20..22].copy_from_slice(&131u16.to_le_bytes());
+        let mut record = Self {
+            header,
+            data: Vec::new(),
+        };
+        record.data = encode_subrecords(subrecords);
+        record.update_size();
+        record
+    }
+
+    /// Record flags
+    pub fn flags(&self) -> u32 {
+        read_u32(&self.header, 8)
+    }
+
+    /// Record type (e.g., `*b"CELL"`)
+    pub fn record_type(&self) -> [u8; 4] {
+        [
+            self.header[0],
+            self.header[1],
+            self.header[2],
+            self.header[3],
+        ]
+    }
+
+    /// Form ID
+    pub fn form_id(&self) -> u32 {
+        read_u32(&self.header, 12)
+    }
+
+    /// Change the form ID
+    pub fn set_form_id(&mut self, form_id: u32) {
+        self.header[12..16].copy_from_slice(&form_id.to_le_bytes());
+    }
+
+    /// The record's subrecords (decompressed if needed)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data cannot be decompressed or parsed
+    pub fn subrecords(&self) -> Result<Vec<([u8; 4], Vec<u8>)>> {
+        let data = if self.flags() & FLAG_COMPRESSED == 0 {
+            std::borrow::Cow::Borrowed(&self.data)
+        } else {
+            std::borrow::Cow::Owned(decompress(&self.data)?)
+        };
+
+        Subrecords::new(&data)
+            .map(|s| s.map(|s| (s.subrecord_type, s.data.to_vec())))
+            .collect()
+    }
+
+    /// Replace the record's subrecords, recompressing if the record is compressed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if compression fails
+    pub fn set_subrecords(&mut self, subrecords: &[([u8; 4], Vec<u8>)]) -> Result<()> {
+        let data = encode_subrecords(subrecords);
+        self.data = if self.flags() & FLAG_COMPRESSED == 0 {
+            data
+        } else {
+            compress(&data)?
+        };
+        self.update_size();
+        Ok(())
+    }
+
+    /// Serialized size of the record in bytes
+    fn len(&self) -> usize {
+        RECORD_HEADER_SIZE + self.data.len()
+    }
+
+    fn update_size(&mut self) {
+        let size = u32::try_from(self.data.len()).unwrap_or(u32::MAX);
+        self.header[4..8].copy_from_slice(&size.to_le_bytes());
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.header);
+        out.extend_from_slice(&self.data);
+    }
+}
+
+/// A group with its raw header and children
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    header: [u8; GROUP_HEADER_SIZE],
+    /// Records and groups in the group, in file order
+    pub children: Vec<Node>,
+}
+
+impl Group {
+    /// Create an empty group
+    pub fn new(label: [u8; 4], group_type: u32) -> Self {
+        let mut header = [0u8; GROUP_HEADER_SIZE];
+        header[..4].copy_from_slice(b"GRUP");
+        header[8..12].copy_from_slice(&label);
+        header[12..16].copy_from_slice(&group_type.to_le_bytes());
+        Self {
+            header,
+            children: Vec::new(),
+        }
+    }
+
+    /// Group label (record type for top-level groups, form ID or block number otherwise)
+    pub fn label(&self) -> [u8; 4] {
+        [
+            self.header[8],
+            self.header[9],
+            self.header[10],
+            self.header[11],
+        ]
+    }
+
+    /// Group type (see the `GROUP_*` constants)
+    pub fn group_type(&self) -> u32 {
+        read_u32(&self.header, 12)
+    }
+
+    /// Child group with the given label and type
+    ///
+    /// A missing group is created after the existing groups of the same or a lower type
+    /// (so a cell's persistent, temporary and distant groups stay in order).
+    fn child_group_mut(&mut self, label: [u8; 4], group_type: u32) -> &mut Group {
+        let index = self.children.iter().position(|node| {
+            matches!(node, Node::Group(g) if g.group_type() == group_type && g.label() == label)
+        });
+        let index = index.unwrap_or_else(|| {
+            let insert_at = self
+                .children
+                .iter()
+                .position(|node| matches!(node, Node::Group(g) if g.group_type() > group_type))
+                .unwrap_or(self.children.len());
+            self.children
+                .insert(insert_at, Node::Group(Group::new(label, group_type)));
+            insert_at
+        });
+        match &mut self.children[index] {
+            Node::Group(group) => group,
+            Node::Record(_) => unreachable!("index points at a group"),
+        }
+    }
+
+    fn len(&self) -> usize {
+        GROUP_HEADER_SIZE + self.children.iter().map(Node::len).sum::<usize>()
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        let size = u32::try_from(self.len()).unwrap_or(u32::MAX);
+        out.extend_from_slice(&self.header[..4]);
+        out.extend_from_slice(&size.to_le_bytes());
+        out.extend_from_slice(&self.header[8..]);
+        for child in &self.children {
+            child.write_to(out);
+        }
+    }
+}
+
+/// A record or a group
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Node {
+    Record(Record),
+    Group(Group),
+}
+
+impl Node {
+    fn len(&self) -> usize {
+        match self {
+            Self::Record(record) => record.len(),
+            Self::Group(group) => group.len(),
+        }
+    }
+
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Self::Record(record) => record.write_to(out),
+            Self::Group(group) => group.write_to(out),
+        }
+    }
+
+    /// Number of records and groups in this node, including itself
+    fn count(&self) -> usize {
+        match self {
+            Self::Record(_) => 1,
+            Self::Group(group) => 1 + group.children.iter().map(Node::count).sum::<usize>(),
+        }
+    }
+}
+
+/// A whole plugin: the `TES4` header record and the top-level groups
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginFile {
+    /// The `TES4` record
+    pub header: Record,
+    /// Top-level groups
+    pub groups: Vec<Group>,
+}
+
+impl PluginFile {
+    /// Read and parse a plugin
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or is not a valid plugin
+    pub fn read(path: &Path) -> Result<Self> {
+        let bytes =
+            fs::read(path).with_context(|| format!("Failed to read plugin: {}", path.display()))?;
+        Self::parse(&bytes).with_context(|| format!("Failed to parse plugin: {}", path.display()))
+    }
+
+    /// Parse a plugin from its contents
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the data is not a valid plugin
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut pos = 0;
+        let header = match parse_node(bytes, &mut pos)? {
+            Node::Record(record) if &record.record_type() == b"TES4" => record,
+            _ => bail!("Not a plugin (missing TES4 header)"),
+        };
+
+        let mut groups = Vec::new();
+        while pos < bytes.len() {
+            match parse_node(bytes, &mut pos)? {
+                Node::Group(group) => groups.push(group),
+                Node::Record(record) => bail!(
+                    "Unexpected top-level {} record",
+                    String::from_utf8_lossy(&record.record_type())
+                ),
+            }
+        }
+
+        Ok(Self { header, groups })
+    }
+
+    /// Serialize the plugin, updating the record count in `HEDR`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header cannot be updated
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let count: usize = self
+            .groups
+            .iter()
+            .map(|group| 1 + group.children.iter().map(Node::count).sum::<usize>())
+            .sum();
+        let count = u32::try_from(count).context("Too many records")?;
+
+        let mut header = self.header.clone();
+        let mut subrecords = header.subrecords()?;
+        if let Some((_, hedr)) = subrecords.iter_mut().find(|(t, _)| t == b"HEDR")
+            && hedr.len() >= 8
+        {
+            hedr[4..8].copy_from_slice(&count.to_le_bytes());
+        }
+        header.set_subrecords(&subrecords)?;
+
+        let mut out = Vec::new();
+        header.write_to(&mut out);
+        for group in &self.groups {
+            group.write_to(&mut out);
+        }
+        Ok(out)
+    }
+
+    /// Write the plugin to `path`
+    ///
+    /// The plugin is written to a temporary file next to `path` first and then renamed, so
+    /// `path` is never left half-written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let bytes = self.to_bytes()?;
+        let mut temp = OsString::from(path.as_os_str());
+        temp.push(".tmp");
+        let temp = PathBuf::from(temp);
+
+        fs::write(&temp, bytes)
+            .with_context(|| format!("Failed to write plugin: {}", temp.display()))?;
+        fs::rename(&temp, path)
+            .with_context(|| format!("Failed to replace plugin: {}", path.display()))
+    }
+
+    /// Parse the plugin's `TES4` header
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the header record is invalid
+    pub fn plugin_header(&self) -> Result<PluginHeader> {
+        let mut bytes = Vec::new();
+        self.header.write_to(&mut bytes);
+        PluginHeader::parse(&bytes)
+    }
+
+    /// Find a record by type and form ID
+    ///
+    /// Cells and references are looked for in the `CELL` and `WRLD` groups, other records
+    /// in their own top-level group. Only references are looked for inside cell children.
+    pub fn find_record_mut(&mut self, record_type: [u8; 4], form_id: u32) -> Option<&mut Record> {
+        fn find(
+            nodes: &mut [Node],
+            record_type: [u8; 4],
+            form_id: u32,
+            skip_references: bool,
+        ) -> Option<&mut Record> {
+            for node in nodes {
+                match node {
+                    Node::Record(record)
+                        if record.record_type() == record_type && record.form_id() == form_id =>
+                    {
+                        return Some(record);
+                    }
+                    Node::Group(group)
+                        if !(skip_references && group.group_type() == GROUP_CELL_CHILDREN) =>
+                    {
+                        if let Some(record) =
+                            find(&mut group.children, record_type, form_id, skip_references)
+                        {
+                            return Some(record);
+                        }
+                    }
+                    Node::Record(_) | Node::Group(_) => {}
+                }
+            }
+            None
+        }
+
+        let is_reference = matches!(&record_type, b"REFR" | b"ACHR");
+        let in_cells = is_reference || &record_type == b"CELL";
+        self.groups
+            .iter_mut()
+            .filter(|group| {
+                let label = group.label();
+                label == record_type || (in_cells && matches!(&label, b"CELL" | b"WRLD"))
+            })
+            .find_map(|group| find(&mut group.children, record_type, form_id, !is_reference))
+    }
+
+    /// Every record of a type, by form ID
+    ///
+    /// Walks the plugin once, so looking up many records stays linear where calling
+    /// [`find_record_mut`](Self::find_record_mut) for each would walk it every time.
+    pub fn records_mut(&mut self, record_type: [u8; 4]) -> HashMap<u32, &mut Record> {
+        fn collect<'a>(
+            nodes: &'a mut [Node],
+            record_type: [u8; 4],
+            out: &mut HashMap<u32, &'a mut Record>,
+        ) {
+            for node in nodes {
+                match node {
+                    Node::Record(record) if record.record_type() == record_type => {
+                        out.insert(record.form_id(), record);
+                    }
+                    Node::Group(group) => collect(&mut group.children, record_type, out),
+                    Node::Record(_) => {}
+                }
+            }
+        }
+
+        let mut records = HashMap::new();
+        for group in &mut self.groups {
+            collect(&mut group.children, record_type, &mut records);
+        }
+        records
+    }
+
+    /// Top-level group for a record type
+    ///
+    /// A missing group is created in the position the game's master files (and xEdit) use
+    /// (`TOP_GROUP_ORDER`), so a `CELL` group added to a worldspace mod goes before
+    /// its `WRLD` group.
+    pub fn top_group_mut(&mut self, record_type: [u8; 4]) -> &mut Group {
+        let index = self
+            .groups
+            .iter()
+            .position(|g| g.group_type() == GROUP_TOP && g.label() == record_type);
+        let index = index.unwrap_or_else(|| {
+            let rank = |label: [u8; 4]| {
+                TOP_GROUP_ORDER
+                    .iter()
+                    .position(|l| *l == label)
+                    .unwrap_or(TOP_GROUP_ORDER.len())
+            };
+            let insert_at = self
+                .groups
+                .iter()
+                .position(|g| rank(g.label()) > rank(record_type))
+                .unwrap_or(self.groups.len());
+            self.groups
+                .insert(insert_at, Group::new(record_type, GROUP_TOP));
+            insert_at
+        });
+        &mut self.groups[index]
+    }
+
+    /// Add references (`REFR`) to a cell's persistent, temporary or distant group
+    ///
+    /// `group_type` is the type of the cell children sub-group (8, 9 or 10). The cell's
+    /// children group is created after the cell record if missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the cell is not in the plugin
+    pub fn insert_references(
+        &mut self,
+        references: Vec<Record>,
+        cell_id: u32,
+        group_type: u32,
+    ) -> Result<()> {
+        fn children_of(nodes: &mut Vec<Node>, cell_id: u32) -> Option<&mut Group> {
+            let cell = nodes.iter().position(
+                |node| matches!(node, Node::Record(r) if &r.record_type() == b"CELL" && r.form_id() == cell_id),
+            );
+            if let Some(index) = cell {
+                let has_children = matches!(
+                    nodes.get(index + 1),
+                    Some(Node::Group(g)) if g.group_type() == GROUP_CELL_CHILDREN && g.label() == cell_id.to_le_bytes()
+                );
+                if !has_children {
+                    nodes.insert(
+                        index + 1,
+                        Node::Group(Group::new(cell_id.to_le_bytes(), GROUP_CELL_CHILDREN)),
+                    );
+                }
+                return match nodes.get_mut(index + 1) {
+                    Some(Node::Group(group)) => Some(group),
+                    _ => None,
+                };
+            }
+
+            nodes.iter_mut().find_map(|node| match node {
+                Node::Group(group) if group.group_type() != GROUP_CELL_CHILDREN => {
+                    children_of(&mut group.children, cell_id)
+                }
+                _ => None,
+            })
+        }
+
+        let Some(children) = self
+            .groups
+            .iter_mut()
+            .find_map(|group| children_of(&mut group.children, cell_id))
+        else {
+            bail!("Cell {cell_id:08X} is not in the plugin");
+        };
+        children
+            .child_group_mut(cell_id.to_le_bytes(), group_type)
+            .children
+            .extend(references.into_iter().map(Node::Record));
+        Ok(())
+    }
+
+    /// Add a worldspace record to the `WRLD` group
+    pub fn insert_worldspace(&mut self, world: Record) {
+        self.top_group_mut(*b"WRLD")
+            .children
+            .push(Node::Record(world));
+    }
+
+    /// Add a cell record in the right block and sub-block
+    ///
+    /// Interior cells (`worldspace` is `None`) are placed by the last two decimal digits
+    /// of their object ID. Exterior cells are placed by their grid position (`grid`, from
+    /// `XCLC`); the worldspace record must already be in the plugin. A worldspace cell
+    /// without a grid position (the persistent cell) goes directly in the worldspace's
+    /// children group.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the worldspace is not in the plugin
+    pub fn insert_cell(
+        &mut self,
+        cell: Record,
+        worldspace: Option<u32>,
+        grid: Option<(i32, i32)>,
+    ) -> Result<()> {
+        let Some(world_id) = worldspace else {
+            let object_id = cell.form_id() & 0x00FF_FFFF;
+            let block = i32::try_from(object_id % 10).unwrap_or_default();
+            let sub_block = i32::try_from(object_id / 10 % 10).unwrap_or_default();
+            self.top_group_mut(*b"CELL")
+                .child_group_mut(block.to_le_bytes(), GROUP_INTERIOR_BLOCK)
+                .child_group_mut(sub_block.to_le_bytes(), GROUP_INTERIOR_SUB_BLOCK)
+                .children
+                .push(Node::Record(cell));
+            return Ok(());
+        };
+
+        let top = self.top_group_mut(*b"WRLD");
+        let Some(world_index) = top.children.iter().position(
+            |node| matches!(node, Node::Record(r) if &r.record_type() == b"WRLD" && r.form_id() == world_id),
+        ) else {
+            bail!("Worldspace {world_id:08X} is not in the plugin");
+        };
+
+        // The world children group directly follows its worldspace record
+        let has_children = matches!(
+            top.children.get(world_index + 1),
+            Some(Node::Group(g)) if g.group_type() == GROUP_WORLD_CHILDREN
+        );
+        if !has_children {
+            top.children.insert(
+                world_index + 1,
+                Node::Group(Group::new(world_id.to_le_bytes(), GROUP_WORLD_CHILDREN)),
+            );
+        }
+        let Some(Node::Group(children)) = top.children.get_mut(world_index + 1) else {
+            unreachable!("world children group was just ensured");
+        };
+
+        let Some((x, y)) = grid else {
+            children.children.insert(0, Node::Record(cell));
+            return Ok(());
+        };
+        children
+            .child_group_mut(
+                grid_label(x.div_euclid(32), y.div_euclid(32)),
+                GROUP_EXTERIOR_BLOCK,
+            )
+            .child_group_mut(
+                grid_label(x.div_euclid(8), y.div_euclid(8)),
+                GROUP_EXTERIOR_SUB_BLOCK,
+            )
+            .children
+            .push(Node::Record(cell));
+        Ok(())
+    }
+}
+
+/// Label of an exterior block or sub-block group: Y then X as `i16`
+fn grid_label(x: i32, y: i32) -> [u8; 4] {
+    // Block coordinates are grid / 8 or grid / 32, always well within i16
+    let x = i16::try_from(x).unwrap_or_default().to_le_bytes();
+    let y = i16::try_from(y).unwrap_or_default().to_le_bytes();
+    [y[0], y[1], x[0], x[1]]
+}
+
+/// Parse one record or group at `pos`, advancing `pos` past it
+fn parse_node(bytes: &[u8], pos: &mut usize) -> Result<Node> {
+    let offset = *pos;
+    let header = RecordHeader::parse(&bytes[offset..])
+        .with_context(|| format!("Invalid record at offset {offset}"))?;
+
+    if &header.record_type == b"GRUP" {
+        let size = header.data_size as usize;
+        if size < GROUP_HEADER_SIZE || offset + size > bytes.len() {
+            bail!("Invalid group size {size} at offset {offset}");
+        }
+
+        let mut group_header = [0u8; GROUP_HEADER_SIZE];
+        group_header.copy_from_slice(&bytes[offset..offset + GROUP_HEADER_SIZE]);
+        let end = offset + size;
+        *pos = offset + GROUP_HEADER_SIZE;
+
+        let mut children = Vec::new();
+        while *pos < end {
+            children.push(parse_node(&bytes[..end], pos)?);
+        }
+        return Ok(Node::Group(Group {
+            header: group_header,
+            children,
+        }));
+    }
+
+    let start = offset + RECORD_HEADER_SIZE;
+    let Some(data) = bytes.get(start..start + header.data_size as usize) else {
+        bail!(
+            "{} record at offset {offset} overruns its group",
+            header.type_name()
+        );
+    };
+    *pos = start + data.len();
+
+    let mut record_header = [0u8; RECORD_HEADER_SIZE];
+    record_header.copy_from_slice(&bytes[offset..start]);
+    Ok(Node::Record(Record {
+        header: record_header,
+        data: data.to_vec(),
+    }))
+}
+
+/// Serialize subrecords, writing an `XXXX` size override for data over 64 KiB
+fn encode_subrecords(subrecords: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (subrecord_type, data) in subrecords {
+        if let Ok(size) = u16::try_from(data.len()) {
+            out.extend_from_slice(subrecord_type);
+            out.extend_from_slice(&size.to_le_bytes());
+        } else {
+            let size = u32::try_from(data.len()).unwrap_or(u32::MAX);
+            out.extend_from_slice(b"XXXX");
+            out.extend_from_slice(&4u16.to_le_bytes());
+            out.extend_from_slice(&size.to_le_bytes());
+            out.extend_from_slice(subrecord_type);
+            out.extend_from_slice(&0u16.to_le_bytes());
+        }
+        out.extend_from_slice(data);
+    }
+    out
+}
+
+/// Compress record data (`u32` decompressed size, then zlib data)
+fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = u32::try_from(data.len())
+        .context("Record too large")?
+        .to_le_bytes()
+        .to_vec();
+    let mut encoder = ZlibEncoder::new(out.split_off(4), Compression::default());
+    encoder.write_all(data)?;
+    out.extend(encoder.finish()?);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::scanner;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+
+    fn cell(form_id: u32, edid: &str, extra: &[([u8; 4], Vec<u8>)]) -> Record {
+        let mut subrecords = vec![(*b"EDID", format!("{edid}\0").into_bytes())];
+        subrecords.extend_from_slice(extra);
+        Record::new(*b"CELL", form_id, &subrecords)
+    }
+
+    #[test]
+    fn test_round_trip_is_unchanged() {
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"EDID", b"Cell\0");
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        // tes4() writes a record count of 42; use the real count so bytes round-trip
+        bytes[34..38].copy_from_slice(&4u32.to_le_bytes());
+        bytes.extend(group(
+            *b"CELL",
+            0,
+            &group(
+                [0; 4],
+                2,
+                &group([0; 4], 3, &record(*b"CELL", 0, 0x0100_0800, &data)),
+            ),
+        ));
+
+        let plugin = PluginFile::parse(&bytes).unwrap();
+        assert_eq!(plugin.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_edit_compressed_record() {
+        let mut record = cell(0x800, "Cell", &[]);
+        record.header[8..12].copy_from_slice(&FLAG_COMPRESSED.to_le_bytes());
+        record
+            .set_subrecords(&[(*b"EDID", b"Compressed\0".to_vec())])
+            .unwrap();
+
+        assert_ne!(&record.data[4..], b"EDID\x0b\0Compressed\0");
+        assert_eq!(
+            record.subrecords().unwrap(),
+            [(*b"EDID", b"Compressed\0".to_vec())]
+        );
+    }
+
+    #[test]
+    fn test_large_subrecord_uses_xxxx() {
+        let large = vec![1u8; 70_000];
+        let record = Record::new(*b"CELL", 0x800, &[(*b"XCRI", large.clone())]);
+        assert_eq!(record.subrecords().unwrap(), [(*b"XCRI", large)]);
+    }
+
+    #[test]
+    fn test_insert_cells_builds_hierarchy() {
+        let mut plugin = PluginFile::parse(&tes4(0, "", &["Fallout4.esm"])).unwrap();
+        plugin
+            .insert_cell(cell(0x0100_0823, "Interior", &[]), None, None)
+            .unwrap();
+
+        assert!(
+            plugin
+                .insert_cell(cell(0x0000_E2F3, "", &[]), Some(0x3C), Some((-3, 22)))
+                .is_err()
+        );
+        plugin.insert_worldspace(Record::new(
+            *b"WRLD",
+            0x3C,
+            &[(*b"EDID", b"Commonwealth\0".to_vec())],
+        ));
+        let xclc = [(-3i32).to_le_bytes(), 22i32.to_le_bytes()].concat();
+        plugin
+            .insert_cell(
+                cell(0x0000_E2F3, "", &[(*b"XCLC", xclc)]),
+                Some(0x3C),
+                Some((-3, 22)),
+            )
+            .unwrap();
+
+        let interior = &plugin.groups[0];
+        let Node::Group(block) = &interior.children[0] else {
+            panic!("expected block")
+        };
+        assert_eq!(block.group_type(), GROUP_INTERIOR_BLOCK);
+        // 0x823 is 2083: block 3, sub-block 8
+        assert_eq!(block.label(), 3i32.to_le_bytes());
+        let Node::Group(sub_block) = &block.children[0] else {
+            panic!("expected sub-block")
+        };
+        assert_eq!(sub_block.label(), 8i32.to_le_bytes());
+
+        let bytes = plugin.to_bytes().unwrap();
+        let header = PluginHeader::parse(&bytes).unwrap();
+        assert_eq!(header.num_records, 10);
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let path = temp.path().join("Test.esp");
+        fs::write(&path, &bytes).unwrap();
+        let scan = scanner::scan(&path).unwrap();
+        assert_eq!(scan.cells.len(), 2);
+        assert_eq!(scan.cells[1].location(), "Commonwealth (-3, 22)");
+
+        assert_eq!(
+            PluginFile::read(&path).unwrap(),
+            PluginFile::parse(&bytes).unwrap()
+        );
+    }
+}
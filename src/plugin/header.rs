@@ -0,0 +1,226 @@
+//! TES4 plugin header
+//!
+//! The first record of every plugin is `TES4`. Its flags say whether the plugin is a
+//! master and/or light plugin, `HEDR` holds the format version, record count and next
+//! object ID, `CNAM` the author, and each `MAST` subrecord names a master the plugin
+//! depends on (followed by an unused `DATA` subrecord).
+
+use anyhow::{Context, Result, bail};
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::record::{RECORD_HEADER_SIZE, RecordHeader, Subrecords, read_u32, zstring};
+
+/// `TES4` flag: the plugin is a master (ESM)
+pub const FLAG_MASTER: u32 = 0x0000_0001;
+
+/// `TES4` flag: the plugin is a light plugin (ESL)
+pub const FLAG_LIGHT: u32 = 0x0000_0200;
+
+/// Parsed `TES4` header of a plugin
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginHeader {
+    /// `TES4` record flags (see [`FLAG_MASTER`], [`FLAG_LIGHT`])
+    pub flags: u32,
+    /// Form version of the header record
+    pub form_version: u16,
+    /// Plugin format version from `HEDR` (1.0 for current Fallout 4 plugins)
+    pub version: f32,
+    /// Number of records and groups, from `HEDR`
+    pub num_records: u32,
+    /// Next object ID to assign to a new record, from `HEDR`
+    pub next_object_id: u32,
+    /// Author (`CNAM`), if set
+    pub author: Option<String>,
+    /// Masters (`MAST`) in load order
+    pub masters: Vec<String>,
+}
+
+impl PluginHeader {
+    /// Read the header of the plugin at `path`
+    ///
+    /// Only the `TES4` record is read, so this is fast even for large plugins.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or does not start with a valid
+    /// `TES4` record
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::path::Path;
+    /// # use anyhow::Result;
+    /// # use generateprevisibines::plugin::PluginHeader;
+    ///
+    /// # fn example() -> Result<()> {
+    /// let header = PluginHeader::read(Path::new("C:\\Games\\Fallout4\\Data\\MyMod.esp"))?;
+    /// println!("Masters: {}", header.masters.join(", "));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn read(path: &Path) -> Result<Self> {
+        let mut file = File::open(path)
+            .with_context(|| format!("Failed to open plugin: {}", path.display()))?;
+
+        let mut bytes = vec![0u8; RECORD_HEADER_SIZE];
+        file.read_exact(&mut bytes)
+            .with_context(|| format!("Failed to read plugin header: {}", path.display()))?;
+        let header = RecordHeader::parse(&bytes)?;
+        if &header.record_type != b"TES4" {
+            bail!(
+                "{} is not a plugin (first record is {:?}, expected TES4)",
+                path.display(),
+                header.type_name()
+            );
+        }
+
+        bytes.resize(RECORD_HEADER_SIZE + header.data_size as usize, 0);
+        file.read_exact(&mut bytes[RECORD_HEADER_SIZE..])
+            .with_context(|| format!("Truncated plugin header: {}", path.display()))?;
+
+        Self::parse(&bytes).with_context(|| format!("Invalid plugin header: {}", path.display()))
+    }
+
+    /// Parse a `TES4` record (header and data)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record is not `TES4`, is truncated, or has no `HEDR`
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let header = RecordHeader::parse(bytes)?;
+        if &header.record_type != b"TES4" {
+            bail!("Expected TES4 record, found {:?}", header.type_name());
+        }
+        let Some(data) =
+            bytes.get(RECORD_HEADER_SIZE..RECORD_HEADER_SIZE + header.data_size as usize)
+        else {
+            bail!("Truncated TES4 record");
+        };
+
+        let mut hedr = None;
+        let mut author = None;
+        let mut masters = Vec::new();
+        for subrecord in Subrecords::new(data) {
+            let subrecord = subrecord?;
+            match &subrecord.subrecord_type {
+                b"HEDR" if subrecord.data.len() >= 12 => {
+                    let d = subrecord.data;
+                    hedr = Some((
+                        f32::from_le_bytes([d[0], d[1], d[2], d[3]]),
+                        read_u32(d, 4),
+                        read_u32(d, 8),
+                    ));
+                }
+                b"CNAM" => author = Some(zstring(subrecord.data)),
+                b"MAST" => masters.push(zstring(subrecord.data)),
+                _ => {}
+            }
+        }
+
+        let Some((version, num_records, next_object_id)) = hedr else {
+            bail!("TES4 record has no HEDR subrecord");
+        };
+
+        Ok(Self {
+            flags: header.flags,
+            form_version: header.form_version,
+            version,
+            num_records,
+            next_object_id,
+            author: author.filter(|a| !a.is_empty()),
+            masters,
+        })
+    }
+
+    /// Whether the master (ESM) flag is set
+    pub fn is_master(&self) -> bool {
+        self.flags & FLAG_MASTER != 0
+    }
+
+    /// Whether the light (ESL) flag is set
+    pub fn is_light(&self) -> bool {
+        self.flags & FLAG_LIGHT != 0
+    }
+
+    /// Short description of the plugin type for messages (e.g., `ESM`, `ESP (ESL-flagged)`)
+    pub fn kind(&self) -> &'static str {
+        match (self.is_master(), self.is_light()) {
+            (true, true) => "ESM (ESL-flagged)",
+            (true, false) => "ESM",
+            (false, true) => "ESP (ESL-flagged)",
+            (false, false) => "ESP",
+        }
+    }
+
+    /// Masters that are not found in any of `search_dirs`
+    ///
+    /// `search_dirs` are the directories that make up the game's view of `Data` (the real
+    /// `Data` directory, plus MO2 mod folders and the overwrite folder in MO2 mode).
+    pub fn missing_masters(&self, search_dirs: &[PathBuf]) -> Vec<String> {
+        self.masters
+            .iter()
+            .filter(|master| !search_dirs.iter().any(|dir| dir.join(master).is_file()))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::test_util::tes4;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_header() {
+        let header = PluginHeader::parse(&tes4(
+            FLAG_LIGHT,
+            "Someone",
+            &["Fallout4.esm", "DLCCoast.esm"],
+        ))
+        .unwrap();
+
+        assert!(header.is_light());
+        assert!(!header.is_master());
+        assert_eq!(header.kind(), "ESP (ESL-flagged)");
+        assert!((header.version - 1.0).abs() < f32::EPSILON);
+        assert_eq!(header.num_records, 42);
+        assert_eq!(header.next_object_id, 0x801);
+        assert_eq!(header.form_version, 131);
+        assert_eq!(header.author.as_deref(), Some("Someone"));
+        assert_eq!(header.masters, ["Fallout4.esm", "DLCCoast.esm"]);
+    }
+
+    #[test]
+    fn test_read_rejects_non_plugin() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("NotAPlugin.esp");
+        fs::write(&path, b"BTDX this is an archive, not a plugin").unwrap();
+        assert!(PluginHeader::read(&path).is_err());
+    }
+
+    #[test]
+    fn test_missing_masters() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        let overwrite = temp.path().join("overwrite");
+        fs::create_dir_all(&data).unwrap();
+        fs::create_dir_all(&overwrite).unwrap();
+        fs::write(data.join("Fallout4.esm"), b"").unwrap();
+        fs::write(overwrite.join("Patch.esp"), b"").unwrap();
+
+        let path = data.join("MyMod.esp");
+        fs::write(
+            &path,
+            tes4(0, "", &["Fallout4.esm", "Patch.esp", "Missing.esm"]),
+        )
+        .unwrap();
+
+        let header = PluginHeader::read(&path).unwrap();
+        assert_eq!(header.author, None);
+        assert_eq!(header.missing_masters(&[data, overwrite]), ["Missing.esm"]);
+    }
+}
@@ -0,0 +1,208 @@
+//! Identical-to-master (ITM) record detection
+//!
+//! The Creation Kit writes an override of every reference in a cell it generates previs
+//! for, even when nothing about the reference changed. Such an override is redundant:
+//! it is identical to the version of the record the plugin's masters already provide.
+//! [`previous_versions`] finds those versions so the previs merge can leave the redundant
+//! overrides out.
+//!
+//! Records are compared byte for byte (decompressed), which is only meaningful when both
+//! plugins number their masters the same way. A master whose master list does not match
+//! the start of the target's is not compared against, and neither is any record it could
+//! override, so a record is never reported as redundant by mistake.
+
+use anyhow::{Context, Result};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::document::Record;
+use super::header::PluginHeader;
+use super::record::{self, FLAG_COMPRESSED, Records, Subrecords, decompress};
+
+/// The parts of a record that decide whether two versions are identical
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordContent {
+    /// Record flags, without the compression flag
+    pub flags: u32,
+    /// Decompressed subrecords
+    pub subrecords: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl RecordContent {
+    /// Content of an in-memory record
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the record cannot be decompressed or parsed
+    pub fn of(record: &Record) -> Result<Self> {
+        Ok(Self {
+            flags: record.flags() & !FLAG_COMPRESSED,
+            subrecords: record.subrecords()?,
+        })
+    }
+
+    fn of_raw(record: &record::Record<'_>) -> Result<Self> {
+        let data = if record.header.flags & FLAG_COMPRESSED == 0 {
+            std::borrow::Cow::Borrowed(record.data)
+        } else {
+            std::borrow::Cow::Owned(decompress(record.data)?)
+        };
+
+        Ok(Self {
+            flags: record.header.flags & !FLAG_COMPRESSED,
+            subrecords: Subrecords::new(&data)
+                .map(|s| s.map(|s| (s.subrecord_type, s.data.to_vec())))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// Find the version of each record that the target plugin's masters provide
+///
+/// `form_ids` are form IDs as the target plugin stores them. For each one, the result
+/// holds the version from the last master (in the target's master order) that has the
+/// record. Form IDs with no comparable version are left out: records the target defines
+/// itself, and records a missing or differently-numbered master could override.
+///
+/// Masters are looked up in `search_dirs` in order, like the game's `Data` folder.
+///
+/// # Errors
+///
+/// Returns an error if a master that was found cannot be read or parsed
+pub fn previous_versions(
+    target: &PluginHeader,
+    search_dirs: &[PathBuf],
+    form_ids: &HashSet<u32>,
+) -> Result<HashMap<u32, RecordContent>> {
+    let mut versions = HashMap::new();
+    // Highest master index that could not be compared; records from masters up to and
+    // including it may have an unseen override
+    let mut unchecked_up_to = None;
+
+    for (index, master) in target.masters.iter().enumerate() {
+        let index = u32::try_from(index).unwrap_or(u32::MAX);
+        let wanted: HashSet<u32> = form_ids
+            .iter()
+            .copied()
+            .filter(|form_id| form_id >> 24 <= index)
+            .collect();
+        if wanted.is_empty() {
+            continue;
+        }
+
+        let Some(path) = search_dirs
+            .iter()
+            .map(|dir| dir.join(master))
+            .find(|path| path.is_file())
+        else {
+            warn!("Master {master} not found; its records are not checked for redundant overrides");
+            unchecked_up_to = Some(index);
+            continue;
+        };
+
+        let header = PluginHeader::read(&path)?;
+        let same_numbering = header.masters.len() == index as usize
+            && header
+                .masters
+                .iter()
+                .zip(&target.masters)
+                .all(|(a, b)| a.eq_ignore_ascii_case(b));
+        if !same_numbering {
+            info!(
+                "{master} numbers its masters differently than the plugin; its records are not \
+                checked for redundant overrides"
+            );
+            unchecked_up_to = Some(index);
+            continue;
+        }
+
+        versions.extend(read_records(&path, &wanted)?);
+    }
+
+    if let Some(limit) = unchecked_up_to {
+        versions.retain(|form_id, _| form_id >> 24 > limit);
+    }
+    Ok(versions)
+}
+
+/// Read the records with the given form IDs from a plugin
+fn read_records(path: &Path, form_ids: &HashSet<u32>) -> Result<HashMap<u32, RecordContent>> {
+    let bytes =
+        fs::read(path).with_context(|| format!("Failed to read plugin: {}", path.display()))?;
+
+    let mut records = HashMap::new();
+    for record in Records::new(&bytes).skip(1) {
+        let record =
+            record.with_context(|| format!("Failed to parse plugin: {}", path.display()))?;
+        if form_ids.contains(&record.header.form_id) {
+            records.insert(record.header.form_id, RecordContent::of_raw(&record)?);
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+    use tempfile::TempDir;
+
+    fn reference(form_id: u32, base: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"NAME", &base.to_le_bytes());
+        subrecord(&mut data, *b"DATA", &[0; 24]);
+        record(*b"REFR", 0, form_id, &data)
+    }
+
+    #[test]
+    fn test_previous_versions_uses_last_master() {
+        let temp = TempDir::new().unwrap();
+        let mut fallout4 = tes4(0, "", &[]);
+        fallout4.extend(group(
+            *b"CELL",
+            0,
+            &[reference(0x0000_1000, 0x10), reference(0x0000_2000, 0x20)].concat(),
+        ));
+        fs::write(temp.path().join("Fallout4.esm"), fallout4).unwrap();
+
+        let mut dlc = tes4(0, "", &["Fallout4.esm"]);
+        dlc.extend(group(*b"CELL", 0, &reference(0x0000_2000, 0x21)));
+        fs::write(temp.path().join("DLCRobot.esm"), dlc).unwrap();
+
+        let target = PluginHeader::parse(&tes4(0, "", &["Fallout4.esm", "DLCRobot.esm"])).unwrap();
+        let wanted = HashSet::from([0x0000_1000, 0x0000_2000, 0x0200_0800]);
+        let versions = previous_versions(&target, &[temp.path().to_path_buf()], &wanted).unwrap();
+
+        assert_eq!(versions.len(), 2);
+        assert_eq!(
+            versions[&0x0000_2000].subrecords[0].1,
+            0x21u32.to_le_bytes()
+        );
+    }
+
+    #[test]
+    fn test_previous_versions_skips_uncomparable_masters() {
+        let temp = TempDir::new().unwrap();
+        let mut fallout4 = tes4(0, "", &[]);
+        fallout4.extend(group(*b"CELL", 0, &reference(0x0000_1000, 0x10)));
+        fs::write(temp.path().join("Fallout4.esm"), fallout4).unwrap();
+        // DLCCoast.esm is index 2 in the target but numbers itself 1
+        fs::write(
+            temp.path().join("DLCCoast.esm"),
+            tes4(0, "", &["Fallout4.esm"]),
+        )
+        .unwrap();
+
+        let target = PluginHeader::parse(&tes4(
+            0,
+            "",
+            &["Fallout4.esm", "DLCRobot.esm", "DLCCoast.esm"],
+        ))
+        .unwrap();
+        let wanted = HashSet::from([0x0000_1000, 0x0200_1000]);
+        let versions = previous_versions(&target, &[temp.path().to_path_buf()], &wanted).unwrap();
+        assert!(versions.is_empty());
+    }
+}
@@ -0,0 +1,245 @@
+//! Light plugin (ESL) form ID limits
+//!
+//! A light plugin (`.esl`, or an `.esp`/`.esm` with the ESL flag) is loaded into the
+//! shared `FE` slot, so the records it defines itself (as opposed to overrides of its
+//! masters' records) must use object IDs `0x800`-`0xFFF`: at most 2048 new records.
+//!
+//! The precombine and previs merges (steps 2 and 7) copy new records from
+//! `CombinedObjects.esp`/`Previs.esp` into the target plugin, so a light plugin near the
+//! limit can be pushed out of range. Before a merge, [`warn_if_merge_exceeds`] projects the
+//! result; after it, [`check_range`] fails the run if the plugin is no longer valid as a
+//! light plugin.
+
+use anyhow::{Result, bail};
+use log::{info, warn};
+use std::fs;
+use std::path::Path;
+
+use super::header::PluginHeader;
+use super::record::Records;
+
+/// Lowest object ID a light plugin can assign
+pub const LIGHT_OBJECT_ID_MIN: u32 = 0x800;
+
+/// Highest object ID a light plugin can assign
+pub const LIGHT_OBJECT_ID_MAX: u32 = 0xFFF;
+
+/// Maximum number of new records in a light plugin
+pub const LIGHT_RECORD_LIMIT: usize = (LIGHT_OBJECT_ID_MAX - LIGHT_OBJECT_ID_MIN + 1) as usize;
+
+/// Records a plugin defines itself (its own form IDs, not overrides)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NewRecords {
+    /// Number of new records
+    pub count: usize,
+    /// Lowest object ID (form ID without the load order index) of a new record
+    pub lowest_object_id: Option<u32>,
+    /// Highest object ID of a new record
+    pub highest_object_id: Option<u32>,
+}
+
+impl NewRecords {
+    /// Whether every new record fits in the light plugin range
+    pub fn fits_light_range(&self) -> bool {
+        self.count <= LIGHT_RECORD_LIMIT
+            && self
+                .lowest_object_id
+                .is_none_or(|id| id >= LIGHT_OBJECT_ID_MIN)
+            && self
+                .highest_object_id
+                .is_none_or(|id| id <= LIGHT_OBJECT_ID_MAX)
+    }
+}
+
+/// Whether a plugin is loaded as a light plugin (`.esl` extension or ESL flag)
+pub fn is_light_plugin(path: &Path, header: &PluginHeader) -> bool {
+    header.is_light()
+        || path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("esl"))
+}
+
+/// Count the records a plugin defines itself
+///
+/// A record is new when the load order index of its form ID (the top byte) equals the
+/// number of masters, i.e. it refers to the plugin itself.
+///
+/// # Errors
+///
+/// Returns an error if the plugin cannot be read or parsed
+pub fn new_records(path: &Path) -> Result<NewRecords> {
+    let bytes = fs::read(path)?;
+    let header = PluginHeader::parse(&bytes)?;
+    let own_index = u32::try_from(header.masters.len()).unwrap_or(u32::MAX);
+
+    let mut records = NewRecords::default();
+    for record in Records::new(&bytes).skip(1) {
+        let record = record?;
+        if record.header.form_id >> 24 != own_index {
+            continue;
+        }
+
+        let object_id = record.header.form_id & 0x00FF_FFFF;
+        records.count += 1;
+        records.lowest_object_id = Some(
+            records
+                .lowest_object_id
+                .map_or(object_id, |id| id.min(object_id)),
+        );
+        records.highest_object_id = Some(
+            records
+                .highest_object_id
+                .map_or(object_id, |id| id.max(object_id)),
+        );
+    }
+
+    Ok(records)
+}
+
+/// Warn if merging `source` into the light plugin at `plugin_path` would exceed its limits
+///
+/// The projection assumes every new record in `source` becomes a new record in the plugin,
+/// numbered from the plugin's next object ID.
+///
+/// # Errors
+///
+/// Returns an error if either plugin cannot be read
+pub fn warn_if_merge_exceeds(plugin_path: &Path, source: &Path) -> Result<()> {
+    let header = PluginHeader::read(plugin_path)?;
+    let existing = new_records(plugin_path)?;
+    let incoming = new_records(source)?;
+
+    let projected = existing.count + incoming.count;
+    let next_object_id = header.next_object_id.max(LIGHT_OBJECT_ID_MIN);
+    let projected_highest = next_object_id
+        .saturating_add(u32::try_from(incoming.count).unwrap_or(u32::MAX))
+        .saturating_sub(1);
+
+    info!(
+        "Light plugin: {} new records, {} more from {} (limit {LIGHT_RECORD_LIMIT})",
+        existing.count,
+        incoming.count,
+        display_name(source)
+    );
+
+    if incoming.count > 0
+        && (projected > LIGHT_RECORD_LIMIT || projected_highest > LIGHT_OBJECT_ID_MAX)
+    {
+        warn!(
+            "Merging {} will likely push {} past the light plugin limit: {projected} new records \
+            (limit {LIGHT_RECORD_LIMIT}), object IDs up to {projected_highest:#05X} (limit {LIGHT_OBJECT_ID_MAX:#05X})",
+            display_name(source),
+            display_name(plugin_path)
+        );
+        warn!("The run will stop after the merge if the plugin is out of range.");
+    }
+
+    Ok(())
+}
+
+/// Check that a light plugin's new records are still within the light plugin range
+///
+/// # Errors
+///
+/// Returns an error if the plugin cannot be read, or if it has too many new records or
+/// object IDs outside `0x800`-`0xFFF`
+pub fn check_range(plugin_path: &Path) -> Result<NewRecords> {
+    let records = new_records(plugin_path)?;
+
+    if !records.fits_light_range() {
+        bail!(
+            "{} is a light plugin but now defines {} new records with object IDs {:#05X}-{:#05X}.\n\
+            A light plugin can define at most {LIGHT_RECORD_LIMIT} new records, with object IDs \
+            {LIGHT_OBJECT_ID_MIN:#05X}-{LIGHT_OBJECT_ID_MAX:#05X}.\n\
+            Remove the ESL flag (or rename the plugin to .esp), or split the cells across several plugins.",
+            display_name(plugin_path),
+            records.count,
+            records.lowest_object_id.unwrap_or_default(),
+            records.highest_object_id.unwrap_or_default()
+        );
+    }
+
+    info!(
+        "Light plugin within range: {} of {LIGHT_RECORD_LIMIT} new records",
+        records.count
+    );
+    Ok(records)
+}
+
+/// File name of a plugin path, for messages
+fn display_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::header::FLAG_LIGHT;
+    use crate::plugin::test_util::{group, record, tes4};
+    use tempfile::TempDir;
+
+    /// Write a plugin with one master and new records with the given object IDs
+    fn write_plugin(path: &Path, flags: u32, object_ids: impl IntoIterator<Item = u32>) {
+        let mut records = record(*b"STAT", 0, 0x0000_1234, b"");
+        for id in object_ids {
+            records.extend(record(*b"STAT", 0, 0x0100_0000 | id, b""));
+        }
+        let mut bytes = tes4(flags, "", &["Fallout4.esm"]);
+        bytes.extend(group(*b"STAT", 0, &records));
+        fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_new_records_ignore_overrides() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("Light.esp");
+        write_plugin(&path, FLAG_LIGHT, [0x800, 0x9FF]);
+
+        let records = new_records(&path).unwrap();
+        assert_eq!(records.count, 2);
+        assert_eq!(records.lowest_object_id, Some(0x800));
+        assert_eq!(records.highest_object_id, Some(0x9FF));
+        assert!(records.fits_light_range());
+        assert!(check_range(&path).is_ok());
+    }
+
+    #[test]
+    fn test_check_range_fails_outside_light_range() {
+        let temp = TempDir::new().unwrap();
+        let path = temp.path().join("Light.esl");
+        write_plugin(&path, 0, [0x800, 0x1000]);
+        assert!(check_range(&path).is_err());
+
+        // One record more than the limit, all within the object ID range
+        write_plugin(
+            &path,
+            0,
+            (0..=0x800).map(|i| LIGHT_OBJECT_ID_MIN + i % 0x800),
+        );
+        assert!(!new_records(&path).unwrap().fits_light_range());
+    }
+
+    #[test]
+    fn test_is_light_plugin() {
+        let header = PluginHeader::parse(&tes4(0, "", &[])).unwrap();
+        assert!(is_light_plugin(Path::new("Patch.esl"), &header));
+        assert!(!is_light_plugin(Path::new("Patch.esp"), &header));
+
+        let flagged = PluginHeader::parse(&tes4(FLAG_LIGHT, "", &[])).unwrap();
+        assert!(is_light_plugin(Path::new("Patch.esp"), &flagged));
+    }
+
+    #[test]
+    fn test_warn_if_merge_exceeds_reads_both_plugins() {
+        let temp = TempDir::new().unwrap();
+        let plugin = temp.path().join("Light.esp");
+        let source = temp.path().join("CombinedObjects.esp");
+        write_plugin(&plugin, FLAG_LIGHT, [0x800]);
+        write_plugin(&source, 0, [0x800, 0x801]);
+        assert!(warn_if_merge_exceeds(&plugin, &source).is_ok());
+        assert!(warn_if_merge_exceeds(&plugin, &temp.path().join("Missing.esp")).is_err());
+    }
+}
@@ -0,0 +1,277 @@
+//! The user's active load order (`plugins.txt`)
+//!
+//! Fallout 4 keeps the load order in `%LOCALAPPDATA%\Fallout4\plugins.txt` (Mod Organizer
+//! 2 keeps one per profile). Each line is a plugin name; active plugins are prefixed with
+//! `*`, and lines starting with `#` are comments:
+//!
+//! ```text
+//! # This file is used by Fallout 4 to keep track of your downloaded content.
+//! *Unofficial Fallout 4 Patch.esp
+//! *MyMod.esp
+//! DisabledMod.esp
+//! ```
+//!
+//! The base game and DLC masters are not listed; they always load first.
+//!
+//! [`LoadOrderFormIds`] numbers a plugin's form IDs the way the Creation Kit does once the
+//! plugin and its masters are loaded, which is how it names the files it generates.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::header::PluginHeader;
+
+/// Where a loaded plugin's records end up in the load-order form ID space
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Slot {
+    /// Full plugin: the top byte is its index among the full plugins
+    Full(u32),
+    /// Light plugin: `FE`, then its index among the light plugins in the next 12 bits
+    Light(u32),
+}
+
+/// Load-order form IDs of a plugin's records
+///
+/// Form IDs are stored relative to the plugin's master list (the top byte indexes it, and
+/// the plugin itself comes after its masters), but the Creation Kit names precombined
+/// meshes and previs files after the form ID a record has once loaded: full plugins are
+/// numbered `00`, `01`, ... in load order and light plugins (ESL flag or `.esl`) share
+/// `FE`, numbered `000`, `001`, ... in the next 12 bits. The two only agree when no plugin
+/// involved is light and the master list holds every plugin the Creation Kit loads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadOrderFormIds {
+    /// One slot per master list index, followed by the plugin's own
+    slots: Vec<Slot>,
+}
+
+impl LoadOrderFormIds {
+    /// Number the form IDs of `plugin` as the Creation Kit does when it loads the plugin
+    ///
+    /// The Creation Kit loads the plugin's masters in master list order, each after its own
+    /// masters, then the plugin. Masters are read from the first of `search_dirs` that has
+    /// them; one that is not found is assumed to be light only if it is an `.esl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the plugin's header or an installed master's header cannot be read
+    pub fn read(plugin: &Path, search_dirs: &[PathBuf]) -> Result<Self> {
+        let header = PluginHeader::read(plugin)?;
+        let mut loaded = Vec::new();
+        let mut seen = HashSet::new();
+        for master in &header.masters {
+            load_master(master, search_dirs, &mut seen, &mut loaded)?;
+        }
+        let name = plugin
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let light = header.is_light() || is_esl(&name);
+        loaded.push((name, light));
+        Ok(Self::new(&loaded, &header.masters))
+    }
+
+    /// Number the form IDs of a plugin with `masters`, given everything the Creation Kit
+    /// loads as `(name, light)` in load order, the plugin itself last
+    pub fn new(loaded: &[(String, bool)], masters: &[String]) -> Self {
+        let mut full = 0;
+        let mut light = 0;
+        let slots: Vec<(&str, Slot)> = loaded
+            .iter()
+            .map(|(name, is_light)| {
+                let slot = if *is_light {
+                    light += 1;
+                    Slot::Light(light - 1)
+                } else {
+                    full += 1;
+                    Slot::Full(full - 1)
+                };
+                (name.as_str(), slot)
+            })
+            .collect();
+        let own = slots.last().map_or(Slot::Full(0), |&(_, slot)| slot);
+
+        let mut numbered: Vec<Slot> = masters
+            .iter()
+            .map(|master| {
+                slots
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(master))
+                    .map_or(own, |&(_, slot)| slot)
+            })
+            .collect();
+        numbered.push(own);
+        Self { slots: numbered }
+    }
+
+    /// The load-order form ID of a form ID stored in the plugin
+    ///
+    /// A master index past the end of the master list refers to the plugin itself, as in
+    /// the game.
+    pub fn resolve(&self, form_id: u32) -> u32 {
+        let index = (form_id >> 24) as usize;
+        match self.slots.get(index).or(self.slots.last()) {
+            Some(Slot::Light(index)) => 0xFE00_0000 | (index << 12) | (form_id & 0xFFF),
+            Some(Slot::Full(index)) => (index << 24) | (form_id & 0x00FF_FFFF),
+            None => form_id,
+        }
+    }
+}
+
+/// Append `name` to `loaded` after its own masters, unless it was already loaded
+fn load_master(
+    name: &str,
+    search_dirs: &[PathBuf],
+    seen: &mut HashSet<String>,
+    loaded: &mut Vec<(String, bool)>,
+) -> Result<()> {
+    if !seen.insert(name.to_ascii_lowercase()) {
+        return Ok(());
+    }
+    let path = search_dirs
+        .iter()
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file());
+    let mut light = is_esl(name);
+    if let Some(path) = path {
+        let header = PluginHeader::read(&path)?;
+        for master in &header.masters {
+            load_master(master, search_dirs, seen, loaded)?;
+        }
+        light |= header.is_light();
+    }
+    loaded.push((name.to_string(), light));
+    Ok(())
+}
+
+fn is_esl(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("esl"))
+}
+
+/// Location of the game's `plugins.txt` (`%LOCALAPPDATA%\Fallout4\plugins.txt`)
+///
+/// Returns `None` if `LOCALAPPDATA` is not set.
+pub fn default_plugins_txt() -> Option<PathBuf> {
+    env::var_os("LOCALAPPDATA").map(|dir| PathBuf::from(dir).join("Fallout4").join("plugins.txt"))
+}
+
+/// Read the active plugins from a `plugins.txt`, in load order
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read
+pub fn read_plugins_txt(path: &Path) -> Result<Vec<String>> {
+    let content =
+        fs::read(path).with_context(|| format!("Failed to read load order: {}", path.display()))?;
+    Ok(parse_plugins_txt(&String::from_utf8_lossy(&content)))
+}
+
+/// Active plugins in the contents of a `plugins.txt`, in load order
+pub fn parse_plugins_txt(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix('*'))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// The active plugins that load after `plugin`
+///
+/// # Errors
+///
+/// Returns an error if `plugin` is not active in the load order
+pub fn plugins_after<'a>(load_order: &'a [String], plugin: &str) -> Result<&'a [String]> {
+    let Some(index) = load_order
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(plugin))
+    else {
+        bail!("{plugin} is not active in the load order");
+    };
+    Ok(&load_order[index + 1..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::header::FLAG_LIGHT;
+    use crate::plugin::test_util::tes4;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_parse_plugins_txt() {
+        let content = "# This file is used by Fallout 4 to keep track of your downloaded content.\r\n\
+                       *Unofficial Fallout 4 Patch.esp\r\n\
+                       DisabledMod.esp\r\n\
+                       *MyMod.esp\r\n\
+                       \r\n\
+                       *Later.esl\r\n";
+        let load_order = parse_plugins_txt(content);
+        assert_eq!(
+            load_order,
+            ["Unofficial Fallout 4 Patch.esp", "MyMod.esp", "Later.esl"]
+        );
+
+        assert_eq!(
+            plugins_after(&load_order, "mymod.esp").unwrap(),
+            ["Later.esl"]
+        );
+        assert!(plugins_after(&load_order, "DisabledMod.esp").is_err());
+    }
+
+    #[test]
+    fn test_load_order_form_ids() {
+        let loaded = [
+            ("Fallout4.esm".to_string(), false),
+            ("ccBGSFO4001-PipBoy(Black).esl".to_string(), true),
+            ("DLCRobot.esm".to_string(), false),
+            ("Target.esp".to_string(), true),
+        ];
+        let masters = [
+            "Fallout4.esm".to_string(),
+            "DLCRobot.esm".to_string(),
+            "ccBGSFO4001-PipBoy(Black).esl".to_string(),
+        ];
+        let ids = LoadOrderFormIds::new(&loaded, &masters);
+
+        assert_eq!(ids.resolve(0x0001_F4A2), 0x0001_F4A2);
+        assert_eq!(ids.resolve(0x0100_0F99), 0x0100_0F99);
+        assert_eq!(ids.resolve(0x0200_0801), 0xFE00_0801);
+        assert_eq!(ids.resolve(0x0300_0802), 0xFE00_1802);
+        assert_eq!(ids.resolve(0x0400_0803), 0xFE00_1803);
+    }
+
+    #[test]
+    fn test_load_order_form_ids_read() {
+        let data = TempDir::new().unwrap();
+        fs::write(data.path().join("Fallout4.esm"), tes4(0, "", &[])).unwrap();
+        fs::write(data.path().join("Base.esp"), tes4(0, "", &["Fallout4.esm"])).unwrap();
+        fs::write(
+            data.path().join("Patch.esp"),
+            tes4(0, "", &["Fallout4.esm", "Base.esp"]),
+        )
+        .unwrap();
+        // DLCCoast.esm is not installed, and Base.esp loads before Patch.esp even though
+        // only Patch.esp lists it
+        let plugin = data.path().join("Target.esp");
+        fs::write(
+            &plugin,
+            tes4(
+                FLAG_LIGHT,
+                "",
+                &["Fallout4.esm", "DLCCoast.esm", "Patch.esp"],
+            ),
+        )
+        .unwrap();
+
+        let ids = LoadOrderFormIds::read(&plugin, &[data.path().to_path_buf()]).unwrap();
+        assert_eq!(ids.resolve(0x0000_1234), 0x0000_1234);
+        assert_eq!(ids.resolve(0x0100_0800), 0x0100_0800);
+        assert_eq!(ids.resolve(0x0200_0900), 0x0300_0900);
+        assert_eq!(ids.resolve(0x0300_0A01), 0xFE00_0A01);
+    }
+}
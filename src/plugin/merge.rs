@@ -0,0 +1,1186 @@
+//! Native merge of the Creation Kit's output plugins into the target plugin
+//!
+//! `-GeneratePrecombined` and `-GeneratePreVisData` write their cell changes to
+//! `CombinedObjects.esp` and `Previs.esp` instead of the plugin being processed, and the
+//! batch script merges them back with xEdit scripts
+//! (`Batch_FO4MergeCombinedObjectsAndCheck.pas`, `Batch_FO4MergePrevisandCleanRefr.pas`).
+//! [`merge_combined_objects`] and [`merge_previs`] do the same without xEdit:
+//!
+//! - Cells the target already overrides get the precombine subrecords (`XCRI`, `XPRI`,
+//!   `PCMB`) or previs subrecords (`PCMB`, `VISI`, `RVIS`) from the source. Subrecords the
+//!   source cell lacks are removed, so stale data from an earlier run does not survive.
+//! - Cells the target does not override are copied whole, along with their worldspace
+//!   if the target does not override it either.
+//! - For previs, the references the Creation Kit overrides in `Previs.esp` are merged
+//!   too, except redundant ones: overrides identical to the version the target or its
+//!   masters already have (see [`itm`](super::itm)) are left out.
+//!
+//! Form IDs are stored relative to each plugin's master list, so every form ID that is
+//! copied is remapped from the source's masters to the target's, in cells and in
+//! references. The target itself is one of the source's masters (its records are
+//! referenced by the precombines).
+
+use anyhow::{Context, Result, bail};
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::document::{Node, PluginFile, Record};
+use super::itm::{self, RecordContent};
+use super::record::{
+    GROUP_CELL_CHILDREN, GROUP_CELL_PERSISTENT, GROUP_CELL_TEMPORARY, GROUP_CELL_VISIBLE_DISTANT,
+    read_u32,
+};
+
+/// Cell subrecords written by `-GeneratePrecombined`, in the order they appear in a cell
+pub const PRECOMBINE_SUBRECORDS: [[u8; 4]; 3] = [*b"PCMB", *b"XPRI", *b"XCRI"];
+
+/// Cell subrecords written by `-GeneratePreVisData`
+pub const PREVIS_SUBRECORDS: [[u8; 4]; 3] = [*b"PCMB", *b"VISI", *b"RVIS"];
+
+/// Cell subrecords that hold form IDs (one or more, 4 bytes each) and are remapped when a
+/// whole cell is copied
+const CELL_FORM_ID_SUBRECORDS: [[u8; 4]; 11] = [
+    *b"LTMP", *b"XCLR", *b"XCWT", *b"XCCM", *b"XCAS", *b"XEZN", *b"XCMO", *b"XCIM", *b"XGDR",
+    *b"XLCN", *b"RVIS",
+];
+
+/// Reference subrecords that hold form IDs (one or more, 4 bytes each)
+const REFERENCE_FORM_ID_SUBRECORDS: [[u8; 4]; 19] = [
+    *b"NAME", *b"XEZN", *b"XLCN", *b"XEMI", *b"XMSP", *b"XLYR", *b"XRFG", *b"XLRT", *b"XLRM",
+    *b"XPOD", *b"XORD", *b"XTNM", *b"XLIB", *b"XSPC", *b"XATR", *b"XLRL", *b"XCZR", *b"XCZC",
+    *b"XLKR",
+];
+
+/// Reference subrecords that start with a form ID, followed by non-form-ID fields
+const REFERENCE_LEADING_FORM_ID_SUBRECORDS: [[u8; 4]; 4] = [*b"XOWN", *b"XESP", *b"XNDP", *b"XPWR"];
+
+/// Reference subrecords without form IDs
+const REFERENCE_PLAIN_SUBRECORDS: [[u8; 4]; 34] = [
+    *b"EDID", *b"DATA", *b"XSCL", *b"XMBO", *b"XPRM", *b"XPTL", *b"XOCP", *b"XRGD", *b"XRGB",
+    *b"XRDS", *b"XLIG", *b"XALP", *b"XRNK", *b"XCNT", *b"XHLT", *b"XHTW", *b"XFVC", *b"XACT",
+    *b"XAMC", *b"XLCM", *b"XTRI", *b"XAPD", *b"XMBP", *b"XMRK", *b"FNAM", *b"FULL", *b"TNAM",
+    *b"XIS2", *b"XCVL", *b"XCVR", *b"XCLP", *b"XBSD", *b"XPDD", *b"XCZA",
+];
+
+/// What a merge changed in the target plugin
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// Cells the target already overrode whose subrecords changed
+    pub cells_updated: usize,
+    /// Cells the target already overrode that were already up to date
+    pub cells_unchanged: usize,
+    /// Cells copied from the source
+    pub cells_added: usize,
+    /// Worldspaces copied from the source for the added cells
+    pub worldspaces_added: usize,
+    /// Records defined by the source itself, which are not merged
+    pub new_records_skipped: usize,
+    /// References copied from the source
+    pub references_added: usize,
+    /// References the target already overrode that were replaced by the source's version
+    pub references_updated: usize,
+    /// Redundant reference overrides in the source that were left out
+    pub references_cleaned: usize,
+}
+
+impl fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} cells updated, {} unchanged, {} added, {} worldspaces added",
+            self.cells_updated, self.cells_unchanged, self.cells_added, self.worldspaces_added
+        )?;
+        if self.references_added + self.references_updated + self.references_cleaned > 0 {
+            write!(
+                f,
+                "; {} references added, {} updated, {} redundant overrides cleaned",
+                self.references_added, self.references_updated, self.references_cleaned
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Merge `CombinedObjects.esp` into the target plugin
+///
+/// The target plugin is rewritten in place (via a temporary file, so it is never left
+/// half-written).
+///
+/// # Errors
+///
+/// Returns an error if either plugin cannot be read or parsed, if a copied form ID refers
+/// to a plugin that is not a master of the target, or if the target cannot be written
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use generateprevisibines::plugin::merge;
+///
+/// let report = merge::merge_combined_objects(
+///     Path::new("C:\\Games\\Fallout4\\Data\\MyMod.esp"),
+///     Path::new("C:\\Games\\Fallout4\\Data\\CombinedObjects.esp"),
+/// )?;
+/// println!("{report}");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn merge_combined_objects(target_path: &Path, source_path: &Path) -> Result<MergeReport> {
+    merge_plugin(target_path, source_path, |target, target_name, source| {
+        merge_into(target, target_name, source, &PRECOMBINE_SUBRECORDS)
+    })
+}
+
+/// Merge `Previs.esp` into the target plugin, leaving out redundant reference overrides
+///
+/// Reference overrides the target does not have are compared against the target's
+/// masters, which are looked up in `search_dirs` in order (like the game's `Data`
+/// folder). The target plugin is rewritten in place (via a temporary file).
+///
+/// # Errors
+///
+/// Returns an error if either plugin or a master cannot be read or parsed, if a copied
+/// form ID refers to a plugin that is not a master of the target, if `Previs.esp` numbers
+/// its masters differently than the target and a reference has a subrecord that cannot be
+/// remapped (see [`remap_reference`]), or if the target cannot be written
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::{Path, PathBuf};
+/// use generateprevisibines::plugin::merge;
+///
+/// let data = PathBuf::from("C:\\Games\\Fallout4\\Data");
+/// let report = merge::merge_previs(
+///     &data.join("MyMod.esp"),
+///     &data.join("Previs.esp"),
+///     &[data.clone()],
+/// )?;
+/// println!("{report}");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn merge_previs(
+    target_path: &Path,
+    source_path: &Path,
+    search_dirs: &[PathBuf],
+) -> Result<MergeReport> {
+    merge_plugin(target_path, source_path, |target, target_name, source| {
+        let mut report = merge_into(target, target_name, source, &PREVIS_SUBRECORDS)?;
+        merge_references(target, target_name, source, search_dirs, &mut report)?;
+        Ok(report)
+    })
+}
+
+/// Read both plugins, merge the source into the target with `merge` and write the target
+fn merge_plugin(
+    target_path: &Path,
+    source_path: &Path,
+    merge: impl FnOnce(&mut PluginFile, &str, &PluginFile) -> Result<MergeReport>,
+) -> Result<MergeReport> {
+    let mut target = PluginFile::read(target_path)?;
+    let source = PluginFile::read(source_path)?;
+    let target_name = file_name(target_path);
+
+    let report = merge(&mut target, &target_name, &source).with_context(|| {
+        format!(
+            "Failed to merge {} into {target_name}",
+            file_name(source_path)
+        )
+    })?;
+
+    if report.new_records_skipped > 0 {
+        warn!(
+            "Skipped {} records defined by {} itself; they are not part of {target_name}",
+            report.new_records_skipped,
+            file_name(source_path)
+        );
+    }
+
+    target.write(target_path)?;
+    info!(
+        "Merged {} into {target_name}: {report}",
+        file_name(source_path)
+    );
+    Ok(report)
+}
+
+/// Merge the cells of `source` into `target` in memory
+fn merge_into(
+    target: &mut PluginFile,
+    target_name: &str,
+    source: &PluginFile,
+    subrecord_types: &[[u8; 4]],
+) -> Result<MergeReport> {
+    let target_header = target.plugin_header()?;
+    let source_header = source.plugin_header()?;
+    let masters = MasterMap::new(&source_header.masters, &target_header.masters, target_name);
+
+    let mut report = MergeReport::default();
+    for group in &source.groups {
+        let label = group.label();
+        if &label != b"CELL" && &label != b"WRLD" {
+            warn!(
+                "Ignoring {} group in the source; only cells are merged",
+                String::from_utf8_lossy(&label)
+            );
+        }
+    }
+
+    for cell in source_cells(source) {
+        if masters.is_source_record(cell.record.form_id()) {
+            report.new_records_skipped += 1;
+            continue;
+        }
+
+        let form_id = masters.remap(cell.record.form_id())?;
+        let source_subrecords = cell.record.subrecords()?;
+
+        if let Some(existing) = target.find_record_mut(*b"CELL", form_id) {
+            let mut subrecords = existing.subrecords()?;
+            if mirror_subrecords(
+                &mut subrecords,
+                &source_subrecords,
+                subrecord_types,
+                &masters,
+            )? {
+                existing.set_subrecords(&subrecords)?;
+                report.cells_updated += 1;
+            } else {
+                report.cells_unchanged += 1;
+            }
+            continue;
+        }
+
+        let world_id = match cell.world {
+            Some(world) => {
+                let world_id = masters.remap(world.form_id())?;
+                if target.find_record_mut(*b"WRLD", world_id).is_none() {
+                    target.insert_worldspace(copy_worldspace(world, world_id, &masters)?);
+                    report.worldspaces_added += 1;
+                }
+                Some(world_id)
+            }
+            None => None,
+        };
+
+        let subrecords = remap_cell(&source_subrecords, &masters)?;
+        let grid = subrecords
+            .iter()
+            .find(|(t, data)| t == b"XCLC" && data.len() >= 8)
+            .map(|(_, data)| {
+                (
+                    i32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                    i32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                )
+            });
+
+        let mut copy = cell.record.clone();
+        copy.set_form_id(form_id);
+        copy.set_subrecords(&subrecords)?;
+        target.insert_cell(copy, world_id, grid)?;
+        report.cells_added += 1;
+    }
+
+    Ok(report)
+}
+
+/// Merge the source's reference overrides into `target`, leaving out redundant ones
+fn merge_references(
+    target: &mut PluginFile,
+    target_name: &str,
+    source: &PluginFile,
+    search_dirs: &[PathBuf],
+    report: &mut MergeReport,
+) -> Result<()> {
+    let references = source_references(source);
+    if references.is_empty() {
+        return Ok(());
+    }
+
+    let target_header = target.plugin_header()?;
+    let masters = MasterMap::new(
+        &source.plugin_header()?.masters,
+        &target_header.masters,
+        target_name,
+    );
+
+    // Renumber first, so an override compares equal to an identical version in the
+    // target or its masters
+    let mut incoming = Vec::with_capacity(references.len());
+    for reference in references {
+        if masters.is_source_record(reference.record.form_id()) {
+            report.new_records_skipped += 1;
+            continue;
+        }
+        incoming.push((
+            remap_reference(reference.record, &masters)?,
+            masters.remap(reference.cell_id)?,
+            reference.group_type,
+        ));
+    }
+
+    let mut existing = target.records_mut(*b"REFR");
+    let unknown: HashSet<u32> = incoming
+        .iter()
+        .map(|(record, ..)| record.form_id())
+        .filter(|form_id| !existing.contains_key(form_id))
+        .collect();
+    let previous = if unknown.is_empty() {
+        HashMap::new()
+    } else {
+        info!(
+            "Checking {} references against the masters of {target_name}",
+            unknown.len()
+        );
+        itm::previous_versions(&target_header, search_dirs, &unknown)?
+    };
+
+    // New references, grouped by the cell children group they go in
+    let mut additions: Vec<((u32, u32), Vec<Record>)> = Vec::new();
+    for (record, cell_id, group_type) in incoming {
+        let content = RecordContent::of(&record)?;
+
+        if let Some(current) = existing.get_mut(&record.form_id()) {
+            if RecordContent::of(current)? == content {
+                report.references_cleaned += 1;
+            } else {
+                **current = record;
+                report.references_updated += 1;
+            }
+            continue;
+        }
+
+        if previous.get(&record.form_id()) == Some(&content) {
+            report.references_cleaned += 1;
+            continue;
+        }
+
+        match additions.last_mut() {
+            Some((key, records)) if *key == (cell_id, group_type) => records.push(record),
+            _ => additions.push(((cell_id, group_type), vec![record])),
+        }
+        report.references_added += 1;
+    }
+
+    for ((cell_id, group_type), records) in additions {
+        target.insert_references(records, cell_id, group_type)?;
+    }
+    Ok(())
+}
+
+/// Copy a reference into the target's master numbering
+///
+/// The form ID and the form ID fields in [`REFERENCE_FORM_ID_SUBRECORDS`] and
+/// [`REFERENCE_LEADING_FORM_ID_SUBRECORDS`] are remapped. When the plugins number their
+/// masters differently, a subrecord that is in neither list nor in
+/// [`REFERENCE_PLAIN_SUBRECORDS`] (e.g., `VMAD` script properties) cannot be remapped and
+/// is an error rather than a silently wrong copy.
+fn remap_reference(reference: &Record, masters: &MasterMap) -> Result<Record> {
+    let mut copy = reference.clone();
+    if masters.is_identity() {
+        return Ok(copy);
+    }
+
+    let form_id = reference.form_id();
+    let mut subrecords = reference.subrecords()?;
+    for (subrecord_type, data) in &mut subrecords {
+        match &*subrecord_type {
+            t if REFERENCE_FORM_ID_SUBRECORDS.contains(t) => {
+                for offset in (0..data.len() - data.len() % 4).step_by(4) {
+                    remap_at(data, offset, masters)?;
+                }
+            }
+            t if REFERENCE_LEADING_FORM_ID_SUBRECORDS.contains(t) && data.len() >= 4 => {
+                remap_at(data, 0, masters)?;
+            }
+            // Door, position and rotation, flags, then the transition interior
+            b"XTEL" => {
+                for offset in [0, 32] {
+                    if data.len() >= offset + 4 {
+                        remap_at(data, offset, masters)?;
+                    }
+                }
+            }
+            // Lock level (padded to 4 bytes), then the key
+            b"XLOC" if data.len() >= 8 => remap_at(data, 4, masters)?,
+            // (activate parent, delay) pairs
+            b"XAPR" => {
+                for offset in (0..data.len() - data.len() % 8).step_by(8) {
+                    remap_at(data, offset, masters)?;
+                }
+            }
+            t if REFERENCE_PLAIN_SUBRECORDS.contains(t) => {}
+            t => bail!(
+                "Reference {form_id:08X} has a {} subrecord, which cannot be remapped between \
+                the plugins' master lists",
+                String::from_utf8_lossy(t)
+            ),
+        }
+    }
+
+    copy.set_form_id(masters.remap(form_id)?);
+    copy.set_subrecords(&subrecords)?;
+    Ok(copy)
+}
+
+/// A reference in the source plugin, with its cell and the cell children group it is in
+struct SourceReference<'a> {
+    record: &'a Record,
+    cell_id: u32,
+    group_type: u32,
+}
+
+/// Every reference (`REFR`) in a plugin
+fn source_references(plugin: &PluginFile) -> Vec<SourceReference<'_>> {
+    fn collect<'a>(
+        nodes: &'a [Node],
+        cell: Option<(u32, u32)>,
+        out: &mut Vec<SourceReference<'a>>,
+    ) {
+        for node in nodes {
+            match node {
+                Node::Record(record) if &record.record_type() == b"REFR" => {
+                    if let Some((cell_id, group_type)) = cell
+                        && group_type != 0
+                    {
+                        out.push(SourceReference {
+                            record,
+                            cell_id,
+                            group_type,
+                        });
+                    }
+                }
+                Node::Group(group) => {
+                    let cell = match group.group_type() {
+                        GROUP_CELL_CHILDREN => Some((u32::from_le_bytes(group.label()), 0)),
+                        group_type @ (GROUP_CELL_PERSISTENT
+                        | GROUP_CELL_TEMPORARY
+                        | GROUP_CELL_VISIBLE_DISTANT) => {
+                            cell.map(|(cell_id, _)| (cell_id, group_type))
+                        }
+                        _ => cell,
+                    };
+                    collect(&group.children, cell, out);
+                }
+                Node::Record(_) => {}
+            }
+        }
+    }
+
+    let mut references = Vec::new();
+    for group in &plugin.groups {
+        collect(&group.children, None, &mut references);
+    }
+    references
+}
+
+/// A cell in the source plugin and the worldspace it belongs to
+pub(super) struct SourceCell<'a> {
+    pub(super) record: &'a Record,
+    pub(super) world: Option<&'a Record>,
+}
+
+/// Every cell in a plugin, skipping cell children (references)
+pub(super) fn source_cells(plugin: &PluginFile) -> Vec<SourceCell<'_>> {
+    fn collect<'a>(nodes: &'a [Node], world: Option<&'a Record>, out: &mut Vec<SourceCell<'a>>) {
+        let mut world = world;
+        for node in nodes {
+            match node {
+                Node::Record(record) if &record.record_type() == b"WRLD" => world = Some(record),
+                Node::Record(record) if &record.record_type() == b"CELL" => {
+                    out.push(SourceCell { record, world });
+                }
+                Node::Group(group) if group.group_type() != GROUP_CELL_CHILDREN => {
+                    collect(&group.children, world, out);
+                }
+                Node::Record(_) | Node::Group(_) => {}
+            }
+        }
+    }
+
+    let mut cells = Vec::new();
+    for group in &plugin.groups {
+        if matches!(&group.label(), b"CELL" | b"WRLD") {
+            collect(&group.children, None, &mut cells);
+        }
+    }
+    cells
+}
+
+/// Replace, add or remove `types` in a target cell's subrecords to match the source cell
+///
+/// Added subrecords are placed after the nearest subrecord that precedes them in the
+/// source cell. Returns whether anything changed.
+fn mirror_subrecords(
+    target: &mut Vec<([u8; 4], Vec<u8>)>,
+    source: &[([u8; 4], Vec<u8>)],
+    types: &[[u8; 4]],
+    masters: &MasterMap,
+) -> Result<bool> {
+    let mut changed = false;
+
+    for subrecord_type in types {
+        let source_index = source.iter().position(|(t, _)| t == subrecord_type);
+        let target_index = target.iter().position(|(t, _)| t == subrecord_type);
+
+        match (source_index, target_index) {
+            (Some(source_index), Some(target_index)) => {
+                let data = remap_subrecord(*subrecord_type, &source[source_index].1, masters)?;
+                if target[target_index].1 != data {
+                    target[target_index].1 = data;
+                    changed = true;
+                }
+            }
+            (Some(source_index), None) => {
+                let data = remap_subrecord(*subrecord_type, &source[source_index].1, masters)?;
+                let insert_at = source[..source_index]
+                    .iter()
+                    .rev()
+                    .find_map(|(previous, _)| target.iter().rposition(|(t, _)| t == previous))
+                    .map_or(target.len(), |index| index + 1);
+                target.insert(insert_at, (*subrecord_type, data));
+                changed = true;
+            }
+            (None, Some(target_index)) => {
+                target.remove(target_index);
+                changed = true;
+            }
+            (None, None) => {}
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Remap the form IDs in a whole cell's subrecords
+fn remap_cell(
+    subrecords: &[([u8; 4], Vec<u8>)],
+    masters: &MasterMap,
+) -> Result<Vec<([u8; 4], Vec<u8>)>> {
+    subrecords
+        .iter()
+        .map(|(subrecord_type, data)| {
+            Ok((
+                *subrecord_type,
+                remap_subrecord(*subrecord_type, data, masters)?,
+            ))
+        })
+        .collect()
+}
+
+/// Remap the form IDs in one cell subrecord
+fn remap_subrecord(subrecord_type: [u8; 4], data: &[u8], masters: &MasterMap) -> Result<Vec<u8>> {
+    let mut data = data.to_vec();
+    match &subrecord_type {
+        // u32 mesh count, u32 reference count, mesh hashes, then (reference, mesh) pairs
+        b"XCRI" => {
+            if data.len() < 8 {
+                bail!("XCRI is truncated ({} bytes)", data.len());
+            }
+            let meshes = read_u32(&data, 0) as usize;
+            let references = read_u32(&data, 4) as usize;
+            let start = 8 + meshes * 4;
+            if data.len() != start + references * 8 {
+                bail!(
+                    "XCRI size {} does not match {meshes} meshes and {references} references",
+                    data.len()
+                );
+            }
+            for offset in (start..data.len()).step_by(8) {
+                remap_at(&mut data, offset, masters)?;
+            }
+        }
+        b"XPRI" => {
+            for offset in (0..data.len() - data.len() % 4).step_by(4) {
+                remap_at(&mut data, offset, masters)?;
+            }
+        }
+        // Owner form ID, followed by non-form-ID fields
+        b"XOWN" if data.len() >= 4 => remap_at(&mut data, 0, masters)?,
+        t if CELL_FORM_ID_SUBRECORDS.contains(t) => {
+            for offset in (0..data.len() - data.len() % 4).step_by(4) {
+                remap_at(&mut data, offset, masters)?;
+            }
+        }
+        _ => {}
+    }
+    Ok(data)
+}
+
+/// Remap the form ID at `offset` in place
+fn remap_at(data: &mut [u8], offset: usize, masters: &MasterMap) -> Result<()> {
+    let form_id = masters.remap(read_u32(data, offset))?;
+    data[offset..offset + 4].copy_from_slice(&form_id.to_le_bytes());
+    Ok(())
+}
+
+/// Copy a worldspace record into the target
+///
+/// A worldspace has many form ID fields this module does not know, so it can only be
+/// copied when the source and target number their masters the same way.
+fn copy_worldspace(world: &Record, world_id: u32, masters: &MasterMap) -> Result<Record> {
+    if !masters.is_identity() {
+        bail!(
+            "Worldspace {:08X} is not in the target plugin and cannot be copied because the \
+            plugins' master lists differ. Add an override of the worldspace to the plugin and \
+            run the step again.",
+            world.form_id()
+        );
+    }
+    let mut copy = world.clone();
+    copy.set_form_id(world_id);
+    Ok(copy)
+}
+
+/// Maps the load order index of a source form ID to the target's
+struct MasterMap {
+    /// Target index for each source master, `None` if the target does not have it
+    indices: Vec<Option<u32>>,
+    /// Source master names, for messages
+    names: Vec<String>,
+    target_name: String,
+}
+
+impl MasterMap {
+    fn new(source_masters: &[String], target_masters: &[String], target_name: &str) -> Self {
+        let own_index = u32::try_from(target_masters.len()).unwrap_or(u32::MAX);
+        let indices = source_masters
+            .iter()
+            .map(|master| {
+                if master.eq_ignore_ascii_case(target_name) {
+                    return Some(own_index);
+                }
+                target_masters
+                    .iter()
+                    .position(|m| m.eq_ignore_ascii_case(master))
+                    .and_then(|index| u32::try_from(index).ok())
+            })
+            .collect();
+
+        Self {
+            indices,
+            names: source_masters.to_vec(),
+            target_name: target_name.to_string(),
+        }
+    }
+
+    /// Whether a form ID belongs to a record the source defines itself
+    fn is_source_record(&self, form_id: u32) -> bool {
+        (form_id >> 24) as usize >= self.indices.len()
+    }
+
+    /// Whether every source master has the same index in the target
+    fn is_identity(&self) -> bool {
+        self.indices
+            .iter()
+            .zip(0..)
+            .all(|(index, expected)| *index == Some(expected))
+    }
+
+    fn remap(&self, form_id: u32) -> Result<u32> {
+        if form_id == 0 {
+            return Ok(0);
+        }
+
+        let index = (form_id >> 24) as usize;
+        match self.indices.get(index) {
+            Some(Some(target_index)) => Ok(target_index << 24 | form_id & 0x00FF_FFFF),
+            Some(None) => bail!(
+                "Form ID {form_id:08X} refers to {}, which is not a master of {}",
+                self.names[index],
+                self.target_name
+            ),
+            None => bail!(
+                "Form ID {form_id:08X} refers to a record defined in the source plugin, which \
+                is not part of {}",
+                self.target_name
+            ),
+        }
+    }
+}
+
+/// File name of a plugin path, for messages
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::PluginHeader;
+    use crate::plugin::document::Group;
+    use crate::plugin::scanner;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// `XCRI` with one mesh and the given references
+    fn xcri(references: &[u32]) -> Vec<u8> {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&u32::try_from(references.len()).unwrap().to_le_bytes());
+        data.extend_from_slice(&0xDEAD_BEEFu32.to_le_bytes());
+        for reference in references {
+            data.extend_from_slice(&reference.to_le_bytes());
+            data.extend_from_slice(&0u32.to_le_bytes());
+        }
+        data
+    }
+
+    fn cell_data(subrecords: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for (subrecord_type, contents) in subrecords {
+            subrecord(&mut data, **subrecord_type, contents);
+        }
+        data
+    }
+
+    fn interior(form_id: u32, subrecords: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let object_id = form_id & 0x00FF_FFFF;
+        let block = group(
+            (object_id % 10).to_le_bytes(),
+            2,
+            &group(
+                (object_id / 10 % 10).to_le_bytes(),
+                3,
+                &record(*b"CELL", 0, form_id, &cell_data(subrecords)),
+            ),
+        );
+        group(*b"CELL", 0, &block)
+    }
+
+    /// A record or group, without version control info, group stamps or compression
+    #[derive(Debug, PartialEq)]
+    enum Canonical {
+        Group {
+            group_type: u32,
+            label: [u8; 4],
+            children: Vec<Canonical>,
+        },
+        Record {
+            record_type: [u8; 4],
+            form_id: u32,
+            content: RecordContent,
+        },
+    }
+
+    fn canonical(group: &Group) -> Canonical {
+        Canonical::Group {
+            group_type: group.group_type(),
+            label: group.label(),
+            children: group
+                .children
+                .iter()
+                .map(|node| match node {
+                    Node::Group(group) => canonical(group),
+                    Node::Record(record) => Canonical::Record {
+                        record_type: record.record_type(),
+                        form_id: record.form_id(),
+                        content: RecordContent::of(record).unwrap(),
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    fn subrecord_types(path: &Path, form_id: u32) -> Vec<[u8; 4]> {
+        let mut plugin = PluginFile::read(path).unwrap();
+        let cell = plugin.find_record_mut(*b"CELL", form_id).unwrap();
+        cell.subrecords()
+            .unwrap()
+            .into_iter()
+            .map(|(t, _)| t)
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_updates_existing_cell_and_remaps_form_ids() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("CombinedObjects.esp");
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "DLCRobot.esm"]);
+        bytes.extend(interior(
+            0x0000_1234,
+            &[
+                (b"EDID", b"Vault\0"),
+                (b"DATA", &[1, 0]),
+                (b"XCRI", &xcri(&[])),
+            ],
+        ));
+        fs::write(&target, bytes).unwrap();
+
+        // The source has an extra master, so DLCRobot.esm and Target.esp are shifted by one
+        let mut bytes = tes4(
+            0,
+            "",
+            &["Fallout4.esm", "DLCCoast.esm", "DLCRobot.esm", "Target.esp"],
+        );
+        bytes.extend(interior(
+            0x0000_1234,
+            &[
+                (b"EDID", b"Vault\0"),
+                (b"DATA", &[1, 0]),
+                (b"PCMB", &[0x12, 0x34]),
+                (b"XCRI", &xcri(&[0x0300_0800, 0x0200_1111])),
+            ],
+        ));
+        fs::write(&source, bytes).unwrap();
+
+        let report = merge_combined_objects(&target, &source).unwrap();
+        assert_eq!(report.cells_updated, 1);
+        assert_eq!(report.cells_added, 0);
+
+        assert_eq!(
+            subrecord_types(&target, 0x0000_1234),
+            [*b"EDID", *b"DATA", *b"PCMB", *b"XCRI"]
+        );
+        let mut plugin = PluginFile::read(&target).unwrap();
+        let subrecords = plugin
+            .find_record_mut(*b"CELL", 0x0000_1234)
+            .unwrap()
+            .subrecords()
+            .unwrap();
+        assert_eq!(subrecords[3].1, xcri(&[0x0200_0800, 0x0100_1111]));
+
+        let scan = scanner::scan(&target).unwrap();
+        assert_eq!(scan.cells[0].precombined_refs, 2);
+
+        // Merging again changes nothing
+        let report = merge_combined_objects(&target, &source).unwrap();
+        assert_eq!(report.cells_unchanged, 1);
+    }
+
+    /// Merges of the fixture plugins
+    ///
+    /// Every directory under `tests/fixtures/merge` is one case: `before/` holds the plugin
+    /// and `CombinedObjects.esp` or `Previs.esp` (plus the masters the previs merge compares
+    /// references against), and `after/` holds only the expected merged plugin (see
+    /// `tests/fixtures/merge/README.md`). The native merge of `before/` must give the same
+    /// header and the same records and groups (see [`canonical`]).
+    #[test]
+    fn test_merge_matches_fixtures() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/merge");
+        let mut cases: Vec<PathBuf> = fs::read_dir(root)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.is_dir())
+            .collect();
+        cases.sort();
+        assert!(!cases.is_empty(), "No cases found");
+
+        let temp = TempDir::new().unwrap();
+        for case in &cases {
+            let before = case.join("before");
+            let after: Vec<PathBuf> = fs::read_dir(case.join("after"))
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .collect();
+            let [expected] = after.as_slice() else {
+                panic!(
+                    "{}: after/ must hold only the merged plugin",
+                    case.display()
+                );
+            };
+            let plugin_name = expected.file_name().unwrap();
+
+            let target = temp.path().join(plugin_name);
+            fs::copy(before.join(plugin_name), &target).unwrap();
+            let previs = before.join("Previs.esp");
+            let merged = if previs.exists() {
+                merge_previs(&target, &previs, std::slice::from_ref(&before))
+            } else {
+                merge_combined_objects(&target, &before.join("CombinedObjects.esp"))
+            };
+            merged.unwrap_or_else(|e| panic!("{}: {e:#}", case.display()));
+
+            let merged = PluginFile::read(&target).unwrap();
+            let expected = PluginFile::read(expected).unwrap();
+            assert_eq!(
+                merged.plugin_header().unwrap(),
+                expected.plugin_header().unwrap(),
+                "{}",
+                case.display()
+            );
+            assert_eq!(
+                merged.groups.iter().map(canonical).collect::<Vec<_>>(),
+                expected.groups.iter().map(canonical).collect::<Vec<_>>(),
+                "{}",
+                case.display()
+            );
+        }
+    }
+
+    #[test]
+    fn test_merge_removes_stale_precombines() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("CombinedObjects.esp");
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        bytes.extend(interior(
+            0x0000_1234,
+            &[
+                (b"EDID", b"Vault\0"),
+                (b"PCMB", &[1, 0]),
+                (b"XCRI", &xcri(&[0x0100_0800])),
+            ],
+        ));
+        fs::write(&target, bytes).unwrap();
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "Target.esp"]);
+        bytes.extend(interior(
+            0x0000_1234,
+            &[(b"EDID", b"Vault\0"), (b"PCMB", &[2, 0])],
+        ));
+        fs::write(&source, bytes).unwrap();
+
+        merge_combined_objects(&target, &source).unwrap();
+        assert_eq!(subrecord_types(&target, 0x0000_1234), [*b"EDID", *b"PCMB"]);
+    }
+
+    #[test]
+    fn test_merge_adds_missing_exterior_cell_and_worldspace() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("CombinedObjects.esp");
+        fs::write(&target, tes4(0, "", &["Fallout4.esm"])).unwrap();
+
+        let xclc = [2i32.to_le_bytes(), (-5i32).to_le_bytes()].concat();
+        let mut world_data = Vec::new();
+        subrecord(&mut world_data, *b"EDID", b"Commonwealth\0");
+        let mut wrld = record(*b"WRLD", 0, 0x0000_003C, &world_data);
+        wrld.extend(group(
+            0x3Cu32.to_le_bytes(),
+            1,
+            &group(
+                [0; 4],
+                4,
+                &group(
+                    [0; 4],
+                    5,
+                    &record(
+                        *b"CELL",
+                        0,
+                        0x0000_E001,
+                        &cell_data(&[(b"XCLC", &xclc), (b"XCRI", &xcri(&[0x0100_0800]))]),
+                    ),
+                ),
+            ),
+        ));
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "Target.esp"]);
+        bytes.extend(group(*b"WRLD", 0, &wrld));
+        fs::write(&source, bytes).unwrap();
+
+        let report = merge_combined_objects(&target, &source).unwrap();
+        assert_eq!(report.cells_added, 1);
+        assert_eq!(report.worldspaces_added, 1);
+
+        let scan = scanner::scan(&target).unwrap();
+        assert_eq!(scan.cells.len(), 1);
+        assert_eq!(scan.cells[0].location(), "Commonwealth (2, -5)");
+        assert_eq!(PluginHeader::read(&target).unwrap().num_records, 6);
+    }
+
+    #[test]
+    fn test_merge_previs_cleans_redundant_references() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("Previs.esp");
+        let reference = |form_id: u32, base: u32| {
+            record(
+                *b"REFR",
+                0,
+                form_id,
+                &cell_data(&[(b"NAME", &base.to_le_bytes()), (b"DATA", &[0; 24])]),
+            )
+        };
+        let children = |references: &[Vec<u8>]| {
+            group(
+                0x1234u32.to_le_bytes(),
+                GROUP_CELL_CHILDREN,
+                &group(
+                    0x1234u32.to_le_bytes(),
+                    GROUP_CELL_TEMPORARY,
+                    &references.concat(),
+                ),
+            )
+        };
+
+        let mut master = tes4(0, "", &[]);
+        let mut cell = record(
+            *b"CELL",
+            0,
+            0x0000_1234,
+            &cell_data(&[(b"EDID", b"Vault\0")]),
+        );
+        cell.extend(children(&[
+            reference(0x0000_1000, 0x10),
+            reference(0x0000_2000, 0x20),
+        ]));
+        master.extend(group(*b"CELL", 0, &cell));
+        fs::write(temp.path().join("Fallout4.esm"), master).unwrap();
+
+        // The target overrides the cell and defines one reference of its own
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        let mut cell = record(
+            *b"CELL",
+            0,
+            0x0000_1234,
+            &cell_data(&[(b"EDID", b"Vault\0")]),
+        );
+        cell.extend(children(&[reference(0x0100_0800, 0x30)]));
+        bytes.extend(group(*b"CELL", 0, &cell));
+        fs::write(&target, bytes).unwrap();
+
+        // Previs.esp overrides all three references; only 0x2000 actually changed
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "Target.esp"]);
+        let mut cell = record(
+            *b"CELL",
+            0,
+            0x0000_1234,
+            &cell_data(&[
+                (b"EDID", b"Vault\0"),
+                (b"VISI", &[5, 0]),
+                (b"RVIS", &0x1234u32.to_le_bytes()),
+            ]),
+        );
+        cell.extend(children(&[
+            reference(0x0000_1000, 0x10),
+            reference(0x0000_2000, 0x21),
+            reference(0x0100_0800, 0x30),
+        ]));
+        bytes.extend(group(*b"CELL", 0, &cell));
+        fs::write(&source, bytes).unwrap();
+
+        let report = merge_previs(&target, &source, &[temp.path().to_path_buf()]).unwrap();
+        assert_eq!(report.cells_updated, 1);
+        assert_eq!(report.references_cleaned, 2);
+        assert_eq!(report.references_added, 1);
+        assert_eq!(
+            subrecord_types(&target, 0x0000_1234),
+            [*b"EDID", *b"VISI", *b"RVIS"]
+        );
+
+        let mut plugin = PluginFile::read(&target).unwrap();
+        assert_eq!(
+            plugin
+                .records_mut(*b"REFR")
+                .into_keys()
+                .collect::<HashSet<_>>(),
+            HashSet::from([0x0100_0800, 0x0000_2000])
+        );
+        let added = plugin.find_record_mut(*b"REFR", 0x0000_2000).unwrap();
+        assert_eq!(added.subrecords().unwrap()[0].1, 0x21u32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_merge_previs_remaps_references() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("Previs.esp");
+        fs::write(temp.path().join("Fallout4.esm"), tes4(0, "", &[])).unwrap();
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "DLCRobot.esm"]);
+        bytes.extend(interior(0x0000_1234, &[(b"EDID", b"Vault\0")]));
+        fs::write(&target, bytes).unwrap();
+
+        // An extra master shifts DLCRobot.esm and Target.esp by one
+        let reference = |subrecords: &[(&[u8; 4], &[u8])]| {
+            record(*b"REFR", 0, 0x0200_0900, &cell_data(subrecords))
+        };
+        let mut xesp = 0x0300_0800u32.to_le_bytes().to_vec();
+        xesp.extend_from_slice(&[1, 0, 0, 0]);
+        let mut cell = record(
+            *b"CELL",
+            0,
+            0x0000_1234,
+            &cell_data(&[(b"EDID", b"Vault\0"), (b"VISI", &[5, 0])]),
+        );
+        let mut references = reference(&[
+            (b"NAME", &0x0200_0010u32.to_le_bytes()),
+            (b"DATA", &[0; 24]),
+            (b"XESP", &xesp),
+        ]);
+        references.extend(record(
+            *b"REFR",
+            0,
+            0x0300_0A00,
+            &cell_data(&[(b"NAME", &0x10u32.to_le_bytes()), (b"DATA", &[0; 24])]),
+        ));
+        cell.extend(group(
+            0x1234u32.to_le_bytes(),
+            GROUP_CELL_CHILDREN,
+            &group(0x1234u32.to_le_bytes(), GROUP_CELL_TEMPORARY, &references),
+        ));
+        let mut bytes = tes4(
+            0,
+            "",
+            &["Fallout4.esm", "DLCCoast.esm", "DLCRobot.esm", "Target.esp"],
+        );
+        bytes.extend(group(*b"CELL", 0, &cell));
+        fs::write(&source, bytes).unwrap();
+
+        let report = merge_previs(&target, &source, &[temp.path().to_path_buf()]).unwrap();
+        assert_eq!(report.references_added, 2);
+
+        let mut plugin = PluginFile::read(&target).unwrap();
+        let mut references = plugin.records_mut(*b"REFR");
+        let robot = references
+            .get_mut(&0x0100_0900)
+            .unwrap()
+            .subrecords()
+            .unwrap();
+        assert_eq!(robot[0].1, 0x0100_0010u32.to_le_bytes());
+        assert_eq!(robot[2].1[..4], 0x0200_0800u32.to_le_bytes());
+        assert!(references.contains_key(&0x0200_0A00));
+
+        // A subrecord whose form IDs are unknown is not copied with the wrong numbering
+        let mut bytes = tes4(0, "", &["Fallout4.esm", "DLCCoast.esm", "Target.esp"]);
+        let mut cell = record(
+            *b"CELL",
+            0,
+            0x0000_1234,
+            &cell_data(&[(b"EDID", b"Vault\0")]),
+        );
+        cell.extend(group(
+            0x1234u32.to_le_bytes(),
+            GROUP_CELL_CHILDREN,
+            &group(
+                0x1234u32.to_le_bytes(),
+                GROUP_CELL_TEMPORARY,
+                &record(*b"REFR", 0, 0x0000_0B00, &cell_data(&[(b"VMAD", &[0; 6])])),
+            ),
+        ));
+        bytes.extend(group(*b"CELL", 0, &cell));
+        fs::write(&source, bytes).unwrap();
+        let error = merge_previs(&target, &source, &[temp.path().to_path_buf()]).unwrap_err();
+        assert!(format!("{error:#}").contains("VMAD"));
+    }
+
+    #[test]
+    fn test_merge_fails_for_unknown_master() {
+        let temp = TempDir::new().unwrap();
+        let target = temp.path().join("Target.esp");
+        let source = temp.path().join("CombinedObjects.esp");
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        bytes.extend(interior(0x0000_1234, &[(b"EDID", b"Vault\0")]));
+        fs::write(&target, &bytes).unwrap();
+
+        let mut source_bytes = tes4(0, "", &["Fallout4.esm", "Other.esp", "Target.esp"]);
+        source_bytes.extend(interior(0x0000_1234, &[(b"XCRI", &xcri(&[0x0100_0800]))]));
+        fs::write(&source, source_bytes).unwrap();
+
+        let error = merge_combined_objects(&target, &source).unwrap_err();
+        assert!(format!("{error:#}").contains("Other.esp"));
+        assert_eq!(
+            fs::read(&target).unwrap(),
+            bytes,
+            "target must be left untouched"
+        );
+    }
+}
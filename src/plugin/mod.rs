@@ -0,0 +1,35 @@
+//! Native reading of Fallout 4 plugin files (`.esp`, `.esm`, `.esl`)
+//!
+//! The workflow only needs a small part of the plugin format, so this module parses just
+//! that rather than depending on xEdit:
+//!
+//! - [`header`]: the `TES4` header (flags, version, author, masters), used to check that
+//!   every master is installed before the Creation Kit is started
+//! - [`light`]: light plugin (ESL) form ID limits, checked around the merge steps
+//! - [`scanner`]: streaming scan of `CELL` records for precombine/previs state, used by
+//!   the `analyze` command and before step 1
+//! - [`merge`]: native merge of `CombinedObjects.esp` and `Previs.esp` into the plugin
+//!   (steps 2 and 7)
+//! - [`itm`]: identical-to-master checks, used to leave out redundant reference overrides
+//! - [`load_order`]: the user's active load order (`plugins.txt`), and the load-order form
+//!   IDs the Creation Kit names generated files after
+//! - [`breakers`]: later plugins in the load order that override precombined cells
+//! - [`seed`]: new plugins overriding chosen cells of a master, to rebuild an area
+//! - [`document`]: in-memory plugin tree used to edit and write plugins
+//! - [`record`]: record and subrecord parsing shared by the modules above
+
+pub mod breakers;
+pub mod document;
+pub mod header;
+pub mod itm;
+pub mod light;
+pub mod load_order;
+pub mod merge;
+pub mod record;
+pub mod scanner;
+pub mod seed;
+
+#[cfg(test)]
+pub(crate) mod test_util;
+
+pub use header::PluginHeader;
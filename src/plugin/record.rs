@@ -0,0 +1,345 @@
+//! Low-level record and subrecord parsing
+//!
+//! Every record starts with a 24-byte header:
+//!
+//! | Offset | Size | Field |
+//! |--------|------|-------|
+//! | 0 | 4 | Record type (e.g., `TES4`, `CELL`) |
+//! | 4 | 4 | Data size (excluding the header) |
+//! | 8 | 4 | Record flags |
+//! | 12 | 4 | Form ID |
+//! | 16 | 4 | Version control info |
+//! | 20 | 2 | Form version |
+//! | 22 | 2 | Unknown |
+//!
+//! Records are organized in groups (`GRUP`), whose 24-byte header holds the size of the
+//! whole group including the header. Groups nest (e.g., a worldspace's cell blocks).
+//!
+//! The record data is a sequence of subrecords, each a 4-byte type and a `u16` size
+//! followed by the data. Subrecords larger than 64 KiB are preceded by an `XXXX`
+//! subrecord whose 4-byte payload is the real size of the next subrecord.
+
+use anyhow::{Result, bail};
+use flate2::read::ZlibDecoder;
+use std::io::Read;
+
+/// Size of a record header in bytes
+pub const RECORD_HEADER_SIZE: usize = 24;
+
+/// Record flag: the record data is zlib-compressed (a `u32` decompressed size followed by
+/// the zlib stream)
+pub const FLAG_COMPRESSED: u32 = 0x0004_0000;
+
+/// Size of a group (`GRUP`) header in bytes; a group's size includes its header
+pub const GROUP_HEADER_SIZE: usize = 24;
+
+/// Group type of a top-level group (one per record type)
+pub const GROUP_TOP: u32 = 0;
+/// Group type of a worldspace's children (cells and blocks)
+pub const GROUP_WORLD_CHILDREN: u32 = 1;
+/// Group type of an interior cell block
+pub const GROUP_INTERIOR_BLOCK: u32 = 2;
+/// Group type of an interior cell sub-block
+pub const GROUP_INTERIOR_SUB_BLOCK: u32 = 3;
+/// Group type of an exterior cell block
+pub const GROUP_EXTERIOR_BLOCK: u32 = 4;
+/// Group type of an exterior cell sub-block
+pub const GROUP_EXTERIOR_SUB_BLOCK: u32 = 5;
+/// Group type of a cell's children (references)
+pub const GROUP_CELL_CHILDREN: u32 = 6;
+/// Group type of a cell's persistent references
+pub const GROUP_CELL_PERSISTENT: u32 = 8;
+/// Group type of a cell's temporary references
+pub const GROUP_CELL_TEMPORARY: u32 = 9;
+/// Group type of a cell's visible-when-distant references
+pub const GROUP_CELL_VISIBLE_DISTANT: u32 = 10;
+
+/// Size of a subrecord header in bytes
+const SUBRECORD_HEADER_SIZE: usize = 6;
+
+/// Header of a record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordHeader {
+    /// Record type (e.g., `*b"CELL"`)
+    pub record_type: [u8; 4],
+    /// Size of the record data following the header
+    pub data_size: u32,
+    /// Record flags
+    pub flags: u32,
+    /// Form ID of the record
+    pub form_id: u32,
+    /// Form version (131 for Fallout 4 1.10+)
+    pub form_version: u16,
+}
+
+impl RecordHeader {
+    /// Parse a record header from the first [`RECORD_HEADER_SIZE`] bytes of `bytes`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `bytes` is shorter than a record header
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let Some(header) = bytes.get(..RECORD_HEADER_SIZE) else {
+            bail!("Truncated record header ({} bytes)", bytes.len());
+        };
+
+        Ok(Self {
+            record_type: [header[0], header[1], header[2], header[3]],
+            data_size: read_u32(header, 4),
+            flags: read_u32(header, 8),
+            form_id: read_u32(header, 12),
+            form_version: u16::from_le_bytes([header[20], header[21]]),
+        })
+    }
+
+    /// Record type as text, for messages
+    pub fn type_name(&self) -> String {
+        String::from_utf8_lossy(&self.record_type).into_owned()
+    }
+}
+
+/// One record in a plugin file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    /// Record header
+    pub header: RecordHeader,
+    /// Raw record data (still compressed if the record is compressed)
+    pub data: &'a [u8],
+}
+
+/// Iterator over every record in a plugin file, descending into all groups
+///
+/// Records are yielded in file order, starting with `TES4`. Group structure is not
+/// reported; a record's form ID and type are enough for what the workflow checks.
+pub struct Records<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Records<'a> {
+    /// Iterate over the records in the contents of a plugin file
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pos >= self.bytes.len() {
+                return None;
+            }
+
+            let offset = self.pos;
+            let header = match RecordHeader::parse(&self.bytes[offset..]) {
+                Ok(header) => header,
+                Err(e) => {
+                    self.pos = self.bytes.len();
+                    return Some(Err(e.context(format!("Invalid record at offset {offset}"))));
+                }
+            };
+
+            // Step into groups; their records follow the group header
+            if &header.record_type == b"GRUP" {
+                self.pos = offset + GROUP_HEADER_SIZE;
+                continue;
+            }
+
+            let start = offset + RECORD_HEADER_SIZE;
+            let Some(data) = self.bytes.get(start..start + header.data_size as usize) else {
+                self.pos = self.bytes.len();
+                return Some(Err(anyhow::anyhow!(
+                    "{} record at offset {offset} overruns the file",
+                    header.type_name()
+                )));
+            };
+            self.pos = start + data.len();
+
+            return Some(Ok(Record { header, data }));
+        }
+    }
+}
+
+/// One subrecord inside a record's data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Subrecord<'a> {
+    /// Subrecord type (e.g., `*b"MAST"`)
+    pub subrecord_type: [u8; 4],
+    /// Subrecord data
+    pub data: &'a [u8],
+}
+
+/// Iterator over the subrecords of a record's data, resolving `XXXX` size overrides
+pub struct Subrecords<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Subrecords<'a> {
+    /// Iterate over the subrecords in `data` (record data without the record header)
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+}
+
+impl<'a> Iterator for Subrecords<'a> {
+    type Item = Result<Subrecord<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut size_override = None;
+
+        loop {
+            if self.pos >= self.data.len() {
+                return None;
+            }
+
+            let offset = self.pos;
+            let Some(header) = self.data.get(offset..offset + SUBRECORD_HEADER_SIZE) else {
+                self.pos = self.data.len();
+                return Some(Err(anyhow::anyhow!(
+                    "Truncated subrecord header at offset {offset}"
+                )));
+            };
+            let subrecord_type = [header[0], header[1], header[2], header[3]];
+            let size = size_override
+                .take()
+                .unwrap_or(usize::from(u16::from_le_bytes([header[4], header[5]])));
+
+            let start = offset + SUBRECORD_HEADER_SIZE;
+            let Some(data) = self.data.get(start..start + size) else {
+                self.pos = self.data.len();
+                return Some(Err(anyhow::anyhow!(
+                    "Subrecord {} at offset {offset} overruns the record ({size} bytes)",
+                    String::from_utf8_lossy(&subrecord_type)
+                )));
+            };
+            self.pos = start + size;
+
+            if &subrecord_type == b"XXXX" && data.len() == 4 {
+                size_override = Some(read_u32(data, 0) as usize);
+                continue;
+            }
+
+            return Some(Ok(Subrecord {
+                subrecord_type,
+                data,
+            }));
+        }
+    }
+}
+
+/// Read a little-endian `u32` at `offset`
+///
+/// Callers must have checked that `offset + 4 <= bytes.len()`.
+pub fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+/// Decompress the data of a compressed record (`u32` decompressed size, then zlib data)
+///
+/// # Errors
+///
+/// Returns an error if the data is truncated or is not a valid zlib stream of the stated size
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    if data.len() < 4 {
+        bail!("Compressed record is too short");
+    }
+    let size = read_u32(data, 0) as usize;
+    let mut out = Vec::with_capacity(size);
+    ZlibDecoder::new(&data[4..]).read_to_end(&mut out)?;
+    if out.len() != size {
+        bail!(
+            "Decompressed size {} does not match the expected {size}",
+            out.len()
+        );
+    }
+    Ok(out)
+}
+
+/// Decode a null-terminated string subrecord
+///
+/// Plugin strings are Windows-1252; bytes are mapped as Latin-1, which matches for all
+/// characters that appear in file names and author names in practice.
+pub fn zstring(data: &[u8]) -> String {
+    data.iter()
+        .take_while(|&&b| b != 0)
+        .map(|&b| char::from(b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+
+    #[test]
+    fn test_record_header() {
+        let bytes = record(*b"CELL", 0x40000, 0x0001_F4A2, &[0; 10]);
+
+        let header = RecordHeader::parse(&bytes).unwrap();
+        assert_eq!(header.type_name(), "CELL");
+        assert_eq!(header.data_size, 10);
+        assert_eq!(header.flags, 0x40000);
+        assert_eq!(header.form_id, 0x0001_F4A2);
+        assert_eq!(header.form_version, 131);
+
+        assert!(RecordHeader::parse(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_subrecords_with_size_override() {
+        let large = vec![7u8; 70_000];
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"EDID", b"Cell\0");
+        subrecord(&mut data, *b"XXXX", &70_000u32.to_le_bytes());
+        data.extend_from_slice(b"DATA\0\0");
+        data.extend_from_slice(&large);
+
+        let subrecords: Vec<_> = Subrecords::new(&data).map(Result::unwrap).collect();
+        assert_eq!(subrecords.len(), 2);
+        assert_eq!(&subrecords[0].subrecord_type, b"EDID");
+        assert_eq!(zstring(subrecords[0].data), "Cell");
+        assert_eq!(&subrecords[1].subrecord_type, b"DATA");
+        assert_eq!(subrecords[1].data.len(), 70_000);
+    }
+
+    #[test]
+    fn test_truncated_subrecord() {
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"EDID", b"Cell\0");
+        data.truncate(8);
+        let mut subrecords = Subrecords::new(&data);
+        assert!(subrecords.next().unwrap().is_err());
+        assert!(subrecords.next().is_none());
+    }
+
+    #[test]
+    fn test_records_descend_into_groups() {
+        let mut cells = record(*b"CELL", 0, 0x0100_0800, b"");
+        cells.extend(group([0; 4], 9, &record(*b"REFR", 0, 0x0100_0801, &[1, 2])));
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        bytes.extend(group(*b"CELL", 0, &group([0; 4], 2, &cells)));
+
+        let records: Vec<_> = Records::new(&bytes)
+            .map(|r| r.map(|r| (r.header.type_name(), r.header.form_id, r.data.len())))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            records[1..],
+            [
+                ("CELL".to_string(), 0x0100_0800, 0),
+                ("REFR".to_string(), 0x0100_0801, 2)
+            ]
+        );
+        assert_eq!(records[0].0, "TES4");
+
+        assert!(Records::new(&bytes[..bytes.len() - 1]).any(|r| r.is_err()));
+    }
+}
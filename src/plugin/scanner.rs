@@ -0,0 +1,540 @@
+//! Streaming scanner for precombine and previs data in cells
+//!
+//! Precombine and previs state is stored on each `CELL` record:
+//!
+//! | Subrecord | Meaning |
+//! |-----------|---------|
+//! | `XCRI` | Combined references: precombined meshes and the references merged into them |
+//! | `XPRI` | Physics references of the precombined meshes |
+//! | `PCMB` | Timestamp of the precombined files |
+//! | `VISI` | Timestamp of the previs files |
+//! | `RVIS` | Cell whose previs file (`vis\<form ID>.uvd`) covers this cell |
+//!
+//! The scanner reads the plugin from disk as a stream: only the `CELL` and `WRLD` top-level
+//! groups are entered, only `CELL`/`WRLD` records are read (and decompressed when their
+//! compressed flag is set), and everything else, including the references inside each
+//! cell, is skipped with a seek. Large city plugins are scanned without loading them
+//! into memory.
+//!
+//! Previs data is *stale* when it is older than the cell's precombines: the previs
+//! visibility data was computed for precombined meshes that have since been regenerated,
+//! which shows up in-game as flickering or missing objects.
+
+use anyhow::{Context, Result, bail};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use super::record::{
+    FLAG_COMPRESSED, GROUP_CELL_CHILDREN, GROUP_HEADER_SIZE, GROUP_WORLD_CHILDREN,
+    RECORD_HEADER_SIZE, RecordHeader, Subrecords, decompress, read_u32, zstring,
+};
+
+/// Precombine and previs state of one cell
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CellInfo {
+    /// Form ID of the cell (as stored in the plugin, including the master index)
+    pub form_id: u32,
+    /// Editor ID (`EDID`), if set
+    pub editor_id: Option<String>,
+    /// Editor ID (or form ID) of the worldspace for exterior cells
+    pub worldspace: Option<String>,
+    /// Form ID of the worldspace for exterior cells
+    pub worldspace_form_id: Option<u32>,
+    /// Grid position (`XCLC`) for exterior cells
+    pub grid: Option<(i32, i32)>,
+    /// Number of precombined meshes (`XCRI`)
+    pub precombined_meshes: u32,
+    /// Hashes of the precombined meshes (`XCRI`); each mesh is the file
+    /// `meshes\precombined\<cell form ID>_<hash>_OC.nif`
+    pub precombined_mesh_hashes: Vec<u32>,
+    /// Number of references merged into precombined meshes (`XCRI`)
+    pub precombined_refs: u32,
+    /// Number of physics references (`XPRI`)
+    pub physics_refs: usize,
+    /// Precombined files timestamp (`PCMB`, low 16 bits)
+    pub precombine_timestamp: Option<u16>,
+    /// Previs files timestamp (`VISI`, low 16 bits)
+    pub previs_timestamp: Option<u16>,
+    /// Form ID of the cell whose previs file covers this cell (`RVIS`)
+    pub previs_cell: Option<u32>,
+}
+
+/// What a cell needs, derived from its [`CellInfo`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CellStatus {
+    /// Precombines and previs data, previs at least as new as the precombines
+    Current,
+    /// Previs data older than the precombines
+    StalePrevis,
+    /// Precombines without previs data
+    MissingPrevis,
+    /// Previs data without precombine references
+    PrevisOnly,
+    /// Neither precombine references nor previs data
+    Neither,
+}
+
+impl CellStatus {
+    /// Short description for reports
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Current => "current",
+            Self::StalePrevis => "stale previs",
+            Self::MissingPrevis => "missing previs",
+            Self::PrevisOnly => "previs only",
+            Self::Neither => "neither",
+        }
+    }
+
+    /// Whether the cell's previs data needs to be regenerated
+    pub fn needs_attention(self) -> bool {
+        matches!(self, Self::StalePrevis | Self::MissingPrevis)
+    }
+}
+
+impl CellInfo {
+    /// Whether the cell references precombined meshes (`XCRI`/`XPRI`)
+    pub fn has_precombines(&self) -> bool {
+        self.precombined_meshes > 0 || self.physics_refs > 0
+    }
+
+    /// Whether the cell carries previs data (`VISI`)
+    pub fn has_previs(&self) -> bool {
+        self.previs_timestamp.is_some()
+    }
+
+    /// The cell's status
+    pub fn status(&self) -> CellStatus {
+        match (self.has_precombines(), self.has_previs()) {
+            (true, true) => match (self.precombine_timestamp, self.previs_timestamp) {
+                (Some(precombined), Some(previs)) if previs < precombined => {
+                    CellStatus::StalePrevis
+                }
+                _ => CellStatus::Current,
+            },
+            (true, false) => CellStatus::MissingPrevis,
+            (false, true) => CellStatus::PrevisOnly,
+            (false, false) => CellStatus::Neither,
+        }
+    }
+
+    /// Human-readable location (e.g., `SanctuaryExt`, `Commonwealth (-3, 22)`)
+    pub fn location(&self) -> String {
+        match (&self.worldspace, self.grid) {
+            (Some(world), Some((x, y))) => format!("{world} ({x}, {y})"),
+            (Some(world), None) => world.clone(),
+            (None, _) => self.editor_id.clone().unwrap_or_default(),
+        }
+    }
+}
+
+/// Result of scanning a plugin
+#[derive(Debug, Clone, Default)]
+pub struct PluginScan {
+    /// Every cell record in the plugin, in file order
+    pub cells: Vec<CellInfo>,
+}
+
+impl PluginScan {
+    /// Number of cells with each status
+    pub fn counts(&self) -> HashMap<CellStatus, usize> {
+        let mut counts = HashMap::new();
+        for cell in &self.cells {
+            *counts.entry(cell.status()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// Number of exterior cells (cells inside a worldspace)
+    pub fn exterior_cells(&self) -> usize {
+        self.cells.iter().filter(|c| c.worldspace.is_some()).count()
+    }
+
+    /// One-line summary (e.g., `42 cells (30 exterior): 28 current, 3 stale previs, ...`)
+    pub fn summary(&self) -> String {
+        let counts = self.counts();
+        let mut parts: Vec<String> = [
+            CellStatus::Current,
+            CellStatus::StalePrevis,
+            CellStatus::MissingPrevis,
+            CellStatus::PrevisOnly,
+            CellStatus::Neither,
+        ]
+        .into_iter()
+        .filter_map(|status| {
+            counts
+                .get(&status)
+                .map(|count| format!("{count} {}", status.as_str()))
+        })
+        .collect();
+        if parts.is_empty() {
+            parts.push("nothing to report".to_string());
+        }
+
+        format!(
+            "{} cells ({} exterior): {}",
+            self.cells.len(),
+            self.exterior_cells(),
+            parts.join(", ")
+        )
+    }
+}
+
+/// Scan a plugin for cells and their precombine/previs state
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, is not a plugin, or contains a malformed
+/// or undecompressable record
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// # use anyhow::Result;
+/// # use generateprevisibines::plugin::scanner;
+///
+/// # fn example() -> Result<()> {
+/// let scan = scanner::scan(Path::new("C:\\Games\\Fallout4\\Data\\MyMod.esp"))?;
+/// println!("{}", scan.summary());
+/// for cell in scan.cells.iter().filter(|c| c.status().needs_attention()) {
+///     println!("{:08X} {}", cell.form_id, cell.location());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn scan(path: &Path) -> Result<PluginScan> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to open plugin: {}", path.display()))?;
+    let len = file.metadata()?.len();
+    scan_reader(BufReader::new(file), len)
+        .with_context(|| format!("Failed to scan plugin: {}", path.display()))
+}
+
+/// Scan plugin data from any seekable reader of `len` bytes
+fn scan_reader<R: Read + Seek>(mut reader: R, len: u64) -> Result<PluginScan> {
+    let mut scan = PluginScan::default();
+    let mut world_names: HashMap<u32, String> = HashMap::new();
+    // Open groups that may contain cells: (end offset, group type, label)
+    let mut groups: Vec<(u64, u32, u32)> = Vec::new();
+    let mut header = [0u8; RECORD_HEADER_SIZE];
+    let mut pos = 0u64;
+
+    while pos < len {
+        groups.retain(|&(end, _, _)| end > pos);
+
+        reader
+            .read_exact(&mut header)
+            .with_context(|| format!("Truncated record header at offset {pos}"))?;
+        let record = RecordHeader::parse(&header)?;
+        if pos == 0 && &record.record_type != b"TES4" {
+            bail!("Not a plugin (first record is {:?})", record.type_name());
+        }
+
+        if &record.record_type == b"GRUP" {
+            // For groups, the size field covers the header and the label is the form ID
+            let group_size = u64::from(record.data_size);
+            let group_type = read_u32(&header, 12);
+            let label = record.flags;
+            if group_size < GROUP_HEADER_SIZE as u64 {
+                bail!("Invalid group size {group_size} at offset {pos}");
+            }
+
+            let top_level = groups.is_empty();
+            let enter = if top_level {
+                matches!(&header[8..12], b"CELL" | b"WRLD")
+            } else {
+                group_type != GROUP_CELL_CHILDREN
+            };
+
+            if enter {
+                groups.push((pos + group_size, group_type, label));
+                pos += GROUP_HEADER_SIZE as u64;
+            } else {
+                pos += group_size;
+                reader.seek(SeekFrom::Start(pos))?;
+            }
+            continue;
+        }
+
+        let data_size = u64::from(record.data_size);
+        let next = pos + RECORD_HEADER_SIZE as u64 + data_size;
+        let wanted = !groups.is_empty() && matches!(&record.record_type, b"CELL" | b"WRLD");
+        if !wanted {
+            reader.seek(SeekFrom::Start(next))?;
+            pos = next;
+            continue;
+        }
+
+        let mut data = vec![0u8; usize::try_from(data_size)?];
+        reader
+            .read_exact(&mut data)
+            .with_context(|| format!("Truncated {} record at offset {pos}", record.type_name()))?;
+        if record.flags & FLAG_COMPRESSED != 0 {
+            data = decompress(&data).with_context(|| {
+                format!(
+                    "Failed to decompress {} record {:08X}",
+                    record.type_name(),
+                    record.form_id
+                )
+            })?;
+        }
+        pos = next;
+
+        if &record.record_type == b"WRLD" {
+            let name = Subrecords::new(&data)
+                .filter_map(Result::ok)
+                .find(|s| &s.subrecord_type == b"EDID")
+                .map(|s| zstring(s.data));
+            if let Some(name) = name {
+                world_names.insert(record.form_id, name);
+            }
+            continue;
+        }
+
+        let world_id = groups
+            .iter()
+            .rev()
+            .find(|&&(_, group_type, _)| group_type == GROUP_WORLD_CHILDREN)
+            .map(|&(_, _, world_id)| world_id);
+        let world = world_id.map(|world_id| {
+            world_names
+                .get(&world_id)
+                .cloned()
+                .unwrap_or_else(|| format!("{world_id:08X}"))
+        });
+        let mut cell = parse_cell(record.form_id, world, &data)?;
+        cell.worldspace_form_id = world_id;
+        scan.cells.push(cell);
+    }
+
+    Ok(scan)
+}
+
+/// Read the precombine/previs subrecords of a `CELL` record
+pub(super) fn parse_cell(
+    form_id: u32,
+    worldspace: Option<String>,
+    data: &[u8],
+) -> Result<CellInfo> {
+    let mut cell = CellInfo {
+        form_id,
+        worldspace,
+        ..CellInfo::default()
+    };
+
+    for subrecord in Subrecords::new(data) {
+        let subrecord = subrecord?;
+        let d = subrecord.data;
+        match &subrecord.subrecord_type {
+            b"EDID" => cell.editor_id = Some(zstring(d)).filter(|id| !id.is_empty()),
+            b"XCLC" if d.len() >= 8 => {
+                cell.grid = Some((
+                    i32::from_le_bytes([d[0], d[1], d[2], d[3]]),
+                    i32::from_le_bytes([d[4], d[5], d[6], d[7]]),
+                ));
+            }
+            b"XCRI" if d.len() >= 8 => {
+                cell.precombined_meshes = read_u32(d, 0);
+                cell.precombined_refs = read_u32(d, 4);
+                cell.precombined_mesh_hashes = d[8..]
+                    .chunks_exact(4)
+                    .take(cell.precombined_meshes as usize)
+                    .map(|hash| read_u32(hash, 0))
+                    .collect();
+            }
+            b"XPRI" => cell.physics_refs = d.len() / 4,
+            b"PCMB" => cell.precombine_timestamp = timestamp(d),
+            b"VISI" => cell.previs_timestamp = timestamp(d),
+            b"RVIS" if d.len() >= 4 => cell.previs_cell = Some(read_u32(d, 0)),
+            _ => {}
+        }
+    }
+
+    Ok(cell)
+}
+
+/// Read a timestamp subrecord (2 or 4 bytes, little-endian)
+///
+/// Only the low 16 bits hold the date; the Creation Kit leaves unrelated values in the
+/// upper bytes of 4-byte subrecords, so they are ignored.
+fn timestamp(data: &[u8]) -> Option<u16> {
+    match *data {
+        [a, b, ..] => Some(u16::from_le_bytes([a, b])),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::{Cursor, Write};
+
+    fn cell_data(edid: &str, extra: &[([u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"EDID", format!("{edid}\0").as_bytes());
+        for (subrecord_type, payload) in extra {
+            subrecord(&mut data, *subrecord_type, payload);
+        }
+        data
+    }
+
+    fn xcri(meshes: u32, refs: u32) -> Vec<u8> {
+        let mut data = meshes.to_le_bytes().to_vec();
+        data.extend_from_slice(&refs.to_le_bytes());
+        data
+    }
+
+    fn compressed_record(record_type: [u8; 4], form_id: u32, data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        let mut payload = u32::try_from(data.len()).unwrap().to_le_bytes().to_vec();
+        payload.extend(encoder.finish().unwrap());
+        record(record_type, FLAG_COMPRESSED, form_id, &payload)
+    }
+
+    fn test_plugin() -> Vec<u8> {
+        // Interior cell with current previs, plus a reference that must be skipped. The
+        // upper bytes of PCMB/VISI differ and would make the previs look stale.
+        let mut interior = record(
+            *b"CELL",
+            0,
+            0x0100_0800,
+            &cell_data(
+                "MyInterior",
+                &[
+                    (*b"XCRI", xcri(3, 12)),
+                    (*b"PCMB", 0xABCD_0064u32.to_le_bytes().to_vec()),
+                    (*b"VISI", 0x0001_0078u32.to_le_bytes().to_vec()),
+                ],
+            ),
+        );
+        interior.extend(group(
+            0x0100_0800u32.to_le_bytes(),
+            GROUP_CELL_CHILDREN,
+            &group(
+                0x0100_0800u32.to_le_bytes(),
+                9,
+                &record(*b"CELL", 0, 0xDEAD, b"not a real cell"),
+            ),
+        ));
+        let interior_group = group(*b"CELL", 0, &group([0; 4], 2, &group([0; 4], 3, &interior)));
+
+        // Worldspace with a compressed stale cell and a cell without any data
+        let mut cells = compressed_record(
+            *b"CELL",
+            0x0000_E2F3,
+            &cell_data(
+                "",
+                &[
+                    (
+                        *b"XCLC",
+                        [(-3i32).to_le_bytes(), 22i32.to_le_bytes()].concat(),
+                    ),
+                    (*b"XPRI", vec![0; 8]),
+                    (*b"PCMB", 200u32.to_le_bytes().to_vec()),
+                    (*b"VISI", 150u32.to_le_bytes().to_vec()),
+                    (*b"RVIS", 0x0000_E2F4u32.to_le_bytes().to_vec()),
+                ],
+            ),
+        );
+        cells.extend(record(*b"CELL", 0, 0x0000_E2F4, &cell_data("", &[])));
+        let mut world = record(*b"WRLD", 0, 0x0000_003C, &cell_data("Commonwealth", &[]));
+        world.extend(group(
+            0x0000_003Cu32.to_le_bytes(),
+            GROUP_WORLD_CHILDREN,
+            &group([0; 4], 4, &group([0; 4], 5, &cells)),
+        ));
+
+        let mut bytes = tes4(0, "", &["Fallout4.esm"]);
+        bytes.extend(group(*b"STAT", 0, &record(*b"STAT", 0, 0x0100_0801, b"")));
+        bytes.extend(interior_group);
+        bytes.extend(group(*b"WRLD", 0, &world));
+        bytes
+    }
+
+    #[test]
+    fn test_scan_cells() {
+        let bytes = test_plugin();
+        let len = bytes.len() as u64;
+        let scan = scan_reader(Cursor::new(bytes), len).unwrap();
+
+        assert_eq!(scan.cells.len(), 3);
+
+        let interior = &scan.cells[0];
+        assert_eq!(interior.editor_id.as_deref(), Some("MyInterior"));
+        assert_eq!(interior.worldspace, None);
+        assert_eq!(interior.precombined_meshes, 3);
+        assert_eq!(interior.precombined_refs, 12);
+        assert_eq!(interior.precombine_timestamp, Some(100));
+        assert_eq!(interior.previs_timestamp, Some(120));
+        assert_eq!(interior.status(), CellStatus::Current);
+
+        let stale = &scan.cells[1];
+        assert_eq!(stale.form_id, 0x0000_E2F3);
+        assert_eq!(stale.location(), "Commonwealth (-3, 22)");
+        assert_eq!(stale.worldspace_form_id, Some(0x0000_003C));
+        assert_eq!(stale.physics_refs, 2);
+        assert_eq!(stale.previs_cell, Some(0x0000_E2F4));
+        assert_eq!(stale.status(), CellStatus::StalePrevis);
+
+        assert_eq!(scan.cells[2].status(), CellStatus::Neither);
+        assert_eq!(scan.exterior_cells(), 2);
+        assert_eq!(
+            scan.summary(),
+            "3 cells (2 exterior): 1 current, 1 stale previs, 1 neither"
+        );
+    }
+
+    #[test]
+    fn test_timestamp_ignores_upper_bytes() {
+        assert_eq!(timestamp(&[0x64, 0x00]), Some(100));
+        assert_eq!(timestamp(&[0x64, 0x00, 0xCD, 0xAB]), Some(100));
+        assert_eq!(timestamp(&[0x64]), None);
+    }
+
+    #[test]
+    fn test_cell_status() {
+        let mut cell = CellInfo {
+            precombined_meshes: 1,
+            ..CellInfo::default()
+        };
+        assert_eq!(cell.status(), CellStatus::MissingPrevis);
+        assert!(cell.status().needs_attention());
+
+        cell.previs_timestamp = Some(5);
+        assert_eq!(cell.status(), CellStatus::Current);
+
+        cell.precombine_timestamp = Some(6);
+        assert_eq!(cell.status(), CellStatus::StalePrevis);
+        cell.precombine_timestamp = None;
+
+        cell.precombined_meshes = 0;
+        assert_eq!(cell.status(), CellStatus::PrevisOnly);
+        assert!(!cell.status().needs_attention());
+    }
+
+    #[test]
+    fn test_scan_rejects_non_plugin() {
+        let bytes = record(*b"CELL", 0, 1, b"");
+        let len = bytes.len() as u64;
+        assert!(scan_reader(Cursor::new(bytes), len).is_err());
+    }
+
+    #[test]
+    fn test_scan_reports_corrupt_compressed_record() {
+        let mut bytes = tes4(0, "", &[]);
+        bytes.extend(group(
+            *b"CELL",
+            0,
+            &record(*b"CELL", FLAG_COMPRESSED, 0x800, &[16, 0, 0, 0, 1, 2, 3]),
+        ));
+        let len = bytes.len() as u64;
+        assert!(scan_reader(Cursor::new(bytes), len).is_err());
+    }
+}
@@ -0,0 +1,550 @@
+//! Seed plugins for rebuilding precombines and previs of an area
+//!
+//! The Creation Kit generates precombines and previs for the cells the loaded plugin
+//! overrides. To rebuild an area of the base game or a DLC, a plugin that overrides just
+//! those cells is needed: the "seed" the workflow starts from (the `xPrevisPatch` plugins
+//! shared between mod authors are such seeds). [`create_seed`] extracts the chosen cells
+//! from a master into a new plugin.
+//!
+//! Cells are chosen with [`CellSelector`]s:
+//!
+//! | Selector | Chooses |
+//! |----------|---------|
+//! | `0001F4A2` | The cell with this form ID, as stored in the source (see below) |
+//! | `DLCCoast.esm:000800` | The cell with this object ID defined by the named plugin |
+//! | `SanctuaryHillsExt` | The cell with this editor ID |
+//! | `Commonwealth:-3,22` | The exterior cell at this grid position |
+//! | `Commonwealth:-5,20:0,25` | Every exterior cell in this grid rectangle |
+//!
+//! A plain form ID is read the way the source stores it, not by load order: the first two
+//! digits are an index into the source's master list, with the source itself last (so
+//! `00` is `Fallout4.esm` itself or the first master of a DLC). Naming the plugin instead
+//! avoids counting masters.
+//!
+//! The seed lists the source's masters followed by the source itself, so every form ID in
+//! the copied records keeps its value. Exterior cells are copied with their worldspace.
+//! References are not copied; the Creation Kit only needs the cell overrides.
+
+use anyhow::{Result, bail};
+use log::info;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+use super::document::{PluginFile, Record};
+use super::merge::source_cells;
+use super::record::zstring;
+
+/// Author written to the header of seed plugins
+const SEED_AUTHOR: &str = "GeneratePrevisibines";
+
+/// First object ID a new plugin assigns (`HEDR`)
+const FIRST_OBJECT_ID: u32 = 0x800;
+
+/// A way of choosing cells for a seed plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CellSelector {
+    /// A cell by form ID as stored in the source: the top byte indexes the source's
+    /// masters followed by the source itself
+    FormId(u32),
+    /// A cell by the plugin that defines it and its object ID (lower 24 bits)
+    PluginFormId { plugin: String, object_id: u32 },
+    /// A cell by editor ID (case-insensitive)
+    EditorId(String),
+    /// Exterior cells of a worldspace (by editor ID) in a grid rectangle, inclusive
+    Region {
+        worldspace: String,
+        min: (i32, i32),
+        max: (i32, i32),
+    },
+}
+
+impl CellSelector {
+    /// Whether a cell matches this selector
+    ///
+    /// `files` is the source's master list followed by the source itself, which is what
+    /// the top byte of `form_id` indexes.
+    fn matches(
+        &self,
+        files: &[String],
+        form_id: u32,
+        editor_id: Option<&str>,
+        worldspace: Option<&str>,
+        grid: Option<(i32, i32)>,
+    ) -> bool {
+        match self {
+            Self::FormId(id) => form_id == *id,
+            Self::PluginFormId { plugin, object_id } => {
+                let index = usize::try_from(form_id >> 24).unwrap_or(usize::MAX);
+                form_id & 0x00FF_FFFF == *object_id
+                    && files
+                        .get(index)
+                        .is_some_and(|file| file.eq_ignore_ascii_case(plugin))
+            }
+            Self::EditorId(name) => editor_id.is_some_and(|id| id.eq_ignore_ascii_case(name)),
+            Self::Region {
+                worldspace: wanted,
+                min,
+                max,
+            } => {
+                worldspace.is_some_and(|world| world.eq_ignore_ascii_case(wanted))
+                    && grid.is_some_and(|(x, y)| {
+                        (min.0..=max.0).contains(&x) && (min.1..=max.1).contains(&y)
+                    })
+            }
+        }
+    }
+}
+
+impl FromStr for CellSelector {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if s.is_empty() {
+            bail!("Empty cell selector");
+        }
+
+        if let Some((plugin, object_id)) = s.split_once(':')
+            && is_plugin_name(plugin)
+        {
+            let Ok(object_id) = u32::from_str_radix(object_id.trim(), 16) else {
+                bail!("Invalid object ID in '{s}': expected hex digits after the plugin name");
+            };
+            return Ok(Self::PluginFormId {
+                plugin: plugin.trim().to_string(),
+                object_id: object_id & 0x00FF_FFFF,
+            });
+        }
+
+        if let Some((worldspace, grid)) = s.split_once(':') {
+            let (first, second) = grid.split_once(':').unwrap_or((grid, grid));
+            let (a, b) = (parse_grid(first)?, parse_grid(second)?);
+            return Ok(Self::Region {
+                worldspace: worldspace.trim().to_string(),
+                min: (a.0.min(b.0), a.1.min(b.1)),
+                max: (a.0.max(b.0), a.1.max(b.1)),
+            });
+        }
+
+        let hex = s
+            .strip_prefix("0x")
+            .or_else(|| s.strip_prefix("0X"))
+            .unwrap_or(s);
+        if hex.len() == 8
+            && let Ok(form_id) = u32::from_str_radix(hex, 16)
+        {
+            return Ok(Self::FormId(form_id));
+        }
+        Ok(Self::EditorId(s.to_string()))
+    }
+}
+
+impl fmt::Display for CellSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::FormId(form_id) => write!(f, "{form_id:08X}"),
+            Self::PluginFormId { plugin, object_id } => write!(f, "{plugin}:{object_id:06X}"),
+            Self::EditorId(name) => f.write_str(name),
+            Self::Region {
+                worldspace,
+                min,
+                max,
+            } if min == max => write!(f, "{worldspace}:{},{}", min.0, min.1),
+            Self::Region {
+                worldspace,
+                min,
+                max,
+            } => write!(f, "{worldspace}:{},{}:{},{}", min.0, min.1, max.0, max.1),
+        }
+    }
+}
+
+/// Whether a selector prefix names a plugin rather than a worldspace
+fn is_plugin_name(s: &str) -> bool {
+    let s = s.trim().to_ascii_lowercase();
+    [".esm", ".esp", ".esl"].iter().any(|ext| s.ends_with(ext))
+}
+
+/// Parse a grid position (`x,y`)
+fn parse_grid(s: &str) -> Result<(i32, i32)> {
+    let Some((x, y)) = s.split_once(',') else {
+        bail!("Invalid grid position {s:?} (expected x,y)");
+    };
+    match (x.trim().parse(), y.trim().parse()) {
+        (Ok(x), Ok(y)) => Ok((x, y)),
+        _ => bail!("Invalid grid position {s:?} (expected x,y)"),
+    }
+}
+
+/// What [`create_seed`] wrote
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    /// Interior cells copied
+    pub interior_cells: usize,
+    /// Exterior cells copied
+    pub exterior_cells: usize,
+    /// Worldspaces copied for the exterior cells
+    pub worldspaces: usize,
+    /// Masters of the seed plugin
+    pub masters: Vec<String>,
+}
+
+impl fmt::Display for SeedReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} interior cells, {} exterior cells in {} worldspaces; masters: {}",
+            self.interior_cells,
+            self.exterior_cells,
+            self.worldspaces,
+            self.masters.join(", ")
+        )
+    }
+}
+
+/// Write a new plugin that overrides the cells of `source_path` chosen by `selectors`
+///
+/// # Errors
+///
+/// Returns an error if `output_path` already exists, if the source cannot be read or
+/// parsed, if a selector matches no cell, or if the seed cannot be written
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use generateprevisibines::plugin::seed::{self, CellSelector};
+///
+/// let selectors = ["Commonwealth:-5,20:0,25".parse::<CellSelector>()?];
+/// let report = seed::create_seed(
+///     Path::new("C:\\Games\\Fallout4\\Data\\Fallout4.esm"),
+///     Path::new("C:\\Games\\Fallout4\\Data\\SanctuarySeed.esp"),
+///     &selectors,
+/// )?;
+/// println!("{report}");
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub fn create_seed(
+    source_path: &Path,
+    output_path: &Path,
+    selectors: &[CellSelector],
+) -> Result<SeedReport> {
+    if selectors.is_empty() {
+        bail!("No cells selected");
+    }
+    if output_path.exists() {
+        bail!(
+            "{} already exists; choose another name or delete it first",
+            output_path.display()
+        );
+    }
+    let Some(source_name) = source_path.file_name().and_then(|name| name.to_str()) else {
+        bail!("Invalid plugin path: {}", source_path.display());
+    };
+
+    let source = PluginFile::read(source_path)?;
+    let mut masters = source.plugin_header()?.masters;
+    masters.push(source_name.to_string());
+
+    let mut seed = PluginFile {
+        header: seed_header(&masters),
+        groups: Vec::new(),
+    };
+    let mut report = SeedReport {
+        masters,
+        ..SeedReport::default()
+    };
+    for selector in selectors {
+        if let CellSelector::PluginFormId { plugin, .. } = selector
+            && !report
+                .masters
+                .iter()
+                .any(|file| file.eq_ignore_ascii_case(plugin))
+        {
+            bail!("{plugin} is neither {source_name} nor one of its masters ({selector})");
+        }
+    }
+    let mut matched = vec![false; selectors.len()];
+    let mut worldspaces = HashSet::new();
+
+    for cell in source_cells(&source) {
+        let subrecords = cell.record.subrecords()?;
+        let editor_id = find_editor_id(&subrecords);
+        let world_name = match cell.world {
+            Some(world) => find_editor_id(&world.subrecords()?),
+            None => None,
+        };
+        let grid = subrecords
+            .iter()
+            .find(|(t, data)| t == b"XCLC" && data.len() >= 8)
+            .map(|(_, data)| {
+                (
+                    i32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+                    i32::from_le_bytes([data[4], data[5], data[6], data[7]]),
+                )
+            });
+
+        let mut selected = false;
+        for (selector, matched) in selectors.iter().zip(&mut matched) {
+            if selector.matches(
+                &report.masters,
+                cell.record.form_id(),
+                editor_id.as_deref(),
+                world_name.as_deref(),
+                grid,
+            ) {
+                *matched = true;
+                selected = true;
+            }
+        }
+        if !selected {
+            continue;
+        }
+
+        let world_id = cell.world.map(Record::form_id);
+        if let Some(world) = cell.world
+            && worldspaces.insert(world.form_id())
+        {
+            seed.insert_worldspace(world.clone());
+        }
+        seed.insert_cell(cell.record.clone(), world_id, grid)?;
+        if world_id.is_some() {
+            report.exterior_cells += 1;
+        } else {
+            report.interior_cells += 1;
+        }
+    }
+
+    let unmatched: Vec<String> = selectors
+        .iter()
+        .zip(&matched)
+        .filter(|(_, matched)| !**matched)
+        .map(|(selector, _)| selector.to_string())
+        .collect();
+    if !unmatched.is_empty() {
+        bail!("No cell in {source_name} matches: {}", unmatched.join(", "));
+    }
+
+    report.worldspaces = worldspaces.len();
+    seed.write(output_path)?;
+    info!(
+        "Created seed plugin {} from {source_name}: {report}",
+        output_path.display()
+    );
+    Ok(report)
+}
+
+/// `TES4` record of a new plugin with the given masters
+fn seed_header(masters: &[String]) -> Record {
+    let mut hedr = 1.0f32.to_le_bytes().to_vec();
+    hedr.extend_from_slice(&0u32.to_le_bytes());
+    hedr.extend_from_slice(&FIRST_OBJECT_ID.to_le_bytes());
+
+    let mut subrecords = vec![
+        (*b"HEDR", hedr),
+        (*b"CNAM", format!("{SEED_AUTHOR}\0").into_bytes()),
+    ];
+    for master in masters {
+        subrecords.push((*b"MAST", format!("{master}\0").into_bytes()));
+        subrecords.push((*b"DATA", vec![0; 8]));
+    }
+    Record::new(*b"TES4", 0, &subrecords)
+}
+
+/// Editor ID (`EDID`) among a record's subrecords
+fn find_editor_id(subrecords: &[([u8; 4], Vec<u8>)]) -> Option<String> {
+    subrecords
+        .iter()
+        .find(|(t, _)| t == b"EDID")
+        .map(|(_, data)| zstring(data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::record::{
+        GROUP_EXTERIOR_BLOCK, GROUP_EXTERIOR_SUB_BLOCK, GROUP_INTERIOR_BLOCK,
+        GROUP_INTERIOR_SUB_BLOCK, GROUP_TOP, GROUP_WORLD_CHILDREN,
+    };
+    use crate::plugin::test_util::{group, record, subrecord, tes4};
+    use crate::plugin::{PluginHeader, scanner};
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn exterior(form_id: u32, x: i32, y: i32) -> Vec<u8> {
+        let mut xclc = x.to_le_bytes().to_vec();
+        xclc.extend_from_slice(&y.to_le_bytes());
+        xclc.extend_from_slice(&[0; 4]);
+        let mut data = Vec::new();
+        subrecord(&mut data, *b"XCLC", &xclc);
+        record(*b"CELL", 0, form_id, &data)
+    }
+
+    fn source() -> Vec<u8> {
+        let mut world = Vec::new();
+        subrecord(&mut world, *b"EDID", b"Commonwealth\0");
+        let mut interior = Vec::new();
+        subrecord(&mut interior, *b"EDID", b"Vault111\0");
+
+        let mut cells = exterior(0x0000_E2F3, -3, 22);
+        cells.extend(exterior(0x0000_E2F4, -3, 23));
+        cells.extend(exterior(0x0000_E300, 10, 10));
+        let mut bytes = tes4(1, "Bethesda", &[]);
+        bytes.extend(group(
+            *b"CELL",
+            GROUP_TOP,
+            &group(
+                1i32.to_le_bytes(),
+                GROUP_INTERIOR_BLOCK,
+                &group(
+                    0i32.to_le_bytes(),
+                    GROUP_INTERIOR_SUB_BLOCK,
+                    &record(*b"CELL", 0, 0x0000_1F21, &interior),
+                ),
+            ),
+        ));
+        let mut worldspace = record(*b"WRLD", 0, 0x0000_003C, &world);
+        worldspace.extend(group(
+            0x3Cu32.to_le_bytes(),
+            GROUP_WORLD_CHILDREN,
+            &group(
+                [0; 4],
+                GROUP_EXTERIOR_BLOCK,
+                &group([0; 4], GROUP_EXTERIOR_SUB_BLOCK, &cells),
+            ),
+        ));
+        bytes.extend(group(*b"WRLD", GROUP_TOP, &worldspace));
+        bytes
+    }
+
+    #[test]
+    fn test_parse_selectors() {
+        assert_eq!(
+            "0001F4A2".parse::<CellSelector>().unwrap(),
+            CellSelector::FormId(0x0001_F4A2)
+        );
+        assert_eq!(
+            "Vault111".parse::<CellSelector>().unwrap(),
+            CellSelector::EditorId("Vault111".to_string())
+        );
+        let region = "Commonwealth:0,25:-5,20".parse::<CellSelector>().unwrap();
+        assert_eq!(
+            region,
+            CellSelector::Region {
+                worldspace: "Commonwealth".to_string(),
+                min: (-5, 20),
+                max: (0, 25),
+            }
+        );
+        assert_eq!(region.to_string(), "Commonwealth:-5,20:0,25");
+        assert_eq!(
+            "Commonwealth:-3,22"
+                .parse::<CellSelector>()
+                .unwrap()
+                .to_string(),
+            "Commonwealth:-3,22"
+        );
+        assert!("Commonwealth:-3".parse::<CellSelector>().is_err());
+
+        let named = "DLCCoast.esm:01000800".parse::<CellSelector>().unwrap();
+        assert_eq!(
+            named,
+            CellSelector::PluginFormId {
+                plugin: "DLCCoast.esm".to_string(),
+                object_id: 0x800,
+            }
+        );
+        assert_eq!(named.to_string(), "DLCCoast.esm:000800");
+        assert!("DLCCoast.esm:xyz".parse::<CellSelector>().is_err());
+    }
+
+    #[test]
+    fn test_form_id_selectors_resolve_masters() {
+        let temp = TempDir::new().unwrap();
+        let source_path = temp.path().join("DLCCoast.esm");
+
+        // An override of a Fallout4.esm cell and the DLC's own cell share object ID 0x800
+        let mut cells = record(*b"CELL", 0, 0x0000_0800, b"");
+        cells.extend(record(*b"CELL", 0, 0x0100_0800, b""));
+        let mut bytes = tes4(1, "Bethesda", &["Fallout4.esm"]);
+        bytes.extend(group(
+            *b"CELL",
+            GROUP_TOP,
+            &group(
+                0i32.to_le_bytes(),
+                GROUP_INTERIOR_BLOCK,
+                &group(0i32.to_le_bytes(), GROUP_INTERIOR_SUB_BLOCK, &cells),
+            ),
+        ));
+        fs::write(&source_path, bytes).unwrap();
+
+        let seeded = |name: &str, selector: &str| {
+            let seed_path = temp.path().join(name);
+            create_seed(&source_path, &seed_path, &[selector.parse().unwrap()])?;
+            Ok::<_, anyhow::Error>(
+                scanner::scan(&seed_path)
+                    .unwrap()
+                    .cells
+                    .iter()
+                    .map(|cell| cell.form_id)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        assert_eq!(seeded("A.esp", "01000800").unwrap(), [0x0100_0800]);
+        assert_eq!(seeded("B.esp", "00000800").unwrap(), [0x0000_0800]);
+        assert_eq!(
+            seeded("C.esp", "DLCCoast.esm:000800").unwrap(),
+            [0x0100_0800]
+        );
+        assert_eq!(
+            seeded("D.esp", "fallout4.esm:000800").unwrap(),
+            [0x0000_0800]
+        );
+        assert!(seeded("E.esp", "DLCRobot.esm:000800").is_err());
+    }
+
+    #[test]
+    fn test_create_seed() {
+        let temp = TempDir::new().unwrap();
+        let source_path = temp.path().join("Fallout4.esm");
+        fs::write(&source_path, source()).unwrap();
+        let seed_path = temp.path().join("Seed.esp");
+
+        let selectors = [
+            "commonwealth:-5,20:0,25".parse().unwrap(),
+            "vault111".parse().unwrap(),
+        ];
+        let report = create_seed(&source_path, &seed_path, &selectors).unwrap();
+        assert_eq!(report.interior_cells, 1);
+        assert_eq!(report.exterior_cells, 2);
+        assert_eq!(report.worldspaces, 1);
+
+        let header = PluginHeader::read(&seed_path).unwrap();
+        assert_eq!(header.masters, ["Fallout4.esm"]);
+        assert!(!header.is_master());
+        let cells: Vec<(u32, String)> = scanner::scan(&seed_path)
+            .unwrap()
+            .cells
+            .iter()
+            .map(|cell| (cell.form_id, cell.location()))
+            .collect();
+        assert_eq!(
+            cells,
+            [
+                (0x0000_1F21, "Vault111".to_string()),
+                (0x0000_E2F3, "Commonwealth (-3, 22)".to_string()),
+                (0x0000_E2F4, "Commonwealth (-3, 23)".to_string()),
+            ]
+        );
+
+        // Existing output and selectors without a match are errors
+        assert!(create_seed(&source_path, &seed_path, &selectors).is_err());
+        let missing = ["Commonwealth:50,50".parse().unwrap()];
+        let error = create_seed(&source_path, &temp.path().join("Other.esp"), &missing)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Commonwealth:50,50"), "{error}");
+    }
+}
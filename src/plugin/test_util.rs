@@ -0,0 +1,47 @@
+//! Builders for plugin data in tests
+
+/// Append a subrecord to `out`
+pub fn subrecord(out: &mut Vec<u8>, subrecord_type: [u8; 4], data: &[u8]) {
+    out.extend_from_slice(&subrecord_type);
+    out.extend_from_slice(&u16::try_from(data.len()).unwrap().to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// Build a record (header and data)
+pub fn record(record_type: [u8; 4], flags: u32, form_id: u32, data: &[u8]) -> Vec<u8> {
+    let mut out = record_type.to_vec();
+    out.extend_from_slice(&u32::try_from(data.len()).unwrap().to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&form_id.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&131u16.to_le_bytes());
+    out.extend_from_slice(&[0; 2]);
+    out.extend_from_slice(data);
+    out
+}
+
+/// Build a group (`GRUP`) around already-built records and groups
+pub fn group(label: [u8; 4], group_type: u32, contents: &[u8]) -> Vec<u8> {
+    let mut out = b"GRUP".to_vec();
+    out.extend_from_slice(&u32::try_from(contents.len() + 24).unwrap().to_le_bytes());
+    out.extend_from_slice(&label);
+    out.extend_from_slice(&group_type.to_le_bytes());
+    out.extend_from_slice(&[0; 8]);
+    out.extend_from_slice(contents);
+    out
+}
+
+/// Build a `TES4` header record (42 records, next object ID 0x801)
+pub fn tes4(flags: u32, author: &str, masters: &[&str]) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut hedr = 1.0f32.to_le_bytes().to_vec();
+    hedr.extend_from_slice(&42u32.to_le_bytes());
+    hedr.extend_from_slice(&0x801u32.to_le_bytes());
+    subrecord(&mut data, *b"HEDR", &hedr);
+    subrecord(&mut data, *b"CNAM", format!("{author}\0").as_bytes());
+    for master in masters {
+        subrecord(&mut data, *b"MAST", format!("{master}\0").as_bytes());
+        subrecord(&mut data, *b"DATA", &[0; 8]);
+    }
+    record(*b"TES4", flags, 0, &data)
+}
@@ -0,0 +1,291 @@
+//! Precombine integrity check
+//!
+//! Each cell with precombines lists the hashes of its precombined meshes in `XCRI`, and
+//! the Creation Kit writes one file per mesh, named after the cell and the hash:
+//! `meshes\precombined\0001F4A2_2A6B3C5D_OC.nif`. The cell part is the cell's form ID
+//! as loaded in the Creation Kit ([`LoadOrderFormIds`]), not as stored in the plugin.
+//! If the Creation Kit crashes part way through step 1, the plugin can end up pointing
+//! at meshes that were never written, which shows up in game as invisible buildings.
+//!
+//! [`check`] cross-references the two and reports:
+//!
+//! - **Missing meshes**: referenced by a cell but not on disk (or in the archive); an error
+//! - **Orphaned meshes**: on disk but referenced by no cell; wasted space, only a warning
+//!
+//! The workflow runs the check after step 2, and the `check-precombines` command runs it
+//! on demand against loose files and/or an archive.
+
+use anyhow::Result;
+use log::{info, warn};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+use crate::plugin::load_order::LoadOrderFormIds;
+use crate::plugin::scanner::PluginScan;
+use crate::tools::ba2_reader::Ba2Archive;
+
+/// Number of missing or orphaned meshes listed in log messages
+const REPORT_FILES: usize = 10;
+
+/// A mesh a cell references that does not exist
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingMesh {
+    /// Form ID of the cell, as stored in the plugin
+    pub cell: u32,
+    /// Cell description (editor ID, worldspace and grid), for messages
+    pub location: String,
+    /// Expected file name (e.g., `0001F4A2_2A6B3C5D_OC.nif`)
+    pub file: String,
+}
+
+/// Result of [`check`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// Number of cells with precombined meshes
+    pub cells: usize,
+    /// Number of meshes referenced by the plugin
+    pub referenced: usize,
+    /// Referenced meshes that do not exist
+    pub missing: Vec<MissingMesh>,
+    /// Mesh files no cell references (file names, sorted)
+    pub orphaned: Vec<String>,
+}
+
+impl IntegrityReport {
+    /// Whether every referenced mesh exists
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty()
+    }
+
+    /// One-line summary (e.g., `1520 meshes in 212 cells, 0 missing, 3 orphaned`)
+    pub fn summary(&self) -> String {
+        format!(
+            "{} meshes in {} cells, {} missing, {} orphaned",
+            self.referenced,
+            self.cells,
+            self.missing.len(),
+            self.orphaned.len()
+        )
+    }
+
+    /// Log the summary and the first missing and orphaned meshes
+    pub fn log(&self) {
+        if self.is_ok() {
+            info!("Precombine integrity: {}", self.summary());
+        } else {
+            warn!("Precombine integrity: {}", self.summary());
+        }
+        for missing in self.missing.iter().take(REPORT_FILES) {
+            warn!("  Missing: {} ({})", missing.file, missing.location);
+        }
+        if self.missing.len() > REPORT_FILES {
+            warn!(
+                "  ... and {} more missing",
+                self.missing.len() - REPORT_FILES
+            );
+        }
+        for orphan in self.orphaned.iter().take(REPORT_FILES) {
+            warn!("  Orphaned: {orphan}");
+        }
+        if self.orphaned.len() > REPORT_FILES {
+            warn!(
+                "  ... and {} more orphaned",
+                self.orphaned.len() - REPORT_FILES
+            );
+        }
+    }
+}
+
+/// File name of a precombined mesh, given the cell's load-order form ID
+pub fn mesh_file_name(cell_form_id: u32, mesh_hash: u32) -> String {
+    format!("{cell_form_id:08X}_{mesh_hash:08X}_OC.nif")
+}
+
+/// Cross-reference the meshes a plugin's cells reference with the mesh files that exist
+///
+/// `files` are file names (without directories) of the existing meshes; case is ignored.
+/// `ids` numbers the plugin's cells as the Creation Kit did when it named the meshes.
+pub fn check(
+    scan: &PluginScan,
+    ids: &LoadOrderFormIds,
+    files: &HashSet<String>,
+) -> IntegrityReport {
+    let existing: HashSet<String> = files.iter().map(|f| f.to_ascii_lowercase()).collect();
+    let mut referenced = HashSet::new();
+    let mut report = IntegrityReport::default();
+
+    for cell in &scan.cells {
+        if cell.precombined_mesh_hashes.is_empty() {
+            continue;
+        }
+        report.cells += 1;
+
+        let cell_id = ids.resolve(cell.form_id);
+        for &hash in &cell.precombined_mesh_hashes {
+            let file = mesh_file_name(cell_id, hash);
+            if !referenced.insert(file.to_ascii_lowercase()) {
+                continue;
+            }
+            report.referenced += 1;
+            if !existing.contains(&file.to_ascii_lowercase()) {
+                report.missing.push(MissingMesh {
+                    cell: cell.form_id,
+                    location: cell.location(),
+                    file,
+                });
+            }
+        }
+    }
+
+    report.orphaned = files
+        .iter()
+        .filter(|f| !referenced.contains(&f.to_ascii_lowercase()))
+        .cloned()
+        .collect();
+    report.orphaned.sort();
+    report
+}
+
+/// File names of the loose meshes in `<data_dir>\meshes\precombined` for each directory
+///
+/// Directories without a `meshes\precombined` folder are skipped.
+///
+/// # Errors
+///
+/// Returns an error if a folder exists but cannot be read
+pub fn loose_meshes(data_dirs: &[&Path]) -> Result<HashSet<String>> {
+    let mut files = HashSet::new();
+    for dir in data_dirs {
+        let precombined = dir.join("meshes").join("precombined");
+        if !precombined.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&precombined)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_file() && is_mesh(&name) {
+                files.insert(name);
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// File names of the meshes under `meshes\precombined` in an archive
+pub fn archive_meshes(archive: &Ba2Archive) -> HashSet<String> {
+    archive
+        .entries()
+        .iter()
+        .filter_map(|entry| {
+            let (dir, name) = entry.name.rsplit_once(['\\', '/'])?;
+            (dir.replace('/', "\\")
+                .eq_ignore_ascii_case("meshes\\precombined")
+                && is_mesh(name))
+            .then(|| name.to_string())
+        })
+        .collect()
+}
+
+fn is_mesh(name: &str) -> bool {
+    Path::new(name)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("nif"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::scanner::CellInfo;
+    use tempfile::TempDir;
+
+    fn scan() -> PluginScan {
+        PluginScan {
+            cells: vec![
+                CellInfo {
+                    form_id: 0x0001_F4A2,
+                    editor_id: Some("Sanctuary".to_string()),
+                    precombined_mesh_hashes: vec![0x2A6B_3C5D, 0x0000_00FF],
+                    ..CellInfo::default()
+                },
+                CellInfo {
+                    form_id: 0x0100_0800,
+                    ..CellInfo::default()
+                },
+            ],
+        }
+    }
+
+    /// `Target.esp` with `Fallout4.esm` as its only master
+    fn ids(light: bool) -> LoadOrderFormIds {
+        LoadOrderFormIds::new(
+            &[
+                ("Fallout4.esm".to_string(), false),
+                ("Target.esp".to_string(), light),
+            ],
+            &["Fallout4.esm".to_string()],
+        )
+    }
+
+    #[test]
+    fn test_mesh_file_name() {
+        assert_eq!(
+            mesh_file_name(0x0001_F4A2, 0x2A6B_3C5D),
+            "0001F4A2_2A6B3C5D_OC.nif"
+        );
+    }
+
+    #[test]
+    fn test_check_reports_missing_and_orphaned() {
+        let files = HashSet::from([
+            "0001f4a2_2a6b3c5d_oc.nif".to_string(),
+            "0001F4A2_DEADBEEF_OC.nif".to_string(),
+        ]);
+
+        let report = check(&scan(), &ids(false), &files);
+        assert_eq!(report.cells, 1);
+        assert_eq!(report.referenced, 2);
+        assert!(!report.is_ok());
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].file, "0001F4A2_000000FF_OC.nif");
+        assert_eq!(report.orphaned, ["0001F4A2_DEADBEEF_OC.nif"]);
+        assert_eq!(
+            report.summary(),
+            "2 meshes in 1 cells, 1 missing, 1 orphaned"
+        );
+    }
+
+    #[test]
+    fn test_loose_meshes_reads_every_data_dir() {
+        let data = TempDir::new().unwrap();
+        let overwrite = TempDir::new().unwrap();
+        let precombined = data.path().join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("0001F4A2_2A6B3C5D_OC.nif"), b"").unwrap();
+        fs::write(precombined.join("readme.txt"), b"").unwrap();
+        let precombined = overwrite.path().join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("0001F4A2_000000FF_OC.nif"), b"").unwrap();
+
+        let files = loose_meshes(&[data.path(), overwrite.path()]).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(check(&scan(), &ids(false), &files).is_ok());
+    }
+
+    #[test]
+    fn test_check_uses_load_order_form_ids() {
+        let scan = PluginScan {
+            cells: vec![CellInfo {
+                form_id: 0x0100_0801,
+                precombined_mesh_hashes: vec![0x2A6B_3C5D],
+                ..CellInfo::default()
+            }],
+        };
+        let files = HashSet::from(["FE000801_2A6B3C5D_OC.nif".to_string()]);
+
+        assert!(check(&scan, &ids(true), &files).is_ok());
+        let report = check(&scan, &ids(false), &files);
+        assert_eq!(report.missing[0].file, "01000801_2A6B3C5D_OC.nif");
+        assert_eq!(report.missing[0].cell, 0x0100_0801);
+    }
+}
@@ -0,0 +1,302 @@
+//! Previs coverage check
+//!
+//! The Creation Kit writes the previs data for a block of exterior cells to one file,
+//! named after the form ID of the cell the block is stored on: `vis\0001F4A2.uvd`.
+//! Every cell in the block points at that cell with `RVIS`. As with precombined meshes,
+//! the name uses the form ID as loaded in the Creation Kit ([`LoadOrderFormIds`]), so
+//! cell and `RVIS` form IDs are resolved before they are compared with the files. When
+//! previs generation fails for some cells, the log only says that a visibility task did
+//! not complete, not which one.
+//!
+//! [`check`] maps each `.uvd` file back to its cells and compares them with the exterior
+//! cells the plugin modifies:
+//!
+//! - **Cells without previs**: neither the cell nor its `RVIS` cell has a `.uvd` file
+//! - **Unmatched files**: `.uvd` files for cells the plugin does not touch
+//!
+//! The workflow runs the check after steps 6 and 7. Both lists are warnings: the Creation
+//! Kit log remains the authority on whether previs generation failed.
+
+use anyhow::Result;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use crate::plugin::load_order::LoadOrderFormIds;
+use crate::plugin::scanner::PluginScan;
+
+/// Number of cells or files listed in log messages
+const REPORT_ENTRIES: usize = 10;
+
+/// An exterior cell with no previs file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UncoveredCell {
+    /// Form ID of the cell, as stored in the plugin
+    pub cell: u32,
+    /// Cell description (worldspace and grid), for messages
+    pub location: String,
+}
+
+/// Result of [`check`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CoverageReport {
+    /// Number of exterior cells the plugin modifies
+    pub cells: usize,
+    /// Number of previs files
+    pub files: usize,
+    /// Exterior cells without a previs file
+    pub uncovered: Vec<UncoveredCell>,
+    /// Previs files for cells the plugin does not modify (file names, sorted)
+    pub unmatched: Vec<String>,
+}
+
+impl CoverageReport {
+    /// Whether every cell has a previs file and every file belongs to a cell
+    pub fn is_complete(&self) -> bool {
+        self.uncovered.is_empty() && self.unmatched.is_empty()
+    }
+
+    /// One-line summary (e.g., `212 exterior cells, 26 previs files, 0 without previs, 1 unmatched`)
+    pub fn summary(&self) -> String {
+        format!(
+            "{} exterior cells, {} previs files, {} without previs, {} unmatched",
+            self.cells,
+            self.files,
+            self.uncovered.len(),
+            self.unmatched.len()
+        )
+    }
+
+    /// Log the summary and the first uncovered cells and unmatched files
+    pub fn log(&self) {
+        if self.is_complete() {
+            info!("Previs coverage: {}", self.summary());
+        } else {
+            warn!("Previs coverage: {}", self.summary());
+        }
+        for cell in self.uncovered.iter().take(REPORT_ENTRIES) {
+            warn!("  No previs: {} [{:08X}]", cell.location, cell.cell);
+        }
+        if self.uncovered.len() > REPORT_ENTRIES {
+            warn!(
+                "  ... and {} more cells without previs",
+                self.uncovered.len() - REPORT_ENTRIES
+            );
+        }
+        for file in self.unmatched.iter().take(REPORT_ENTRIES) {
+            warn!("  Unmatched: {file}");
+        }
+        if self.unmatched.len() > REPORT_ENTRIES {
+            warn!(
+                "  ... and {} more unmatched files",
+                self.unmatched.len() - REPORT_ENTRIES
+            );
+        }
+    }
+}
+
+/// Load-order form ID of the cell a previs file is named after (e.g., `0001F4A2.uvd`)
+pub fn previs_file_cell(file_name: &str) -> Option<u32> {
+    let (stem, extension) = file_name.rsplit_once('.')?;
+    if !extension.eq_ignore_ascii_case("uvd") || stem.len() != 8 {
+        return None;
+    }
+    u32::from_str_radix(stem, 16).ok()
+}
+
+/// Compare the exterior cells of a plugin with the previs files that exist
+///
+/// `files` are file names (without directories) of the `.uvd` files. Each cell's `RVIS`
+/// is taken from `previs` when given (the unmerged `Previs.esp`, numbered like the plugin)
+/// and from the plugin otherwise. `ids` numbers the plugin's form IDs as the Creation Kit
+/// did when it named the files.
+pub fn check(
+    plugin: &PluginScan,
+    previs: Option<&PluginScan>,
+    ids: &LoadOrderFormIds,
+    files: &HashSet<String>,
+) -> CoverageReport {
+    let previs_cells: HashMap<u32, u32> = previs
+        .into_iter()
+        .flat_map(|scan| &scan.cells)
+        .filter_map(|cell| Some((cell.form_id, cell.previs_cell?)))
+        .collect();
+    let file_cells: HashSet<u32> = files.iter().filter_map(|f| previs_file_cell(f)).collect();
+
+    let mut report = CoverageReport {
+        files: files.len(),
+        ..CoverageReport::default()
+    };
+    let mut known = HashSet::new();
+
+    for cell in &plugin.cells {
+        let cell_id = ids.resolve(cell.form_id);
+        let previs_cell = previs_cells
+            .get(&cell.form_id)
+            .copied()
+            .or(cell.previs_cell)
+            .map(|id| ids.resolve(id));
+        known.insert(cell_id);
+        known.extend(previs_cell);
+
+        if cell.worldspace.is_none() || cell.grid.is_none() {
+            continue;
+        }
+        report.cells += 1;
+
+        let covered =
+            file_cells.contains(&cell_id) || previs_cell.is_some_and(|id| file_cells.contains(&id));
+        if !covered {
+            report.uncovered.push(UncoveredCell {
+                cell: cell.form_id,
+                location: cell.location(),
+            });
+        }
+    }
+
+    report.unmatched = files
+        .iter()
+        .filter(|f| !previs_file_cell(f).is_some_and(|id| known.contains(&id)))
+        .cloned()
+        .collect();
+    report.unmatched.sort();
+    report
+}
+
+/// File names of the `.uvd` files in `<data_dir>\vis` for each directory
+///
+/// Directories without a `vis` folder are skipped.
+///
+/// # Errors
+///
+/// Returns an error if a folder exists but cannot be read
+pub fn previs_files(data_dirs: &[&Path]) -> Result<HashSet<String>> {
+    let mut files = HashSet::new();
+    for dir in data_dirs {
+        let vis = dir.join("vis");
+        if !vis.is_dir() {
+            continue;
+        }
+        for entry in fs::read_dir(&vis)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_previs = Path::new(&name)
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("uvd"));
+            if entry.file_type()?.is_file() && is_previs {
+                files.insert(name);
+            }
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::scanner::CellInfo;
+    use tempfile::TempDir;
+
+    fn exterior(form_id: u32, grid: (i32, i32), previs_cell: Option<u32>) -> CellInfo {
+        CellInfo {
+            form_id,
+            worldspace: Some("Commonwealth".to_string()),
+            grid: Some(grid),
+            previs_cell,
+            ..CellInfo::default()
+        }
+    }
+
+    /// `Target.esp` with `Fallout4.esm` as its only master
+    fn ids(light: bool) -> LoadOrderFormIds {
+        LoadOrderFormIds::new(
+            &[
+                ("Fallout4.esm".to_string(), false),
+                ("Target.esp".to_string(), light),
+            ],
+            &["Fallout4.esm".to_string()],
+        )
+    }
+
+    fn scan() -> PluginScan {
+        PluginScan {
+            cells: vec![
+                exterior(0x0000_E2F3, (-3, 22), Some(0x0000_E2F4)),
+                exterior(0x0000_E2F4, (-3, 23), Some(0x0000_E2F4)),
+                exterior(0x0100_0800, (10, 10), None),
+                CellInfo {
+                    form_id: 0x0100_0801,
+                    editor_id: Some("MyInterior".to_string()),
+                    ..CellInfo::default()
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_previs_file_cell() {
+        assert_eq!(previs_file_cell("0000e2f4.UVD"), Some(0x0000_E2F4));
+        assert_eq!(previs_file_cell("E2F4.uvd"), None);
+        assert_eq!(previs_file_cell("0000E2F4.nif"), None);
+    }
+
+    #[test]
+    fn test_check_reports_uncovered_and_unmatched() {
+        let files = HashSet::from([
+            "0000E2F4.uvd".to_string(),
+            "00012345.uvd".to_string(),
+            "01000801.uvd".to_string(),
+        ]);
+
+        let report = check(&scan(), None, &ids(false), &files);
+        assert_eq!(report.cells, 3);
+        assert_eq!(report.files, 3);
+        assert!(!report.is_complete());
+        assert_eq!(
+            report.uncovered,
+            [UncoveredCell {
+                cell: 0x0100_0800,
+                location: "Commonwealth (10, 10)".to_string(),
+            }]
+        );
+        assert_eq!(report.unmatched, ["00012345.uvd"]);
+        assert_eq!(
+            report.summary(),
+            "3 exterior cells, 3 previs files, 1 without previs, 1 unmatched"
+        );
+    }
+
+    #[test]
+    fn test_check_uses_previs_plugin_rvis() {
+        let previs = PluginScan {
+            cells: vec![exterior(0x0100_0800, (10, 10), Some(0x0100_0900))],
+        };
+        let temp = TempDir::new().unwrap();
+        let vis = temp.path().join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000E2F4.uvd"), b"").unwrap();
+        fs::write(vis.join("01000900.uvd"), b"").unwrap();
+        fs::write(vis.join("readme.txt"), b"").unwrap();
+
+        let files = previs_files(&[temp.path()]).unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(check(&scan(), Some(&previs), &ids(false), &files).is_complete());
+    }
+
+    #[test]
+    fn test_check_uses_load_order_form_ids() {
+        let plugin = PluginScan {
+            cells: vec![
+                exterior(0x0100_0800, (10, 10), Some(0x0100_0801)),
+                exterior(0x0100_0801, (10, 11), Some(0x0100_0801)),
+            ],
+        };
+        let files = HashSet::from(["FE000801.uvd".to_string()]);
+
+        assert!(check(&plugin, None, &ids(true), &files).is_complete());
+        let report = check(&plugin, None, &ids(false), &files);
+        assert_eq!(report.uncovered.len(), 2);
+        assert_eq!(report.unmatched, ["FE000801.uvd"]);
+    }
+}
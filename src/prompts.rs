@@ -2,14 +2,14 @@ use anyhow::Result;
 use dialoguer::{Confirm, Input, Select};
 use std::path::Path;
 
-use crate::validation::validate_plugin_name;
+use crate::validation::{has_plugin_extension, validate_plugin_name};
 
 /// Prompt user for plugin name with validation
 ///
 /// Validates:
 /// - No reserved names (previs, combinedobjects, xprevispatch)
 /// - No spaces in clean mode
-/// - Ensures .esp/.esm extension
+/// - Ensures .esp/.esm/.esl extension (adds .esp if missing)
 pub fn prompt_plugin_name(clean_mode: bool) -> Result<String> {
     loop {
         let input: String = Input::new()
@@ -24,12 +24,11 @@ pub fn prompt_plugin_name(clean_mode: bool) -> Result<String> {
         }
 
         // Ensure extension is present
-        let plugin_name =
-            if !input.to_lowercase().ends_with(".esp") && !input.to_lowercase().ends_with(".esm") {
-                format!("{input}.esp")
-            } else {
-                input.to_string()
-            };
+        let plugin_name = if has_plugin_extension(input) {
+            input.to_string()
+        } else {
+            format!("{input}.esp")
+        };
 
         match validate_plugin_name(&plugin_name, clean_mode) {
             Ok(()) => return Ok(plugin_name),
@@ -51,7 +50,7 @@ pub fn prompt_use_existing_plugin(plugin_path: &Path) -> Result<Option<bool>> {
 
     let choices = vec![
         "Yes - Use existing plugin and continue",
-        "No - Start fresh (will backup existing)",
+        "No - Start fresh (a snapshot of the existing plugin is kept)",
         "Exit - Cancel operation",
     ];
 
@@ -68,6 +67,26 @@ pub fn prompt_use_existing_plugin(plugin_path: &Path) -> Result<Option<bool>> {
     }
 }
 
+/// Prompt for the seed plugin to copy when several `xPrevisPatch` plugins exist
+///
+/// Returns:
+/// - Some(index): Index of the chosen plugin in `plugins`
+/// - None: User chose to exit
+pub fn prompt_select_seed_plugin(plugins: &[String]) -> Result<Option<usize>> {
+    println!("\nSeveral xPrevisPatch plugins can be copied to create the plugin:");
+
+    let mut choices: Vec<&str> = plugins.iter().map(String::as_str).collect();
+    choices.push("Exit - Cancel operation");
+
+    let selection = Select::new()
+        .with_prompt("Which plugin should be used as the seed?")
+        .items(&choices)
+        .default(0)
+        .interact()?;
+
+    Ok((selection < plugins.len()).then_some(selection))
+}
+
 /// Prompt for which step to restart from
 ///
 /// Returns:
@@ -76,12 +95,12 @@ pub fn prompt_use_existing_plugin(plugin_path: &Path) -> Result<Option<bool>> {
 pub fn prompt_restart_step() -> Result<Option<u8>> {
     println!("\nWorkflow can resume from any of these steps:");
     println!("  1. Generate Precombines Via CK");
-    println!("  2. Merge PrecombineObjects.esp Via xEdit");
+    println!("  2. Merge PrecombineObjects.esp");
     println!("  3. Create BA2 Archive from Precombines");
     println!("  4. Compress PSG Via CK (clean mode only)");
     println!("  5. Build CDX Via CK (clean mode only)");
     println!("  6. Generate Previs Via CK");
-    println!("  7. Merge Previs.esp Via xEdit");
+    println!("  7. Merge Previs.esp");
     println!("  8. Add Previs files to BA2 Archive");
     println!("  0. Exit");
 
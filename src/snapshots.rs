@@ -0,0 +1,314 @@
+//! Plugin snapshots
+//!
+//! Steps 2 and 7 rewrite the target plugin, and a failed merge (or an `FO4Edit` crash
+//! part way through saving) can leave it damaged. The workflow copies the plugin to a
+//! timestamped snapshot before step 1 and before each merge step, and restores the
+//! snapshot automatically when a merge fails. The `snapshots` command lists and restores
+//! them by hand.
+//!
+//! Snapshots are kept per plugin, outside `Data` so the game and MO2 never see them:
+//!
+//! ```text
+//! <FO4>\GeneratePrevisibines\snapshots\MyMod.esp\
+//! ├── 20261016-142233-118_before-step-1_MyMod.esp
+//! └── 20261016-151002-540_before-step-2_MyMod.esp
+//! ```
+//!
+//! Only the [`MAX_SNAPSHOTS`] most recent snapshots of a plugin are kept, except that the
+//! oldest `before-step-1` snapshot, the plugin as it was before the workflow first touched
+//! it, is never deleted.
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveDateTime};
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// Number of snapshots kept per plugin; older ones are deleted when a new one is taken
+pub const MAX_SNAPSHOTS: usize = 20;
+
+/// Label of the snapshot taken before step 1; the oldest one is never pruned
+const ORIGINAL_LABEL: &str = "before-step-1";
+
+/// Timestamp at the start of snapshot file names (local time, with milliseconds)
+const TIMESTAMP_FORMAT: &str = "%Y%m%d-%H%M%S-%3f";
+
+/// A saved copy of a plugin
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snapshot {
+    /// Path of the snapshot file
+    pub path: PathBuf,
+    /// When the snapshot was taken (local time)
+    pub created: NaiveDateTime,
+    /// Why the snapshot was taken (e.g., `before-step-2`)
+    pub label: String,
+    /// Size in bytes
+    pub size: u64,
+}
+
+impl Snapshot {
+    /// Parse a snapshot file name (`<timestamp>_<label>_<plugin>`)
+    fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let (timestamp, rest) = name.split_once('_')?;
+        let (label, _plugin) = rest.split_once('_')?;
+        Some(Self {
+            path: path.to_path_buf(),
+            created: NaiveDateTime::parse_from_str(timestamp, TIMESTAMP_FORMAT).ok()?,
+            label: label.to_string(),
+            size: fs::metadata(path).ok()?.len(),
+        })
+    }
+}
+
+/// The snapshots of one plugin
+#[derive(Debug, Clone)]
+pub struct SnapshotStore {
+    dir: PathBuf,
+    plugin_name: String,
+}
+
+impl SnapshotStore {
+    /// The store for a plugin: `<FO4>\GeneratePrevisibines\snapshots\<Plugin>`
+    pub fn new(fo4_dir: &Path, plugin_name: &str) -> Self {
+        Self::in_dir(
+            fo4_dir
+                .join("GeneratePrevisibines")
+                .join("snapshots")
+                .join(plugin_name),
+            plugin_name,
+        )
+    }
+
+    /// A store in a specific folder
+    pub fn in_dir(dir: PathBuf, plugin_name: &str) -> Self {
+        Self {
+            dir,
+            plugin_name: plugin_name.to_string(),
+        }
+    }
+
+    /// Folder the snapshots are kept in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Copy the plugin to a new snapshot, then delete all but the newest
+    /// [`MAX_SNAPSHOTS`] snapshots (and the oldest `before-step-1` one)
+    ///
+    /// `label` says why the snapshot was taken; it must not contain `_`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot folder cannot be created or the plugin cannot be
+    /// copied
+    pub fn take(&self, plugin_path: &Path, label: &str) -> Result<Snapshot> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create snapshot folder: {}", self.dir.display()))?;
+
+        // Timestamps have millisecond resolution; never overwrite an earlier snapshot
+        let (timestamp, path) = loop {
+            let timestamp = Local::now().format(TIMESTAMP_FORMAT).to_string();
+            let path = self
+                .dir
+                .join(format!("{timestamp}_{label}_{}", self.plugin_name));
+            if !path.exists() {
+                break (timestamp, path);
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        let created = NaiveDateTime::parse_from_str(&timestamp, TIMESTAMP_FORMAT)?;
+        let size = fs::copy(plugin_path, &path).with_context(|| {
+            format!(
+                "Failed to snapshot {} to {}",
+                plugin_path.display(),
+                path.display()
+            )
+        })?;
+        info!("Snapshot of {}: {}", self.plugin_name, path.display());
+
+        self.prune();
+        Ok(Snapshot {
+            path,
+            created,
+            label: label.to_string(),
+            size,
+        })
+    }
+
+    /// Every snapshot of the plugin, oldest first
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot folder exists but cannot be read
+    pub fn list(&self) -> Result<Vec<Snapshot>> {
+        if !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let mut snapshots = Vec::new();
+        for entry in fs::read_dir(&self.dir)
+            .with_context(|| format!("Failed to read snapshots: {}", self.dir.display()))?
+        {
+            let path = entry?.path();
+            if let Some(snapshot) = Snapshot::from_path(&path) {
+                snapshots.push(snapshot);
+            }
+        }
+        snapshots.sort_by(|a, b| a.created.cmp(&b.created).then(a.path.cmp(&b.path)));
+        Ok(snapshots)
+    }
+
+    /// Delete all but the newest [`MAX_SNAPSHOTS`] snapshots; failures are only logged
+    ///
+    /// The oldest `before-step-1` snapshot is kept (and counts towards the limit): it is
+    /// the only copy of the plugin from before the workflow's first merge.
+    fn prune(&self) {
+        let snapshots = match self.list() {
+            Ok(snapshots) => snapshots,
+            Err(e) => {
+                warn!("Could not list old snapshots: {e:#}");
+                return;
+            }
+        };
+        let original = snapshots.iter().position(|s| s.label == ORIGINAL_LABEL);
+        let excess = snapshots.len().saturating_sub(MAX_SNAPSHOTS);
+        let old = snapshots
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| Some(index) != original)
+            .map(|(_, snapshot)| snapshot)
+            .take(excess);
+        for snapshot in old {
+            if let Err(e) = fs::remove_file(&snapshot.path) {
+                warn!(
+                    "Could not delete old snapshot {}: {e}",
+                    snapshot.path.display()
+                );
+            }
+        }
+    }
+}
+
+/// Replace the plugin with a snapshot
+///
+/// The snapshot is copied next to the plugin first and then renamed over it, so the
+/// plugin is never left half-written.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot cannot be copied or the plugin cannot be replaced
+pub fn restore(snapshot: &Snapshot, plugin_path: &Path) -> Result<()> {
+    let mut temp_name = plugin_path.as_os_str().to_owned();
+    temp_name.push(".restore");
+    let temp_path = PathBuf::from(temp_name);
+
+    fs::copy(&snapshot.path, &temp_path).with_context(|| {
+        format!(
+            "Failed to copy snapshot {} to {}",
+            snapshot.path.display(),
+            temp_path.display()
+        )
+    })?;
+    if let Err(e) = fs::rename(&temp_path, plugin_path) {
+        let _ = fs::remove_file(&temp_path);
+        return Err(e).with_context(|| format!("Failed to replace {}", plugin_path.display()));
+    }
+
+    info!(
+        "Restored {} from snapshot {}",
+        plugin_path.display(),
+        snapshot.path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_take_list_and_restore() {
+        let temp = TempDir::new().unwrap();
+        let plugin = temp.path().join("MyMod.esp");
+        fs::write(&plugin, b"original").unwrap();
+        let store = SnapshotStore::new(temp.path(), "MyMod.esp");
+
+        let first = store.take(&plugin, "before-step-1").unwrap();
+        assert_eq!(first.label, "before-step-1");
+        assert_eq!(first.size, 8);
+        assert!(
+            first.path.starts_with(
+                temp.path()
+                    .join("GeneratePrevisibines")
+                    .join("snapshots")
+                    .join("MyMod.esp")
+            )
+        );
+
+        fs::write(&plugin, b"merged").unwrap();
+        let second = store.take(&plugin, "before-step-2").unwrap();
+        fs::write(&plugin, b"broken").unwrap();
+
+        let snapshots = store.list().unwrap();
+        assert_eq!(snapshots, [first.clone(), second]);
+
+        restore(&first, &plugin).unwrap();
+        assert_eq!(fs::read(&plugin).unwrap(), b"original");
+        assert!(!temp.path().join("MyMod.esp.restore").exists());
+    }
+
+    #[test]
+    fn test_prune_keeps_newest() {
+        let temp = TempDir::new().unwrap();
+        let store = SnapshotStore::in_dir(temp.path().join("snapshots"), "MyMod.esp");
+        fs::create_dir_all(store.dir()).unwrap();
+        fs::write(
+            store
+                .dir()
+                .join("20191231-120000-000_before-step-1_MyMod.esp"),
+            b"",
+        )
+        .unwrap();
+        for day in 1..=MAX_SNAPSHOTS + 2 {
+            fs::write(
+                store
+                    .dir()
+                    .join(format!("202001{day:02}-120000-000_before-step-2_MyMod.esp")),
+                b"",
+            )
+            .unwrap();
+        }
+        fs::write(store.dir().join("notes.txt"), b"").unwrap();
+
+        let plugin = temp.path().join("MyMod.esp");
+        fs::write(&plugin, b"plugin").unwrap();
+        store.take(&plugin, "before-step-7").unwrap();
+
+        // The original snapshot survives; the oldest of the others make room for it
+        let snapshots = store.list().unwrap();
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(snapshots.last().unwrap().label, "before-step-7");
+        assert_eq!(snapshots[0].label, "before-step-1");
+        assert_eq!(
+            snapshots[1].created.format("%Y-%m-%d").to_string(),
+            "2020-01-05"
+        );
+
+        // Later before-step-1 snapshots are pruned like any other
+        store.take(&plugin, "before-step-1").unwrap();
+        let snapshots = store.list().unwrap();
+        assert_eq!(snapshots.len(), MAX_SNAPSHOTS);
+        assert_eq!(
+            snapshots[0].created.format("%Y-%m-%d").to_string(),
+            "2019-12-31"
+        );
+        assert_eq!(
+            snapshots[1].created.format("%Y-%m-%d").to_string(),
+            "2020-01-06"
+        );
+    }
+}
@@ -1,8 +1,9 @@
 //! Archive management abstraction for Fallout 4 BA2 archives
 //!
 //! This module provides a unified interface for managing Fallout 4 BA2 archives using
-//! either Archive2.exe (Bethesda's official tool) or BSArch.exe (third-party tool).
-//! The choice of tool significantly impacts workflow performance and capabilities.
+//! Archive2.exe (Bethesda's official tool), BSArch.exe (third-party tool), or the
+//! built-in native writer. The choice of tool significantly impacts workflow performance
+//! and capabilities.
 //!
 //! # Supported Archive Tools
 //!
@@ -12,16 +13,15 @@
 //!
 //! **CRITICAL LIMITATION: NO APPEND SUPPORT**
 //!
-//! Archive2 **cannot** append files to existing archives. To add files to an existing
-//! archive, Archive2 requires:
-//! 1. Extract the entire archive to a temporary directory
-//! 2. Copy new files into the extracted directory
-//! 3. Delete the old archive
-//! 4. Re-create the archive from the combined directory
-//! 5. Clean up temporary files
+//! Archive2 **cannot** append files to existing archives. The original batch script
+//! (lines 390-414) worked around this by extracting the entire archive, copying the new
+//! files in and re-archiving everything, which on large worldspace mods took longer than
+//! previs generation and needed twice the disk space.
 //!
-//! **This is NOT inefficient code - it's a fundamental limitation of Archive2.exe.**
-//! See the original batch script lines 390-414 for the same workaround.
+//! Instead, files are appended to Archive2-created archives **in place** with the native
+//! BA2 code (see [`Ba2Writer::append_to`]): existing file data is left untouched, the new
+//! payloads are written after it, and only the header, record table and name table are
+//! rewritten.
 //!
 //! ## BSArch.exe (Third-Party)
 //!
@@ -35,6 +35,14 @@
 //! `BSArch` is the recommended tool when available, especially for workflows that
 //! add files to existing archives (Step 8: adding previs data to precombined archives).
 //!
+//! ## Native Writer (Built-in)
+//!
+//! The native backend writes Fallout 4 General BA2 archives directly (see the
+//! [`ba2`](super::ba2) module), with zlib-compressed or uncompressed entries. It needs no
+//! external executable, so archiving works on machines without the Creation Kit tools
+//! (including non-Windows build boxes and CI). Source files are deleted after archiving,
+//! matching Archive2, and appends use the same in-place path as Archive2.
+//!
 //! # MO2 Virtual File System Considerations
 //!
 //! When running through Mod Organizer 2 (MO2), archive tools cannot see files in
@@ -52,6 +60,7 @@
 //! use std::path::PathBuf;
 //! use generateprevisibines::tools::ArchiveManager;
 //! use generateprevisibines::config::ArchiveTool;
+//! use generateprevisibines::tools::archive_backend::CompressionProfile;
 //!
 //! // Create manager with Archive2
 //! let manager = ArchiveManager::new(
@@ -62,48 +71,65 @@
 //! )?;
 //!
 //! // Create archive from precombined meshes
-//! manager.create_archive_from_precombines("MyMod - Main.ba2", false, None)?;
+//! manager.create_archive_from_precombines("MyMod - Main.ba2", CompressionProfile::Default, None)?;
 //!
 //! // Add previs data to the archive
-//! manager.add_previs_to_archive("MyMod - Main.ba2", false, None)?;
+//! manager.add_previs_to_archive("MyMod - Main.ba2", CompressionProfile::Default, None)?;
 //! # Ok::<(), anyhow::Error>(())
 //! ```
 
 use anyhow::{Context, Result, bail};
 use log::{error, info, warn};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use walkdir::WalkDir;
 
+use crate::archive_budget::{self, SizeBudget};
 use crate::config::ArchiveTool;
 use crate::mo2_helper::Mo2Helper;
+use crate::tools::archive_backend::{
+    Archive2Backend, ArchiveBackend, BsarchBackend, CompressionProfile, NativeBackend, Packer,
+    archive_root,
+};
+use crate::tools::ba2::{Ba2Writer, archive_name_for, normalize_archive_path};
+use crate::tools::ba2_reader::{Ba2Archive, Ba2Entry, index_key};
+
+/// An archive written by [`ArchiveManager::create_archive`]
+#[derive(Debug)]
+pub struct CreatedArchive {
+    /// Writer that packed the archive (see [`Packer`])
+    pub packer: Packer,
+
+    /// Archive paths of the entries preserved from a previous archive of the same name
+    /// (empty if there was no previous archive)
+    pub preserved: Vec<String>,
+}
 
-/// Archive manager that abstracts Archive2 and `BSArch` operations
+/// Archive manager that abstracts Archive2, `BSArch` and native BA2 operations
 ///
 /// Provides a unified interface for creating and modifying Fallout 4 BA2 archives
-/// using either Archive2.exe or BSArch.exe. The implementation automatically handles
-/// the significant differences between these tools.
+/// using Archive2.exe, BSArch.exe or the built-in writer. The tool-specific operations
+/// live behind an [`ArchiveBackend`] (see the [`archive_backend`](super::archive_backend)
+/// module); the manager applies backups, verification, asset preservation and source
+/// cleanup the same way for every backend.
 ///
 /// # Key Differences Between Tools
 ///
-/// | Feature | Archive2 | `BSArch` |
-/// |---------|----------|--------|
-/// | Append support | **NO** (must extract/repack) | **YES** (direct append) |
-/// | Multi-threading | No | Yes |
-/// | Source | Bethesda official | Community tool |
-/// | Performance (append) | Slow (extract entire archive) | Fast (direct write) |
+/// | Feature | Archive2 | `BSArch` | Native |
+/// |---------|----------|--------|--------|
+/// | Append support | **NO** (appended in place natively) | **YES** (direct append) | In place |
+/// | Multi-threading | No | Yes | No |
+/// | Source | Bethesda official | Community tool | Built-in |
+/// | Performance (append) | Fast (new files only) | Fast (direct write) | Fast (new files only) |
+/// | Platform | Windows | Windows | Any |
 ///
 /// # Archive2 Limitations
 ///
-/// **CRITICAL:** Archive2.exe has **NO APPEND FUNCTIONALITY**. To add files to an
-/// existing archive, Archive2 must:
-/// 1. Extract the entire archive
-/// 2. Add new files to extracted directory
-/// 3. Delete old archive
-/// 4. Re-create archive from scratch
-///
-/// **This is NOT code smell - it's an Archive2.exe limitation.** The original batch
-/// script (lines 390-414) uses the same workaround. Do not attempt to "optimize" this.
+/// **CRITICAL:** Archive2.exe has **NO APPEND FUNCTIONALITY**. The original batch script
+/// (lines 390-414) extracted and re-created the whole archive to add files. This manager
+/// instead appends to the archive in place with [`Ba2Writer::append_to`], which only
+/// writes the new files and a new file table. Archive2 is still used to create archives.
 ///
 /// # `BSArch` Advantages
 ///
@@ -146,21 +172,22 @@ use crate::mo2_helper::Mo2Helper;
 /// # Ok::<(), anyhow::Error>(())
 /// ```
 pub struct ArchiveManager {
-    tool: ArchiveTool,
-    archive2_exe: Option<PathBuf>,
-    bsarch_exe: Option<PathBuf>,
+    backend: Box<dyn ArchiveBackend>,
     fallout4_dir: PathBuf,
+    size_budget: Option<SizeBudget>,
 }
 
 impl ArchiveManager {
     /// Create a new archive manager
     ///
-    /// Initializes an archive manager configured to use either Archive2 or `BSArch`.
-    /// The appropriate executable path must be provided for the selected tool.
+    /// Initializes an archive manager configured to use Archive2, `BSArch` or the native
+    /// writer. The appropriate executable path must be provided for the selected external
+    /// tool; [`ArchiveTool::Native`] needs neither path.
     ///
     /// # Arguments
     ///
-    /// * `tool` - Which archive tool to use ([`ArchiveTool::Archive2`] or [`ArchiveTool::BSArch`])
+    /// * `tool` - Which archive tool to use ([`ArchiveTool::Archive2`], [`ArchiveTool::BSArch`]
+    ///   or [`ArchiveTool::Native`])
     /// * `archive2_exe` - Path to Archive2.exe (required if `tool` is Archive2, ignored otherwise)
     /// * `bsarch_exe` - Path to BSArch.exe (required if `tool` is `BSArch`, ignored otherwise)
     /// * `fallout4_dir` - Path to Fallout 4 installation directory (e.g., `C:\Games\Fallout4`)
@@ -205,26 +232,59 @@ impl ArchiveManager {
         bsarch_exe: Option<PathBuf>,
         fallout4_dir: impl AsRef<Path>,
     ) -> Result<Self> {
+        let fallout4_dir = fallout4_dir.as_ref();
+
         // Validate tool availability
-        match tool {
+        let backend: Box<dyn ArchiveBackend> = match tool {
             ArchiveTool::Archive2 => {
-                if archive2_exe.is_none() {
+                let Some(exe) = archive2_exe else {
                     bail!("Archive2.exe not found");
-                }
+                };
+                Box::new(Archive2Backend::new(exe, fallout4_dir))
             }
             ArchiveTool::BSArch => {
-                if bsarch_exe.is_none() {
+                let Some(exe) = bsarch_exe else {
                     bail!("BSArch.exe not found");
-                }
+                };
+                Box::new(BsarchBackend::new(exe, fallout4_dir))
             }
-        }
+            ArchiveTool::Native => Box::new(NativeBackend),
+        };
 
-        Ok(Self {
-            tool,
-            archive2_exe,
-            bsarch_exe,
+        Ok(Self::with_backend(backend, fallout4_dir))
+    }
+
+    /// Create an archive manager that uses a specific [`ArchiveBackend`]
+    ///
+    /// [`new`](Self::new) calls this with the backend for the configured
+    /// [`ArchiveTool`]. Tests use it to plug in a recording backend so the backup,
+    /// verification and cleanup logic can run without Archive2 or `BSArch`.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use generateprevisibines::tools::ArchiveManager;
+    /// use generateprevisibines::tools::archive_backend::NativeBackend;
+    ///
+    /// let manager = ArchiveManager::with_backend(Box::new(NativeBackend), "C:\\Games\\Fallout4");
+    /// ```
+    pub fn with_backend(backend: Box<dyn ArchiveBackend>, fallout4_dir: impl AsRef<Path>) -> Self {
+        Self {
+            backend,
             fallout4_dir: fallout4_dir.as_ref().to_path_buf(),
-        })
+            size_budget: None,
+        }
+    }
+
+    /// Check every written archive against `budget` before the source files are deleted
+    ///
+    /// An archive over the limit fails the operation like a failed verification: the
+    /// previous archive is restored from its backup and the source directory is kept (see
+    /// [`archive_budget::check_actual`]).
+    #[must_use]
+    pub fn with_size_budget(mut self, budget: SizeBudget) -> Self {
+        self.size_budget = Some(budget);
+        self
     }
 
     /// Create a new archive from a directory
@@ -232,43 +292,71 @@ impl ArchiveManager {
     /// Creates a BA2 archive from all files in the specified directory. The behavior
     /// differs between Archive2 and `BSArch` regarding source file cleanup.
     ///
+    /// If the archive already exists, it is **not** simply overwritten: entries under
+    /// `meshes\precombined\` and `vis\` are replaced by the new build, and every other entry
+    /// (the mod's scripts, meshes, sounds, ...) is copied into the new archive unchanged.
+    ///
     /// # Arguments
     ///
     /// * `source_dir` - Directory containing files to archive
     /// * `archive_name` - Name of the archive to create (e.g., `"MyMod - Main.ba2"`)
-    /// * `is_xbox` - If `true`, uses Xbox compression format; if `false`, uses PC format
+    /// * `profile` - Compression for the new entries (see [`CompressionProfile::for_build_mode`])
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if the archive was created successfully
+    /// Returns the writer that packed the archive and the archive paths of the entries
+    /// preserved from a previous archive of the same name (see [`CreatedArchive`])
     ///
     /// # Errors
     ///
     /// This function will return an error if:
     /// - Source directory does not exist or cannot be read
     /// - Archive creation fails (disk full, permission denied, invalid format)
-    /// - **Archive2:** Source directory cannot be deleted after archiving
+    /// - Verification fails: a source file is missing from the archive or differs from
+    ///   the archived copy (the source directory is kept)
+    /// - An existing archive cannot be read or merged (it is restored from
+    ///   `<archive>.ba2.bak` and the source directory is kept)
+    /// - The archive exceeds the size budget (see [`with_size_budget`](Self::with_size_budget));
+    ///   the previous archive is restored and the source directory is kept
+    /// - **Archive2/Native:** Source directory cannot be deleted after archiving
+    ///
+    /// # Existing Archives
+    ///
+    /// 1. The existing archive is renamed to `<archive>.ba2.bak`
+    /// 2. The new archive is created and verified as described below
+    /// 3. Preserved entries are appended byte-for-byte (no extraction or recompression)
+    /// 4. The backup is deleted; on any failure it is renamed back instead
     ///
     /// # Tool-Specific Behavior
     ///
     /// ## Archive2
     /// 1. Creates the archive from `source_dir`
-    /// 2. **Deletes** the source directory and all its contents
+    /// 2. Verifies the archive against `source_dir` (size and content hash of every file)
+    /// 3. **Deletes** the source directory and all its contents
     ///
     /// This is Archive2's standard behavior - it assumes you want to replace loose files
     /// with archived versions.
     ///
     /// ## `BSArch`
     /// 1. Creates the archive from `source_dir`
-    /// 2. **Preserves** the source directory and files
+    /// 2. Verifies the archive against `source_dir`
+    /// 3. **Preserves** the source directory and files
     ///
     /// `BSArch` keeps the source files, allowing you to verify the archive before cleanup.
     ///
+    /// ## Native
+    /// 1. Writes the archive from `source_dir` with the built-in [`Ba2Writer`]
+    /// 2. Verifies the archive against `source_dir`
+    /// 3. **Deletes** the source directory and all its contents (same as Archive2)
+    ///
+    /// Xbox builds are written with the native writer's Xbox zlib (4 KiB window).
+    ///
     /// # Examples
     ///
     /// ```no_run
     /// use std::path::Path;
     /// # use generateprevisibines::tools::ArchiveManager;
+    /// # use generateprevisibines::tools::archive_backend::CompressionProfile;
     /// # use generateprevisibines::config::ArchiveTool;
     /// # use std::path::PathBuf;
     /// # let manager = ArchiveManager::new(
@@ -279,42 +367,89 @@ impl ArchiveManager {
     /// # )?;
     ///
     /// let precombined_dir = Path::new("C:\\Games\\Fallout4\\Data\\meshes\\precombined");
-    /// manager.create_archive(precombined_dir, "MyMod - Main.ba2", false)?;
+    /// manager.create_archive(precombined_dir, "MyMod - Main.ba2", CompressionProfile::Default)?;
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     ///
     /// # Notes
     ///
     /// - Archive is always created in `Data/` directory
-    /// - **Archive2:** Source files are permanently deleted - ensure workflow completed successfully
-    /// - **`BSArch`:** You may want to manually delete source files after verification
+    /// - **Archive2/Native:** Source files are deleted only after the archive verifies and
+    ///   any preserved assets are merged
+    /// - **`BSArch`:** Source files are never deleted
     pub fn create_archive(
         &self,
         source_dir: impl AsRef<Path>,
         archive_name: &str,
-        is_xbox: bool,
-    ) -> Result<()> {
+        profile: CompressionProfile,
+    ) -> Result<CreatedArchive> {
         let source_dir = source_dir.as_ref();
         let data_dir = self.fallout4_dir.join("Data");
         let archive_path = data_dir.join(archive_name);
 
-        match self.tool {
-            ArchiveTool::Archive2 => {
-                self.archive2_create(source_dir, &archive_path, is_xbox)?;
+        // An existing archive may ship other assets (scripts, meshes, sounds); move it
+        // aside so its non-precombine entries can be merged into the new archive
+        let backup_path = archive_path.with_extension("ba2.bak");
+        let existing = if archive_path.exists() {
+            info!(
+                "Existing archive found, its other assets will be preserved: {}",
+                archive_path.display()
+            );
+            fs::rename(&archive_path, &backup_path).with_context(|| {
+                format!(
+                    "Failed to create backup of archive: {}",
+                    archive_path.display()
+                )
+            })?;
+            Some(backup_path.as_path())
+        } else {
+            None
+        };
 
-                // Archive2: Delete source files after archiving
-                info!("Deleting source files: {}", source_dir.display());
-                fs::remove_dir_all(source_dir).with_context(|| {
-                    format!("Failed to delete source: {}", source_dir.display())
-                })?;
+        let result = self
+            .backend
+            .create(source_dir, &archive_path, profile)
+            .and_then(|packer| {
+                Self::verify_archive(source_dir, &archive_path)?;
+                let preserved = match existing {
+                    Some(old_archive) => Self::merge_preserved_assets(old_archive, &archive_path)?,
+                    None => Vec::new(),
+                };
+                self.check_size(&archive_path)?;
+                Ok(CreatedArchive { packer, preserved })
+            });
+
+        let created = match (result, existing) {
+            (Ok(created), Some(old_archive)) => {
+                // Success - remove backup
+                if let Err(e) = fs::remove_file(old_archive) {
+                    warn!("Failed to remove backup archive: {e}");
+                }
+                created
             }
-            ArchiveTool::BSArch => {
-                self.bsarch_pack(source_dir, &archive_path)?;
-                // BSArch: Keep source files
+            (Err(e), Some(old_archive)) => {
+                // Failed - restore backup
+                error!("Archive creation failed, restoring original archive");
+                if let Err(restore_err) = fs::rename(old_archive, &archive_path) {
+                    error!(
+                        "CRITICAL: Failed to restore backup! Backup is at: {}. Error: {}",
+                        old_archive.display(),
+                        restore_err
+                    );
+                }
+                return Err(e);
             }
+            (result, None) => result?,
+        };
+
+        // Archive2 and native: Delete source files after archiving; BSArch: Keep them
+        if !self.backend.keeps_source() {
+            info!("Deleting source files: {}", source_dir.display());
+            fs::remove_dir_all(source_dir)
+                .with_context(|| format!("Failed to delete source: {}", source_dir.display()))?;
         }
 
-        Ok(())
+        Ok(created)
     }
 
     /// Create a new archive from precombined meshes (MO2-aware)
@@ -329,14 +464,15 @@ impl ArchiveManager {
     /// # Arguments
     ///
     /// * `archive_name` - Name of the archive to create (e.g., `"MyMod - Main.ba2"`)
-    /// * `is_xbox` - If `true`, uses Xbox compression format; if `false`, uses PC format
+    /// * `profile` - Compression for the new entries (see [`CompressionProfile::for_build_mode`])
     /// * `mo2_data_dir` - Optional path to MO2's VFS staging directory (e.g., `overwrite` folder).
     ///   When `Some`, files are collected from MO2's VFS. When `None`, files are read directly
     ///   from `Data/meshes/precombined`.
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if the archive was created successfully
+    /// Returns the writer that packed the archive and the entries preserved from an
+    /// existing archive (see [`create_archive`](Self::create_archive))
     ///
     /// # Errors
     ///
@@ -370,6 +506,7 @@ impl ArchiveManager {
     /// ```no_run
     /// use std::path::Path;
     /// # use generateprevisibines::tools::ArchiveManager;
+    /// # use generateprevisibines::tools::archive_backend::CompressionProfile;
     /// # use generateprevisibines::config::ArchiveTool;
     /// # use std::path::PathBuf;
     /// # let manager = ArchiveManager::new(
@@ -380,36 +517,36 @@ impl ArchiveManager {
     /// # )?;
     ///
     /// // Standard mode (no MO2)
-    /// manager.create_archive_from_precombines("MyMod - Main.ba2", false, None)?;
+    /// manager.create_archive_from_precombines("MyMod - Main.ba2", CompressionProfile::Default, None)?;
     ///
     /// // MO2 mode - collect from VFS
     /// let mo2_overwrite = Path::new("C:\\MO2\\overwrite");
     /// manager.create_archive_from_precombines(
     ///     "MyMod - Main.ba2",
-    ///     false,
+    ///     CompressionProfile::Default,
     ///     Some(mo2_overwrite)
     /// )?;
     ///
     /// // Xbox format
-    /// manager.create_archive_from_precombines("MyMod - Main.ba2", true, None)?;
+    /// manager.create_archive_from_precombines("MyMod - Main.ba2", CompressionProfile::Xbox, None)?;
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     ///
     /// # Notes
     ///
     /// - The archive is created in `Data/` directory regardless of MO2 mode
-    /// - For Archive2, source files are deleted after archiving
+    /// - For Archive2 and Native, source files are deleted after archiving
     /// - For `BSArch`, source files are preserved
     /// - Temporary MO2 collection directories are always cleaned up
     pub fn create_archive_from_precombines(
         &self,
         archive_name: &str,
-        is_xbox: bool,
+        profile: CompressionProfile,
         mo2_data_dir: Option<&Path>,
-    ) -> Result<()> {
+    ) -> Result<CreatedArchive> {
         let data_dir = self.fallout4_dir.join("Data");
 
-        if let Some(mo2_staging) = mo2_data_dir {
+        let created = if let Some(mo2_staging) = mo2_data_dir {
             // MO2 mode: Collect files from staging directory
             let mo2_helper = Mo2Helper::new(mo2_staging)?;
             info!(
@@ -425,28 +562,32 @@ impl ArchiveManager {
 
             if let Some(collected) = collected_dir {
                 // Archive from collected files
-                self.create_archive(&collected, archive_name, is_xbox)?;
+                let created = self.create_archive(&collected, archive_name, profile)?;
 
                 // Cleanup temp directory
                 if temp_collect.exists() {
                     fs::remove_dir_all(&temp_collect)?;
                 }
+                created
             } else {
                 bail!("No precombined meshes found in MO2 staging directory");
             }
         } else {
             // Standard mode: Use files from Data directory
             let precombined_dir = data_dir.join("meshes").join("precombined");
-            self.create_archive(&precombined_dir, archive_name, is_xbox)?;
-        }
+            self.create_archive(&precombined_dir, archive_name, profile)?
+        };
 
-        Ok(())
+        Ok(created)
     }
 
     /// Add files to an existing archive
     ///
-    /// Appends new files to an existing BA2 archive. The implementation differs
-    /// significantly between Archive2 and `BSArch` due to **Archive2's critical limitation**.
+    /// Appends new files to an existing BA2 archive through the configured
+    /// [`ArchiveBackend`]. Every backend appends the files in place with
+    /// [`Ba2Writer::append_to`]: Archive2 has no append functionality, and `BSArch pack`
+    /// always builds a new archive from the given folder alone. Every backend gets the same
+    /// backup, verification and restore handling.
     ///
     /// This is typically used in **Step 8** of the workflow to add previs data (`.uvd` files)
     /// to the archive containing precombined meshes.
@@ -455,67 +596,49 @@ impl ArchiveManager {
     ///
     /// * `source_dir` - Directory containing files to add to the archive (typically `Data/vis`)
     /// * `archive_name` - Name of the existing archive (e.g., `"MyMod - Main.ba2"`). **Must exist.**
-    /// * `is_xbox` - If `true`, uses Xbox compression format; if `false`, uses PC format
-    ///   (only relevant for Archive2 re-archiving)
+    /// * `profile` - Requested compression. Archive2 and `BSArch` append Xbox builds with
+    ///   the native writer's Xbox zlib
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if files were successfully added to the archive
+    /// Returns the writer that packed the new files; this is the native writer for every
+    /// backend (see [`Packer`])
     ///
     /// # Errors
     ///
     /// This function will return an error if:
     /// - Archive does not exist (must be created first)
-    /// - **Archive2:** Extraction fails (corrupted archive, insufficient disk space, permission denied)
-    /// - **Archive2:** Cannot delete old archive after extraction (file locked, permission denied)
-    /// - **Archive2:** Cannot create temporary extraction directory
-    /// - File copying fails (disk full, permission denied, I/O error)
-    /// - Archive creation/packing fails (corrupted data, invalid format, disk full)
-    /// - **Archive2:** Partial operation failure may leave archive in inconsistent state
-    ///
-    /// # Archive2 Limitation - Extract/Repack Workaround
-    ///
-    /// **CRITICAL: Archive2.exe has NO APPEND FUNCTIONALITY.**
-    ///
-    /// This is **NOT inefficient code** - it's a fundamental limitation of Archive2.exe itself.
-    /// The original batch script (lines 390-414) uses the exact same workaround.
-    ///
-    /// ## Archive2 Workflow (REQUIRED)
-    ///
-    /// To add files to an existing archive using Archive2, we must:
-    /// 1. **Extract** the entire archive to `Data/_temp_archive_extract`
-    /// 2. **Copy** new files into the extracted directory
-    /// 3. **Delete** the old archive file
-    /// 4. **Re-create** the archive from the combined directory (existing + new files)
-    /// 5. **Clean up** the temporary extraction directory
-    /// 6. **Delete** the source directory
-    ///
-    /// This process extracts and re-compresses the entire archive, even if only adding
-    /// a few small files. For large archives (e.g., 500MB+), this can take several minutes.
-    ///
-    /// **Do not attempt to "optimize" this - Archive2.exe provides no append functionality.**
-    ///
-    /// ## Why Not Just Use `BSArch`?
-    ///
-    /// `BSArch` is recommended when available, but:
-    /// - Not all users have `BSArch` installed
-    /// - Archive2 ships with the Creation Kit (guaranteed availability)
-    /// - Some users prefer official Bethesda tools
-    ///
-    /// # `BSArch` Behavior
-    ///
-    /// `BSArch` can **append files directly** to existing archives without extraction:
-    /// 1. Add new files to the archive using `bsarch pack` command
-    /// 2. Delete source directory
-    ///
-    /// This is **much faster** than Archive2's extract/repack process, often completing
-    /// in seconds rather than minutes for large archives.
+    /// - The archive cannot be backed up
+    /// - The archive is not a General BA2 archive, a new file cannot be read, or the
+    ///   archive cannot be written (the original archive is restored from the backup)
+    /// - Verification fails (the original archive is restored from the backup)
+    /// - The archive exceeds the size budget (see [`with_size_budget`](Self::with_size_budget));
+    ///   the original archive is restored from the backup
+    ///
+    /// # In-Place Append
+    ///
+    /// **Archive2.exe has NO APPEND FUNCTIONALITY**, and the original batch script
+    /// (lines 390-414) extracted and re-archived everything to work around it. Instead:
+    /// 1. **Back up** the archive by copying it to `<archive>.ba2.bak`
+    /// 2. **Append** the new files in place with [`Ba2Writer::append_to`]. Existing file
+    ///    data is not extracted or recompressed; only the header, record table and name
+    ///    table are rewritten. Files already in the archive under the same path are replaced.
+    /// 3. **Verify** that every new file is in the archive with matching size and hash, and
+    ///    that every entry of the backup is still there with the same size (see
+    ///    [`verify_kept_entries`](Self::verify_kept_entries))
+    /// 4. On success, **delete** the backup and the source directory (`BSArch` keeps it)
+    /// 5. On failure (including verification), **restore** the archive from the backup and
+    ///    keep the source directory
+    ///
+    /// The extra disk space needed is one copy of the (compressed) archive for the backup,
+    /// rather than the full uncompressed contents plus a second archive.
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use std::path::Path;
     /// # use generateprevisibines::tools::ArchiveManager;
+    /// # use generateprevisibines::tools::archive_backend::CompressionProfile;
     /// # use generateprevisibines::config::ArchiveTool;
     /// # use std::path::PathBuf;
     /// # let manager = ArchiveManager::new(
@@ -527,38 +650,38 @@ impl ArchiveManager {
     ///
     /// // Add previs data to existing precombined archive
     /// let vis_dir = Path::new("C:\\Games\\Fallout4\\Data\\vis");
-    /// manager.add_to_archive(vis_dir, "MyMod - Main.ba2", false)?;
+    /// manager.add_to_archive(vis_dir, "MyMod - Main.ba2", CompressionProfile::Default)?;
     ///
     /// // With Xbox compression
-    /// manager.add_to_archive(vis_dir, "MyMod - Main.ba2", true)?;
+    /// manager.add_to_archive(vis_dir, "MyMod - Main.ba2", CompressionProfile::Xbox)?;
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     ///
     /// # Performance Comparison
     ///
-    /// For a 500MB archive with 10MB of new files:
-    /// - **Archive2:** ~3-5 minutes (extract 500MB, compress 510MB)
-    /// - **`BSArch`:** ~5-10 seconds (compress and append 10MB)
+    /// For a 500MB archive with 10MB of new files, a few seconds with every backend (copy
+    /// 500MB for the backup, compress and append 10MB).
     ///
     /// # Notes
     ///
-    /// - The source directory is **always deleted** after successful archiving (both tools)
-    /// - For Archive2, temporary directories are cleaned up even if errors occur
-    /// - **Archive2 only:** If an error occurs during re-archiving, the original archive
-    ///   may be lost. Consider backing up important archives before modification.
+    /// - **Archive2/Native:** The source directory is deleted after successful archiving
+    /// - If appending fails, the original archive is restored from
+    ///   `<archive>.ba2.bak`. If even the restore fails, the backup is left in place and
+    ///   its location is logged.
     /// - The archive must exist before calling this function (use `create_archive` first)
     ///
     /// # See Also
     ///
-    /// - Original batch script lines 390-414 for the same Archive2 workaround
+    /// - Original batch script lines 390-414 for the Archive2 extract/repack workaround
+    ///   this replaces
     /// - [`create_archive_from_precombines`](Self::create_archive_from_precombines) for creating new archives
     /// - [`add_previs_to_archive`](Self::add_previs_to_archive) for MO2-aware previs addition
     pub fn add_to_archive(
         &self,
         source_dir: impl AsRef<Path>,
         archive_name: &str,
-        is_xbox: bool,
-    ) -> Result<()> {
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
         let source_dir = source_dir.as_ref();
         let data_dir = self.fallout4_dir.join("Data");
         let archive_path = data_dir.join(archive_name);
@@ -567,75 +690,85 @@ impl ArchiveManager {
             bail!("Archive does not exist: {}", archive_path.display());
         }
 
-        match self.tool {
-            ArchiveTool::Archive2 => {
-                // REQUIRED WORKAROUND: Archive2 cannot append
-                // Must extract, add files, then re-archive
-                info!("Archive2: Extracting archive to add files (no append support)");
+        // Create backup by copying (the archive is modified in place)
+        let backup_path = archive_path.with_extension("ba2.bak");
+        fs::copy(&archive_path, &backup_path).with_context(|| {
+            format!(
+                "Failed to create backup of archive: {}",
+                archive_path.display()
+            )
+        })?;
 
-                let temp_extract = data_dir.join("_temp_archive_extract");
+        let result = self
+            .backend
+            .append(source_dir, &archive_path, profile)
+            .and_then(|packer| {
+                Self::verify_archive(source_dir, &archive_path)?;
+                Self::verify_kept_entries(&backup_path, &archive_path, source_dir)?;
+                self.check_size(&archive_path)?;
+                Ok(packer)
+            });
 
-                // Create temp directory
-                if temp_extract.exists() {
-                    fs::remove_dir_all(&temp_extract)?;
-                }
-                fs::create_dir_all(&temp_extract)?;
-
-                // Use closure to ensure cleanup on both success and error paths
-                let result = (|| -> Result<()> {
-                    // Extract existing archive
-                    self.archive2_extract(&archive_path, &temp_extract)?;
-
-                    // Copy new files to extracted directory
-                    Self::copy_dir_recursive(source_dir, &temp_extract)?;
-
-                    // Create backup by renaming (safer than delete-then-create)
-                    let backup_path = archive_path.with_extension("ba2.bak");
-                    fs::rename(&archive_path, &backup_path)
-                        .with_context(|| format!("Failed to create backup of archive: {}", archive_path.display()))?;
-
-                    // Re-create archive with all files
-                    match self.archive2_create(&temp_extract, &archive_path, is_xbox) {
-                        Ok(()) => {
-                            // Success - remove backup
-                            if let Err(e) = fs::remove_file(&backup_path) {
-                                warn!("Failed to remove backup archive: {e}");
-                            }
-                            Ok(())
-                        }
-                        Err(e) => {
-                            // Failed - restore backup
-                            error!("Archive creation failed, restoring backup");
-                            if let Err(restore_err) = fs::rename(&backup_path, &archive_path) {
-                                error!(
-                                    "CRITICAL: Failed to restore backup! Backup is at: {}. Error: {}",
-                                    backup_path.display(),
-                                    restore_err
-                                );
-                            }
-                            Err(e)
-                        }
-                    }
-                })();
-
-                // Cleanup temp directory regardless of success/failure
-                if temp_extract.exists() {
-                    let _ = fs::remove_dir_all(&temp_extract);
+        let packer = match result {
+            Ok(packer) => packer,
+            Err(e) => {
+                // Failed - restore backup
+                error!(
+                    "Appending to archive with {} failed, restoring backup",
+                    self.backend.name()
+                );
+                if let Err(restore_err) = fs::rename(&backup_path, &archive_path) {
+                    error!(
+                        "CRITICAL: Failed to restore backup! Backup is at: {}. Error: {}",
+                        backup_path.display(),
+                        restore_err
+                    );
                 }
+                return Err(e);
+            }
+        };
 
-                // Propagate any error from the operation
-                result?;
+        // Success - remove backup
+        if let Err(e) = fs::remove_file(&backup_path) {
+            warn!("Failed to remove backup archive: {e}");
+        }
 
-                // Clean up source directory on success
-                fs::remove_dir_all(source_dir)?;
-            }
-            ArchiveTool::BSArch => {
-                // BSArch can append
-                self.bsarch_pack(source_dir, &archive_path)?;
-            }
+        // Archive2 and native: Clean up source directory on success; BSArch: Keep it
+        if !self.backend.keeps_source() {
+            fs::remove_dir_all(source_dir)?;
         }
 
-        Ok(())
+        Ok(packer)
+    }
+
+    /// List every file in an archive
+    ///
+    /// `archive` is the name of an archive in `Data/` (e.g., `"MyMod - Main.ba2"`) or an
+    /// absolute path. Used by the `archive list` subcommand.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive cannot be read by the configured backend
+    pub fn list_archive(&self, archive: impl AsRef<Path>) -> Result<Vec<Ba2Entry>> {
+        let archive_path = self.fallout4_dir.join("Data").join(archive);
+        self.backend.list(&archive_path)
+    }
+
+    /// Extract every file in an archive into `dest_dir`
+    ///
+    /// `archive` is the name of an archive in `Data/` or an absolute path, as for
+    /// [`list_archive`](Self::list_archive). Used by the `archive extract` subcommand.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive cannot be read or the files cannot be written
+    pub fn extract_archive(
+        &self,
+        archive: impl AsRef<Path>,
+        dest_dir: impl AsRef<Path>,
+    ) -> Result<()> {
+        let archive_path = self.fallout4_dir.join("Data").join(archive);
+        self.backend.extract(&archive_path, dest_dir.as_ref())
     }
 
     /// Add previs files to an existing archive (MO2-aware)
@@ -650,15 +783,16 @@ impl ArchiveManager {
     /// # Arguments
     ///
     /// * `archive_name` - Name of the existing archive (e.g., `"MyMod - Main.ba2"`). **Must exist.**
-    /// * `is_xbox` - If `true`, uses Xbox compression format; if `false`, uses PC format
-    ///   (only relevant for Archive2 re-archiving)
+    /// * `profile` - Requested compression (see [`add_to_archive`](Self::add_to_archive) for
+    ///   how each backend handles Xbox builds)
     /// * `mo2_data_dir` - Optional path to MO2's VFS staging directory (e.g., `overwrite` folder).
     ///   When `Some`, files are collected from MO2's VFS. When `None`, files are read directly
     ///   from `Data/vis`.
     ///
     /// # Returns
     ///
-    /// Returns `Ok(())` if previs files were successfully added to the archive
+    /// Returns the writer that packed the previs files (see
+    /// [`add_to_archive`](Self::add_to_archive))
     ///
     /// # Errors
     ///
@@ -683,9 +817,9 @@ impl ArchiveManager {
     ///
     /// # Archive Tool Behavior
     ///
-    /// - **Archive2:** Extracts entire archive, adds previs files, re-archives everything
-    ///   (see [`add_to_archive`](Self::add_to_archive) for details on the extract/repack process)
-    /// - **`BSArch`:** Directly appends previs files to existing archive (much faster)
+    /// - **Archive2/Native:** Appends previs files to the archive in place, with a
+    ///   `.ba2.bak` backup (see [`add_to_archive`](Self::add_to_archive) for details)
+    /// - **`BSArch`:** Directly appends previs files to existing archive
     ///
     /// # File Collection Process
     ///
@@ -698,6 +832,7 @@ impl ArchiveManager {
     /// ```no_run
     /// use std::path::Path;
     /// # use generateprevisibines::tools::ArchiveManager;
+    /// # use generateprevisibines::tools::archive_backend::CompressionProfile;
     /// # use generateprevisibines::config::ArchiveTool;
     /// # use std::path::PathBuf;
     /// # let manager = ArchiveManager::new(
@@ -708,50 +843,47 @@ impl ArchiveManager {
     /// # )?;
     ///
     /// // Standard mode (no MO2)
-    /// manager.add_previs_to_archive("MyMod - Main.ba2", false, None)?;
+    /// manager.add_previs_to_archive("MyMod - Main.ba2", CompressionProfile::Default, None)?;
     ///
     /// // MO2 mode - collect from VFS
     /// let mo2_overwrite = Path::new("C:\\MO2\\overwrite");
     /// manager.add_previs_to_archive(
     ///     "MyMod - Main.ba2",
-    ///     false,
+    ///     CompressionProfile::Default,
     ///     Some(mo2_overwrite)
     /// )?;
     ///
     /// // Xbox format
-    /// manager.add_previs_to_archive("MyMod - Main.ba2", true, None)?;
+    /// manager.add_previs_to_archive("MyMod - Main.ba2", CompressionProfile::Xbox, None)?;
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     ///
     /// # Performance Notes
     ///
-    /// For a 500MB precombined archive with 10MB of previs data:
-    /// - **Archive2:** ~3-5 minutes (must extract and re-compress entire 510MB)
-    /// - **`BSArch`:** ~5-10 seconds (directly appends 10MB)
-    ///
-    /// `BSArch` is **strongly recommended** for this operation due to the significant
-    /// performance difference.
+    /// For a 500MB precombined archive with 10MB of previs data, every tool only compresses
+    /// and writes the 10MB of new data, plus one copy of the archive for the backup.
     ///
     /// # Notes
     ///
     /// - The archive **must exist** before calling this function
     /// - Typically called after `create_archive_from_precombines`
     /// - Temporary MO2 collection directories are always cleaned up
-    /// - Source files in `Data/vis` (or MO2 staging) are deleted after archiving
+    /// - Source files in `Data/vis` (or MO2 staging) are deleted after archiving, except
+    ///   with `BSArch`
     ///
     /// # See Also
     ///
-    /// - [`add_to_archive`](Self::add_to_archive) for detailed Archive2 limitation documentation
+    /// - [`add_to_archive`](Self::add_to_archive) for details on the in-place append
     /// - [`create_archive_from_precombines`](Self::create_archive_from_precombines) for creating the initial archive
     pub fn add_previs_to_archive(
         &self,
         archive_name: &str,
-        is_xbox: bool,
+        profile: CompressionProfile,
         mo2_data_dir: Option<&Path>,
-    ) -> Result<()> {
+    ) -> Result<Packer> {
         let data_dir = self.fallout4_dir.join("Data");
 
-        if let Some(mo2_staging) = mo2_data_dir {
+        let packer = if let Some(mo2_staging) = mo2_data_dir {
             // MO2 mode: Collect files from staging directory
             let mo2_helper = Mo2Helper::new(mo2_staging)?;
             info!(
@@ -767,219 +899,321 @@ impl ArchiveManager {
 
             if let Some(collected) = collected_dir {
                 // Add collected files to archive
-                self.add_to_archive(&collected, archive_name, is_xbox)?;
+                let packer = self.add_to_archive(&collected, archive_name, profile)?;
 
                 // Cleanup temp directory
                 if temp_collect.exists() {
                     fs::remove_dir_all(&temp_collect)?;
                 }
+                packer
             } else {
                 bail!("No previs data found in MO2 staging directory");
             }
         } else {
             // Standard mode: Use files from Data directory
             let vis_dir = data_dir.join("vis");
-            self.add_to_archive(&vis_dir, archive_name, is_xbox)?;
-        }
+            self.add_to_archive(&vis_dir, archive_name, profile)?
+        };
 
-        Ok(())
+        Ok(packer)
     }
 
-    /// Create archive using Archive2
+    /// Describe what [`create_archive_from_precombines`](Self::create_archive_from_precombines)
+    /// would do, without doing it
     ///
-    /// Internal helper that invokes Archive2.exe to create a BA2 archive from a directory.
-    ///
-    /// # Arguments
-    ///
-    /// * `source_dir` - Directory containing files to archive
-    /// * `archive_path` - Full path to the archive file to create
-    /// * `is_xbox` - If `true`, uses Xbox compression; otherwise uses PC compression
-    ///
-    /// # Archive2 Command
-    ///
-    /// Executes: `Archive2.exe <source_dir> -c=<archive_path> -f=General -q [-compression=XBox]`
-    ///
-    /// # Errors
+    /// Used by `--dry-run`. Returns the actions in order: collecting from the MO2 staging
+    /// directory (if given), backing up an existing archive, the backend's command line,
+    /// verification and deleting the source. Nothing is copied, written or deleted.
+    pub fn plan_precombines(
+        &self,
+        archive_name: &str,
+        profile: CompressionProfile,
+        mo2_data_dir: Option<&Path>,
+    ) -> Vec<String> {
+        self.plan_collected(
+            &["meshes", "precombined"],
+            mo2_data_dir,
+            |source, actions| {
+                self.plan_create(source, archive_name, profile, actions);
+            },
+        )
+    }
+
+    /// Describe what [`add_previs_to_archive`](Self::add_previs_to_archive) would do,
+    /// without doing it
     ///
-    /// Returns an error if Archive2.exe fails or cannot be executed
-    fn archive2_create(&self, source_dir: &Path, archive_path: &Path, is_xbox: bool) -> Result<()> {
-        let Some(ref archive2_exe) = self.archive2_exe else {
-            bail!("Archive2.exe not configured");
-        };
+    /// Used by `--dry-run`; see [`plan_precombines`](Self::plan_precombines).
+    pub fn plan_previs(
+        &self,
+        archive_name: &str,
+        profile: CompressionProfile,
+        mo2_data_dir: Option<&Path>,
+    ) -> Vec<String> {
+        self.plan_collected(&["vis"], mo2_data_dir, |source, actions| {
+            self.plan_append(source, archive_name, profile, actions);
+        })
+    }
 
-        info!("Creating archive with Archive2: {}", archive_path.display());
+    /// Plan an archive operation on `Data\<subpath>`, or on files collected from the MO2
+    /// staging directory into `Data\_temp_mo2_collect`
+    fn plan_collected(
+        &self,
+        subpath: &[&str],
+        mo2_data_dir: Option<&Path>,
+        plan: impl FnOnce(&Path, &mut Vec<String>),
+    ) -> Vec<String> {
+        let data_dir = self.fallout4_dir.join("Data");
+        let join = |base: &Path| {
+            subpath
+                .iter()
+                .fold(base.to_path_buf(), |dir, part| dir.join(part))
+        };
+        let mut actions = Vec::new();
 
-        let mut args = vec![
-            source_dir.to_string_lossy().to_string(),
-            format!("-c={}", archive_path.display()),
-            "-f=General".to_string(),
-            "-q".to_string(), // Quiet mode
-        ];
+        if let Some(mo2_staging) = mo2_data_dir {
+            let temp_collect = data_dir.join("_temp_mo2_collect");
+            actions.push(format!(
+                "Copy {} to {}",
+                join(mo2_staging).display(),
+                join(&temp_collect).display()
+            ));
+            plan(&temp_collect, &mut actions);
+            // Backends that delete their source have already removed it
+            if self.backend.keeps_source() {
+                actions.push(format!("Delete {}", temp_collect.display()));
+            }
+        } else {
+            plan(&join(&data_dir), &mut actions);
+        }
+        actions
+    }
 
-        if is_xbox {
-            args.push("-compression=XBox".to_string());
+    /// Plan [`create_archive`](Self::create_archive)
+    fn plan_create(
+        &self,
+        source_dir: &Path,
+        archive_name: &str,
+        profile: CompressionProfile,
+        actions: &mut Vec<String>,
+    ) {
+        let archive_path = self.fallout4_dir.join("Data").join(archive_name);
+        if archive_path.exists() {
+            actions.push(format!(
+                "Move the existing archive to {} and copy its entries outside \
+                 meshes\\precombined\\ and vis\\ into the new archive",
+                archive_path.with_extension("ba2.bak").display()
+            ));
         }
 
-        let output = Command::new(archive2_exe)
-            .args(&args)
-            .current_dir(&self.fallout4_dir)
-            .output()
-            .with_context(|| format!("Failed to run Archive2: {}", archive2_exe.display()))?;
+        actions.push(self.backend.plan(source_dir, &archive_path, profile, false));
+        self.plan_verify_and_cleanup(source_dir, actions);
+    }
 
-        if !output.status.success() {
-            bail!(
-                "Archive2 failed: {}\nStderr: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stderr)
-            );
+    /// Plan [`add_to_archive`](Self::add_to_archive)
+    fn plan_append(
+        &self,
+        source_dir: &Path,
+        archive_name: &str,
+        profile: CompressionProfile,
+        actions: &mut Vec<String>,
+    ) {
+        let archive_path = self.fallout4_dir.join("Data").join(archive_name);
+        actions.push(format!(
+            "Copy {} to {}",
+            archive_path.display(),
+            archive_path.with_extension("ba2.bak").display()
+        ));
+
+        actions.push(self.backend.plan(source_dir, &archive_path, profile, true));
+        self.plan_verify_and_cleanup(source_dir, actions);
+    }
+
+    fn plan_verify_and_cleanup(&self, source_dir: &Path, actions: &mut Vec<String>) {
+        actions.push(format!(
+            "Verify the archive against {}",
+            source_dir.display()
+        ));
+        if let Some(budget) = self.size_budget {
+            actions.push(format!(
+                "Check the archive size against the {} ({})",
+                budget.description,
+                archive_budget::format_size(budget.limit)
+            ));
         }
+        if !self.backend.keeps_source() {
+            actions.push(format!("Delete {}", source_dir.display()));
+        }
+    }
 
+    /// Check a written archive against the size budget, if one is set
+    fn check_size(&self, archive_path: &Path) -> Result<()> {
+        if let Some(budget) = self.size_budget {
+            archive_budget::check_actual(budget, archive_path)?;
+        }
         Ok(())
     }
 
-    /// Extract archive using Archive2
-    ///
-    /// Internal helper that invokes Archive2.exe to extract a BA2 archive.
+    /// Whether an archive entry is regenerated by the workflow
     ///
-    /// This is used as part of the extract/repack workaround for adding files to
-    /// existing archives (see [`add_to_archive`](Self::add_to_archive)).
-    ///
-    /// # Arguments
-    ///
-    /// * `archive_path` - Full path to the archive file to extract
-    /// * `dest_dir` - Directory where files will be extracted
+    /// Precombined meshes (`meshes\precombined\`) and previs data (`vis\`) are replaced on
+    /// every run; everything else in `<Plugin> - Main.ba2` belongs to the mod and is kept.
+    fn is_generated_asset(name: &str) -> bool {
+        let name = normalize_archive_path(name).to_lowercase();
+        name.starts_with("meshes\\precombined\\") || name.starts_with("vis\\")
+    }
+
+    /// Copy the mod's own assets from the previous archive into the new one
     ///
-    /// # Archive2 Command
+    /// Internal helper for [`create_archive`](Self::create_archive). Every entry of
+    /// `old_archive` that is not a generated asset (see
+    /// [`is_generated_asset`](Self::is_generated_asset)) is appended to `archive_path`
+    /// byte-for-byte with [`Ba2Writer::add_archived`], keeping its original compression.
     ///
-    /// Executes: `Archive2.exe <archive_path> -e=<dest_dir> -q`
+    /// Returns the archive paths of the preserved entries.
     ///
     /// # Errors
     ///
-    /// Returns an error if Archive2.exe fails or cannot be executed
-    fn archive2_extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()> {
-        let Some(ref archive2_exe) = self.archive2_exe else {
-            bail!("Archive2.exe not configured");
-        };
+    /// Returns an error if the old archive cannot be read (e.g., it is a texture archive),
+    /// the append fails, or a preserved entry is missing from the result
+    fn merge_preserved_assets(old_archive: &Path, archive_path: &Path) -> Result<Vec<String>> {
+        let old = Ba2Archive::open(old_archive)
+            .context("Failed to read the existing archive to preserve its assets")?;
+
+        let mut writer = Ba2Writer::new(true);
+        let mut preserved = Vec::new();
+        for entry in old.entries() {
+            if !Self::is_generated_asset(&entry.name) {
+                writer.add_archived(&old, entry)?;
+                preserved.push(entry.name.clone());
+            }
+        }
+
+        if preserved.is_empty() {
+            info!("Existing archive contained only precombine/previs files; nothing to preserve");
+            return Ok(preserved);
+        }
 
         info!(
-            "Extracting archive with Archive2: {}",
-            archive_path.display()
+            "Preserving {} existing assets from {}",
+            preserved.len(),
+            old_archive.display()
         );
+        writer.append_to(archive_path)?;
 
-        let output = Command::new(archive2_exe)
-            .args(&[
-                archive_path.to_string_lossy().to_string(),
-                format!("-e={}", dest_dir.display()),
-                "-q".to_string(),
-            ])
-            .current_dir(&self.fallout4_dir)
-            .output()
-            .with_context(|| format!("Failed to run Archive2: {}", archive2_exe.display()))?;
-
-        if !output.status.success() {
-            bail!(
-                "Archive2 extraction failed: {}\nStderr: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stderr)
-            );
+        let merged = Ba2Archive::open(archive_path)?;
+        let original = old.name_index();
+        let copied = merged.name_index();
+        for name in &preserved {
+            let key = index_key(name);
+            let stored_size = |index: &HashMap<String, &Ba2Entry>| {
+                index.get(&key).map(|entry| entry.stored_size())
+            };
+            if stored_size(&copied) != stored_size(&original) {
+                bail!("Preserved asset '{name}' did not survive the merge");
+            }
         }
 
-        Ok(())
+        Ok(preserved)
     }
 
-    /// Pack archive using `BSArch`
-    ///
-    /// Internal helper that invokes BSArch.exe to create or append to a BA2 archive.
-    ///
-    /// Unlike Archive2, `BSArch` can both create new archives and append to existing ones
-    /// using the same command. If the archive exists, files are appended; if not, it's created.
-    ///
-    /// # Arguments
-    ///
-    /// * `source_dir` - Directory containing files to archive or append
-    /// * `archive_path` - Full path to the archive file (created if doesn't exist)
-    ///
-    /// # `BSArch` Command
+    /// Verify that every file in `source_dir` made it into the archive intact
     ///
-    /// Executes: `BSArch.exe pack <source_dir> <archive_path> -mt -fo4 -z`
-    ///
-    /// Flags:
-    /// - `-mt`: Multi-threaded compression
-    /// - `-fo4`: Fallout 4 archive format
-    /// - `-z`: Compress files
+    /// Internal helper called after every create/append and **before** any source files
+    /// are deleted. Archive tools only report an exit status, so without this a silently
+    /// truncated archive would leave the mod with neither loose files nor working archives.
+    /// Each file's size and SHA-256 content hash are compared (see
+    /// [`Ba2Archive::verify_directory`]).
     ///
     /// # Errors
     ///
-    /// Returns an error if BSArch.exe fails or cannot be executed
-    fn bsarch_pack(&self, source_dir: &Path, archive_path: &Path) -> Result<()> {
-        let Some(ref bsarch_exe) = self.bsarch_exe else {
-            bail!("BSArch.exe not configured");
-        };
+    /// Returns an error if the archive cannot be read, or if any source file is missing
+    /// from it or differs from the archived copy (each problem is logged)
+    fn verify_archive(source_dir: &Path, archive_path: &Path) -> Result<()> {
+        info!("Verifying archive contents: {}", archive_path.display());
 
-        info!("Packing archive with BSArch: {}", archive_path.display());
-
-        let output = Command::new(bsarch_exe)
-            .args([
-                "pack",
-                &source_dir.to_string_lossy(),
-                &archive_path.to_string_lossy(),
-                "-mt",  // Multi-threaded
-                "-fo4", // Fallout 4 format
-                "-z",   // Compress
-            ])
-            .current_dir(&self.fallout4_dir)
-            .output()
-            .with_context(|| format!("Failed to run BSArch: {}", bsarch_exe.display()))?;
-
-        if !output.status.success() {
+        let report = Ba2Archive::open(archive_path)
+            .context("Archive verification failed")?
+            .verify_directory(archive_root(source_dir), source_dir)?;
+
+        if !report.is_ok() {
+            for name in &report.missing {
+                error!("Missing from archive: {name}");
+            }
+            for problem in &report.mismatched {
+                error!("Archive content mismatch: {problem}");
+            }
             bail!(
-                "BSArch failed: {}\nStderr: {}",
-                output.status,
-                String::from_utf8_lossy(&output.stderr)
+                "Archive verification failed for {}: {} files missing, {} files differ.\n\
+                Source files have been kept in {}. See the log for the file list.",
+                archive_path.display(),
+                report.missing.len(),
+                report.mismatched.len(),
+                source_dir.display()
             );
         }
 
+        info!(
+            "Verified {} files in {}",
+            report.verified,
+            archive_path.display()
+        );
         Ok(())
     }
 
-    /// Recursively copy directory contents
-    ///
-    /// Internal helper that copies all files and subdirectories from source to destination.
-    /// Used as part of the Archive2 extract/repack workaround to merge new files with
-    /// extracted archive contents.
-    ///
-    /// # Arguments
+    /// Verify that an append kept every entry the archive already had
     ///
-    /// * `src` - Source directory to copy from
-    /// * `dst` - Destination directory to copy to (created if doesn't exist)
-    ///
-    /// # Behavior
-    ///
-    /// - Creates destination directory if it doesn't exist
-    /// - Recursively copies all subdirectories
-    /// - Overwrites existing files at destination
+    /// Internal helper for [`add_to_archive`](Self::add_to_archive), called before the
+    /// backup is deleted. [`verify_archive`](Self::verify_archive) only checks the new
+    /// files; this checks that every entry of `backup_path` is still in `archive_path` with
+    /// the same unpacked size, so an append that rebuilt the archive from the new files
+    /// alone (dropping the precombined meshes) is caught. Entries replaced by a file in
+    /// `source_dir` only need to be present.
     ///
     /// # Errors
     ///
-    /// Returns an error if any file or directory operation fails
-    fn copy_dir_recursive(src: &Path, dst: &Path) -> Result<()> {
-        if !dst.exists() {
-            fs::create_dir_all(dst)?;
-        }
+    /// Returns an error if either archive cannot be read, or if any existing entry is
+    /// missing or changed size (each problem is logged)
+    fn verify_kept_entries(
+        backup_path: &Path,
+        archive_path: &Path,
+        source_dir: &Path,
+    ) -> Result<()> {
+        let before = Ba2Archive::open(backup_path).context("Failed to read the archive backup")?;
+        let after = Ba2Archive::open(archive_path).context("Archive verification failed")?;
+        let root = archive_root(source_dir);
 
-        for entry in fs::read_dir(src)? {
-            let entry = entry?;
-            let file_type = entry.file_type()?;
-            let src_path = entry.path();
-            let dst_path = dst.join(entry.file_name());
+        let current = after.name_index();
+        let mut replaced = HashSet::new();
+        for source in WalkDir::new(source_dir) {
+            let source =
+                source.with_context(|| format!("Failed to walk {}", source_dir.display()))?;
+            if source.file_type().is_file() {
+                replaced.insert(index_key(&archive_name_for(root, source.path())?));
+            }
+        }
 
-            if file_type.is_dir() {
-                Self::copy_dir_recursive(&src_path, &dst_path)?;
-            } else {
-                fs::copy(&src_path, &dst_path)?;
+        let mut lost = 0;
+        for entry in before.entries() {
+            let key = index_key(&entry.name);
+            let replaced = replaced.contains(&key);
+            match current.get(&key) {
+                None => error!("Existing entry dropped from archive: {}", entry.name),
+                Some(kept) if !replaced && kept.unpacked_size != entry.unpacked_size => error!(
+                    "Existing entry changed size: {} ({} -> {} bytes)",
+                    entry.name, entry.unpacked_size, kept.unpacked_size
+                ),
+                Some(_) => continue,
             }
+            lost += 1;
         }
 
+        if lost > 0 {
+            bail!(
+                "Archive verification failed for {}: {lost} of its existing files were lost or \
+                changed by the append. See the log for the file list.",
+                archive_path.display()
+            );
+        }
         Ok(())
     }
 }
@@ -987,6 +1221,7 @@ impl ArchiveManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tools::archive_backend::{BackendCall, RecordingBackend};
 
     #[test]
     fn test_archive_manager_requires_exe() {
@@ -1016,5 +1251,473 @@ mod tests {
             "F:\\Games\\Fallout4",
         );
         assert!(result.is_ok());
+
+        // Native needs no executable
+        let result = ArchiveManager::new(ArchiveTool::Native, None, None, "F:\\Games\\Fallout4");
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_native_create_archive_deletes_source() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let precombined = temp.path().join("Data").join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("0001F4A2_OC.nif"), b"mesh").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        manager
+            .create_archive(&precombined, "Test - Main.ba2", CompressionProfile::Default)
+            .unwrap();
+
+        assert!(temp.path().join("Data").join("Test - Main.ba2").exists());
+        assert!(!precombined.exists());
+    }
+
+    #[test]
+    fn test_native_create_xbox_is_compressed() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let precombined = temp.path().join("Data").join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a.nif"), b"mesh").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        manager
+            .create_archive(&precombined, "Test - Main.ba2", CompressionProfile::Xbox)
+            .unwrap();
+
+        // Never silently stored uncompressed
+        let archive = Ba2Archive::open(temp.path().join("Data").join("Test - Main.ba2")).unwrap();
+        assert!(archive.entries().iter().all(Ba2Entry::is_compressed));
+    }
+
+    #[test]
+    fn test_add_previs_appends_in_place() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("0001F4A2_OC.nif"), b"mesh").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        manager
+            .create_archive_from_precombines("Test - Main.ba2", CompressionProfile::Default, None)
+            .unwrap();
+
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+        manager
+            .add_previs_to_archive("Test - Main.ba2", CompressionProfile::Default, None)
+            .unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        let archive = crate::tools::ba2_reader::Ba2Archive::open(&archive_path).unwrap();
+        assert_eq!(archive.entries().len(), 2);
+        let uvd = archive.find("vis\\0000003c.uvd").unwrap();
+        assert_eq!(archive.read(uvd).unwrap(), b"previs");
+
+        assert!(!vis.exists());
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+        assert!(!data_dir.join("_temp_archive_extract").exists());
+    }
+
+    #[test]
+    fn test_failed_append_restores_backup() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+
+        // Not a valid BA2, so the append fails after the backup is taken
+        let archive_path = data_dir.join("Test - Main.ba2");
+        fs::write(&archive_path, b"original bytes").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        assert!(
+            manager
+                .add_to_archive(&vis, "Test - Main.ba2", CompressionProfile::Default)
+                .is_err()
+        );
+
+        assert_eq!(fs::read(&archive_path).unwrap(), b"original bytes");
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+        assert!(vis.join("0000003c.uvd").exists());
+    }
+
+    #[test]
+    fn test_verify_archive_detects_missing_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a.nif"), b"mesh").unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.write(&archive_path).unwrap();
+
+        assert!(ArchiveManager::verify_archive(&precombined, &archive_path).is_ok());
+
+        fs::write(precombined.join("b.nif"), b"not archived").unwrap();
+        assert!(ArchiveManager::verify_archive(&precombined, &archive_path).is_err());
+    }
+
+    #[test]
+    fn test_plans_touch_nothing() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let staging = temp.path().join("overwrite");
+        fs::create_dir_all(staging.join("vis")).unwrap();
+        fs::write(staging.join("vis").join("0000003c.uvd"), b"previs").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        let actions =
+            manager.plan_previs("Test - Main.ba2", CompressionProfile::Xbox, Some(&staging));
+
+        let temp_collect = data_dir.join("_temp_mo2_collect");
+        assert_eq!(
+            actions,
+            [
+                format!(
+                    "Copy {} to {}",
+                    staging.join("vis").display(),
+                    temp_collect.join("vis").display()
+                ),
+                format!(
+                    "Copy {} to {}",
+                    data_dir.join("Test - Main.ba2").display(),
+                    data_dir.join("Test - Main.ba2.bak").display()
+                ),
+                format!(
+                    "Append to {} from {} with the built-in BA2 writer (Xbox compression)",
+                    data_dir.join("Test - Main.ba2").display(),
+                    temp_collect.display()
+                ),
+                format!("Verify the archive against {}", temp_collect.display()),
+                format!("Delete {}", temp_collect.display()),
+            ]
+        );
+        assert!(!data_dir.exists());
+    }
+
+    #[test]
+    fn test_create_preserves_existing_assets() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let archive_path = data_dir.join("Test - Main.ba2");
+
+        // Existing archive with a script, an old precombine and old previs data
+        let old = temp.path().join("old");
+        for (path, data) in [
+            ("scripts/MyQuest.pex", &b"script"[..]),
+            ("meshes/precombined/old_OC.nif", b"old mesh"),
+            ("vis/old.uvd", b"old previs"),
+        ] {
+            let file = old.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, data).unwrap();
+        }
+        fs::create_dir_all(&data_dir).unwrap();
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(&old, &old).unwrap();
+        writer.write(&archive_path).unwrap();
+
+        let precombined = data_dir.join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("new_OC.nif"), b"new mesh").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        let created = manager
+            .create_archive_from_precombines("Test - Main.ba2", CompressionProfile::Default, None)
+            .unwrap();
+        assert_eq!(created.preserved, ["scripts\\MyQuest.pex"]);
+
+        let archive = Ba2Archive::open(&archive_path).unwrap();
+        let mut names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            ["meshes\\precombined\\new_OC.nif", "scripts\\MyQuest.pex"]
+        );
+        let script = archive.find("scripts\\MyQuest.pex").unwrap();
+        assert_eq!(archive.read(script).unwrap(), b"script");
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+    }
+
+    #[test]
+    fn test_create_restores_unreadable_existing_archive() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("new_OC.nif"), b"new mesh").unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        fs::write(&archive_path, b"not a general archive").unwrap();
+
+        let manager = ArchiveManager::new(ArchiveTool::Native, None, None, temp.path()).unwrap();
+        assert!(
+            manager
+                .create_archive_from_precombines(
+                    "Test - Main.ba2",
+                    CompressionProfile::Default,
+                    None
+                )
+                .is_err()
+        );
+
+        assert_eq!(fs::read(&archive_path).unwrap(), b"not a general archive");
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+        assert!(precombined.join("new_OC.nif").exists());
+    }
+
+    #[test]
+    fn test_is_generated_asset() {
+        assert!(ArchiveManager::is_generated_asset(
+            "meshes\\precombined\\a.nif"
+        ));
+        assert!(ArchiveManager::is_generated_asset(
+            "Meshes/Precombined/a.nif"
+        ));
+        assert!(ArchiveManager::is_generated_asset("VIS\\0000003c.uvd"));
+        assert!(!ArchiveManager::is_generated_asset(
+            "meshes\\mymod\\chair.nif"
+        ));
+        assert!(!ArchiveManager::is_generated_asset(
+            "scripts\\vis\\quest.pex"
+        ));
+    }
+
+    #[test]
+    fn test_manager_dispatches_to_backend() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a_OC.nif"), b"mesh").unwrap();
+
+        let backend = RecordingBackend::default();
+        let calls = backend.calls();
+        let manager = ArchiveManager::with_backend(Box::new(backend), temp.path());
+        manager
+            .create_archive_from_precombines("Test - Main.ba2", CompressionProfile::Xbox, None)
+            .unwrap();
+
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+        manager
+            .add_previs_to_archive("Test - Main.ba2", CompressionProfile::Xbox, None)
+            .unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        let names: Vec<_> = manager
+            .list_archive("Test - Main.ba2")
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["meshes\\precombined\\a_OC.nif", "vis\\0000003c.uvd"]
+        );
+        assert_eq!(
+            *calls.borrow(),
+            [
+                BackendCall::Create {
+                    source_dir: precombined.clone(),
+                    archive_path: archive_path.clone(),
+                    profile: CompressionProfile::Xbox,
+                },
+                BackendCall::Append {
+                    source_dir: vis.clone(),
+                    archive_path: archive_path.clone(),
+                    profile: CompressionProfile::Xbox,
+                },
+                BackendCall::List { archive_path },
+            ]
+        );
+
+        // The manager deletes sources for backends that do not keep them
+        assert!(!precombined.exists());
+        assert!(!vis.exists());
+    }
+
+    #[test]
+    fn test_backend_append_failure_restores_backup() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        fs::write(&archive_path, b"original bytes").unwrap();
+
+        let manager = ArchiveManager::with_backend(
+            Box::new(RecordingBackend::default().failing_append()),
+            temp.path(),
+        );
+        assert!(
+            manager
+                .add_to_archive(&vis, "Test - Main.ba2", CompressionProfile::Default)
+                .is_err()
+        );
+
+        assert_eq!(fs::read(&archive_path).unwrap(), b"original bytes");
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+        assert!(vis.join("0000003c.uvd").exists());
+    }
+
+    #[test]
+    fn test_append_dropping_existing_entries_restores_backup() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("0001F4A2_OC.nif"), b"mesh").unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.write(&archive_path).unwrap();
+        let original = fs::read(&archive_path).unwrap();
+
+        // The new files verify, but the precombined mesh is gone
+        let manager = ArchiveManager::with_backend(
+            Box::new(RecordingBackend::default().rebuilding_on_append()),
+            temp.path(),
+        );
+        let err = manager
+            .add_to_archive(&vis, "Test - Main.ba2", CompressionProfile::Default)
+            .unwrap_err();
+        assert!(err.to_string().contains("existing files were lost"));
+
+        assert_eq!(fs::read(&archive_path).unwrap(), original);
+        assert!(!archive_path.with_extension("ba2.bak").exists());
+        assert!(vis.join("0000003c.uvd").exists());
+    }
+
+    #[test]
+    fn test_oversized_archive_keeps_sources() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("0001F4A2_OC.nif"), b"mesh").unwrap();
+        fs::write(vis.join("0000003c.uvd"), [7u8; 4096]).unwrap();
+
+        let archive_path = data_dir.join("Test - Main.ba2");
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.write(&archive_path).unwrap();
+        let original = fs::read(&archive_path).unwrap();
+
+        let budget = SizeBudget {
+            limit: original.len() as u64 + 1024,
+            description: "test limit",
+        };
+        let manager =
+            ArchiveManager::with_backend(Box::new(RecordingBackend::default()), temp.path())
+                .with_size_budget(budget);
+
+        // Appending fails the budget: the archive is restored and the previs files kept
+        let err = manager
+            .add_to_archive(&vis, "Test - Main.ba2", CompressionProfile::None)
+            .unwrap_err();
+        assert!(err.to_string().contains("exceeds the test limit"));
+        assert_eq!(fs::read(&archive_path).unwrap(), original);
+        assert!(vis.join("0000003c.uvd").exists());
+
+        // Rebuilding fails the same way and keeps the loose meshes
+        fs::write(precombined.join("0001F4A2_OC.nif"), [7u8; 4096]).unwrap();
+        assert!(
+            manager
+                .create_archive(&precombined, "Test - Main.ba2", CompressionProfile::None)
+                .is_err()
+        );
+        assert_eq!(fs::read(&archive_path).unwrap(), original);
+        assert!(precombined.join("0001F4A2_OC.nif").exists());
+    }
+
+    #[test]
+    fn test_verify_kept_entries_allows_replaced_files() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"old").unwrap();
+
+        let backup_path = data_dir.join("Test - Main.ba2.bak");
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(&data_dir, &vis).unwrap();
+        writer.write(&backup_path).unwrap();
+
+        fs::write(vis.join("0000003c.uvd"), b"new previs").unwrap();
+        let archive_path = data_dir.join("Test - Main.ba2");
+        fs::copy(&backup_path, &archive_path).unwrap();
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(&data_dir, &vis).unwrap();
+        writer.append_to(&archive_path).unwrap();
+
+        assert!(ArchiveManager::verify_kept_entries(&backup_path, &archive_path, &vis).is_ok());
+
+        // A changed size is only accepted for entries the source folder replaces
+        fs::remove_dir_all(&vis).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        assert!(ArchiveManager::verify_kept_entries(&backup_path, &archive_path, &vis).is_err());
+    }
+
+    #[test]
+    fn test_backend_create_failure_keeps_source() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let precombined = temp.path().join("Data").join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a.nif"), b"mesh").unwrap();
+
+        let manager = ArchiveManager::with_backend(
+            Box::new(RecordingBackend::default().failing_create()),
+            temp.path(),
+        );
+        assert!(
+            manager
+                .create_archive(&precombined, "Test - Main.ba2", CompressionProfile::Default)
+                .is_err()
+        );
+        assert!(precombined.join("a.nif").exists());
+    }
+
+    #[test]
+    fn test_backend_keeping_source() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("a_OC.nif"), b"mesh").unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+
+        let manager = ArchiveManager::with_backend(
+            Box::new(RecordingBackend::default().keeping_source()),
+            temp.path(),
+        );
+        manager
+            .create_archive_from_precombines("Test - Main.ba2", CompressionProfile::Default, None)
+            .unwrap();
+        manager
+            .add_previs_to_archive("Test - Main.ba2", CompressionProfile::Default, None)
+            .unwrap();
+
+        assert!(precombined.join("a_OC.nif").exists());
+        assert!(vis.join("0000003c.uvd").exists());
+        assert!(!data_dir.join("Test - Main.ba2.bak").exists());
     }
 }
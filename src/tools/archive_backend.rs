@@ -0,0 +1,924 @@
+//! Pluggable archive backends
+//!
+//! [`ArchiveManager`](super::ArchiveManager) handles the workflow-level concerns (MO2
+//! collection, backups, verification, preserving existing assets and deleting source
+//! folders) and delegates the actual archive operations to an [`ArchiveBackend`]:
+//!
+//! | Backend | `create` | `append` | `extract` | `list` | Keeps source |
+//! |---------|----------|----------|-----------|--------|--------------|
+//! | [`Archive2Backend`] | Archive2.exe | In place (native) | Archive2.exe | Native reader | No |
+//! | [`BsarchBackend`] | `BSArch pack` | In place (native) | `BSArch unpack` | Native reader | Yes |
+//! | [`NativeBackend`] | [`Ba2Writer`] | In place (native) | [`Ba2Archive`] | Native reader | No |
+//!
+//! Tests use `RecordingBackend`, which records every call and writes real (uncompressed)
+//! archives with the native writer, so the manager's verification and merging logic can
+//! be exercised without Archive2 or `BSArch` installed.
+//!
+//! # Compression Profiles
+//!
+//! Each [`BuildMode`] maps to a [`CompressionProfile`] (see
+//! [`CompressionProfile::for_build_mode`]); `--uncompressed` (or `uncompressed = true` in a
+//! batch manifest) selects [`CompressionProfile::None`] instead. The workflow passes the
+//! profile to the manager, which passes it to `create`/`append`.
+//! Every backend writes every profile, using the native writer where the tool cannot:
+//!
+//! | Backend | Default | Xbox | None |
+//! |---------|---------|------|------|
+//! | Archive2 (create) | Default zlib | `-compression=XBox` | `-compression=None` |
+//! | Archive2 (append) | zlib | Native writer (Xbox zlib) | Uncompressed |
+//! | `BSArch` | `-z` | Native writer (Xbox zlib) | Uncompressed |
+//! | Native | zlib | Xbox zlib | Uncompressed |
+//!
+//! Xbox zlib is ordinary zlib with a 4 KiB window (see
+//! [`XBOX_WINDOW_BITS`](crate::tools::ba2::XBOX_WINDOW_BITS)).
+//!
+//! Because of this, the selected tool is not always the one that packed an archive.
+//! `create` and `append` return the [`Packer`] that actually did, which the workflow
+//! reports in its summary and in the archive manifest.
+//!
+//! # Archive Paths
+//!
+//! All backends name files relative to the directory that corresponds to `Data` (see
+//! [`archive_root`]), so `Data/meshes/precombined/a.nif` is stored as
+//! `meshes\precombined\a.nif` regardless of the tool.
+
+use anyhow::{Context, Result, bail};
+use log::info;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::config::BuildMode;
+use crate::tools::ba2::Ba2Writer;
+use crate::tools::ba2_reader::{Ba2Archive, Ba2Entry};
+use crate::utils;
+
+/// How the files in an archive are compressed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionProfile {
+    /// Standard zlib compression used by PC archives
+    Default,
+    /// Archive2's Xbox compression variant (`-compression=XBox`)
+    Xbox,
+    /// Files stored uncompressed (valid on every platform)
+    None,
+}
+
+impl CompressionProfile {
+    /// The profile a build mode requires: Xbox builds use Xbox compression, everything
+    /// else uses default zlib
+    pub fn for_build_mode(build_mode: BuildMode) -> Self {
+        match build_mode {
+            BuildMode::Clean | BuildMode::Filtered => Self::Default,
+            BuildMode::Xbox => Self::Xbox,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Default => "zlib",
+            Self::Xbox => "Xbox",
+            Self::None => "uncompressed",
+        }
+    }
+}
+
+/// The writer that actually packed a `create` or `append`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packer {
+    /// Tool name; when the native writer stands in for the selected tool, it says why
+    /// (e.g., `"native (BSArch has no Xbox compression)"`)
+    pub tool: &'static str,
+
+    /// Tool version, if it can be determined
+    pub version: Option<String>,
+}
+
+impl Packer {
+    /// The native writer, whose version is the version of this program
+    fn native(tool: &'static str) -> Self {
+        Self {
+            tool,
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+        }
+    }
+}
+
+/// Operations every archive tool must provide
+///
+/// Implementations only perform the raw archive operation. They must not delete source
+/// files, take backups or verify results; [`ArchiveManager`](super::ArchiveManager) does
+/// that uniformly for every backend.
+pub trait ArchiveBackend {
+    /// Tool name for log messages (e.g., `"Archive2"`)
+    fn name(&self) -> &'static str;
+
+    /// Tool version, if it can be determined
+    fn version(&self) -> Option<String> {
+        None
+    }
+
+    /// Create a new archive at `archive_path` from every file under `source_dir`
+    ///
+    /// Any existing file at `archive_path` is replaced. Entries are compressed as `profile`
+    /// says. Returns the writer that packed the archive.
+    fn create(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer>;
+
+    /// Add every file under `source_dir` to the existing archive at `archive_path`
+    ///
+    /// Files already in the archive under the same path are replaced. New entries are
+    /// compressed as `profile` says. Returns the writer that packed the new entries.
+    fn append(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer>;
+
+    /// Describe what [`create`](Self::create), or [`append`](Self::append) when
+    /// `appending`, would do without doing it
+    ///
+    /// Used by `--dry-run`. External tools return their exact command line; the default
+    /// implementation describes the native writer.
+    fn plan(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+        appending: bool,
+    ) -> String {
+        native_plan(source_dir, archive_path, profile, appending)
+    }
+
+    /// Extract every file in the archive into `dest_dir`
+    fn extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()>;
+
+    /// List every file in the archive, in record order
+    ///
+    /// The default implementation reads the archive with the native reader, which works
+    /// for every General archive regardless of which tool wrote it.
+    fn list(&self, archive_path: &Path) -> Result<Vec<Ba2Entry>> {
+        Ok(Ba2Archive::open(archive_path)?.entries().to_vec())
+    }
+
+    /// Whether the source folder should be kept after archiving
+    ///
+    /// Archive2 and the native writer replace loose files with the archive; `BSArch`
+    /// keeps them so the archive can be checked before cleanup.
+    fn keeps_source(&self) -> bool {
+        false
+    }
+}
+
+/// Determine which directory corresponds to `Data` for a given archive source
+///
+/// - `.../meshes/precombined` → the directory containing `meshes`
+/// - `.../vis` → the directory containing `vis`
+/// - Anything else (e.g., an MO2 collection directory that already contains
+///   `meshes/precombined` or `vis`) → `source_dir` itself
+pub fn archive_root(source_dir: &Path) -> &Path {
+    let is_named = |path: &Path, name: &str| {
+        path.file_name()
+            .is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case(name))
+    };
+
+    if is_named(source_dir, "precombined")
+        && let Some(meshes) = source_dir.parent()
+        && is_named(meshes, "meshes")
+        && let Some(root) = meshes.parent()
+    {
+        return root;
+    }
+
+    if is_named(source_dir, "vis")
+        && let Some(root) = source_dir.parent()
+    {
+        return root;
+    }
+
+    source_dir
+}
+
+/// Append files to an existing archive in place with the native BA2 code
+///
+/// Shared by every backend: Archive2 has no append functionality, and `BSArch pack`
+/// replaces the archive with one built from the given folder alone.
+/// New files are compressed with `profile`.
+fn append_in_place(
+    source_dir: &Path,
+    archive_path: &Path,
+    profile: CompressionProfile,
+) -> Result<()> {
+    let mut writer = Ba2Writer::with_profile(profile);
+    writer.add_directory(archive_root(source_dir), source_dir)?;
+    writer.append_to(archive_path)
+}
+
+/// Write a new archive of `source_dir` with the native writer
+///
+/// Shared by the native backend and `BSArch`'s Xbox builds (`BSArch` has no Xbox
+/// compression).
+fn write_native(source_dir: &Path, archive_path: &Path, profile: CompressionProfile) -> Result<()> {
+    info!(
+        "Creating archive with native writer ({} compression): {}",
+        profile.as_str(),
+        archive_path.display()
+    );
+
+    let mut writer = Ba2Writer::with_profile(profile);
+    writer.add_directory(archive_root(source_dir), source_dir)?;
+    writer.write(archive_path)
+}
+
+/// [`ArchiveBackend::plan`] for the native writer
+fn native_plan(
+    source_dir: &Path,
+    archive_path: &Path,
+    profile: CompressionProfile,
+    appending: bool,
+) -> String {
+    let action = if appending { "Append to" } else { "Write" };
+    format!(
+        "{action} {} from {} with the built-in BA2 writer ({} compression)",
+        archive_path.display(),
+        source_dir.display(),
+        profile.as_str()
+    )
+}
+
+/// The command an external archive tool is run with
+fn tool_command(exe: &Path, args: &[String], working_dir: &Path) -> Command {
+    let mut command = Command::new(exe);
+    command.args(args).current_dir(working_dir);
+    command
+}
+
+/// [`ArchiveBackend::plan`] for an external tool: its exact command line
+fn tool_plan(exe: &Path, args: &[String], working_dir: &Path) -> String {
+    format!(
+        "Run: {} (in {})",
+        utils::command_line(&tool_command(exe, args, working_dir)),
+        working_dir.display()
+    )
+}
+
+/// Run an external archive tool and fail with its stderr if it exits unsuccessfully
+fn run_tool(tool_name: &str, exe: &Path, args: &[String], working_dir: &Path) -> Result<()> {
+    let output = tool_command(exe, args, working_dir)
+        .output()
+        .with_context(|| format!("Failed to run {tool_name}: {}", exe.display()))?;
+
+    if !output.status.success() {
+        bail!(
+            "{tool_name} failed: {}\nStderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    Ok(())
+}
+
+/// Bethesda's Archive2.exe
+///
+/// **CRITICAL:** Archive2.exe has **NO APPEND FUNCTIONALITY**. The original batch script
+/// (lines 390-414) extracted and re-created the whole archive to add files;
+/// [`append`](ArchiveBackend::append) instead appends in place with
+/// [`Ba2Writer::append_to`].
+pub struct Archive2Backend {
+    exe: PathBuf,
+    fallout4_dir: PathBuf,
+}
+
+impl Archive2Backend {
+    /// Create a backend that runs `exe` from the Fallout 4 directory
+    pub fn new(exe: impl Into<PathBuf>, fallout4_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            exe: exe.into(),
+            fallout4_dir: fallout4_dir.into(),
+        }
+    }
+
+    /// Arguments of `Archive2.exe <source_dir> -c=<archive_path> -f=General -q [-compression=XBox|None]`
+    fn create_args(
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Vec<String> {
+        let mut args = vec![
+            source_dir.to_string_lossy().to_string(),
+            format!("-c={}", archive_path.display()),
+            "-f=General".to_string(),
+            "-q".to_string(), // Quiet mode
+        ];
+
+        match profile {
+            CompressionProfile::Default => {}
+            CompressionProfile::Xbox => args.push("-compression=XBox".to_string()),
+            CompressionProfile::None => args.push("-compression=None".to_string()),
+        }
+        args
+    }
+}
+
+impl ArchiveBackend for Archive2Backend {
+    fn name(&self) -> &'static str {
+        "Archive2"
+    }
+
+    fn version(&self) -> Option<String> {
+        utils::get_file_version(&self.exe).ok()
+    }
+
+    /// Executes: `Archive2.exe <source_dir> -c=<archive_path> -f=General -q [-compression=XBox|None]`
+    fn create(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        info!(
+            "Creating archive with Archive2 ({} compression): {}",
+            profile.as_str(),
+            archive_path.display()
+        );
+
+        let args = Self::create_args(source_dir, archive_path, profile);
+        run_tool("Archive2", &self.exe, &args, &self.fallout4_dir)?;
+        Ok(Packer {
+            tool: "Archive2",
+            version: self.version(),
+        })
+    }
+
+    fn plan(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+        appending: bool,
+    ) -> String {
+        if appending {
+            native_plan(source_dir, archive_path, profile, true)
+        } else {
+            let args = Self::create_args(source_dir, archive_path, profile);
+            tool_plan(&self.exe, &args, &self.fallout4_dir)
+        }
+    }
+
+    fn append(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        // REQUIRED WORKAROUND: Archive2 cannot append, so append in place natively
+        // instead of extracting and re-archiving everything
+        info!(
+            "Appending to Archive2 archive in place: {}",
+            archive_path.display()
+        );
+        append_in_place(source_dir, archive_path, profile)?;
+        Ok(Packer::native("native (Archive2 cannot append)"))
+    }
+
+    /// Executes: `Archive2.exe <archive_path> -e=<dest_dir> -q`
+    fn extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()> {
+        info!(
+            "Extracting archive with Archive2: {}",
+            archive_path.display()
+        );
+
+        let args = [
+            archive_path.to_string_lossy().to_string(),
+            format!("-e={}", dest_dir.display()),
+            "-q".to_string(),
+        ];
+        run_tool("Archive2", &self.exe, &args, &self.fallout4_dir)
+    }
+}
+
+/// The community `BSArch` tool
+///
+/// `BSArch` creates new archives with its `pack` command and keeps the source files.
+/// `pack` cannot add to an existing archive (it rebuilds it from the given folder, which
+/// would drop the precombined meshes in step 8), so appends go through the native writer.
+pub struct BsarchBackend {
+    exe: PathBuf,
+    fallout4_dir: PathBuf,
+}
+
+impl BsarchBackend {
+    /// Create a backend that runs `exe` from the Fallout 4 directory
+    pub fn new(exe: impl Into<PathBuf>, fallout4_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            exe: exe.into(),
+            fallout4_dir: fallout4_dir.into(),
+        }
+    }
+
+    /// Arguments of `BSArch.exe pack <source_dir> <archive_path> -mt -fo4 [-z]`
+    ///
+    /// Flags:
+    /// - `-mt`: Multi-threaded compression
+    /// - `-fo4`: Fallout 4 archive format
+    /// - `-z`: Compress files (only for [`CompressionProfile::Default`])
+    fn pack_args(
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Vec<String> {
+        let mut args = vec![
+            "pack".to_string(),
+            source_dir.to_string_lossy().to_string(),
+            archive_path.to_string_lossy().to_string(),
+            "-mt".to_string(),  // Multi-threaded
+            "-fo4".to_string(), // Fallout 4 format
+        ];
+        if profile == CompressionProfile::Default {
+            args.push("-z".to_string()); // Compress
+        }
+        args
+    }
+
+    /// Executes: `BSArch.exe pack <source_dir> <archive_path> -mt -fo4 [-z]`
+    fn pack(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        info!(
+            "Packing archive with BSArch ({} compression): {}",
+            profile.as_str(),
+            archive_path.display()
+        );
+
+        let args = Self::pack_args(source_dir, archive_path, profile);
+        run_tool("BSArch", &self.exe, &args, &self.fallout4_dir)?;
+        Ok(Packer {
+            tool: "BSArch",
+            version: self.version(),
+        })
+    }
+}
+
+impl ArchiveBackend for BsarchBackend {
+    fn name(&self) -> &'static str {
+        "BSArch"
+    }
+
+    fn version(&self) -> Option<String> {
+        utils::get_file_version(&self.exe).ok()
+    }
+
+    /// `BSArch` has no Xbox compression, so Xbox builds are written by the native writer
+    fn create(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        if profile == CompressionProfile::Xbox {
+            write_native(source_dir, archive_path, profile)?;
+            Ok(Packer::native("native (BSArch has no Xbox compression)"))
+        } else {
+            self.pack(source_dir, archive_path, profile)
+        }
+    }
+
+    fn append(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        info!(
+            "Appending to BSArch archive in place: {}",
+            archive_path.display()
+        );
+        append_in_place(source_dir, archive_path, profile)?;
+        Ok(Packer::native("native (BSArch cannot append)"))
+    }
+
+    /// `BSArch` creates archives with `pack`; appends and Xbox builds use the native writer
+    fn plan(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+        appending: bool,
+    ) -> String {
+        if appending || profile == CompressionProfile::Xbox {
+            return native_plan(source_dir, archive_path, profile, appending);
+        }
+        let args = Self::pack_args(source_dir, archive_path, profile);
+        tool_plan(&self.exe, &args, &self.fallout4_dir)
+    }
+
+    /// Executes: `BSArch.exe unpack <archive_path> <dest_dir> -mt`
+    fn extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()> {
+        info!("Extracting archive with BSArch: {}", archive_path.display());
+
+        let args = [
+            "unpack".to_string(),
+            archive_path.to_string_lossy().to_string(),
+            dest_dir.to_string_lossy().to_string(),
+            "-mt".to_string(),
+        ];
+        run_tool("BSArch", &self.exe, &args, &self.fallout4_dir)
+    }
+
+    fn keeps_source(&self) -> bool {
+        true
+    }
+}
+
+/// The built-in BA2 writer and reader (no external executable required)
+pub struct NativeBackend;
+
+impl ArchiveBackend for NativeBackend {
+    fn name(&self) -> &'static str {
+        "native writer"
+    }
+
+    /// The version of this program
+    fn version(&self) -> Option<String> {
+        Some(env!("CARGO_PKG_VERSION").to_string())
+    }
+
+    /// Writes a General archive with [`Ba2Writer`]
+    fn create(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        write_native(source_dir, archive_path, profile)?;
+        Ok(Packer::native("native"))
+    }
+
+    fn append(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        append_in_place(source_dir, archive_path, profile)?;
+        Ok(Packer::native("native"))
+    }
+
+    fn extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()> {
+        let count = Ba2Archive::open(archive_path)?.extract_all(dest_dir)?;
+        info!(
+            "Extracted {count} files from {} natively",
+            archive_path.display()
+        );
+        Ok(())
+    }
+}
+
+/// A call made to a [`RecordingBackend`]
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BackendCall {
+    Create {
+        source_dir: PathBuf,
+        archive_path: PathBuf,
+        profile: CompressionProfile,
+    },
+    Append {
+        source_dir: PathBuf,
+        archive_path: PathBuf,
+        profile: CompressionProfile,
+    },
+    Extract {
+        archive_path: PathBuf,
+        dest_dir: PathBuf,
+    },
+    List {
+        archive_path: PathBuf,
+    },
+}
+
+/// Test double that records every call
+///
+/// Archives are written uncompressed with the native writer so that the manager's
+/// verification and asset merging see real archives. Individual operations can be made
+/// to fail to exercise the manager's backup/restore paths.
+#[cfg(test)]
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct RecordingBackend {
+    calls: std::rc::Rc<std::cell::RefCell<Vec<BackendCall>>>,
+    fail_create: bool,
+    fail_append: bool,
+    rebuild_on_append: bool,
+    keeps_source: bool,
+}
+
+#[cfg(test)]
+impl RecordingBackend {
+    /// Shared handle to the recorded calls, usable after the backend is moved into a manager
+    pub fn calls(&self) -> std::rc::Rc<std::cell::RefCell<Vec<BackendCall>>> {
+        std::rc::Rc::clone(&self.calls)
+    }
+
+    /// Make `create` fail after recording the call
+    pub fn failing_create(mut self) -> Self {
+        self.fail_create = true;
+        self
+    }
+
+    /// Make `append` fail after recording the call
+    pub fn failing_append(mut self) -> Self {
+        self.fail_append = true;
+        self
+    }
+
+    /// Make `append` replace the archive with one holding only the new files, the way
+    /// `BSArch pack` does
+    pub fn rebuilding_on_append(mut self) -> Self {
+        self.rebuild_on_append = true;
+        self
+    }
+
+    /// Keep source folders, like `BSArch`
+    pub fn keeping_source(mut self) -> Self {
+        self.keeps_source = true;
+        self
+    }
+}
+
+#[cfg(test)]
+impl ArchiveBackend for RecordingBackend {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn create(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        self.calls.borrow_mut().push(BackendCall::Create {
+            source_dir: source_dir.to_path_buf(),
+            archive_path: archive_path.to_path_buf(),
+            profile,
+        });
+        if self.fail_create {
+            bail!("create failed (injected)");
+        }
+
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(archive_root(source_dir), source_dir)?;
+        writer.write(archive_path)?;
+        Ok(Packer {
+            tool: "recording",
+            version: None,
+        })
+    }
+
+    fn append(
+        &self,
+        source_dir: &Path,
+        archive_path: &Path,
+        profile: CompressionProfile,
+    ) -> Result<Packer> {
+        self.calls.borrow_mut().push(BackendCall::Append {
+            source_dir: source_dir.to_path_buf(),
+            archive_path: archive_path.to_path_buf(),
+            profile,
+        });
+        if self.fail_append {
+            bail!("append failed (injected)");
+        }
+
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(archive_root(source_dir), source_dir)?;
+        if self.rebuild_on_append {
+            writer.write(archive_path)?;
+        } else {
+            writer.append_to(archive_path)?;
+        }
+        Ok(Packer {
+            tool: "recording",
+            version: None,
+        })
+    }
+
+    fn extract(&self, archive_path: &Path, dest_dir: &Path) -> Result<()> {
+        self.calls.borrow_mut().push(BackendCall::Extract {
+            archive_path: archive_path.to_path_buf(),
+            dest_dir: dest_dir.to_path_buf(),
+        });
+        Ba2Archive::open(archive_path)?.extract_all(dest_dir)?;
+        Ok(())
+    }
+
+    fn list(&self, archive_path: &Path) -> Result<Vec<Ba2Entry>> {
+        self.calls.borrow_mut().push(BackendCall::List {
+            archive_path: archive_path.to_path_buf(),
+        });
+        Ok(Ba2Archive::open(archive_path)?.entries().to_vec())
+    }
+
+    fn keeps_source(&self) -> bool {
+        self.keeps_source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_archive_root() {
+        let data = Path::new("Data");
+        assert_eq!(archive_root(&data.join("meshes").join("precombined")), data);
+        assert_eq!(archive_root(&data.join("vis")), data);
+
+        let collect = data.join("_temp_mo2_collect");
+        assert_eq!(archive_root(&collect), collect.as_path());
+    }
+
+    #[test]
+    fn test_native_backend_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("a_OC.nif"), b"mesh").unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs").unwrap();
+
+        let archive = data_dir.join("Test - Main.ba2");
+        let backend = NativeBackend;
+        let packer = backend
+            .create(&precombined, &archive, CompressionProfile::Default)
+            .unwrap();
+        assert_eq!(packer.tool, "native");
+        assert_eq!(packer.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        backend
+            .append(&vis, &archive, CompressionProfile::Default)
+            .unwrap();
+        assert!(!backend.keeps_source());
+
+        let names: Vec<_> = backend
+            .list(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(
+            names,
+            ["meshes\\precombined\\a_OC.nif", "vis\\0000003c.uvd"]
+        );
+
+        let out = temp.path().join("out");
+        backend.extract(&archive, &out).unwrap();
+        assert_eq!(
+            fs::read(out.join("vis").join("0000003c.uvd")).unwrap(),
+            b"previs"
+        );
+    }
+
+    #[test]
+    fn test_native_backend_writes_xbox_compressed() {
+        let temp = TempDir::new().unwrap();
+        let precombined = temp.path().join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a.nif"), b"mesh").unwrap();
+
+        let archive = temp.path().join("Test - Main.ba2");
+        NativeBackend
+            .create(&precombined, &archive, CompressionProfile::Xbox)
+            .unwrap();
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert!(ba2.entries()[0].is_compressed());
+        assert_eq!(ba2.read(&ba2.entries()[0]).unwrap(), b"mesh");
+    }
+
+    #[test]
+    fn test_bsarch_xbox_uses_native_writer() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        let vis = data.join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("a.uvd"), b"previs").unwrap();
+
+        // BSArch.exe does not exist, so this only passes if BSArch is never run
+        let bsarch = BsarchBackend::new(temp.path().join("BSArch.exe"), temp.path());
+        let profile = CompressionProfile::Xbox;
+        let archive = data.join("Test - Main.ba2");
+        let packer = bsarch.create(&vis, &archive, profile).unwrap();
+        assert_eq!(packer.tool, "native (BSArch has no Xbox compression)");
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert!(ba2.entries()[0].is_compressed());
+        assert_eq!(ba2.entries()[0].name, "vis\\a.uvd");
+        assert!(
+            bsarch
+                .plan(&vis, &archive, profile, true)
+                .starts_with("Append to ")
+        );
+    }
+
+    #[test]
+    fn test_profile_for_build_mode() {
+        assert_eq!(
+            CompressionProfile::for_build_mode(BuildMode::Clean),
+            CompressionProfile::Default
+        );
+        assert_eq!(
+            CompressionProfile::for_build_mode(BuildMode::Filtered),
+            CompressionProfile::Default
+        );
+        assert_eq!(
+            CompressionProfile::for_build_mode(BuildMode::Xbox),
+            CompressionProfile::Xbox
+        );
+    }
+
+    #[test]
+    fn test_plans() {
+        let source = Path::new("Data").join("vis");
+        let archive = Path::new("Data").join("MyMod - Main.ba2");
+
+        let archive2 = Archive2Backend::new("Archive2.exe", "Fallout4");
+        assert_eq!(
+            archive2.plan(&source, &archive, CompressionProfile::Xbox, false),
+            format!(
+                "Run: Archive2.exe {} \"-c={}\" -f=General -q -compression=XBox (in Fallout4)",
+                source.display(),
+                archive.display()
+            )
+        );
+        assert!(
+            archive2
+                .plan(&source, &archive, CompressionProfile::None, true)
+                .starts_with("Append to ")
+        );
+
+        let bsarch = BsarchBackend::new("BSArch.exe", "Fallout4");
+        assert_eq!(
+            bsarch.plan(&source, &archive, CompressionProfile::Default, false),
+            format!(
+                "Run: BSArch.exe pack {} \"{}\" -mt -fo4 -z (in Fallout4)",
+                source.display(),
+                archive.display()
+            )
+        );
+        assert!(
+            bsarch
+                .plan(&source, &archive, CompressionProfile::Default, true)
+                .starts_with("Append to ")
+        );
+    }
+
+    #[test]
+    fn test_bsarch_append_keeps_existing_entries() {
+        let temp = TempDir::new().unwrap();
+        let data = temp.path().join("Data");
+        let precombined = data.join("meshes").join("precombined");
+        let vis = data.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("a.nif"), b"mesh").unwrap();
+        fs::write(vis.join("a.uvd"), b"previs").unwrap();
+
+        let archive = data.join("Test - Main.ba2");
+        NativeBackend
+            .create(&precombined, &archive, CompressionProfile::Default)
+            .unwrap();
+
+        // BSArch.exe does not exist, so this only passes if BSArch is never run
+        let bsarch = BsarchBackend::new(temp.path().join("BSArch.exe"), temp.path());
+        let packer = bsarch
+            .append(&vis, &archive, CompressionProfile::Default)
+            .unwrap();
+        assert_eq!(packer.tool, "native (BSArch cannot append)");
+
+        let mut names: Vec<_> = bsarch
+            .list(&archive)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        names.sort();
+        assert_eq!(names, ["meshes\\precombined\\a.nif", "vis\\a.uvd"]);
+    }
+
+    #[test]
+    fn test_external_backends_keep_source_policy() {
+        assert!(!Archive2Backend::new("Archive2.exe", "Fallout4").keeps_source());
+        assert!(BsarchBackend::new("BSArch.exe", "Fallout4").keeps_source());
+    }
+}
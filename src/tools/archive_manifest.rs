@@ -0,0 +1,326 @@
+//! JSON manifests written next to generated archives
+//!
+//! Every time the workflow creates or updates `<Plugin> - Main.ba2` it writes
+//! `<Plugin> - Main.ba2.manifest.json` beside it. The manifest lists every entry with its
+//! size and SHA-256 hash, and which workflow step put it there, plus the build mode and
+//! the tool (and version) that actually packed the last write. It can be diffed between
+//! releases, or checked for missing precombines, without opening the archive in a GUI.
+//!
+//! Entries are attributed as follows:
+//! - Assets kept from a previous archive (see
+//!   [`ArchiveManager::create_archive`](super::ArchiveManager::create_archive)) are
+//!   `"preserved"`, unless the previous manifest already attributed them
+//! - Entries whose path and hash match the previous manifest keep their attribution
+//! - Everything else is attributed to the step that is writing the manifest
+//!
+//! # Example
+//!
+//! ```json
+//! {
+//!   "archive": "MyMod - Main.ba2",
+//!   "plugin": "MyMod.esp",
+//!   "build_mode": "clean",
+//!   "archive_tool": "Archive2",
+//!   "archive_tool_version": "1.1.0.4",
+//!   "compression": "zlib",
+//!   "ba2_version": 1,
+//!   "entries": [
+//!     {
+//!       "path": "meshes\\precombined\\0001F4A2_2A6B3C5D_OC.nif",
+//!       "size": 18342,
+//!       "packed_size": 7214,
+//!       "sha256": "9f86d081884c7d65...",
+//!       "added_by": "step 3"
+//!     }
+//!   ]
+//! }
+//! ```
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::tools::ba2::normalize_archive_path;
+use crate::tools::ba2_reader::Ba2Archive;
+
+/// Attribution for assets kept from a previous archive
+pub const PRESERVED: &str = "preserved";
+
+/// Contents of a `<archive>.manifest.json` file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    /// Archive file name (e.g., `MyMod - Main.ba2`)
+    pub archive: String,
+    /// Plugin the archive was generated for
+    pub plugin: String,
+    /// Build mode of the run that last wrote the archive (`clean`, `filtered`, `xbox`)
+    pub build_mode: String,
+    /// Writer that packed the last write: the selected tool, or the native writer with the
+    /// reason it stood in (e.g., `native (Archive2 cannot append)`)
+    pub archive_tool: String,
+    /// Version of the archive tool, if it could be determined
+    pub archive_tool_version: Option<String>,
+    /// Compression profile used for the last write (see
+    /// [`CompressionProfile`](super::archive_backend::CompressionProfile))
+    pub compression: String,
+    /// BA2 format version from the archive header
+    pub ba2_version: u32,
+    /// One entry per file in the archive, in archive order
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// One file in an [`ArchiveManifest`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Path inside the archive (e.g., `vis\0000003c.uvd`)
+    pub path: String,
+    /// Uncompressed size in bytes
+    pub size: u32,
+    /// Compressed size in bytes (`None` if stored uncompressed)
+    pub packed_size: Option<u32>,
+    /// SHA-256 of the uncompressed data, lowercase hex
+    pub sha256: String,
+    /// Workflow step that added the file (e.g., `step 3`), or [`PRESERVED`]
+    pub added_by: String,
+}
+
+/// Details of the run that are recorded in a manifest
+#[derive(Debug, Clone, Copy)]
+pub struct ManifestContext<'a> {
+    pub plugin: &'a str,
+    pub build_mode: &'a str,
+    pub archive_tool: &'a str,
+    pub archive_tool_version: Option<&'a str>,
+    pub compression: &'a str,
+    /// Attribution for new entries (e.g., `step 8`)
+    pub step: &'a str,
+}
+
+impl ArchiveManifest {
+    /// Path of the manifest for an archive (`<archive>.manifest.json`)
+    pub fn path_for(archive_path: &Path) -> PathBuf {
+        let mut path = OsString::from(archive_path.as_os_str());
+        path.push(".manifest.json");
+        PathBuf::from(path)
+    }
+
+    /// Build a manifest describing the archive at `archive_path`
+    ///
+    /// Every entry is read and hashed. `previous` is the manifest from the last write, if
+    /// any, and `preserved` lists the assets kept from a previous archive (see the
+    /// module docs for how entries are attributed).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive or any entry cannot be read
+    pub fn build(
+        archive_path: &Path,
+        context: &ManifestContext<'_>,
+        previous: Option<&ArchiveManifest>,
+        preserved: &[String],
+    ) -> Result<Self> {
+        let archive = Ba2Archive::open(archive_path)?;
+
+        let previous: HashMap<String, &ManifestEntry> = previous
+            .map(|manifest| {
+                manifest
+                    .entries
+                    .iter()
+                    .map(|entry| (normalize_archive_path(&entry.path).to_lowercase(), entry))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let preserved: Vec<String> = preserved
+            .iter()
+            .map(|name| normalize_archive_path(name).to_lowercase())
+            .collect();
+
+        let mut entries = Vec::with_capacity(archive.entries().len());
+        for entry in archive.entries() {
+            let data = archive.read(entry)?;
+            let sha256 = hex(&Sha256::digest(&data));
+
+            let key = normalize_archive_path(&entry.name).to_lowercase();
+            let earlier = previous.get(&key);
+            let added_by = match earlier {
+                Some(earlier) if earlier.sha256 == sha256 => earlier.added_by.clone(),
+                _ if preserved.contains(&key) => {
+                    earlier.map_or(PRESERVED.to_string(), |e| e.added_by.clone())
+                }
+                _ => context.step.to_string(),
+            };
+
+            entries.push(ManifestEntry {
+                path: entry.name.clone(),
+                size: entry.unpacked_size,
+                packed_size: entry.is_compressed().then_some(entry.packed_size),
+                sha256,
+                added_by,
+            });
+        }
+
+        Ok(Self {
+            archive: archive_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            plugin: context.plugin.to_string(),
+            build_mode: context.build_mode.to_string(),
+            archive_tool: context.archive_tool.to_string(),
+            archive_tool_version: context.archive_tool_version.map(str::to_string),
+            compression: context.compression.to_string(),
+            ba2_version: archive.version(),
+            entries,
+        })
+    }
+
+    /// Read the manifest for an archive, if one exists
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest exists but cannot be read or parsed
+    pub fn load(archive_path: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(archive_path);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read manifest: {}", path.display()))?;
+        let manifest = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse manifest: {}", path.display()))?;
+        Ok(Some(manifest))
+    }
+
+    /// Write the manifest next to its archive
+    ///
+    /// Returns the path of the manifest file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written
+    pub fn write(&self, archive_path: &Path) -> Result<PathBuf> {
+        let path = Self::path_for(archive_path);
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(&path, json + "\n")
+            .with_context(|| format!("Failed to write manifest: {}", path.display()))?;
+        Ok(path)
+    }
+}
+
+/// Lowercase hex encoding
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::ba2::Ba2Writer;
+    use tempfile::TempDir;
+
+    fn context(step: &str) -> ManifestContext<'_> {
+        ManifestContext {
+            plugin: "Test.esp",
+            build_mode: "clean",
+            archive_tool: "Native",
+            archive_tool_version: Some("0.1.0"),
+            compression: "zlib",
+            step,
+        }
+    }
+
+    fn write_archive(root: &Path, archive: &Path, files: &[(&str, &[u8])]) {
+        for (path, data) in files {
+            let file = root.join(path);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, data).unwrap();
+        }
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(root, root).unwrap();
+        writer.write(archive).unwrap();
+    }
+
+    #[test]
+    fn test_manifest_path() {
+        assert_eq!(
+            ArchiveManifest::path_for(Path::new("Data/Test - Main.ba2")),
+            Path::new("Data/Test - Main.ba2.manifest.json")
+        );
+    }
+
+    #[test]
+    fn test_build_and_round_trip() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("Test - Main.ba2");
+        write_archive(
+            &temp.path().join("src"),
+            &archive,
+            &[
+                ("meshes/precombined/0001F4A2_OC.nif", b"mesh"),
+                ("scripts/Quest.pex", b"script"),
+            ],
+        );
+
+        let preserved = ["scripts\\Quest.pex".to_string()];
+        let manifest =
+            ArchiveManifest::build(&archive, &context("step 3"), None, &preserved).unwrap();
+        assert_eq!(manifest.archive, "Test - Main.ba2");
+        assert_eq!(manifest.entries.len(), 2);
+
+        let mesh = &manifest.entries[0];
+        assert_eq!(mesh.path, "meshes\\precombined\\0001F4A2_OC.nif");
+        assert_eq!(mesh.size, 4);
+        assert_eq!(
+            mesh.sha256,
+            hex(&Sha256::digest(b"mesh")),
+            "hash is of the uncompressed data"
+        );
+        assert_eq!(mesh.added_by, "step 3");
+        assert_eq!(manifest.entries[1].added_by, PRESERVED);
+
+        manifest.write(&archive).unwrap();
+        assert_eq!(ArchiveManifest::load(&archive).unwrap(), Some(manifest));
+    }
+
+    #[test]
+    fn test_unchanged_entries_keep_attribution() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("Test - Main.ba2");
+        let root = temp.path().join("src");
+        write_archive(&root, &archive, &[("meshes/precombined/a_OC.nif", b"mesh")]);
+        let first = ArchiveManifest::build(&archive, &context("step 3"), None, &[]).unwrap();
+
+        write_archive(&root, &archive, &[("vis/0000003c.uvd", b"previs")]);
+        let second =
+            ArchiveManifest::build(&archive, &context("step 8"), Some(&first), &[]).unwrap();
+
+        let added_by: Vec<_> = second
+            .entries
+            .iter()
+            .map(|e| (e.path.as_str(), e.added_by.as_str()))
+            .collect();
+        assert_eq!(
+            added_by,
+            [
+                ("meshes\\precombined\\a_OC.nif", "step 3"),
+                ("vis\\0000003c.uvd", "step 8")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_load_missing_manifest() {
+        let temp = TempDir::new().unwrap();
+        let archive = temp.path().join("Test - Main.ba2");
+        assert_eq!(ArchiveManifest::load(&archive).unwrap(), None);
+    }
+}
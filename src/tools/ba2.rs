@@ -0,0 +1,1005 @@
+//! Native Fallout 4 BA2 (BTDX) archive support
+//!
+//! This module implements the Fallout 4 "General" BA2 archive format directly in Rust,
+//! so that archives can be created without Archive2.exe or BSArch.exe. It is used by the
+//! [`ArchiveTool::Native`](crate::config::ArchiveTool::Native) backend of
+//! [`ArchiveManager`](super::ArchiveManager).
+//!
+//! # File Layout (General archives)
+//!
+//! ```text
+//! +---------------------------+
+//! | Header (24 bytes)         |  "BTDX", version, "GNRL", file count, name table offset
+//! +---------------------------+
+//! | File records (36 bytes    |  name hash, extension, directory hash, flags,
+//! |   per file)               |  data offset, packed size, unpacked size, 0xBAADF00D
+//! +---------------------------+
+//! | File data                 |  zlib stream (packed size > 0) or raw bytes (packed size = 0)
+//! +---------------------------+
+//! | Name table                |  u16 length + path bytes, one per file, in record order
+//! +---------------------------+
+//! ```
+//!
+//! All integers are little-endian. Paths are stored with backslash separators and are
+//! relative to the game's `Data` directory (e.g., `meshes\precombined\0001F4A2_OC.nif`).
+//!
+//! # Hashing
+//!
+//! The game locates files by hash, not by name. Each record stores a CRC-32 of the
+//! lowercase file stem and of the lowercase directory path. Fallout 4 uses the standard
+//! reflected CRC-32 polynomial but with an initial value of `0` and no final XOR, so
+//! the result does **not** match `crc32fast`/zlib's `crc32()`. See [`hash_path`].
+//!
+//! # Scope
+//!
+//! Only the General (`GNRL`) format is supported. Texture archives (`DX10`) are not needed
+//! for precombine/previs output and are not implemented.
+
+use anyhow::{Context, Result, bail};
+use flate2::write::ZlibEncoder;
+use flate2::{Compress, Compression};
+use log::info;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::archive_backend::CompressionProfile;
+use super::ba2_reader::{Ba2Archive, Ba2Entry};
+
+/// Archive magic (`"BTDX"`)
+pub const BA2_MAGIC: &[u8; 4] = b"BTDX";
+
+/// Archive format version written by Archive2 for Fallout 4 (pre next-gen update)
+pub const BA2_VERSION: u32 = 1;
+
+/// Archive type tag for General archives
+pub const BA2_TYPE_GENERAL: &[u8; 4] = b"GNRL";
+
+/// Size of the archive header in bytes
+pub const HEADER_SIZE: u64 = 24;
+
+/// Size of a single General file record in bytes
+pub const RECORD_SIZE: u64 = 36;
+
+/// Per-file flags value written by Archive2 for General archive entries
+pub const RECORD_FLAGS: u32 = 0x0010_0100;
+
+/// Sentinel stored at the end of every General file record
+pub const RECORD_SENTINEL: u32 = 0xBAAD_F00D;
+
+/// zlib window size (log2) used for Xbox archives
+///
+/// Archive2's `-compression=XBox` writes ordinary zlib streams with a 4 KiB window
+/// instead of the default 32 KiB, which the console's decompressor requires.
+pub const XBOX_WINDOW_BITS: u8 = 12;
+
+/// Lookup table for the Fallout 4 flavour of CRC-32 (reflected polynomial `0xEDB88320`)
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        #[allow(clippy::cast_possible_truncation)]
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// Compute the Fallout 4 archive CRC-32 of a byte string
+///
+/// Unlike zlib's CRC-32, the Fallout 4 variant starts from `0` and does not invert the
+/// result, so an empty input hashes to `0`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0u32, |crc, &b| {
+        (crc >> 8) ^ CRC_TABLE[((crc ^ u32::from(b)) & 0xFF) as usize]
+    })
+}
+
+/// Normalize an archive path to the form stored in the name table
+///
+/// Converts forward slashes to backslashes and strips any leading separators.
+pub fn normalize_archive_path(path: &str) -> String {
+    path.replace('/', "\\").trim_start_matches('\\').to_string()
+}
+
+/// Compute the archive path for a file on disk, relative to the directory that maps to `Data`
+///
+/// For example, with `root = Data`, the file `Data/vis/0000003c.uvd` is named
+/// `vis\0000003c.uvd`.
+///
+/// # Errors
+///
+/// Returns an error if `path` is not inside `root`.
+pub fn archive_name_for(root: &Path, path: &Path) -> Result<String> {
+    let relative = path
+        .strip_prefix(root)
+        .with_context(|| format!("Failed to get relative path for: {}", path.display()))?;
+
+    Ok(relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(part) => Some(part.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("\\"))
+}
+
+/// Normalize an archive path and check that it can be stored
+fn validated_name(archive_path: &str) -> Result<String> {
+    let name = normalize_archive_path(archive_path);
+
+    if name.is_empty() {
+        bail!("Archive path cannot be empty");
+    }
+    if name.split('\\').any(|part| part == "..") {
+        bail!("Archive path escapes the archive root: {archive_path}");
+    }
+    if name.len() > usize::from(u16::MAX) {
+        bail!("Archive path is too long: {archive_path}");
+    }
+
+    Ok(name)
+}
+
+/// Compute the lookup hashes for an archive path
+///
+/// Returns `(name_hash, extension, directory_hash)` as stored in a General file record.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use generateprevisibines::tools::ba2::hash_path;
+/// let (name_hash, ext, dir_hash) = hash_path("meshes\\precombined\\0001F4A2_OC.nif");
+/// assert_eq!(&ext, b"nif\0");
+/// ```
+pub fn hash_path(path: &str) -> (u32, [u8; 4], u32) {
+    let normalized = normalize_archive_path(path).to_lowercase();
+
+    let (dir, file) = match normalized.rfind('\\') {
+        Some(idx) => (&normalized[..idx], &normalized[idx + 1..]),
+        None => ("", normalized.as_str()),
+    };
+
+    let (stem, ext) = match file.rfind('.') {
+        Some(idx) => (&file[..idx], &file[idx + 1..]),
+        None => (file, ""),
+    };
+
+    let mut ext_bytes = [0u8; 4];
+    for (slot, byte) in ext_bytes.iter_mut().zip(ext.bytes()) {
+        *slot = byte;
+    }
+
+    (crc32(stem.as_bytes()), ext_bytes, crc32(dir.as_bytes()))
+}
+
+/// A file queued for inclusion in a new archive
+#[derive(Debug, Clone)]
+struct PendingEntry {
+    /// Path inside the archive (backslash-separated, relative to `Data`)
+    name: String,
+    /// Where the file's data comes from
+    source: PendingSource,
+}
+
+/// Data source of a queued file
+#[derive(Debug, Clone)]
+enum PendingSource {
+    /// A loose file on disk, compressed according to the writer's setting
+    File(PathBuf),
+    /// An entry of another archive, copied as stored (no recompression)
+    Archived {
+        archive: PathBuf,
+        record: FileRecord,
+    },
+}
+
+/// Writer for Fallout 4 General BA2 archives
+///
+/// Files are queued with [`add_file`](Self::add_file) or
+/// [`add_directory`](Self::add_directory) and only read from disk when
+/// [`write`](Self::write) is called. Each file is read, compressed and written one at a
+/// time, so memory use is bounded by the largest single file rather than the archive size.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use generateprevisibines::tools::ba2::Ba2Writer;
+/// let mut writer = Ba2Writer::new(true);
+/// writer.add_directory("C:\\Games\\Fallout4\\Data", "C:\\Games\\Fallout4\\Data\\meshes\\precombined")?;
+/// writer.write("C:\\Games\\Fallout4\\Data\\MyMod - Main.ba2")?;
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+pub struct Ba2Writer {
+    profile: CompressionProfile,
+    entries: Vec<PendingEntry>,
+}
+
+impl Ba2Writer {
+    /// Create a new writer
+    ///
+    /// * `compress` - If `true`, entries are zlib-compressed; if `false`, stored as-is
+    pub fn new(compress: bool) -> Self {
+        Self::with_profile(if compress {
+            CompressionProfile::Default
+        } else {
+            CompressionProfile::None
+        })
+    }
+
+    /// Create a new writer that compresses loose files with the given profile
+    ///
+    /// [`CompressionProfile::Xbox`] writes zlib streams with a 4 KiB window (see
+    /// [`XBOX_WINDOW_BITS`]), matching Archive2's `-compression=XBox`.
+    pub fn with_profile(profile: CompressionProfile) -> Self {
+        Self {
+            profile,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Queue a single file under the given archive path
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive path is empty, escapes the archive root with `..`,
+    /// or is longer than the name table allows (65535 bytes).
+    pub fn add_file(&mut self, archive_path: &str, source: impl AsRef<Path>) -> Result<()> {
+        let name = validated_name(archive_path)?;
+        self.entries.push(PendingEntry {
+            name,
+            source: PendingSource::File(source.as_ref().to_path_buf()),
+        });
+        Ok(())
+    }
+
+    /// Queue an entry of an existing archive, copied byte-for-byte
+    ///
+    /// The entry keeps its name, hashes, flags and compression; its stored data is copied
+    /// from `archive` when the new archive is written. This is how existing assets are
+    /// carried over into a rebuilt archive without extracting or recompressing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the entry's name is not a valid archive path (see
+    /// [`add_file`](Self::add_file)).
+    pub fn add_archived(&mut self, archive: &Ba2Archive, entry: &Ba2Entry) -> Result<()> {
+        let name = validated_name(&entry.name)?;
+        self.entries.push(PendingEntry {
+            name,
+            source: PendingSource::Archived {
+                archive: archive.path().to_path_buf(),
+                record: entry.record(),
+            },
+        });
+        Ok(())
+    }
+
+    /// Queue every file under `dir`, named relative to `root`
+    ///
+    /// `root` is the directory that corresponds to `Data` inside the archive. For example,
+    /// with `root = Data` and `dir = Data/meshes/precombined`, a file
+    /// `Data/meshes/precombined/a.nif` is stored as `meshes\precombined\a.nif`.
+    ///
+    /// Files are queued in sorted path order so that output is deterministic.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` is not inside `root`, or if the directory cannot be walked.
+    pub fn add_directory(&mut self, root: impl AsRef<Path>, dir: impl AsRef<Path>) -> Result<()> {
+        let root = root.as_ref();
+        let dir = dir.as_ref();
+
+        if !dir.starts_with(root) {
+            bail!(
+                "Directory {} is not inside archive root {}",
+                dir.display(),
+                root.display()
+            );
+        }
+
+        for entry in WalkDir::new(dir).sort_by_file_name() {
+            let entry = entry.with_context(|| format!("Failed to walk {}", dir.display()))?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+
+            let name = archive_name_for(root, entry.path())?;
+            self.add_file(&name, entry.path())?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the archive to `archive_path`, replacing any existing file
+    ///
+    /// On failure the partially written archive is removed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no files were queued, a source file cannot be read, a file is
+    /// larger than 4 GiB (the per-file size limit of the format), or the archive cannot be
+    /// written.
+    pub fn write(&self, archive_path: impl AsRef<Path>) -> Result<()> {
+        let archive_path = archive_path.as_ref();
+
+        if self.entries.is_empty() {
+            bail!("No files to archive for {}", archive_path.display());
+        }
+
+        info!(
+            "Writing BA2 archive natively: {} ({} files, {})",
+            archive_path.display(),
+            self.entries.len(),
+            self.profile.as_str()
+        );
+
+        let result = self.write_inner(archive_path, BA2_VERSION);
+        if result.is_err() && archive_path.exists() {
+            let _ = fs::remove_file(archive_path);
+        }
+        result
+    }
+
+    /// Write the archive with the given header version (see [`write`](Self::write))
+    fn write_inner(&self, archive_path: &Path, version: u32) -> Result<()> {
+        let file = File::create(archive_path)
+            .with_context(|| format!("Failed to create archive: {}", archive_path.display()))?;
+        let mut out = BufWriter::new(file);
+
+        let file_count = u32::try_from(self.entries.len()).context("Too many files for BA2")?;
+
+        // Header and record table are written as placeholders first, then patched once
+        // the data offsets and packed sizes are known.
+        write_header(&mut out, version, file_count, 0)?;
+        let records_len = RECORD_SIZE * u64::from(file_count);
+        #[allow(clippy::cast_possible_truncation)]
+        out.write_all(&vec![0u8; records_len as usize])?;
+
+        let mut records = Vec::with_capacity(self.entries.len());
+        let mut offset = HEADER_SIZE + records_len;
+        let mut sources = SourceArchives::default();
+
+        for entry in &self.entries {
+            let (payload, record) = self.encode_entry(entry, offset, &mut sources)?;
+            out.write_all(&payload)?;
+            records.push(record);
+            offset += payload.len() as u64;
+        }
+
+        let name_table_offset = offset;
+        for entry in &self.entries {
+            write_name(&mut out, &entry.name)?;
+        }
+
+        out.seek(SeekFrom::Start(0))?;
+        write_header(&mut out, version, file_count, name_table_offset)?;
+        for record in &records {
+            record.write_to(&mut out)?;
+        }
+
+        out.flush()
+            .with_context(|| format!("Failed to write archive: {}", archive_path.display()))?;
+        Ok(())
+    }
+
+    /// Add the queued files to an existing archive in place
+    ///
+    /// Instead of extracting and re-packing the archive, this keeps every existing
+    /// file's data where it is and only:
+    /// 1. Appends the new payloads after the existing data (over the old name table)
+    /// 2. Writes a new name table after them
+    /// 3. Rewrites the header and record table at the start of the file
+    ///
+    /// The record table grows by 36 bytes per new file. Existing files whose data lies in
+    /// that region are moved to the end of the archive as raw (still compressed) bytes,
+    /// which is usually just the first one or two files.
+    ///
+    /// Queued files whose path already exists in the archive (compared case-insensitively)
+    /// replace the old entry. Since the old data would otherwise stay behind as unused
+    /// space (growing the archive on every rerun of step 8), the archive is then rewritten
+    /// instead: the remaining entries are copied as stored (no recompression) to a
+    /// temporary file, followed by the new files, and the temporary file replaces the
+    /// archive.
+    ///
+    /// The archive keeps its header version (1, or 7/8 for next-gen archives).
+    ///
+    /// The archive is modified in place, so callers that need to recover from a failure
+    /// should back it up first (see [`ArchiveManager::add_to_archive`](super::ArchiveManager::add_to_archive)).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if no files were queued, the archive cannot be parsed (see
+    /// [`Ba2Archive::open`]), a source file cannot be read, or the archive cannot be
+    /// written.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use generateprevisibines::tools::ba2::Ba2Writer;
+    /// let mut writer = Ba2Writer::new(true);
+    /// writer.add_directory("C:\\Games\\Fallout4\\Data", "C:\\Games\\Fallout4\\Data\\vis")?;
+    /// writer.append_to("C:\\Games\\Fallout4\\Data\\MyMod - Main.ba2")?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn append_to(&self, archive_path: impl AsRef<Path>) -> Result<()> {
+        let archive_path = archive_path.as_ref();
+
+        if self.entries.is_empty() {
+            bail!("No files to add to {}", archive_path.display());
+        }
+
+        let existing = Ba2Archive::open(archive_path)?;
+
+        let new_names: HashSet<String> = self
+            .entries
+            .iter()
+            .map(|entry| entry.name.to_lowercase())
+            .collect();
+        let kept: Vec<&Ba2Entry> = existing
+            .entries()
+            .iter()
+            .filter(|entry| !new_names.contains(&entry.name.to_lowercase()))
+            .collect();
+        let replaced = existing.entries().len() - kept.len();
+        if replaced > 0 {
+            return self.rewrite_with(&existing, &kept, replaced, archive_path);
+        }
+
+        info!(
+            "Appending to BA2 archive in place: {} ({} existing, {} new)",
+            archive_path.display(),
+            kept.len(),
+            self.entries.len()
+        );
+
+        let file_count =
+            u32::try_from(kept.len() + self.entries.len()).context("Too many files for BA2")?;
+        let records_end = HEADER_SIZE + RECORD_SIZE * u64::from(file_count);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(archive_path)
+            .with_context(|| format!("Failed to open archive: {}", archive_path.display()))?;
+
+        // Read the data that the larger record table will overwrite before touching the file
+        let mut relocated = Vec::new();
+        for (index, entry) in kept.iter().enumerate() {
+            if entry.offset < records_end {
+                let mut data = vec![0u8; entry.stored_size() as usize];
+                file.seek(SeekFrom::Start(entry.offset))?;
+                file.read_exact(&mut data)
+                    .with_context(|| format!("Failed to read data for '{}'", entry.name))?;
+                relocated.push((index, data));
+            }
+        }
+
+        let data_end = kept
+            .iter()
+            // Ba2Archive::open rejects entries whose range does not fit
+            .filter_map(|entry| entry.data_range().map(|(_, end)| end))
+            .chain([existing.name_table_offset(), records_end])
+            .max()
+            .unwrap_or(records_end);
+
+        let mut records: Vec<FileRecord> = kept.iter().map(|entry| entry.record()).collect();
+        let mut out = BufWriter::new(&mut file);
+        out.seek(SeekFrom::Start(data_end))?;
+        let mut offset = data_end;
+
+        for (index, data) in relocated {
+            out.write_all(&data)?;
+            records[index].offset = offset;
+            offset += data.len() as u64;
+        }
+
+        let mut sources = SourceArchives::default();
+        for entry in &self.entries {
+            let (payload, record) = self.encode_entry(entry, offset, &mut sources)?;
+            out.write_all(&payload)?;
+            records.push(record);
+            offset += payload.len() as u64;
+        }
+
+        let name_table_offset = offset;
+        for name in kept
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .chain(self.entries.iter().map(|entry| entry.name.as_str()))
+        {
+            write_name(&mut out, name)?;
+        }
+        let archive_end = out.stream_position()?;
+
+        out.seek(SeekFrom::Start(0))?;
+        write_header(&mut out, existing.version(), file_count, name_table_offset)?;
+        for record in &records {
+            record.write_to(&mut out)?;
+        }
+        out.flush()
+            .with_context(|| format!("Failed to write archive: {}", archive_path.display()))?;
+        drop(out);
+
+        file.set_len(archive_end)
+            .with_context(|| format!("Failed to write archive: {}", archive_path.display()))?;
+        Ok(())
+    }
+
+    /// Rewrite `existing` with its `kept` entries followed by the queued files
+    ///
+    /// Used by [`append_to`](Self::append_to) when queued files replace `replaced` entries,
+    /// so the replaced data is dropped. The new archive is written next to the old one and only
+    /// replaces it once complete.
+    fn rewrite_with(
+        &self,
+        existing: &Ba2Archive,
+        kept: &[&Ba2Entry],
+        replaced: usize,
+        archive_path: &Path,
+    ) -> Result<()> {
+        info!(
+            "Rewriting BA2 archive: {} ({} kept, {} new, {} replaced)",
+            archive_path.display(),
+            kept.len(),
+            self.entries.len() - replaced,
+            replaced
+        );
+
+        let mut writer = Self::with_profile(self.profile);
+        for entry in kept {
+            writer.add_archived(existing, entry)?;
+        }
+        writer.entries.extend(self.entries.iter().cloned());
+
+        let mut temp_name = archive_path.as_os_str().to_owned();
+        temp_name.push(".tmp");
+        let temp_path = PathBuf::from(temp_name);
+        let result = writer
+            .write_inner(&temp_path, existing.version())
+            .and_then(|()| {
+                fs::rename(&temp_path, archive_path).with_context(|| {
+                    format!("Failed to replace archive: {}", archive_path.display())
+                })
+            });
+        if result.is_err() && temp_path.exists() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+
+    /// Read a queued file and build its payload and record at the given data offset
+    ///
+    /// Archived entries are read through `sources`, which keeps each source archive open
+    /// for the whole write.
+    fn encode_entry(
+        &self,
+        entry: &PendingEntry,
+        offset: u64,
+        sources: &mut SourceArchives,
+    ) -> Result<(Vec<u8>, FileRecord)> {
+        let path = match &entry.source {
+            PendingSource::File(path) => path,
+            PendingSource::Archived { archive, record } => {
+                let file = sources.get(archive)?;
+                let stored_size = if record.packed_size == 0 {
+                    record.unpacked_size
+                } else {
+                    record.packed_size
+                };
+                let mut payload = vec![0u8; stored_size as usize];
+                file.seek(SeekFrom::Start(record.offset))?;
+                file.read_exact(&mut payload).with_context(|| {
+                    format!("Failed to read '{}' from {}", entry.name, archive.display())
+                })?;
+                return Ok((payload, FileRecord { offset, ..*record }));
+            }
+        };
+
+        let data = fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+        let unpacked_size = u32::try_from(data.len())
+            .with_context(|| format!("File too large for BA2: {}", path.display()))?;
+
+        let compressed = match self.profile {
+            CompressionProfile::Default if !data.is_empty() => Some(zlib_compress(&data)?),
+            CompressionProfile::Xbox if !data.is_empty() => Some(zlib_compress_xbox(&data)?),
+            _ => None,
+        };
+        let (payload, packed_size) = if let Some(compressed) = compressed {
+            let packed = u32::try_from(compressed.len()).context("Compressed file too large")?;
+            (compressed, packed)
+        } else {
+            (data, 0)
+        };
+
+        let (name_hash, ext, dir_hash) = hash_path(&entry.name);
+        let record = FileRecord {
+            name_hash,
+            ext,
+            dir_hash,
+            flags: RECORD_FLAGS,
+            offset,
+            packed_size,
+            unpacked_size,
+        };
+
+        Ok((payload, record))
+    }
+}
+
+/// Source archives of [`PendingSource::Archived`] entries, each opened once per write
+#[derive(Default)]
+struct SourceArchives {
+    files: HashMap<PathBuf, File>,
+}
+
+impl SourceArchives {
+    /// The open handle of `archive`, opening it on first use
+    fn get(&mut self, archive: &Path) -> Result<&mut File> {
+        Ok(match self.files.entry(archive.to_path_buf()) {
+            Entry::Occupied(open) => open.into_mut(),
+            Entry::Vacant(slot) => slot.insert(
+                File::open(archive)
+                    .with_context(|| format!("Failed to open archive: {}", archive.display()))?,
+            ),
+        })
+    }
+}
+
+/// A General archive file record as stored on disk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileRecord {
+    pub name_hash: u32,
+    pub ext: [u8; 4],
+    pub dir_hash: u32,
+    pub flags: u32,
+    pub offset: u64,
+    /// Compressed size, or `0` if the data is stored uncompressed
+    pub packed_size: u32,
+    pub unpacked_size: u32,
+}
+
+impl FileRecord {
+    /// Serialize the record in on-disk layout
+    pub fn write_to(&self, out: &mut impl Write) -> Result<()> {
+        out.write_all(&self.name_hash.to_le_bytes())?;
+        out.write_all(&self.ext)?;
+        out.write_all(&self.dir_hash.to_le_bytes())?;
+        out.write_all(&self.flags.to_le_bytes())?;
+        out.write_all(&self.offset.to_le_bytes())?;
+        out.write_all(&self.packed_size.to_le_bytes())?;
+        out.write_all(&self.unpacked_size.to_le_bytes())?;
+        out.write_all(&RECORD_SENTINEL.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// Write a General archive header
+pub fn write_header(
+    out: &mut impl Write,
+    version: u32,
+    file_count: u32,
+    name_table_offset: u64,
+) -> Result<()> {
+    out.write_all(BA2_MAGIC)?;
+    out.write_all(&version.to_le_bytes())?;
+    out.write_all(BA2_TYPE_GENERAL)?;
+    out.write_all(&file_count.to_le_bytes())?;
+    out.write_all(&name_table_offset.to_le_bytes())?;
+    Ok(())
+}
+
+/// Write a single name table entry (`u16` length followed by the path bytes)
+pub fn write_name(out: &mut impl Write, name: &str) -> Result<()> {
+    let len =
+        u16::try_from(name.len()).with_context(|| format!("Archive path too long: {name}"))?;
+    out.write_all(&len.to_le_bytes())?;
+    out.write_all(name.as_bytes())?;
+    Ok(())
+}
+
+/// Compress a buffer as a zlib stream at the default compression level
+pub fn zlib_compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+    encoder.write_all(data)?;
+    encoder.finish().context("zlib compression failed")
+}
+
+/// Compress a buffer as a zlib stream with the Xbox window size ([`XBOX_WINDOW_BITS`])
+pub fn zlib_compress_xbox(data: &[u8]) -> Result<Vec<u8>> {
+    let compress = Compress::new_with_window_bits(Compression::default(), true, XBOX_WINDOW_BITS);
+    let mut encoder = ZlibEncoder::new_with_compress(Vec::with_capacity(data.len() / 2), compress);
+    encoder.write_all(data)?;
+    encoder.finish().context("zlib compression failed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn read_u32(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    fn read_u64(bytes: &[u8], at: usize) -> u64 {
+        u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_crc32_variant() {
+        // Fallout 4 CRC starts at zero and is not inverted
+        assert_eq!(crc32(b""), 0);
+        assert_ne!(crc32(b"a"), crc32(b"b"));
+    }
+
+    #[test]
+    fn test_hash_path_is_case_and_separator_insensitive() {
+        let a = hash_path("meshes\\precombined\\Test.NIF");
+        let b = hash_path("Meshes/Precombined/test.nif");
+        assert_eq!(a, b);
+        assert_eq!(&a.1, b"nif\0");
+        assert_eq!(a.2, crc32(b"meshes\\precombined"));
+        assert_eq!(a.0, crc32(b"test"));
+    }
+
+    #[test]
+    fn test_add_file_rejects_traversal() {
+        let mut writer = Ba2Writer::new(false);
+        assert!(writer.add_file("..\\evil.nif", "x").is_err());
+        assert!(writer.add_file("", "x").is_err());
+        assert!(writer.add_file("meshes\\ok.nif", "x").is_ok());
+    }
+
+    #[test]
+    fn test_write_empty_archive_fails() {
+        let temp = TempDir::new().unwrap();
+        let writer = Ba2Writer::new(true);
+        assert!(writer.write(temp.path().join("empty.ba2")).is_err());
+    }
+
+    #[test]
+    fn test_write_general_archive_layout() {
+        let temp = TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("a.nif"), vec![7u8; 4096]).unwrap();
+        fs::write(precombined.join("b.nif"), b"tiny").unwrap();
+
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        assert_eq!(writer.entries.len(), 2);
+
+        let archive = temp.path().join("Test - Main.ba2");
+        writer.write(&archive).unwrap();
+
+        let bytes = fs::read(&archive).unwrap();
+        assert_eq!(&bytes[0..4], BA2_MAGIC);
+        assert_eq!(read_u32(&bytes, 4), BA2_VERSION);
+        assert_eq!(&bytes[8..12], BA2_TYPE_GENERAL);
+        assert_eq!(read_u32(&bytes, 12), 2);
+
+        // First record: compressed payload that is smaller than the original
+        let rec = usize::try_from(HEADER_SIZE).unwrap();
+        assert_eq!(read_u32(&bytes, rec + 12), RECORD_FLAGS);
+        assert_eq!(read_u64(&bytes, rec + 16), HEADER_SIZE + 2 * RECORD_SIZE);
+        let packed = read_u32(&bytes, rec + 24);
+        assert!(packed > 0 && packed < 4096);
+        assert_eq!(read_u32(&bytes, rec + 28), 4096);
+        assert_eq!(read_u32(&bytes, rec + 32), RECORD_SENTINEL);
+
+        // Name table lists both files in record order
+        let name_table = usize::try_from(read_u64(&bytes, 16)).unwrap();
+        let len = usize::from(u16::from_le_bytes([
+            bytes[name_table],
+            bytes[name_table + 1],
+        ]));
+        let first = std::str::from_utf8(&bytes[name_table + 2..name_table + 2 + len]).unwrap();
+        assert_eq!(first, "meshes\\precombined\\a.nif");
+    }
+
+    #[test]
+    fn test_append_in_place_keeps_existing_files() {
+        use crate::tools::ba2_reader::Ba2Archive;
+
+        let temp = TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("a.nif"), vec![1u8; 3000]).unwrap();
+        fs::write(precombined.join("b.nif"), b"small mesh").unwrap();
+
+        let archive = temp.path().join("Test - Main.ba2");
+        let mut writer = Ba2Writer::new(true);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.write(&archive).unwrap();
+
+        // Enough new files that the record table grows over the first file's data
+        for i in 0..5 {
+            fs::write(
+                vis.join(format!("{i:08x}.uvd")),
+                vec![u8::try_from(i).unwrap(); 100],
+            )
+            .unwrap();
+        }
+        let mut appender = Ba2Writer::new(true);
+        appender.add_directory(&data_dir, &vis).unwrap();
+        appender.append_to(&archive).unwrap();
+
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert_eq!(ba2.entries().len(), 7);
+        let a = ba2.find("meshes\\precombined\\a.nif").unwrap();
+        assert!(a.offset >= HEADER_SIZE + 7 * RECORD_SIZE);
+        assert_eq!(ba2.read(a).unwrap(), vec![1u8; 3000]);
+        let b = ba2.find("meshes\\precombined\\b.nif").unwrap();
+        assert_eq!(ba2.read(b).unwrap(), b"small mesh");
+        let uvd = ba2.find("vis\\00000003.uvd").unwrap();
+        assert_eq!(ba2.read(uvd).unwrap(), vec![3u8; 100]);
+
+        // New records carry the game's lookup hashes for their paths
+        let first_new = &ba2.entries()[2];
+        assert_eq!(first_new.name, "vis\\00000000.uvd");
+        let (name_hash, ext, dir_hash) = hash_path(&first_new.name);
+        assert_eq!(
+            (first_new.name_hash, first_new.ext, first_new.dir_hash),
+            (name_hash, ext, dir_hash)
+        );
+    }
+
+    #[test]
+    fn test_append_replaces_existing_entry() {
+        use crate::tools::ba2_reader::Ba2Archive;
+
+        let temp = TempDir::new().unwrap();
+        let vis = temp.path().join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("cell.uvd"), b"old previs").unwrap();
+
+        let archive = temp.path().join("out.ba2");
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(temp.path(), &vis).unwrap();
+        writer.write(&archive).unwrap();
+
+        fs::write(vis.join("cell.uvd"), b"new previs data").unwrap();
+        writer.append_to(&archive).unwrap();
+
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert_eq!(ba2.entries().len(), 1);
+        assert_eq!(ba2.read(&ba2.entries()[0]).unwrap(), b"new previs data");
+    }
+
+    #[test]
+    fn test_append_replacing_entries_leaves_no_dead_space() {
+        use crate::tools::ba2_reader::Ba2Archive;
+
+        let temp = TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("a.nif"), vec![1u8; 3000]).unwrap();
+        fs::write(vis.join("cell.uvd"), vec![2u8; 2000]).unwrap();
+
+        let archive = temp.path().join("Test - Main.ba2");
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.write(&archive).unwrap();
+
+        // The same files written from scratch
+        let fresh = temp.path().join("Fresh.ba2");
+        let mut full = Ba2Writer::new(false);
+        full.add_directory(&data_dir, &precombined).unwrap();
+        full.add_directory(&data_dir, &vis).unwrap();
+        full.write(&fresh).unwrap();
+        let size = fs::metadata(&fresh).unwrap().len();
+
+        // Rerunning step 8 replaces the same previs file each time
+        let mut appender = Ba2Writer::new(false);
+        appender.add_directory(&data_dir, &vis).unwrap();
+        appender.append_to(&archive).unwrap();
+        for _ in 0..3 {
+            appender.append_to(&archive).unwrap();
+            assert_eq!(fs::metadata(&archive).unwrap().len(), size);
+        }
+
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert_eq!(ba2.entries().len(), 2);
+        let a = ba2.find("meshes\\precombined\\a.nif").unwrap();
+        assert_eq!(ba2.read(a).unwrap(), vec![1u8; 3000]);
+        assert!(!temp.path().join("Test - Main.ba2.tmp").exists());
+    }
+
+    #[test]
+    fn test_append_keeps_archive_version() {
+        use crate::tools::ba2_reader::Ba2Archive;
+
+        let temp = TempDir::new().unwrap();
+        let vis = temp.path().join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("a.uvd"), b"first").unwrap();
+
+        let archive = temp.path().join("out.ba2");
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(temp.path(), &vis).unwrap();
+        writer.write(&archive).unwrap();
+
+        // Mark the archive as a next-gen (version 8) archive
+        let mut bytes = fs::read(&archive).unwrap();
+        bytes[4..8].copy_from_slice(&8u32.to_le_bytes());
+        fs::write(&archive, bytes).unwrap();
+
+        // Both the in-place append and the rewrite keep the version
+        fs::write(vis.join("b.uvd"), b"second").unwrap();
+        let mut appender = Ba2Writer::new(false);
+        appender.add_file("vis\\b.uvd", vis.join("b.uvd")).unwrap();
+        appender.append_to(&archive).unwrap();
+        assert_eq!(Ba2Archive::open(&archive).unwrap().version(), 8);
+
+        appender.append_to(&archive).unwrap();
+        let ba2 = Ba2Archive::open(&archive).unwrap();
+        assert_eq!(ba2.version(), 8);
+        assert_eq!(ba2.entries().len(), 2);
+    }
+
+    #[test]
+    fn test_write_uncompressed_archive() {
+        let temp = TempDir::new().unwrap();
+        let vis = temp.path().join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(vis.join("cell.uvd"), b"previs").unwrap();
+
+        let mut writer = Ba2Writer::new(false);
+        writer.add_directory(temp.path(), &vis).unwrap();
+        let archive = temp.path().join("out.ba2");
+        writer.write(&archive).unwrap();
+
+        let bytes = fs::read(&archive).unwrap();
+        let rec = usize::try_from(HEADER_SIZE).unwrap();
+        assert_eq!(read_u32(&bytes, rec + 24), 0);
+        let offset = usize::try_from(read_u64(&bytes, rec + 16)).unwrap();
+        assert_eq!(&bytes[offset..offset + 6], b"previs");
+    }
+
+    #[test]
+    fn test_write_xbox_archive_uses_small_window() {
+        let temp = TempDir::new().unwrap();
+        let vis = temp.path().join("vis");
+        fs::create_dir_all(&vis).unwrap();
+        let data = b"previs data ".repeat(64);
+        fs::write(vis.join("cell.uvd"), &data).unwrap();
+
+        let mut writer = Ba2Writer::with_profile(CompressionProfile::Xbox);
+        writer.add_directory(temp.path(), &vis).unwrap();
+        let archive = temp.path().join("out.ba2");
+        writer.write(&archive).unwrap();
+
+        let bytes = fs::read(&archive).unwrap();
+        let rec = usize::try_from(HEADER_SIZE).unwrap();
+        assert!(read_u32(&bytes, rec + 24) > 0);
+        let offset = usize::try_from(read_u64(&bytes, rec + 16)).unwrap();
+        // zlib CMF byte: CINFO (window bits - 8) in the high nibble, deflate (8) in the low
+        assert_eq!(bytes[offset], ((XBOX_WINDOW_BITS - 8) << 4) | 8);
+
+        let read = Ba2Archive::open(&archive).unwrap();
+        let entry = read.find("vis\\cell.uvd").unwrap();
+        assert_eq!(read.read(entry).unwrap(), data);
+    }
+}
@@ -0,0 +1,651 @@
+//! Native Fallout 4 BA2 (BTDX) archive reader
+//!
+//! Parses the header, file records and name table of a General (`GNRL`) BA2 archive so
+//! that its contents can be listed and extracted without Archive2.exe. This is the
+//! counterpart of the writer in [`ba2`](super::ba2) and uses the same layout constants.
+//!
+//! # Supported Versions
+//!
+//! - Version 1: original Fallout 4 archives (written by Archive2 and [`Ba2Writer`](super::ba2::Ba2Writer))
+//! - Versions 7 and 8: Fallout 4 next-gen update archives (same General layout)
+//!
+//! Starfield archives (versions 2 and 3) and texture archives (`DX10`) are rejected.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! # use generateprevisibines::tools::ba2_reader::Ba2Archive;
+//! let archive = Ba2Archive::open("C:\\Games\\Fallout4\\Data\\MyMod - Main.ba2")?;
+//! for entry in archive.entries() {
+//!     println!("{} ({} bytes)", entry.name, entry.unpacked_size);
+//! }
+//! archive.extract_all("C:\\Temp\\MyMod")?;
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+
+use anyhow::{Context, Result, bail};
+use flate2::read::ZlibDecoder;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use super::ba2::{
+    BA2_MAGIC, BA2_TYPE_GENERAL, FileRecord, HEADER_SIZE, RECORD_SIZE, archive_name_for,
+    normalize_archive_path,
+};
+
+/// Archive versions that share the Fallout 4 General layout
+const SUPPORTED_VERSIONS: [u32; 3] = [1, 7, 8];
+
+/// Most memory reserved up front for a decompressed entry
+///
+/// Larger entries grow as they are read, so a corrupt `unpacked_size` cannot make the
+/// reader allocate gigabytes before the data is checked.
+const MAX_PREALLOCATION: u32 = 64 * 1024 * 1024;
+
+/// A single file stored in a General archive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ba2Entry {
+    /// Path inside the archive as stored in the name table (backslash-separated)
+    pub name: String,
+    /// CRC-32 of the lowercase file stem, as stored in the record
+    pub name_hash: u32,
+    /// File extension (up to 4 bytes, zero-padded), as stored in the record
+    pub ext: [u8; 4],
+    /// CRC-32 of the lowercase directory path, as stored in the record
+    pub dir_hash: u32,
+    /// Record flags
+    pub flags: u32,
+    /// Absolute offset of the file data within the archive
+    pub offset: u64,
+    /// Compressed size, or `0` if the data is stored uncompressed
+    pub packed_size: u32,
+    /// Size of the file after decompression
+    pub unpacked_size: u32,
+}
+
+impl Ba2Entry {
+    /// Whether the entry's data is zlib-compressed
+    pub fn is_compressed(&self) -> bool {
+        self.packed_size != 0
+    }
+
+    /// Number of bytes the entry occupies in the archive
+    pub fn stored_size(&self) -> u32 {
+        if self.is_compressed() {
+            self.packed_size
+        } else {
+            self.unpacked_size
+        }
+    }
+
+    /// Byte range `[start, end)` of the entry's data within the archive
+    ///
+    /// Returns `None` if the range does not fit in a `u64`, which only a corrupt offset
+    /// can cause.
+    pub fn data_range(&self) -> Option<(u64, u64)> {
+        let end = self.offset.checked_add(u64::from(self.stored_size()))?;
+        Some((self.offset, end))
+    }
+
+    /// The on-disk record for this entry
+    pub fn record(&self) -> FileRecord {
+        FileRecord {
+            name_hash: self.name_hash,
+            ext: self.ext,
+            dir_hash: self.dir_hash,
+            flags: self.flags,
+            offset: self.offset,
+            packed_size: self.packed_size,
+            unpacked_size: self.unpacked_size,
+        }
+    }
+}
+
+/// A parsed General BA2 archive
+///
+/// Only the header, record table and name table are read by [`open`](Self::open). File
+/// data is read from disk on demand, so opening a large archive is cheap.
+#[derive(Debug, Clone)]
+pub struct Ba2Archive {
+    path: PathBuf,
+    version: u32,
+    name_table_offset: u64,
+    entries: Vec<Ba2Entry>,
+}
+
+impl Ba2Archive {
+    /// Open and parse an archive
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if:
+    /// - The file cannot be opened or is truncated
+    /// - The file is not a BA2 archive, or uses an unsupported version or type
+    /// - The name table does not match the record count
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open archive: {}", path.display()))?;
+        let mut reader = BufReader::new(file);
+
+        Self::parse(&mut reader, path)
+            .with_context(|| format!("Failed to read archive: {}", path.display()))
+    }
+
+    fn parse(reader: &mut (impl Read + Seek), path: &Path) -> Result<Self> {
+        let mut header = [0u8; 24];
+        reader
+            .read_exact(&mut header)
+            .context("File is too small to be a BA2 archive")?;
+
+        if &header[0..4] != BA2_MAGIC {
+            bail!("Not a BA2 archive (missing BTDX signature)");
+        }
+
+        let version = u32_at(&header, 4);
+        if !SUPPORTED_VERSIONS.contains(&version) {
+            bail!("Unsupported BA2 version: {version}");
+        }
+
+        if &header[8..12] != BA2_TYPE_GENERAL {
+            bail!(
+                "Unsupported BA2 type '{}' (only General archives are supported)",
+                String::from_utf8_lossy(&header[8..12])
+            );
+        }
+
+        let file_count = u32_at(&header, 12);
+        let name_table_offset = u64::from_le_bytes(header[16..24].try_into()?);
+
+        let archive_len = reader.seek(SeekFrom::End(0))?;
+        let records_end = HEADER_SIZE + RECORD_SIZE * u64::from(file_count);
+        if records_end > archive_len {
+            bail!("Record table extends past end of archive ({file_count} records)");
+        }
+
+        reader.seek(SeekFrom::Start(HEADER_SIZE))?;
+        let mut entries = Vec::with_capacity(file_count as usize);
+        let mut record = [0u8; 36];
+        for _ in 0..file_count {
+            reader.read_exact(&mut record)?;
+            entries.push(Ba2Entry {
+                name: String::new(),
+                name_hash: u32_at(&record, 0),
+                ext: record[4..8].try_into()?,
+                dir_hash: u32_at(&record, 8),
+                flags: u32_at(&record, 12),
+                offset: u64::from_le_bytes(record[16..24].try_into()?),
+                packed_size: u32_at(&record, 24),
+                unpacked_size: u32_at(&record, 28),
+            });
+        }
+
+        if name_table_offset == 0 || name_table_offset > archive_len {
+            bail!("Archive has no valid name table");
+        }
+
+        reader.seek(SeekFrom::Start(name_table_offset))?;
+        for (index, entry) in entries.iter_mut().enumerate() {
+            let mut len = [0u8; 2];
+            reader
+                .read_exact(&mut len)
+                .with_context(|| format!("Name table is truncated at entry {index}"))?;
+            let mut name = vec![0u8; usize::from(u16::from_le_bytes(len))];
+            reader
+                .read_exact(&mut name)
+                .with_context(|| format!("Name table is truncated at entry {index}"))?;
+            entry.name = String::from_utf8_lossy(&name).into_owned();
+
+            if entry.data_range().is_none_or(|(_, end)| end > archive_len) {
+                bail!("Data for '{}' extends past end of archive", entry.name);
+            }
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            version,
+            name_table_offset,
+            entries,
+        })
+    }
+
+    /// Path of the archive on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Archive format version from the header
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// Offset of the name table, which follows the last file's data
+    pub fn name_table_offset(&self) -> u64 {
+        self.name_table_offset
+    }
+
+    /// All entries in record order
+    pub fn entries(&self) -> &[Ba2Entry] {
+        &self.entries
+    }
+
+    /// Look up an entry by path
+    ///
+    /// Matching is case-insensitive and accepts either `/` or `\` as the separator, the
+    /// same way the game resolves paths. This scans every entry; use
+    /// [`name_index`](Self::name_index) for repeated lookups.
+    pub fn find(&self, name: &str) -> Option<&Ba2Entry> {
+        let wanted = index_key(name);
+        self.entries
+            .iter()
+            .find(|entry| index_key(&entry.name) == wanted)
+    }
+
+    /// Index the entries by lowercase path
+    ///
+    /// Look entries up with [`index_key`] of the wanted path. Builds the map once, so
+    /// checking many names against a large archive stays linear.
+    pub fn name_index(&self) -> HashMap<String, &Ba2Entry> {
+        self.entries
+            .iter()
+            .map(|entry| (index_key(&entry.name), entry))
+            .collect()
+    }
+
+    /// Read and decompress the data of a single entry
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the archive cannot be read, or the zlib stream is corrupt or
+    /// does not decompress to the recorded size.
+    pub fn read(&self, entry: &Ba2Entry) -> Result<Vec<u8>> {
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("Failed to open archive: {}", self.path.display()))?;
+        read_entry_from(&mut file, entry)
+    }
+
+    /// Extract a single file to `dest`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `name` is not in the archive, or if the data cannot be read or
+    /// written.
+    pub fn extract_file(&self, name: &str, dest: impl AsRef<Path>) -> Result<()> {
+        let dest = dest.as_ref();
+        let entry = self
+            .find(name)
+            .with_context(|| format!("'{name}' not found in {}", self.path.display()))?;
+
+        let data = self.read(entry)?;
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+        fs::write(dest, data).with_context(|| format!("Failed to write {}", dest.display()))
+    }
+
+    /// Check that every file under `dir` is in the archive with the same size and content
+    ///
+    /// Archive paths are computed relative to `root`, the directory that corresponds to
+    /// `Data` (see [`Ba2Writer::add_directory`](super::ba2::Ba2Writer::add_directory)). Each
+    /// archived copy is decompressed and its SHA-256 hash compared to the source file's, so
+    /// truncated or corrupted entries are caught, not just missing ones.
+    ///
+    /// Files in the archive that are not under `dir` are ignored.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `dir` cannot be walked or a source file cannot be read. Missing
+    /// or mismatched files are reported in the returned [`VerificationReport`], not as
+    /// errors; an archived entry that cannot be decompressed counts as a mismatch.
+    pub fn verify_directory(
+        &self,
+        root: impl AsRef<Path>,
+        dir: impl AsRef<Path>,
+    ) -> Result<VerificationReport> {
+        let root = root.as_ref();
+        let dir = dir.as_ref();
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("Failed to open archive: {}", self.path.display()))?;
+        let mut report = VerificationReport::default();
+
+        let index = self.name_index();
+
+        for source in WalkDir::new(dir).sort_by_file_name() {
+            let source = source.with_context(|| format!("Failed to walk {}", dir.display()))?;
+            if !source.file_type().is_file() {
+                continue;
+            }
+
+            let name = archive_name_for(root, source.path())?;
+            let Some(entry) = index.get(&index_key(&name)).copied() else {
+                report.missing.push(name);
+                continue;
+            };
+
+            let expected = fs::read(source.path())
+                .with_context(|| format!("Failed to read {}", source.path().display()))?;
+            if entry.unpacked_size as usize != expected.len() {
+                report.mismatched.push(format!(
+                    "{name}: size {} in archive, {} on disk",
+                    entry.unpacked_size,
+                    expected.len()
+                ));
+                continue;
+            }
+
+            match read_entry_from(&mut file, entry) {
+                Ok(actual) if Sha256::digest(&actual) == Sha256::digest(&expected) => {
+                    report.verified += 1;
+                }
+                Ok(_) => report
+                    .mismatched
+                    .push(format!("{name}: content hash does not match")),
+                Err(e) => report.mismatched.push(format!("{name}: {e:#}")),
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Extract every file into `dest_dir`, recreating the archive's directory structure
+    ///
+    /// Returns the number of files extracted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if an entry name would escape `dest_dir` (absolute path or `..`),
+    /// or if any file cannot be read or written.
+    pub fn extract_all(&self, dest_dir: impl AsRef<Path>) -> Result<usize> {
+        let dest_dir = dest_dir.as_ref();
+        let mut file = File::open(&self.path)
+            .with_context(|| format!("Failed to open archive: {}", self.path.display()))?;
+
+        for entry in &self.entries {
+            let dest = dest_dir.join(safe_relative_path(&entry.name)?);
+            let data = read_entry_from(&mut file, entry)?;
+
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+            fs::write(&dest, data)
+                .with_context(|| format!("Failed to write {}", dest.display()))?;
+        }
+
+        Ok(self.entries.len())
+    }
+}
+
+/// Result of comparing an archive against the folder it was built from
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// Number of source files found in the archive with matching size and content hash
+    pub verified: usize,
+    /// Source files that are not in the archive
+    pub missing: Vec<String>,
+    /// Source files whose archived copy differs (`"<name>: <reason>"`)
+    pub mismatched: Vec<String>,
+}
+
+impl VerificationReport {
+    /// Whether every source file was found intact
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// Key of an archive path in [`Ba2Archive::name_index`]
+///
+/// Lowercase with `\` separators, so paths match the way the game resolves them.
+pub fn index_key(name: &str) -> String {
+    normalize_archive_path(name).to_lowercase()
+}
+
+/// Read one entry's data from an already opened archive file
+fn read_entry_from(file: &mut File, entry: &Ba2Entry) -> Result<Vec<u8>> {
+    file.seek(SeekFrom::Start(entry.offset))?;
+    let mut stored = vec![0u8; entry.stored_size() as usize];
+    file.read_exact(&mut stored)
+        .with_context(|| format!("Failed to read data for '{}'", entry.name))?;
+
+    if !entry.is_compressed() {
+        return Ok(stored);
+    }
+
+    // Decompress at most one byte more than the record claims, enough to detect a
+    // mismatch without inflating a zlib bomb
+    let mut data = Vec::with_capacity(entry.unpacked_size.min(MAX_PREALLOCATION) as usize);
+    ZlibDecoder::new(stored.as_slice())
+        .take(u64::from(entry.unpacked_size) + 1)
+        .read_to_end(&mut data)
+        .with_context(|| format!("Failed to decompress '{}'", entry.name))?;
+
+    if data.len() > entry.unpacked_size as usize {
+        bail!(
+            "'{}' decompresses to more than the {} bytes its record gives",
+            entry.name,
+            entry.unpacked_size
+        );
+    }
+    if data.len() != entry.unpacked_size as usize {
+        bail!(
+            "'{}' decompressed to {} bytes, expected {}",
+            entry.name,
+            data.len(),
+            entry.unpacked_size
+        );
+    }
+
+    Ok(data)
+}
+
+/// Convert an archive path into a relative filesystem path, rejecting traversal
+fn safe_relative_path(name: &str) -> Result<PathBuf> {
+    let mut relative = PathBuf::new();
+    for part in normalize_archive_path(name).split('\\') {
+        match part {
+            "" | "." => {}
+            ".." => bail!("Archive entry escapes the output directory: {name}"),
+            part if part.contains(':') => {
+                bail!("Archive entry has an absolute path: {name}");
+            }
+            part => relative.push(part),
+        }
+    }
+
+    if relative.as_os_str().is_empty() {
+        bail!("Archive entry has an empty name");
+    }
+    Ok(relative)
+}
+
+fn u32_at(bytes: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([bytes[at], bytes[at + 1], bytes[at + 2], bytes[at + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::ba2::Ba2Writer;
+    use tempfile::TempDir;
+
+    fn build_archive(temp: &TempDir, compress: bool) -> PathBuf {
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        let vis = data_dir.join("vis");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::create_dir_all(&vis).unwrap();
+        fs::write(precombined.join("0001F4A2_OC.nif"), vec![3u8; 10_000]).unwrap();
+        fs::write(vis.join("0000003c.uvd"), b"previs data").unwrap();
+        fs::write(vis.join("empty.uvd"), b"").unwrap();
+
+        let mut writer = Ba2Writer::new(compress);
+        writer.add_directory(&data_dir, &precombined).unwrap();
+        writer.add_directory(&data_dir, &vis).unwrap();
+        let archive = temp.path().join("Test - Main.ba2");
+        writer.write(&archive).unwrap();
+        archive
+    }
+
+    #[test]
+    fn test_open_lists_entries() {
+        let temp = TempDir::new().unwrap();
+        let archive = Ba2Archive::open(build_archive(&temp, true)).unwrap();
+
+        assert_eq!(archive.version(), 1);
+        let names: Vec<_> = archive.entries().iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "meshes\\precombined\\0001F4A2_OC.nif",
+                "vis\\0000003c.uvd",
+                "vis\\empty.uvd"
+            ]
+        );
+        assert!(archive.entries()[0].is_compressed());
+        assert_eq!(archive.entries()[0].unpacked_size, 10_000);
+    }
+
+    #[test]
+    fn test_read_round_trip() {
+        for compress in [true, false] {
+            let temp = TempDir::new().unwrap();
+            let archive = Ba2Archive::open(build_archive(&temp, compress)).unwrap();
+
+            let nif = archive.find("Meshes/Precombined/0001f4a2_oc.NIF").unwrap();
+            assert_eq!(archive.read(nif).unwrap(), vec![3u8; 10_000]);
+
+            let uvd = archive.find("vis\\0000003c.uvd").unwrap();
+            assert_eq!(archive.read(uvd).unwrap(), b"previs data");
+
+            let empty = archive.find("vis\\empty.uvd").unwrap();
+            assert!(archive.read(empty).unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_read_rejects_wrong_unpacked_size() {
+        let temp = TempDir::new().unwrap();
+        let path = build_archive(&temp, true);
+
+        // unpacked_size of the first record (after the name hash, extension, directory
+        // hash, flags, offset and packed size)
+        let field = usize::try_from(HEADER_SIZE).unwrap() + 28;
+        let mut bytes = fs::read(&path).unwrap();
+        for (size, expected) in [
+            (100u32, "more than the 100 bytes"),
+            (20_000, "expected 20000"),
+        ] {
+            bytes[field..field + 4].copy_from_slice(&size.to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            let archive = Ba2Archive::open(&path).unwrap();
+            let err = archive.read(&archive.entries()[0]).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_open_rejects_data_past_end() {
+        let temp = TempDir::new().unwrap();
+        let path = build_archive(&temp, true);
+
+        // offset of the first record (after the name hash, extension, directory hash
+        // and flags)
+        let field = usize::try_from(HEADER_SIZE).unwrap() + 16;
+        let mut bytes = fs::read(&path).unwrap();
+        for offset in [u64::MAX - 1, u64::try_from(bytes.len()).unwrap()] {
+            bytes[field..field + 8].copy_from_slice(&offset.to_le_bytes());
+            fs::write(&path, &bytes).unwrap();
+
+            let err = Ba2Archive::open(&path).unwrap_err();
+            assert!(format!("{err:#}").contains("past end of archive"), "{err:#}");
+        }
+    }
+
+    #[test]
+    fn test_extract_all_and_single_file() {
+        let temp = TempDir::new().unwrap();
+        let archive = Ba2Archive::open(build_archive(&temp, true)).unwrap();
+
+        let out = temp.path().join("out");
+        assert_eq!(archive.extract_all(&out).unwrap(), 3);
+        assert_eq!(
+            fs::read(out.join("vis").join("0000003c.uvd")).unwrap(),
+            b"previs data"
+        );
+        assert_eq!(
+            fs::read(out.join("meshes/precombined/0001F4A2_OC.nif"))
+                .unwrap()
+                .len(),
+            10_000
+        );
+
+        let single = temp.path().join("single.uvd");
+        archive.extract_file("vis/0000003c.uvd", &single).unwrap();
+        assert_eq!(fs::read(&single).unwrap(), b"previs data");
+        assert!(archive.extract_file("vis\\missing.uvd", &single).is_err());
+    }
+
+    #[test]
+    fn test_verify_directory() {
+        let temp = TempDir::new().unwrap();
+        let archive = Ba2Archive::open(build_archive(&temp, true)).unwrap();
+        let data_dir = temp.path().join("Data");
+        let vis = data_dir.join("vis");
+
+        let report = archive.verify_directory(&data_dir, &data_dir).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.verified, 3);
+
+        // Same size, different content
+        fs::write(vis.join("0000003c.uvd"), b"PREVIS DATA").unwrap();
+        // Not archived at all
+        fs::write(vis.join("0000003d.uvd"), b"late").unwrap();
+
+        let report = archive.verify_directory(&data_dir, &vis).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.verified, 1);
+        assert_eq!(report.missing, ["vis\\0000003d.uvd"]);
+        assert_eq!(report.mismatched.len(), 1);
+        assert!(report.mismatched[0].contains("hash"));
+    }
+
+    #[test]
+    fn test_open_rejects_non_archives() {
+        let temp = TempDir::new().unwrap();
+
+        let bogus = temp.path().join("bogus.ba2");
+        fs::write(&bogus, b"not an archive at all, definitely").unwrap();
+        assert!(Ba2Archive::open(&bogus).is_err());
+
+        let short = temp.path().join("short.ba2");
+        fs::write(&short, b"BTDX").unwrap();
+        assert!(Ba2Archive::open(&short).is_err());
+
+        let textures = temp.path().join("textures.ba2");
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(b"BTDX");
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+        bytes.extend_from_slice(b"DX10");
+        bytes.extend_from_slice(&[0u8; 12]);
+        fs::write(&textures, bytes).unwrap();
+        assert!(Ba2Archive::open(&textures).is_err());
+    }
+
+    #[test]
+    fn test_safe_relative_path() {
+        assert_eq!(
+            safe_relative_path("vis\\a.uvd").unwrap(),
+            Path::new("vis").join("a.uvd")
+        );
+        assert!(safe_relative_path("..\\evil.dll").is_err());
+        assert!(safe_relative_path("C:\\Windows\\evil.dll").is_err());
+        assert!(safe_relative_path("").is_err());
+    }
+}
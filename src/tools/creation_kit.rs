@@ -94,6 +94,7 @@ use std::process::Command;
 
 use crate::config::BuildMode;
 use crate::tools::dll_manager::{DllGuard, DllManager};
+use crate::utils;
 
 /// Critical error pattern: `CreationKit` handle limit exceeded
 ///
@@ -159,6 +160,58 @@ const HANDLE_LIMIT_ERROR: &str = "OUT OF HANDLE ARRAY ENTRIES";
 /// is specific to previs operations and not checked during other CK operations.
 const PREVIS_ERROR: &str = "visibility task did not complete";
 
+/// A `CreationKit` command-line operation
+///
+/// Each public operation of [`CreationKitRunner`] runs one of these; [`CreationKitRunner::plan`]
+/// describes one without running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CkOperation {
+    /// `-GeneratePrecombined:<plugin> clean|filtered all`
+    GeneratePrecombined(BuildMode),
+    /// `-CompressPSG:<plugin>`
+    CompressPsg,
+    /// `-BuildCDX:<plugin>`
+    BuildCdx,
+    /// `-GeneratePreVisData:<plugin> clean all`
+    GeneratePrevis,
+}
+
+impl CkOperation {
+    /// Human-readable operation name for logging
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::GeneratePrecombined(_) => "Generate Precombined",
+            Self::CompressPsg => "Compress PSG",
+            Self::BuildCdx => "Build CDX",
+            Self::GeneratePrevis => "Generate Previs",
+        }
+    }
+
+    /// `CreationKit` arguments for this operation on `plugin_name`
+    fn args(self, plugin_name: &str) -> Vec<String> {
+        match self {
+            Self::GeneratePrecombined(build_mode) => {
+                let mode = match build_mode {
+                    BuildMode::Clean => "clean",
+                    BuildMode::Filtered | BuildMode::Xbox => "filtered",
+                };
+                vec![
+                    format!("-GeneratePrecombined:{plugin_name}"),
+                    mode.to_string(),
+                    "all".to_string(),
+                ]
+            }
+            Self::CompressPsg => vec![format!("-CompressPSG:{plugin_name}")],
+            Self::BuildCdx => vec![format!("-BuildCDX:{plugin_name}")],
+            Self::GeneratePrevis => vec![
+                format!("-GeneratePreVisData:{plugin_name}"),
+                "clean".to_string(),
+                "all".to_string(),
+            ],
+        }
+    }
+}
+
 /// Runner for CreationKit.exe operations
 ///
 /// Provides a safe interface for running the Fallout 4 Creation Kit (CK) in automated
@@ -312,15 +365,7 @@ impl CreationKitRunner {
     /// - DLL guard is automatically applied (ENB/ReShade DLLs disabled during execution)
     /// - Generated files are placed in `Data/meshes/precombined/`
     pub fn generate_precombined(&self, plugin_name: &str, build_mode: BuildMode) -> Result<()> {
-        let (arg1, arg2) = match build_mode {
-            BuildMode::Clean => ("clean", "all"),
-            BuildMode::Filtered | BuildMode::Xbox => ("filtered", "all"),
-        };
-
-        self.run_with_dll_guard(
-            &[&format!("-GeneratePrecombined:{plugin_name}"), arg1, arg2],
-            "Generate Precombined",
-        )
+        self.run_with_dll_guard(CkOperation::GeneratePrecombined(build_mode), plugin_name)
     }
 
     /// Compress PSG file (clean mode only)
@@ -330,7 +375,7 @@ impl CreationKitRunner {
     /// Note: The output file name (e.g., "`MyMod` - Geometry.csg") is NOT part of the command.
     /// CK determines the output filename automatically from the plugin name.
     pub fn compress_psg(&self, plugin_name: &str) -> Result<()> {
-        self.run_with_dll_guard(&[&format!("-CompressPSG:{plugin_name}")], "Compress PSG")
+        self.run_with_dll_guard(CkOperation::CompressPsg, plugin_name)
     }
 
     /// Build CDX file (clean mode only)
@@ -340,7 +385,7 @@ impl CreationKitRunner {
     /// Note: The command takes the PLUGIN name, not the CDX filename.
     /// CK automatically creates the .cdx file from the plugin name.
     pub fn build_cdx(&self, plugin_name: &str) -> Result<()> {
-        self.run_with_dll_guard(&[&format!("-BuildCDX:{plugin_name}")], "Build CDX")
+        self.run_with_dll_guard(CkOperation::BuildCdx, plugin_name)
     }
 
     /// Generate previs data
@@ -405,14 +450,7 @@ impl CreationKitRunner {
     /// - DLL guard is automatically applied (ENB/ReShade DLLs disabled during execution)
     /// - Generated files are placed in `Data/vis/`
     pub fn generate_previs(&self, plugin_name: &str) -> Result<()> {
-        self.run_with_dll_guard(
-            &[
-                &format!("-GeneratePreVisData:{plugin_name}"),
-                "clean",
-                "all",
-            ],
-            "Generate Previs",
-        )?;
+        self.run_with_dll_guard(CkOperation::GeneratePrevis, plugin_name)?;
 
         // Check for specific previs failure in log
         if let Some(ref log_path) = self.log_file
@@ -432,6 +470,81 @@ impl CreationKitRunner {
         Ok(())
     }
 
+    /// Describe what running an operation would do, without doing it
+    ///
+    /// Used by `--dry-run`. Returns the actions in the order
+    /// [`run_with_dll_guard`](Self::run_with_dll_guard) would take them: deleting the old
+    /// log, renaming the DLLs [`DllManager::scan`] finds, and the exact command line
+    /// (through MO2 if configured). Nothing is deleted, renamed or run.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use generateprevisibines::tools::creation_kit::{CkOperation, CreationKitRunner};
+    /// # let runner = CreationKitRunner::new("ck.exe", "fo4");
+    /// for action in runner.plan(CkOperation::BuildCdx, "MyMod.esp") {
+    ///     println!("{action}");
+    /// }
+    /// ```
+    pub fn plan(&self, operation: CkOperation, plugin_name: &str) -> Vec<String> {
+        let mut actions = Vec::new();
+
+        if let Some(ref log_path) = self.log_file
+            && log_path.exists()
+        {
+            actions.push(format!("Delete old CK log: {}", log_path.display()));
+        }
+
+        let dlls = DllManager::new(&self.fallout4_dir).scan();
+        if !dlls.is_empty() {
+            let names: Vec<String> = dlls
+                .iter()
+                .filter_map(|dll| dll.file_name())
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            actions.push(format!(
+                "Rename {} DLL(s) to *-PJMdisabled while CreationKit runs: {}",
+                dlls.len(),
+                names.join(", ")
+            ));
+        }
+
+        let command = self.command(operation, plugin_name);
+        actions.push(format!("Run: {}", utils::command_line(&command)));
+        actions.push(format!(
+            "Working directory: {}",
+            self.fallout4_dir.display()
+        ));
+
+        match self.log_file {
+            Some(ref log_path) => {
+                actions.push(format!(
+                    "Check the CK log for errors: {}",
+                    log_path.display()
+                ));
+            }
+            None => actions.push("No CK log configured; errors will not be detected".to_string()),
+        }
+
+        actions
+    }
+
+    /// The `CreationKit` command for an operation (through MO2 if configured)
+    fn command(&self, operation: CkOperation, plugin_name: &str) -> Command {
+        let args = operation.args(plugin_name);
+        let mut command = if let Some(ref mo2_path) = self.mo2_path {
+            MO2Command::new(mo2_path, &self.ck_exe)
+                .args(args.iter().map(String::as_str))
+                .execute()
+        } else {
+            let mut command = Command::new(&self.ck_exe);
+            command.args(&args);
+            command
+        };
+        command.current_dir(&self.fallout4_dir);
+        command
+    }
+
     /// Run `CreationKit` with DLL guard and log management
     ///
     /// Internal wrapper that handles all the necessary workarounds for running `CreationKit`
@@ -440,8 +553,8 @@ impl CreationKitRunner {
     ///
     /// # Arguments
     ///
-    /// * `args` - Command-line arguments to pass to CreationKit.exe
-    /// * `operation` - Human-readable operation name for logging (e.g., "Generate Precombined")
+    /// * `operation` - Operation to run; provides the arguments and the name for logging
+    /// * `plugin_name` - Name of the plugin file (e.g., "MyMod.esp")
     ///
     /// # Process Flow
     ///
@@ -488,8 +601,9 @@ impl CreationKitRunner {
     /// - DLL restoration happens automatically via RAII (`DllGuard` drop)
     /// - If log file is not configured, error checking is skipped (warning logged)
     /// - MO2 mode is automatically used if `mo2_path` is set
-    fn run_with_dll_guard(&self, args: &[&str], operation: &str) -> Result<()> {
-        info!("Running CreationKit: {operation}");
+    fn run_with_dll_guard(&self, operation: CkOperation, plugin_name: &str) -> Result<()> {
+        let operation_name = operation.name();
+        info!("Running CreationKit: {operation_name}");
 
         // Delete old log file if it exists
         // NOTE: This operation may fail if the log is open in another process or
@@ -519,31 +633,21 @@ impl CreationKitRunner {
         let _guard = DllGuard::new(&mut dll_manager)?;
 
         // Run CreationKit (optionally through MO2)
-        info!("Executing: {} {}", self.ck_exe.display(), args.join(" "));
+        let mut command = self.command(operation, plugin_name);
+        info!("Executing: {}", utils::command_line(&command));
 
         let status = if let Some(ref mo2_path) = self.mo2_path {
-            // Use MO2 mode
             info!("Launching through Mod Organizer 2: {}", mo2_path.display());
-            let mut cmd = MO2Command::new(mo2_path, &self.ck_exe)
-                .args(args.iter().copied())
-                .execute();
-            cmd.current_dir(&self.fallout4_dir)
-                .status()
-                .with_context(|| {
-                    format!(
-                        "Failed to execute CreationKit through MO2: {}",
-                        mo2_path.display()
-                    )
-                })?
+            command.status().with_context(|| {
+                format!(
+                    "Failed to execute CreationKit through MO2: {}",
+                    mo2_path.display()
+                )
+            })?
         } else {
-            // Direct execution
-            Command::new(&self.ck_exe)
-                .args(args)
-                .current_dir(&self.fallout4_dir)
-                .status()
-                .with_context(|| {
-                    format!("Failed to execute CreationKit: {}", self.ck_exe.display())
-                })?
+            command.status().with_context(|| {
+                format!("Failed to execute CreationKit: {}", self.ck_exe.display())
+            })?
         };
 
         // Parse log for errors (even if exit code is non-zero)
@@ -558,7 +662,7 @@ impl CreationKitRunner {
             );
         }
 
-        info!("CreationKit {operation} completed");
+        info!("CreationKit {operation_name} completed");
         Ok(())
     }
 
@@ -673,4 +777,25 @@ mod tests {
 
         assert_eq!(runner.log_file, Some(PathBuf::from("CreationKit.log")));
     }
+
+    #[test]
+    fn test_plan_lists_command_and_dlls() {
+        let temp = tempfile::TempDir::new().unwrap();
+        fs::write(temp.path().join("d3d11.dll"), b"enb").unwrap();
+
+        let runner = CreationKitRunner::new("CreationKit.exe", temp.path());
+        let actions = runner.plan(
+            CkOperation::GeneratePrecombined(BuildMode::Filtered),
+            "MyMod.esp",
+        );
+
+        assert!(actions.contains(
+            &"Rename 1 DLL(s) to *-PJMdisabled while CreationKit runs: d3d11.dll".to_string()
+        ));
+        assert!(actions.contains(
+            &"Run: CreationKit.exe -GeneratePrecombined:MyMod.esp filtered all".to_string()
+        ));
+        // Planning renames nothing
+        assert!(temp.path().join("d3d11.dll").exists());
+    }
 }
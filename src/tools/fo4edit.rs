@@ -113,6 +113,8 @@ use std::process::Command;
 use std::thread;
 use std::time::Duration;
 
+use crate::utils;
+
 #[cfg(windows)]
 use windows::Win32::UI::Input::KeyboardAndMouse::{
     INPUT, INPUT_KEYBOARD, KEYBD_EVENT_FLAGS, KEYBDINPUT, KEYEVENTF_KEYUP, SendInput, VK_RETURN,
@@ -164,6 +166,44 @@ pub const SCRIPT_MERGE_COMBINED: &str = "Batch_FO4MergeCombinedObjectsAndCheck.p
 /// Used by [`FO4EditRunner::merge_previs`]
 pub const SCRIPT_MERGE_PREVIS: &str = "Batch_FO4MergePrevisandCleanRefr.pas";
 
+/// An `FO4Edit` merge script
+///
+/// Each merge of [`FO4EditRunner`] runs one of these; [`FO4EditRunner::plan`] describes one
+/// without running it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeScript {
+    /// [`SCRIPT_MERGE_COMBINED`]: merges `CombinedObjects.esp`
+    CombinedObjects,
+    /// [`SCRIPT_MERGE_PREVIS`]: merges `Previs.esp`
+    Previs,
+}
+
+impl MergeScript {
+    /// Human-readable operation name for logging
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::CombinedObjects => "Merge Combined Objects",
+            Self::Previs => "Merge Previs",
+        }
+    }
+
+    /// Pascal script file name
+    pub fn script(self) -> &'static str {
+        match self {
+            Self::CombinedObjects => SCRIPT_MERGE_COMBINED,
+            Self::Previs => SCRIPT_MERGE_PREVIS,
+        }
+    }
+
+    /// Plugin created by `CreationKit` that the script merges into the target plugin
+    pub fn source_plugin(self) -> &'static str {
+        match self {
+            Self::CombinedObjects => "CombinedObjects.esp",
+            Self::Previs => "Previs.esp",
+        }
+    }
+}
+
 /// Success indicator in `FO4Edit` log files
 ///
 /// **Pattern:** `"Completed: No Errors."`
@@ -331,12 +371,7 @@ impl FO4EditRunner {
     /// Script: `Batch_FO4MergeCombinedObjectsAndCheck.pas`
     /// Merges PrecombineObjects.esp into the main plugin
     pub fn merge_combined_objects(&self, plugin_name: &str) -> Result<()> {
-        self.run_script(
-            plugin_name,
-            "CombinedObjects.esp",
-            SCRIPT_MERGE_COMBINED,
-            "Merge Combined Objects",
-        )
+        self.run_script(plugin_name, MergeScript::CombinedObjects)
     }
 
     /// Run `FO4Edit` script to merge previs data
@@ -344,14 +379,91 @@ impl FO4EditRunner {
     /// Script: `Batch_FO4MergePrevisandCleanRefr.pas`
     /// Merges Previs.esp into the main plugin
     pub fn merge_previs(&self, plugin_name: &str) -> Result<()> {
-        self.run_script(
-            plugin_name,
-            "Previs.esp",
-            SCRIPT_MERGE_PREVIS,
-            "Merge Previs",
+        self.run_script(plugin_name, MergeScript::Previs)
+    }
+
+    /// Describe what running a merge script would do, without doing it
+    ///
+    /// Used by `--dry-run`. Returns the actions in the order
+    /// [`run_script`](Self::run_script) would take them: writing Plugins.txt, deleting the
+    /// old log and the exact command line (through MO2 if configured). Nothing is written,
+    /// deleted or run.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use generateprevisibines::tools::fo4edit::{FO4EditRunner, MergeScript};
+    /// # let runner = FO4EditRunner::new("FO4Edit.exe", "F:\\Games\\Fallout4");
+    /// for action in runner.plan(MergeScript::Previs, "MyMod.esp") {
+    ///     println!("{action}");
+    /// }
+    /// ```
+    pub fn plan(&self, script: MergeScript, plugin_name: &str) -> Vec<String> {
+        let (plugins_file, log_file) = Self::script_files();
+        let mut actions = vec![format!(
+            "Write {}: *{plugin_name}, *{}",
+            plugins_file.display(),
+            script.source_plugin()
+        )];
+        if log_file.exists() {
+            actions.push(format!("Delete old log: {}", log_file.display()));
+        }
+
+        let command = self.command(script, plugin_name, &plugins_file, &log_file);
+        actions.push(format!("Run: {}", utils::command_line(&command)));
+        actions.push(format!(
+            "Working directory: {}",
+            self.fallout4_dir.display()
+        ));
+        actions.push(
+            "Press ENTER in the Module Selection dialog, then close FO4Edit once the log appears"
+                .to_string(),
+        );
+        actions.push(format!("Check the log for errors: {}", log_file.display()));
+        actions
+    }
+
+    /// Plugins.txt and log file used by every script run, in the temp directory
+    ///
+    /// Matches the batch file's file names to minimize path issues.
+    fn script_files() -> (PathBuf, PathBuf) {
+        let temp_dir = std::env::temp_dir();
+        (
+            temp_dir.join("Plugins.txt"),
+            temp_dir.join("UnattendedScript.log"),
         )
     }
 
+    /// The `FO4Edit` command for a script (through MO2 if configured)
+    fn command(
+        &self,
+        script: MergeScript,
+        plugin_name: &str,
+        plugins_file: &Path,
+        log_file: &Path,
+    ) -> Command {
+        let args = vec![
+            "-fo4".to_string(),
+            "-autoexit".to_string(),
+            format!("-P:{}", plugins_file.display()),
+            format!("-Script:{}", script.script()),
+            format!("-Mod:{plugin_name}"),
+            format!("-log:{}", log_file.display()),
+        ];
+
+        let mut command = if let Some(ref mo2_path) = self.mo2_path {
+            MO2Command::new(mo2_path, &self.fo4edit_exe)
+                .args(args.iter().map(String::as_str))
+                .execute()
+        } else {
+            let mut command = Command::new(&self.fo4edit_exe);
+            command.args(&args);
+            command
+        };
+        command.current_dir(&self.fallout4_dir);
+        command
+    }
+
     /// Run an `FO4Edit` script with full automation
     ///
     /// Internal wrapper that handles all the necessary workarounds for running `FO4Edit` scripts
@@ -361,8 +473,8 @@ impl FO4EditRunner {
     /// # Arguments
     ///
     /// * `plugin_name` - Name of the plugin to process (e.g., "MyMod.esp")
-    /// * `script_name` - Name of the Pascal script to run (e.g., "`Batch_FO4MergeCombinedObjectsAndCheck.pas`")
-    /// * `operation` - Human-readable operation name for logging (e.g., "Merge Combined Objects")
+    /// * `script` - Merge script to run; provides the script, the second plugin and the
+    ///   name for logging
     ///
     /// # Process Flow
     ///
@@ -438,15 +550,11 @@ impl FO4EditRunner {
     /// # Examples
     ///
     /// ```no_run
-    /// # use generateprevisibines::tools::fo4edit::FO4EditRunner;
+    /// # use generateprevisibines::tools::fo4edit::{FO4EditRunner, MergeScript};
     /// # use std::path::Path;
     /// # let runner = FO4EditRunner::new("FO4Edit.exe", "F:\\Games\\Fallout4");
     /// // Merge PrecombineObjects.esp
-    /// runner.run_script(
-    ///     "MyMod.esp",
-    ///     "Batch_FO4MergeCombinedObjectsAndCheck.pas",
-    ///     "Merge Combined Objects"
-    /// )?;
+    /// runner.run_script("MyMod.esp", MergeScript::CombinedObjects)?;
     /// # Ok::<(), anyhow::Error>(())
     /// ```
     ///
@@ -456,27 +564,19 @@ impl FO4EditRunner {
     /// - Temporary files are created in the system's temp directory (`std::env::temp_dir()`)
     /// - Log files persist after execution for debugging purposes
     /// - Plugins.txt is cleaned up even if errors occur
-    fn run_script(
-        &self,
-        plugin_name: &str,
-        second_plugin: &str,
-        script_name: &str,
-        operation: &str,
-    ) -> Result<()> {
+    fn run_script(&self, plugin_name: &str, script: MergeScript) -> Result<()> {
+        let operation = script.name();
         info!("Running FO4Edit: {operation}");
 
         // Create temporary Plugins.txt
-        // Match batch file filenames exactly to minimize path issues and ensure compatibility
-        let temp_dir = std::env::temp_dir();
-        let plugins_file = temp_dir.join("Plugins.txt");
-        let log_file = temp_dir.join("UnattendedScript.log");
+        let (plugins_file, log_file) = Self::script_files();
 
         // Write plugin names to Plugins.txt with '*' prefix
         // Matches batch file behavior:
         // Echo *%~3 > "%LocPlugins_%"
         // Echo *%~4 >> "%LocPlugins_%"
         // Use CRLF for Windows compatibility (echo behavior)
-        let plugins_content = format!("*{plugin_name}\r\n*{second_plugin}");
+        let plugins_content = format!("*{plugin_name}\r\n*{}", script.source_plugin());
         fs::write(&plugins_file, plugins_content)
             .with_context(|| format!("Failed to create Plugins.txt: {}", plugins_file.display()))?;
 
@@ -485,46 +585,22 @@ impl FO4EditRunner {
             fs::remove_file(&log_file)?;
         }
 
-        // Build command arguments
-        let args = vec![
-            "-fo4".to_string(),
-            "-autoexit".to_string(),
-            format!("-P:{}", plugins_file.display()),
-            format!("-Script:{}", script_name),
-            format!("-Mod:{}", plugin_name),
-            format!("-log:{}", log_file.display()),
-        ];
-
-        info!(
-            "Executing: {} {}",
-            self.fo4edit_exe.display(),
-            args.join(" ")
-        );
+        let mut command = self.command(script, plugin_name, &plugins_file, &log_file);
+        info!("Executing: {}", utils::command_line(&command));
 
         // Launch FO4Edit (optionally through MO2)
         let mut child = if let Some(ref mo2_path) = self.mo2_path {
-            // Use MO2 mode
             info!("Launching through Mod Organizer 2: {}", mo2_path.display());
-            let mut cmd = MO2Command::new(mo2_path, &self.fo4edit_exe)
-                .args(args.iter().map(std::string::String::as_str))
-                .execute();
-            cmd.current_dir(&self.fallout4_dir)
-                .spawn()
-                .with_context(|| {
-                    format!(
-                        "Failed to launch FO4Edit through MO2: {}",
-                        mo2_path.display()
-                    )
-                })?
+            command.spawn().with_context(|| {
+                format!(
+                    "Failed to launch FO4Edit through MO2: {}",
+                    mo2_path.display()
+                )
+            })?
         } else {
-            // Direct execution
-            Command::new(&self.fo4edit_exe)
-                .args(&args)
-                .current_dir(&self.fallout4_dir)
-                .spawn()
-                .with_context(|| {
-                    format!("Failed to launch FO4Edit: {}", self.fo4edit_exe.display())
-                })?
+            command.spawn().with_context(|| {
+                format!("Failed to launch FO4Edit: {}", self.fo4edit_exe.display())
+            })?
         };
 
         // Wait for window to appear, then send ENTER keystroke
@@ -1094,4 +1170,18 @@ mod tests {
         let runner = FO4EditRunner::new("FO4Edit.exe", "F:\\Games\\Fallout4");
         assert_eq!(runner.fo4edit_exe, PathBuf::from("FO4Edit.exe"));
     }
+
+    #[test]
+    fn test_plan_builds_script_command() {
+        let runner = FO4EditRunner::new("FO4Edit.exe", "F:\\Games\\Fallout4");
+        let actions = runner.plan(MergeScript::Previs, "MyMod.esp");
+
+        let run = actions
+            .iter()
+            .find(|action| action.starts_with("Run: "))
+            .unwrap();
+        assert!(run.starts_with("Run: FO4Edit.exe -fo4 -autoexit -P:"));
+        assert!(run.contains(" -Script:Batch_FO4MergePrevisandCleanRefr.pas -Mod:MyMod.esp "));
+        assert!(actions[0].ends_with(": *MyMod.esp, *Previs.esp"));
+    }
 }
@@ -1,4 +1,8 @@
 pub mod archive;
+pub mod archive_backend;
+pub mod archive_manifest;
+pub mod ba2;
+pub mod ba2_reader;
 pub mod creation_kit;
 pub mod dll_manager;
 pub mod fo4edit;
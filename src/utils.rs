@@ -3,6 +3,7 @@ use log::{LevelFilter, warn};
 use std::env;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::process::Command;
 use windows::Win32::Storage::FileSystem::{
     GetFileVersionInfoSizeW, GetFileVersionInfoW, VerQueryValueW,
 };
@@ -279,12 +280,56 @@ pub fn get_simple_version(exe_path: &Path) -> String {
     }
 }
 
+/// Format a command the way it would be typed at a command prompt
+///
+/// The program and every argument that is empty or contains whitespace are quoted. Used
+/// by `--dry-run` to print the exact command lines the workflow would run.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::process::Command;
+///
+/// let mut command = Command::new("C:\\Games\\Fallout 4\\CreationKit.exe");
+/// command.arg("-BuildCDX:MyMod.esp");
+/// assert_eq!(
+///     command_line(&command),
+///     "\"C:\\Games\\Fallout 4\\CreationKit.exe\" -BuildCDX:MyMod.esp"
+/// );
+/// ```
+pub fn command_line(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|part| {
+            let part = part.to_string_lossy();
+            if part.is_empty() || part.contains(char::is_whitespace) {
+                format!("\"{part}\"")
+            } else {
+                part.into_owned()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
     #[ignore] // Requires actual executable file
     fn test_get_file_version() {
         // This test would need a real Windows executable to work
         // We can't test it without one
     }
+
+    #[test]
+    fn test_command_line_quotes_spaces() {
+        let mut command = Command::new("C:\\Games\\Fallout 4\\CreationKit.exe");
+        command.args(["-GeneratePrecombined:My Mod.esp", "clean", "all", ""]);
+        assert_eq!(
+            command_line(&command),
+            "\"C:\\Games\\Fallout 4\\CreationKit.exe\" \"-GeneratePrecombined:My Mod.esp\" clean all \"\""
+        );
+    }
 }
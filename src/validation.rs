@@ -1,15 +1,27 @@
 use anyhow::{Result, bail};
+use std::path::{Path, PathBuf};
+
+use crate::plugin::PluginHeader;
 
 /// Reserved plugin name patterns that are forbidden
 /// These match the batch script lines 147-154
 /// Note: xprevispatch is reserved for the SOURCE file - don't name your plugin this
 const RESERVED_NAMES: &[&str] = &["previs", "combinedobjects", "xprevispatch"];
 
+/// Plugin file extensions (light plugins use `.esl`)
+const PLUGIN_EXTENSIONS: &[&str] = &[".esp", ".esm", ".esl"];
+
+/// Check if a file name ends with a plugin extension (case insensitive)
+pub fn has_plugin_extension(name: &str) -> bool {
+    let lower = name.to_lowercase();
+    PLUGIN_EXTENSIONS.iter().any(|ext| lower.ends_with(ext))
+}
+
 /// Validate plugin name according to rules from batch script
 ///
 /// Rules (from batch lines 134-158):
 /// 1. Cannot be empty
-/// 2. Must end with .esp or .esm
+/// 2. Must end with .esp, .esm or .esl
 /// 3. Base name cannot exactly match reserved names (previs, combinedobjects, xprevispatch)
 /// 4. In clean mode, cannot contain spaces
 ///
@@ -21,11 +33,10 @@ pub fn validate_plugin_name(name: &str, clean_mode: bool) -> Result<()> {
     }
 
     // Check file extension
-    let name_lower = name.to_lowercase();
-    #[allow(clippy::case_sensitive_file_extension_comparisons)]
-    if !name_lower.ends_with(".esp") && !name_lower.ends_with(".esm") {
-        bail!("Plugin name must end with .esp or .esm");
+    if !has_plugin_extension(name) {
+        bail!("Plugin name must end with .esp, .esm or .esl");
     }
+    let name_lower = name.to_lowercase();
 
     // Get base name (without extension) for reserved name check
     // Note: name_lower is already lowercase, so we can strip extension directly
@@ -40,9 +51,9 @@ pub fn validate_plugin_name(name: &str, clean_mode: bool) -> Result<()> {
                 "Plugin name cannot be '{reserved}'\n\
                 \n\
                 Reserved names:\n\
-                - previs.esp/esm (working file)\n\
-                - combinedobjects.esp/esm (working file)\n\
-                - xprevispatch.esp/esm (source data file)\n\
+                - previs.esp/esm/esl (working file)\n\
+                - combinedobjects.esp/esm/esl (working file)\n\
+                - xprevispatch.esp/esm/esl (source data file)\n\
                 \n\
                 Please choose a different plugin name for your mod."
             );
@@ -62,11 +73,9 @@ pub fn validate_plugin_name(name: &str, clean_mode: bool) -> Result<()> {
 
 /// Extract the plugin name without extension
 ///
-/// Handles all case variations of .esp and .esm extensions.
+/// Handles all case variations of .esp, .esm and .esl extensions.
 pub fn get_plugin_base_name(name: &str) -> &str {
-    let lower = name.to_lowercase();
-    #[allow(clippy::case_sensitive_file_extension_comparisons)]
-    if lower.ends_with(".esp") || lower.ends_with(".esm") {
+    if has_plugin_extension(name) {
         &name[..name.len() - 4]
     } else {
         name
@@ -74,11 +83,38 @@ pub fn get_plugin_base_name(name: &str) -> &str {
 }
 
 /// Check if a plugin file exists in the Data directory
-pub fn plugin_exists(data_dir: &std::path::Path, plugin_name: &str) -> bool {
+pub fn plugin_exists(data_dir: &Path, plugin_name: &str) -> bool {
     let plugin_path = data_dir.join(plugin_name);
     plugin_path.exists() && plugin_path.is_file()
 }
 
+/// Read a plugin's header and check that all of its masters are installed
+///
+/// `search_dirs` are the directories the game sees as `Data` (see
+/// [`PluginHeader::missing_masters`]). Catching a missing master here avoids a Creation
+/// Kit failure halfway through precombine generation.
+///
+/// Returns the parsed header.
+///
+/// # Errors
+///
+/// Returns an error if the header cannot be read, or if any master is missing
+pub fn check_masters(plugin_path: &Path, search_dirs: &[PathBuf]) -> Result<PluginHeader> {
+    let header = PluginHeader::read(plugin_path)?;
+
+    let missing = header.missing_masters(search_dirs);
+    if !missing.is_empty() {
+        bail!(
+            "{} requires masters that are not installed:\n  {}\n\
+            Install and enable them (in MO2 mode, in the active profile) and try again.",
+            plugin_path.display(),
+            missing.join("\n  ")
+        );
+    }
+
+    Ok(header)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -88,13 +124,15 @@ mod tests {
         assert!(validate_plugin_name("MyMod.esp", true).is_ok());
         assert!(validate_plugin_name("MyMod.esm", true).is_ok());
         assert!(validate_plugin_name("My_Mod_123.esp", true).is_ok());
+        assert!(validate_plugin_name("MyPatch.esl", true).is_ok());
+        assert!(validate_plugin_name("MyPatch.ESL", true).is_ok());
     }
 
     #[test]
     fn test_invalid_extensions() {
         assert!(validate_plugin_name("MyMod.txt", true).is_err());
         assert!(validate_plugin_name("MyMod", true).is_err());
-        assert!(validate_plugin_name("MyMod.esl", true).is_err());
+        assert!(validate_plugin_name("MyMod.esl.txt", true).is_err());
     }
 
     #[test]
@@ -133,6 +171,31 @@ mod tests {
         assert_eq!(get_plugin_base_name("MyMod.esp"), "MyMod");
         assert_eq!(get_plugin_base_name("MyMod.esm"), "MyMod");
         assert_eq!(get_plugin_base_name("MyMod.ESP"), "MyMod");
+        assert_eq!(get_plugin_base_name("MyPatch.esl"), "MyPatch");
         assert_eq!(get_plugin_base_name("My_Mod_123.esp"), "My_Mod_123");
     }
+
+    #[test]
+    fn test_check_masters() {
+        let temp = tempfile::TempDir::new().unwrap();
+        let data = temp.path().to_path_buf();
+        std::fs::write(data.join("Fallout4.esm"), b"").unwrap();
+        let plugin = data.join("MyMod.esp");
+
+        std::fs::write(
+            &plugin,
+            crate::plugin::test_util::tes4(0, "", &["Fallout4.esm"]),
+        )
+        .unwrap();
+        let header = check_masters(&plugin, std::slice::from_ref(&data)).unwrap();
+        assert_eq!(header.masters, ["Fallout4.esm"]);
+
+        std::fs::write(
+            &plugin,
+            crate::plugin::test_util::tes4(0, "", &["Fallout4.esm", "DLCCoast.esm"]),
+        )
+        .unwrap();
+        let error = check_masters(&plugin, &[data]).unwrap_err().to_string();
+        assert!(error.contains("DLCCoast.esm"));
+    }
 }
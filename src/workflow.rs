@@ -4,9 +4,23 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::time::Instant;
 
-use crate::config::{BuildMode, Config};
+use crate::archive_budget::{self, SizeBudget};
+use crate::config::{BuildMode, Config, MergeTool};
 use crate::filesystem;
+use crate::hooks::{self, HookTiming};
+use crate::journal::{JournalConfig, RunJournal, StepStatus};
+use crate::loose_files;
+use crate::mo2_helper;
+use crate::plugin::load_order::LoadOrderFormIds;
+use crate::plugin::{PluginHeader, light, merge, scanner};
+use crate::precombine_check;
+use crate::previs_check;
 use crate::prompts;
+use crate::snapshots::{self, Snapshot, SnapshotStore};
+use crate::tools::archive_backend::{CompressionProfile, Packer};
+use crate::tools::archive_manifest::{ArchiveManifest, ManifestContext};
+use crate::tools::creation_kit::CkOperation;
+use crate::tools::fo4edit::MergeScript;
 use crate::tools::{ArchiveManager, CreationKitRunner, FO4EditRunner};
 use crate::validation;
 
@@ -33,12 +47,12 @@ impl WorkflowStep {
     pub fn name(self) -> &'static str {
         match self {
             Self::GeneratePrecombined => "Generate Precombines Via CK",
-            Self::MergeCombinedObjects => "Merge PrecombineObjects.esp Via xEdit",
+            Self::MergeCombinedObjects => "Merge PrecombineObjects.esp",
             Self::CreatePrecombinedArchive => "Create BA2 Archive from Precombines",
             Self::CompressPSG => "Compress PSG Via CK",
             Self::BuildCDX => "Build CDX Via CK",
             Self::GeneratePrevis => "Generate Previs Via CK",
-            Self::MergePrevis => "Merge Previs.esp Via xEdit",
+            Self::MergePrevis => "Merge Previs.esp",
             Self::AddPrevisToArchive => "Add Previs files to BA2 Archive",
         }
     }
@@ -48,6 +62,25 @@ impl WorkflowStep {
         matches!(self, Self::CompressPSG | Self::BuildCDX)
     }
 
+    /// Check if step packs files into `<Plugin> - Main.ba2` (skipped in loose-files mode)
+    pub fn is_archive_step(self) -> bool {
+        matches!(
+            self,
+            Self::CreatePrecombinedArchive | Self::AddPrevisToArchive
+        )
+    }
+
+    /// Why the step is skipped with this configuration, or `None` if it runs
+    pub fn skip_reason(self, config: &Config) -> Option<&'static str> {
+        if self.is_clean_mode_only() && config.build_mode != BuildMode::Clean {
+            Some("clean mode only")
+        } else if self.is_archive_step() && config.loose_files {
+            Some("loose-files mode")
+        } else {
+            None
+        }
+    }
+
     /// Convert from step number (1-8)
     pub fn from_number(n: u8) -> Option<Self> {
         match n {
@@ -69,6 +102,114 @@ impl WorkflowStep {
     }
 }
 
+/// Inclusive range of workflow steps to run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepRange {
+    pub start: WorkflowStep,
+    pub end: WorkflowStep,
+}
+
+impl StepRange {
+    /// From `start` through step 8
+    pub fn from_step(start: WorkflowStep) -> Self {
+        Self {
+            start,
+            end: WorkflowStep::AddPrevisToArchive,
+        }
+    }
+
+    /// A range chosen on the command line (`--from`, `--to`, `--only`)
+    ///
+    /// A step named explicitly must be one that runs with this configuration: clean-mode-only
+    /// steps are rejected in filtered and Xbox mode, and the archive steps with
+    /// `--loose-files`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `start` comes after `end`, if a named step does not run with
+    /// `config`, or if no step in the range would run
+    pub fn new(
+        start: WorkflowStep,
+        end: WorkflowStep,
+        named: &[WorkflowStep],
+        config: &Config,
+    ) -> Result<Self> {
+        if start > end {
+            bail!(
+                "Step {} comes after step {}; the range must run forward",
+                start.number(),
+                end.number()
+            );
+        }
+
+        let range = Self { start, end };
+        for &step in named {
+            if let Some(reason) = step.skip_reason(config) {
+                bail!(
+                    "Step {} - {} does not run in {reason}",
+                    step.number(),
+                    step.name()
+                );
+            }
+        }
+        if range.steps().all(|step| step.skip_reason(config).is_some()) {
+            bail!(
+                "None of steps {} to {} run with this configuration",
+                start.number(),
+                end.number()
+            );
+        }
+        Ok(range)
+    }
+
+    /// Steps in the range, in order
+    pub fn steps(self) -> impl Iterator<Item = WorkflowStep> {
+        (self.start.number()..=self.end.number()).filter_map(WorkflowStep::from_number)
+    }
+
+    /// Whether the range ends with the last step
+    pub fn is_to_end(self) -> bool {
+        self.end == WorkflowStep::AddPrevisToArchive
+    }
+}
+
+/// Details collected while the workflow runs, reported by the final summary
+#[derive(Debug, Default)]
+pub struct RunSummary {
+    /// Entries kept from a pre-existing `<Plugin> - Main.ba2` in step 3
+    pub preserved_assets: Vec<String>,
+
+    /// Compression used for the precombines in step 3
+    pub precombine_compression: Option<CompressionProfile>,
+
+    /// Compression used for the previs data in step 8
+    pub previs_compression: Option<CompressionProfile>,
+
+    /// Writer that packed the precombines in step 3
+    pub precombine_packer: Option<Packer>,
+
+    /// Writer that appended the previs data in step 8
+    pub previs_packer: Option<Packer>,
+
+    /// Size of `<Plugin> - Main.ba2` after the last archive step, in bytes
+    pub archive_size: Option<u64>,
+
+    /// Folder the build was copied to in loose-files mode
+    pub loose_output: Option<PathBuf>,
+}
+
+/// What a step would do, from [`WorkflowExecutor::plan_steps`]
+#[derive(Debug)]
+pub struct StepPlan {
+    pub step: WorkflowStep,
+
+    /// Why the step would be skipped, if it would
+    pub skipped: Option<&'static str>,
+
+    /// Actions in the order the step would take them
+    pub actions: Vec<String>,
+}
+
 /// Workflow executor for the 8-step previs generation process
 pub struct WorkflowExecutor<'a> {
     config: &'a Config,
@@ -76,12 +217,17 @@ pub struct WorkflowExecutor<'a> {
     data_dir: PathBuf,
     start_time: Instant,
     interactive: bool,
+    summary: RunSummary,
+    journal: RunJournal,
+    journal_path: PathBuf,
 }
 
 impl<'a> WorkflowExecutor<'a> {
     /// Create a new workflow executor
     pub fn new(config: &'a Config, plugin_name: String, interactive: bool) -> Self {
         let data_dir = config.data_dir();
+        let journal = RunJournal::new(&plugin_name, JournalConfig::of(config));
+        let journal_path = RunJournal::path_for(&config.fo4_dir, &plugin_name);
 
         Self {
             config,
@@ -89,19 +235,39 @@ impl<'a> WorkflowExecutor<'a> {
             data_dir,
             start_time: Instant::now(),
             interactive,
+            summary: RunSummary::default(),
+            journal,
+            journal_path,
         }
     }
 
     /// Run the complete workflow from step 1 to 8
-    pub fn run_all(&self) -> Result<()> {
+    pub fn run_all(&mut self) -> Result<()> {
         self.run_from_step(WorkflowStep::GeneratePrecombined)
     }
 
     /// Run the workflow starting from a specific step
-    pub fn run_from_step(&self, start_step: WorkflowStep) -> Result<()> {
-        // Automatically copy xPrevisPatch to target plugin if needed (step 1 only)
+    pub fn run_from_step(&mut self, start_step: WorkflowStep) -> Result<()> {
+        self.run_steps(StepRange::from_step(start_step))
+    }
+
+    /// Run the steps in a range
+    ///
+    /// The loose-files export only runs when the range ends with step 8.
+    pub fn run_steps(&mut self, range: StepRange) -> Result<()> {
+        let start_step = range.start;
+        self.run_hooks(HookTiming::Before, None)?;
+
+        // Automatically copy the seed plugin to the target plugin if needed (step 1 only)
+        if start_step == WorkflowStep::GeneratePrecombined {
+            self.copy_seed_if_needed()?;
+        }
+
+        // Catch missing masters before the Creation Kit fails on them
+        self.validate_masters()?;
+
         if start_step == WorkflowStep::GeneratePrecombined {
-            self.copy_xprevis_if_needed()?;
+            self.report_cells();
         }
 
         if start_step == WorkflowStep::GeneratePrecombined {
@@ -123,36 +289,198 @@ impl<'a> WorkflowExecutor<'a> {
             );
         }
 
-        let mut current_step = Some(start_step);
+        if !range.is_to_end() {
+            info!(
+                "Stopping after: Step {} - {}",
+                range.end.number(),
+                range.end.name()
+            );
+        }
+
+        self.open_journal(start_step);
 
-        while let Some(step) = current_step {
-            // Skip clean-mode-only steps if not in clean mode
-            if step.is_clean_mode_only() && self.config.build_mode != BuildMode::Clean {
+        for step in range.steps() {
+            // Skip clean-mode-only steps if not in clean mode, and keep precombines and
+            // previs data loose in loose-files mode
+            if let Some(reason) = step.skip_reason(self.config) {
                 info!(
-                    "Skipping Step {} - {} (clean mode only)",
+                    "Skipping Step {} - {} ({reason})",
                     step.number(),
                     step.name()
                 );
-                current_step = step.next();
+                self.record(|journal| journal.step_skipped(step, reason));
                 continue;
             }
 
             info!("");
             info!("=== Step {} - {} ===", step.number(), step.name());
 
-            // Execute the step
-            self.execute_step(step)?;
+            // Execute the step, after its before hooks
+            self.record(|journal| journal.step_started(step));
+            let result = self
+                .run_hooks(HookTiming::Before, Some(step))
+                .and_then(|()| self.execute_step(step));
+            if let Err(e) = result {
+                let error = format!("{e:#}");
+                self.record(|journal| journal.step_finished(step, StepStatus::Failed, Some(error)));
+                return Err(e);
+            }
+            self.record(|journal| journal.step_finished(step, StepStatus::Completed, None));
 
             info!("Step {} completed successfully", step.number());
-            current_step = step.next();
+            self.run_after_hooks(Some(step));
+        }
+
+        if self.config.loose_files && range.is_to_end() {
+            self.export_loose_files()?;
         }
 
+        self.run_after_hooks(None);
+
         self.print_summary();
         Ok(())
     }
 
+    /// Run the hooks for a step (or, with `None`, for the whole run)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a hook fails (see [`Hooks::run`](crate::hooks::Hooks::run))
+    fn run_hooks(&self, timing: HookTiming, step: Option<WorkflowStep>) -> Result<()> {
+        let env = hooks::environment(self.config, &self.plugin_name, timing, step);
+        self.config.hooks.run(timing, step, &env)
+    }
+
+    /// Run the after hooks for a step (or the whole run), which has already succeeded;
+    /// failures are only logged
+    fn run_after_hooks(&self, step: Option<WorkflowStep>) {
+        if let Err(e) = self.run_hooks(HookTiming::After, step) {
+            warn!("{e:#}");
+        }
+    }
+
+    /// Step that failed in this run, if the last step it started failed
+    pub fn failed_step(&self) -> Option<WorkflowStep> {
+        self.journal
+            .steps
+            .last()
+            .filter(|entry| entry.status == StepStatus::Failed)
+            .and_then(|entry| WorkflowStep::from_number(entry.step))
+    }
+
+    /// Print what running the steps in a range would do, without doing any of it
+    ///
+    /// Used by `--dry-run`: every tool path is resolved and every command line is built
+    /// exactly as [`run_steps`](Self::run_steps) would, but nothing is run, written,
+    /// renamed or deleted and the run journal is left alone.
+    pub fn print_plan(&self, range: StepRange) {
+        println!("======================================");
+        println!("  Dry Run: {}", self.plugin_name);
+        println!("======================================");
+        println!("Nothing below is run, written, renamed or deleted.");
+
+        let hooks = &self.config.hooks;
+        let before_run = hooks.plan(HookTiming::Before, None);
+        if !before_run.is_empty() {
+            println!();
+            println!("Before the run");
+            for action in &before_run {
+                println!("  - {action}");
+            }
+        }
+
+        for plan in self.plan_steps(range) {
+            println!();
+            let step = plan.step;
+            if let Some(reason) = plan.skipped {
+                println!(
+                    "Step {} - {}: skipped ({reason})",
+                    step.number(),
+                    step.name()
+                );
+                continue;
+            }
+            println!("Step {} - {}", step.number(), step.name());
+            for action in &plan.actions {
+                println!("  - {action}");
+            }
+        }
+
+        if self.config.loose_files && range.is_to_end() {
+            let output_dir = self.config.loose_output_dir.clone().unwrap_or_else(|| {
+                loose_files::default_output_dir(&self.config.fo4_dir, &self.plugin_name)
+            });
+            println!();
+            println!("Loose files");
+            println!("  - Copy the loose build to {}", output_dir.display());
+        }
+
+        let after_run = hooks.plan(HookTiming::After, None);
+        if !after_run.is_empty() {
+            println!();
+            println!("After the run");
+            for action in &after_run {
+                println!("  - {action}");
+            }
+        }
+
+        println!();
+        println!("Run journal (not written): {}", self.journal_path.display());
+    }
+
+    /// What each step in a range would do, based on the current state of the game folder
+    ///
+    /// A directory wiped by an earlier step of the range is not listed again. The step's
+    /// hooks are listed before and after its own actions.
+    pub fn plan_steps(&self, range: StepRange) -> Vec<StepPlan> {
+        let hooks = &self.config.hooks;
+        let mut wiped = Vec::new();
+        range
+            .steps()
+            .map(|step| {
+                let skipped = step.skip_reason(self.config);
+                let actions = if skipped.is_some() {
+                    Vec::new()
+                } else {
+                    let mut actions = hooks.plan(HookTiming::Before, Some(step));
+                    actions.extend(self.plan_step(step, &mut wiped));
+                    actions.extend(hooks.plan(HookTiming::After, Some(step)));
+                    actions
+                };
+                StepPlan {
+                    step,
+                    skipped,
+                    actions,
+                }
+            })
+            .collect()
+    }
+
+    /// Start the run journal: a new one from step 1, otherwise the existing one (if any)
+    fn open_journal(&mut self, start_step: WorkflowStep) {
+        if start_step != WorkflowStep::GeneratePrecombined {
+            match RunJournal::load(&self.journal_path) {
+                Ok(Some(journal)) => {
+                    self.journal = journal;
+                    self.journal.config = JournalConfig::of(self.config);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Starting a new run journal: {e:#}"),
+            }
+        }
+        info!("Run journal: {}", self.journal_path.display());
+    }
+
+    /// Update the run journal and write it; failures are only logged
+    fn record(&mut self, update: impl FnOnce(&mut RunJournal)) {
+        update(&mut self.journal);
+        if let Err(e) = self.journal.write(&self.journal_path) {
+            warn!("Could not update the run journal: {e:#}");
+        }
+    }
+
     /// Execute a specific workflow step
-    fn execute_step(&self, step: WorkflowStep) -> Result<()> {
+    fn execute_step(&mut self, step: WorkflowStep) -> Result<()> {
         match step {
             WorkflowStep::GeneratePrecombined => self.step1_generate_precombined(),
             WorkflowStep::MergeCombinedObjects => self.step2_merge_combined_objects(),
@@ -165,6 +493,199 @@ impl<'a> WorkflowExecutor<'a> {
         }
     }
 
+    /// Actions a step would take; the planning counterpart of [`execute_step`](Self::execute_step)
+    fn plan_step(&self, step: WorkflowStep, wiped: &mut Vec<PathBuf>) -> Vec<String> {
+        let precombined_dir = self.data_dir.join("meshes").join("precombined");
+        let vis_dir = self.data_dir.join("vis");
+        let plugin_base = validation::get_plugin_base_name(&self.plugin_name);
+        let psg_file = self.data_dir.join(format!("{plugin_base} - Geometry.psg"));
+        let archive_name = format!("{plugin_base} - Main.ba2");
+        let profile = self.config.compression_profile();
+        let mo2_data_dir = self.config.mo2_data_dir.as_deref();
+
+        let mut actions = Vec::new();
+        match step {
+            WorkflowStep::GeneratePrecombined => {
+                match self.plan_seed() {
+                    Ok(Some(copy)) => {
+                        actions.push(copy);
+                        let plugin_path = self.data_dir.join(&self.plugin_name);
+                        actions.push(self.describe_snapshot(&plugin_path, step));
+                    }
+                    Ok(None) => actions.push(self.plan_snapshot(step)),
+                    Err(reason) => actions.push(reason),
+                }
+                actions.extend(self.plan_clean(&precombined_dir, wiped));
+                actions.extend(self.plan_clean(&vis_dir, wiped));
+                actions
+                    .extend(self.plan_ck(CkOperation::GeneratePrecombined(self.config.build_mode)));
+            }
+            WorkflowStep::MergeCombinedObjects => {
+                actions.extend(self.plan_merge(step, MergeScript::CombinedObjects));
+            }
+            WorkflowStep::CreatePrecombinedArchive => match self.archive_manager() {
+                Ok(manager) => {
+                    actions.extend(manager.plan_precombines(&archive_name, profile, mo2_data_dir));
+                    actions.push(self.plan_manifest(&archive_name));
+                }
+                Err(e) => actions.push(format!("Fails: {e:#}")),
+            },
+            WorkflowStep::CompressPSG => {
+                actions.extend(self.plan_ck(CkOperation::CompressPsg));
+                actions.push(format!("Delete {}", psg_file.display()));
+            }
+            WorkflowStep::BuildCDX => actions.extend(self.plan_ck(CkOperation::BuildCdx)),
+            WorkflowStep::GeneratePrevis => {
+                actions.extend(self.plan_clean(&vis_dir, wiped));
+                actions.extend(self.plan_ck(CkOperation::GeneratePrevis));
+            }
+            WorkflowStep::MergePrevis => {
+                actions.extend(self.plan_merge(step, MergeScript::Previs));
+            }
+            WorkflowStep::AddPrevisToArchive => match self.archive_manager() {
+                Ok(manager) => {
+                    actions.extend(manager.plan_previs(&archive_name, profile, mo2_data_dir));
+                    actions.push(self.plan_manifest(&archive_name));
+                }
+                Err(e) => actions.push(format!("Fails: {e:#}")),
+            },
+        }
+        actions
+    }
+
+    /// Plan a `CreationKit` operation
+    fn plan_ck(&self, operation: CkOperation) -> Vec<String> {
+        match self.ck_runner() {
+            Ok(runner) => runner.plan(operation, &self.plugin_name),
+            Err(e) => vec![format!("Fails: {e:#}")],
+        }
+    }
+
+    /// Plan a merge step: snapshot, merge and rollback
+    fn plan_merge(&self, step: WorkflowStep, script: MergeScript) -> Vec<String> {
+        let mut actions = vec![self.plan_snapshot(step)];
+        match self.config.merge_tool {
+            MergeTool::Native => {
+                let plugin = self
+                    .locate_plugin(&self.plugin_name)
+                    .unwrap_or_else(|| self.data_dir.join(&self.plugin_name));
+                actions.push(format!(
+                    "Merge {} into {} with the built-in merge",
+                    script.source_plugin(),
+                    plugin.display()
+                ));
+            }
+            MergeTool::FO4Edit => {
+                actions.extend(self.fo4edit_runner().plan(script, &self.plugin_name));
+            }
+        }
+        actions.push("Restore the plugin from the snapshot if the merge fails".to_string());
+        actions
+    }
+
+    /// Plan [`snapshot_plugin`](Self::snapshot_plugin)
+    fn plan_snapshot(&self, step: WorkflowStep) -> String {
+        match self.locate_plugin(&self.plugin_name) {
+            Some(plugin_path) => self.describe_snapshot(&plugin_path, step),
+            None => "No snapshot: the plugin does not exist yet".to_string(),
+        }
+    }
+
+    /// The action of snapshotting the plugin at `plugin_path` before `step`
+    fn describe_snapshot(&self, plugin_path: &Path, step: WorkflowStep) -> String {
+        let store = SnapshotStore::new(&self.config.fo4_dir, &self.plugin_name);
+        format!(
+            "Snapshot {} to {} (before-step-{})",
+            plugin_path.display(),
+            store.dir().display(),
+            step.number()
+        )
+    }
+
+    /// Plan [`copy_seed_if_needed`](Self::copy_seed_if_needed)
+    ///
+    /// Returns `Ok(None)` if the plugin exists, the copy if there is a seed to copy, and
+    /// the reason the run would stop otherwise.
+    fn plan_seed(&self) -> std::result::Result<Option<String>, String> {
+        let target = self.data_dir.join(&self.plugin_name);
+        if target.exists() {
+            return Ok(None);
+        }
+
+        let candidates = match self.config.seed_plugin {
+            Some(ref seed) if !self.data_dir.join(seed).is_file() => {
+                return Err(format!(
+                    "Stop: seed plugin '{seed}' not found in {}",
+                    self.data_dir.display()
+                ));
+            }
+            Some(ref seed) => vec![seed.clone()],
+            None => filesystem::find_xprevis_patch_plugins(&self.data_dir)
+                .map_err(|e| format!("Fails: {e:#}"))?,
+        };
+        match candidates.as_slice() {
+            [] => Err(format!(
+                "Stop: {} does not exist and there is no xPrevisPatch plugin or --seed to create it from",
+                target.display()
+            )),
+            [only] => Ok(Some(format!(
+                "Copy seed plugin {} to {}",
+                self.data_dir.join(only).display(),
+                target.display()
+            ))),
+            _ if self.interactive => Ok(Some(format!(
+                "Ask which seed plugin to copy to {}: {}",
+                target.display(),
+                candidates.join(", ")
+            ))),
+            _ => Err(format!(
+                "Stop: several xPrevisPatch plugins were found ({}); pass --seed <PLUGIN>",
+                candidates.join(", ")
+            )),
+        }
+    }
+
+    /// Whether step 1 has a seed plugin to create the missing plugin from
+    ///
+    /// True if `--seed` was given or there is an `xPrevisPatch` plugin in `Data`.
+    /// [`copy_seed_if_needed`](Self::copy_seed_if_needed) reports what is wrong with the
+    /// seed (missing file, several candidates) when step 1 runs.
+    pub fn has_seed(&self) -> bool {
+        self.config.seed_plugin.is_some()
+            || filesystem::find_xprevis_patch_plugins(&self.data_dir)
+                .is_ok_and(|plugins| !plugins.is_empty())
+    }
+
+    /// Plan [`check_and_clean_directory`](Self::check_and_clean_directory): the directory
+    /// it would wipe, if any
+    fn plan_clean(&self, dir: &Path, wiped: &mut Vec<PathBuf>) -> Option<String> {
+        if wiped.iter().any(|done| done == dir)
+            || filesystem::is_directory_empty(dir).unwrap_or(true)
+        {
+            return None;
+        }
+        wiped.push(dir.to_path_buf());
+
+        let files = filesystem::count_all_files(dir);
+        Some(if self.interactive {
+            format!("Wipe {} ({files} files) if confirmed", dir.display())
+        } else {
+            format!(
+                "Stop: {} is not empty ({files} files); interactive runs ask to wipe it",
+                dir.display()
+            )
+        })
+    }
+
+    /// Plan [`write_archive_manifest`](Self::write_archive_manifest)
+    fn plan_manifest(&self, archive_name: &str) -> String {
+        let archive_path = self.data_dir.join(archive_name);
+        format!(
+            "Write {}",
+            ArchiveManifest::path_for(&archive_path).display()
+        )
+    }
+
     /// Check if a directory needs cleaning, prompt user if interactive
     ///
     /// Validates that a directory is empty before proceeding with a workflow step.
@@ -306,10 +827,11 @@ impl<'a> WorkflowExecutor<'a> {
     ///
     /// # Process
     ///
-    /// 1. Cleans working directories if needed
-    /// 2. Runs `CreationKit` with precombine generation flags
-    /// 3. Validates that .nif files were created
-    /// 4. In clean mode, validates that .psg file was created
+    /// 1. Snapshots the plugin as it was before the run (see [`snapshots`])
+    /// 2. Cleans working directories if needed
+    /// 3. Runs `CreationKit` with precombine generation flags
+    /// 4. Validates that .nif files were created
+    /// 5. In clean mode, validates that .psg file was created
     ///
     /// # Post-Checks
     ///
@@ -319,11 +841,15 @@ impl<'a> WorkflowExecutor<'a> {
     /// # Errors
     ///
     /// Returns an error if:
+    /// - The plugin cannot be snapshotted
     /// - User declines to clean non-empty directories (interactive mode)
     /// - Directories are not empty (non-interactive mode)
     /// - `CreationKit` fails to run or crashes
     /// - No precombined meshes were generated
     fn step1_generate_precombined(&self) -> Result<()> {
+        // Keep the plugin as it was before this run
+        self.snapshot_plugin(WorkflowStep::GeneratePrecombined)?;
+
         // Pre-check: meshes\precombined and vis must be empty
         let precombined_dir = self.data_dir.join("meshes").join("precombined");
         let vis_dir = self.data_dir.join("vis");
@@ -332,19 +858,7 @@ impl<'a> WorkflowExecutor<'a> {
         self.check_and_clean_directory(&vis_dir, "vis")?;
 
         // Run CreationKit
-        let ck_log = self
-            .config
-            .ck_log_path
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("CK log path not configured"))?;
-
-        let mut ck_runner =
-            CreationKitRunner::new(&self.config.creation_kit_path, &self.config.fo4_dir)
-                .with_log_file(ck_log);
-
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            ck_runner = ck_runner.with_mo2(mo2_path);
-        }
+        let ck_runner = self.ck_runner()?;
 
         ck_runner.generate_precombined(&self.plugin_name, self.config.build_mode)?;
 
@@ -366,20 +880,35 @@ impl<'a> WorkflowExecutor<'a> {
         Ok(())
     }
 
-    /// Step 2: Merge PrecombineObjects.esp Via xEdit
+    /// Step 2: Merge PrecombineObjects.esp
     ///
-    /// Runs `FO4Edit` to merge the temporary PrecombineObjects.esp (created by `CreationKit`)
-    /// into the main plugin. This consolidates precombine data into the plugin itself.
+    /// Merges the temporary `CombinedObjects.esp` (created by `CreationKit`) into the main
+    /// plugin. By default the `FO4Edit` batch script is run; with [`MergeTool::Native`] the
+    /// built-in merge ([`merge::merge_combined_objects`]) edits the plugin directly instead.
     ///
     /// # Pre-Checks
     ///
     /// - Verifies precombined meshes exist from Step 1
     ///
+    /// # Post-Checks
+    ///
+    /// - Every precombined mesh the merged plugin references exists (orphaned meshes are
+    ///   only reported)
+    ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - No precombined meshes found (Step 1 not completed)
-    /// - `FO4Edit` fails to run or merge operation fails
+    /// - The plugin or `CombinedObjects.esp` cannot be found (built-in merge)
+    /// - The merge fails (see [`merge::merge_combined_objects`]), or `FO4Edit` fails to run
+    /// - The plugin is a light plugin and the merge pushed it out of the light form ID
+    ///   range (see [`light::check_range`])
+    /// - The plugin references precombined meshes that do not exist (see
+    ///   [`precombine_check`])
+    /// - The plugin cannot be snapshotted before the merge
+    ///
+    /// If the merge, the light plugin check or the precombine integrity check fails, the
+    /// plugin is restored from the snapshot taken before the merge.
     fn step2_merge_combined_objects(&self) -> Result<()> {
         // Pre-check: Precombined meshes exist
         let precombined_dir = self.data_dir.join("meshes").join("precombined");
@@ -388,17 +917,35 @@ impl<'a> WorkflowExecutor<'a> {
             bail!("No precombined meshes found. Run Step 1 first.");
         }
 
-        // Run FO4Edit
-        let mut fo4edit_runner =
-            FO4EditRunner::new(&self.config.fo4edit_path, &self.config.fo4_dir);
-
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            fo4edit_runner = fo4edit_runner.with_mo2(mo2_path);
+        let light_plugin = self.light_plugin_path()?;
+        if let Some(ref plugin_path) = light_plugin {
+            self.warn_light_merge(plugin_path, "CombinedObjects.esp");
         }
 
-        fo4edit_runner.merge_combined_objects(&self.plugin_name)?;
+        self.with_rollback(WorkflowStep::MergeCombinedObjects, || {
+            match self.config.merge_tool {
+                MergeTool::Native => {
+                    let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+                        bail!("Plugin not found: {}", self.plugin_name);
+                    };
+                    let Some(source_path) = self.locate_plugin("CombinedObjects.esp") else {
+                        bail!("CombinedObjects.esp not found. Run Step 1 first.");
+                    };
+                    merge::merge_combined_objects(&plugin_path, &source_path)?;
+                }
+                MergeTool::FO4Edit => {
+                    self.fo4edit_runner()
+                        .merge_combined_objects(&self.plugin_name)?;
+                }
+            }
 
-        Ok(())
+            if let Some(ref plugin_path) = light_plugin {
+                light::check_range(plugin_path)?;
+            }
+
+            // Post-check: every mesh the plugin now references was written by step 1
+            self.check_precombine_integrity()
+        })
     }
 
     /// Step 3: Create BA2 Archive from Precombines
@@ -408,41 +955,61 @@ impl<'a> WorkflowExecutor<'a> {
     ///
     /// # Process
     ///
-    /// - Uses Archive2 or `BSArch` (depending on configuration)
+    /// - Uses Archive2, `BSArch` or the native writer (depending on configuration)
     /// - Archives all .nif files from `meshes/precombined`
     /// - MO2-aware: Collects files from MO2 staging directory if configured
+    /// - If the archive already exists, replaces its precombine/previs files and keeps
+    ///   everything else (recorded in the run summary)
     ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - Archive tool fails to create the BA2 file
     /// - No precombined meshes found to archive
-    fn step3_create_precombined_archive(&self) -> Result<()> {
+    /// - The archive exceeds the size limit for the build mode (see [`SizeBudget`]); this is
+    ///   checked before the loose meshes are deleted, and any previous archive is restored
+    fn step3_create_precombined_archive(&mut self) -> Result<()> {
         let plugin_base = validation::get_plugin_base_name(&self.plugin_name);
         let archive_name = format!("{plugin_base} - Main.ba2");
 
-        let (archive2_path, bsarch_path) = match self.config.archive_tool {
-            crate::config::ArchiveTool::Archive2 => {
-                (Some(self.config.archive_exe_path.clone()), None)
-            }
-            crate::config::ArchiveTool::BSArch => {
-                (None, Some(self.config.archive_exe_path.clone()))
-            }
-        };
-
-        let archive_manager = ArchiveManager::new(
-            self.config.archive_tool,
-            archive2_path,
-            bsarch_path,
-            &self.config.fo4_dir,
-        )?;
+        let archive_manager = self.archive_manager()?;
 
-        let is_xbox = self.config.build_mode == BuildMode::Xbox;
+        let profile = self.config.compression_profile();
         let mo2_data_dir = self.config.mo2_data_dir.as_deref();
 
-        archive_manager.create_archive_from_precombines(&archive_name, is_xbox, mo2_data_dir)?;
+        let budget = SizeBudget::for_build_mode(self.config.build_mode);
+        let archive_path = self.data_dir.join(&archive_name);
+        archive_budget::check_projected(
+            budget,
+            &archive_path,
+            &self.archive_source_dir(&["meshes", "precombined"]),
+        );
+
+        let created = archive_manager.create_archive_from_precombines(
+            &archive_name,
+            profile,
+            mo2_data_dir,
+        )?;
+        let preserved = created.preserved;
+        self.write_archive_manifest(
+            &archive_path,
+            WorkflowStep::CreatePrecombinedArchive,
+            profile,
+            &created.packer,
+            &preserved,
+        );
+        self.summary.precombine_compression = Some(profile);
+        self.summary.precombine_packer = Some(created.packer);
+        self.summary.archive_size = fs::metadata(&archive_path).ok().map(|m| m.len());
 
         info!("Created archive: {archive_name}");
+        if !preserved.is_empty() {
+            info!(
+                "Preserved {} existing assets from the previous {archive_name}",
+                preserved.len()
+            );
+        }
+        self.summary.preserved_assets = preserved;
         Ok(())
     }
 
@@ -476,19 +1043,7 @@ impl<'a> WorkflowExecutor<'a> {
         }
 
         // Run CreationKit
-        let ck_log = self
-            .config
-            .ck_log_path
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("CK log path not configured"))?;
-
-        let mut ck_runner =
-            CreationKitRunner::new(&self.config.creation_kit_path, &self.config.fo4_dir)
-                .with_log_file(ck_log);
-
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            ck_runner = ck_runner.with_mo2(mo2_path);
-        }
+        let ck_runner = self.ck_runner()?;
 
         ck_runner.compress_psg(&self.plugin_name)?;
 
@@ -509,19 +1064,7 @@ impl<'a> WorkflowExecutor<'a> {
     ///
     /// Returns an error if `CreationKit` fails to build the CDX files
     fn step5_build_cdx(&self) -> Result<()> {
-        let ck_log = self
-            .config
-            .ck_log_path
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("CK log path not configured"))?;
-
-        let mut ck_runner =
-            CreationKitRunner::new(&self.config.creation_kit_path, &self.config.fo4_dir)
-                .with_log_file(ck_log);
-
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            ck_runner = ck_runner.with_mo2(mo2_path);
-        }
+        let ck_runner = self.ck_runner()?;
 
         ck_runner.build_cdx(&self.plugin_name)?;
 
@@ -547,6 +1090,8 @@ impl<'a> WorkflowExecutor<'a> {
     /// # Post-Checks
     ///
     /// - Verifies previs data exists in `vis` directory
+    /// - Logs exterior cells without a previs file and previs files for cells the plugin
+    ///   does not modify (also when `CreationKit` reports failed visibility tasks)
     ///
     /// # Errors
     ///
@@ -562,46 +1107,55 @@ impl<'a> WorkflowExecutor<'a> {
         self.check_and_clean_directory(&vis_dir, "vis")?;
 
         // Run CreationKit
-        let ck_log = self
-            .config
-            .ck_log_path
-            .as_ref()
-            .ok_or_else(|| anyhow::anyhow!("CK log path not configured"))?;
-
-        let mut ck_runner =
-            CreationKitRunner::new(&self.config.creation_kit_path, &self.config.fo4_dir)
-                .with_log_file(ck_log);
+        let ck_runner = self.ck_runner()?;
 
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            ck_runner = ck_runner.with_mo2(mo2_path);
+        if let Err(e) = ck_runner.generate_previs(&self.plugin_name) {
+            // The log does not say which cells failed; the coverage report does
+            if vis_dir.is_dir() {
+                self.check_previs_coverage(true);
+            }
+            return Err(e);
         }
 
-        ck_runner.generate_previs(&self.plugin_name)?;
-
         // Post-check: .uvd files created
         if !vis_dir.exists() || filesystem::is_directory_empty(&vis_dir)? {
             bail!("No previs data was generated");
         }
+        self.check_previs_coverage(true);
 
         Ok(())
     }
 
-    /// Step 7: Merge Previs.esp Via xEdit
+    /// Step 7: Merge Previs.esp
     ///
-    /// Runs `FO4Edit` to merge the temporary Previs.esp (created by `CreationKit`)
-    /// into the main plugin. This consolidates previs data into the plugin itself.
+    /// Merges the temporary Previs.esp (created by `CreationKit`) into the main plugin. By
+    /// default the `FO4Edit` batch script is run; with [`MergeTool::Native`] the built-in
+    /// merge ([`merge::merge_previs`]) edits the plugin directly and leaves out the redundant
+    /// reference overrides `CreationKit` writes instead.
     ///
     /// # Pre-Checks
     ///
     /// - Verifies previs data (.uvd files) exist from Step 6
     /// - Verifies Previs.esp was created by `CreationKit`
     ///
+    /// # Post-Checks
+    ///
+    /// - Logs exterior cells of the merged plugin without a previs file, and previs files
+    ///   for cells it does not modify
+    ///
     /// # Errors
     ///
     /// Returns an error if:
     /// - No previs data found (Step 6 not completed)
     /// - Previs.esp not found (`CreationKit` failed to create it)
-    /// - `FO4Edit` fails to run or merge operation fails
+    /// - The plugin cannot be found (built-in merge)
+    /// - The merge fails (see [`merge::merge_previs`]), or `FO4Edit` fails to run
+    /// - The plugin is a light plugin and the merge pushed it out of the light form ID
+    ///   range (see [`light::check_range`])
+    /// - The plugin cannot be snapshotted before the merge
+    ///
+    /// If the merge or the light plugin check fails, the plugin is restored from the
+    /// snapshot taken before the merge.
     fn step7_merge_previs(&self) -> Result<()> {
         // Pre-check: .uvd files exist
         let vis_dir = self.data_dir.join("vis");
@@ -616,15 +1170,30 @@ impl<'a> WorkflowExecutor<'a> {
             bail!("Previs.esp not found. CreationKit should have created it.");
         }
 
-        // Run FO4Edit
-        let mut fo4edit_runner =
-            FO4EditRunner::new(&self.config.fo4edit_path, &self.config.fo4_dir);
-
-        if let Some(ref mo2_path) = self.config.mo2_path {
-            fo4edit_runner = fo4edit_runner.with_mo2(mo2_path);
+        let light_plugin = self.light_plugin_path()?;
+        if let Some(ref plugin_path) = light_plugin {
+            self.warn_light_merge(plugin_path, "Previs.esp");
         }
 
-        fo4edit_runner.merge_previs(&self.plugin_name)?;
+        self.with_rollback(WorkflowStep::MergePrevis, || {
+            match self.config.merge_tool {
+                MergeTool::Native => {
+                    let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+                        bail!("Plugin not found: {}", self.plugin_name);
+                    };
+                    merge::merge_previs(&plugin_path, &previs_esp, &self.game_data_dirs())?;
+                }
+                MergeTool::FO4Edit => {
+                    self.fo4edit_runner().merge_previs(&self.plugin_name)?;
+                }
+            }
+
+            if let Some(ref plugin_path) = light_plugin {
+                light::check_range(plugin_path)?;
+            }
+            Ok(())
+        })?;
+        self.check_previs_coverage(false);
 
         Ok(())
     }
@@ -636,8 +1205,8 @@ impl<'a> WorkflowExecutor<'a> {
     ///
     /// # Process
     ///
-    /// - Uses Archive2 or `BSArch` (depending on configuration)
-    /// - For Archive2: Extract → Add files → Re-archive (no append support)
+    /// - Uses Archive2, `BSArch` or the native writer (depending on configuration)
+    /// - For Archive2 and native: Appends files in place (backup → append → remove backup)
     /// - For `BSArch`: Appends files directly to existing archive
     /// - MO2-aware: Collects files from MO2 staging directory if configured
     ///
@@ -646,11 +1215,67 @@ impl<'a> WorkflowExecutor<'a> {
     /// Returns an error if:
     /// - Archive tool fails to add files to the BA2
     /// - No previs data found to add
-    /// - For Archive2: Extraction or re-archiving fails
-    fn step8_add_previs_to_archive(&self) -> Result<()> {
+    /// - For Archive2 and native: The in-place append fails (the archive is restored)
+    /// - The archive exceeds the size limit for the build mode (see [`SizeBudget`]); this is
+    ///   checked before the previs files are deleted, and the archive is restored
+    fn step8_add_previs_to_archive(&mut self) -> Result<()> {
         let plugin_base = validation::get_plugin_base_name(&self.plugin_name);
         let archive_name = format!("{plugin_base} - Main.ba2");
 
+        let archive_manager = self.archive_manager()?;
+
+        let profile = self.config.compression_profile();
+        let mo2_data_dir = self.config.mo2_data_dir.as_deref();
+
+        let budget = SizeBudget::for_build_mode(self.config.build_mode);
+        let archive_path = self.data_dir.join(&archive_name);
+        archive_budget::check_projected(budget, &archive_path, &self.archive_source_dir(&["vis"]));
+
+        let packer = archive_manager.add_previs_to_archive(&archive_name, profile, mo2_data_dir)?;
+        self.write_archive_manifest(
+            &archive_path,
+            WorkflowStep::AddPrevisToArchive,
+            profile,
+            &packer,
+            &[],
+        );
+        self.summary.previs_compression = Some(profile);
+        self.summary.previs_packer = Some(packer);
+        self.summary.archive_size = fs::metadata(&archive_path).ok().map(|m| m.len());
+
+        info!("Added previs data to archive: {archive_name}");
+        Ok(())
+    }
+
+    /// `CreationKit` runner with the configured log file and MO2 instance
+    fn ck_runner(&self) -> Result<CreationKitRunner> {
+        let ck_log = self
+            .config
+            .ck_log_path
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("CK log path not configured"))?;
+
+        let mut ck_runner =
+            CreationKitRunner::new(&self.config.creation_kit_path, &self.config.fo4_dir)
+                .with_log_file(ck_log);
+
+        if let Some(ref mo2_path) = self.config.mo2_path {
+            ck_runner = ck_runner.with_mo2(mo2_path);
+        }
+        Ok(ck_runner)
+    }
+
+    /// `FO4Edit` runner with the configured MO2 instance
+    fn fo4edit_runner(&self) -> FO4EditRunner {
+        let fo4edit_runner = FO4EditRunner::new(&self.config.fo4edit_path, &self.config.fo4_dir);
+        match self.config.mo2_path {
+            Some(ref mo2_path) => fo4edit_runner.with_mo2(mo2_path),
+            None => fo4edit_runner,
+        }
+    }
+
+    /// Archive manager for the configured archive tool, checking the build mode's size budget
+    fn archive_manager(&self) -> Result<ArchiveManager> {
         let (archive2_path, bsarch_path) = match self.config.archive_tool {
             crate::config::ArchiveTool::Archive2 => {
                 (Some(self.config.archive_exe_path.clone()), None)
@@ -658,62 +1283,376 @@ impl<'a> WorkflowExecutor<'a> {
             crate::config::ArchiveTool::BSArch => {
                 (None, Some(self.config.archive_exe_path.clone()))
             }
+            crate::config::ArchiveTool::Native => (None, None),
         };
 
-        let archive_manager = ArchiveManager::new(
+        Ok(ArchiveManager::new(
             self.config.archive_tool,
             archive2_path,
             bsarch_path,
             &self.config.fo4_dir,
+        )?
+        .with_size_budget(SizeBudget::for_build_mode(self.config.build_mode)))
+    }
+
+    /// Write `<Plugin> - Main.ba2.manifest.json` after an archive step
+    ///
+    /// Entries are attributed to `step` unless the previous manifest already lists them
+    /// unchanged, or they are in `preserved` (see [`ArchiveManifest::build`]). The archive
+    /// itself is already complete at this point, so a manifest failure is logged rather
+    /// than failing the run. The tool recorded is `packer`, the writer that actually packed
+    /// the step's files, not necessarily the configured archive tool.
+    fn write_archive_manifest(
+        &self,
+        archive_path: &Path,
+        step: WorkflowStep,
+        profile: CompressionProfile,
+        packer: &Packer,
+        preserved: &[String],
+    ) {
+        let step_label = format!("step {}", step.number());
+        let context = ManifestContext {
+            plugin: &self.plugin_name,
+            build_mode: self.config.build_mode.as_str(),
+            archive_tool: packer.tool,
+            archive_tool_version: packer.version.as_deref(),
+            compression: profile.as_str(),
+            step: &step_label,
+        };
+
+        let previous = ArchiveManifest::load(archive_path).unwrap_or_else(|e| {
+            warn!("Ignoring unreadable archive manifest: {e:#}");
+            None
+        });
+        let result = ArchiveManifest::build(archive_path, &context, previous.as_ref(), preserved)
+            .and_then(|manifest| manifest.write(archive_path));
+
+        match result {
+            Ok(path) => info!("Wrote archive manifest: {}", path.display()),
+            Err(e) => warn!("Failed to write archive manifest: {e:#}"),
+        }
+    }
+
+    /// Directory the archive steps read generated files from
+    ///
+    /// In MO2 mode CK output lands in the staging directory rather than `Data`.
+    fn archive_source_dir(&self, subpath: &[&str]) -> PathBuf {
+        let base = self
+            .config
+            .mo2_data_dir
+            .as_deref()
+            .unwrap_or(&self.data_dir);
+        subpath
+            .iter()
+            .fold(base.to_path_buf(), |dir, part| dir.join(part))
+    }
+
+    /// Copy the loose build to the loose-files output folder
+    ///
+    /// Generated files are looked up in the MO2 staging directory first (if configured),
+    /// then in `Data`.
+    fn export_loose_files(&mut self) -> Result<()> {
+        info!("");
+        info!("=== Exporting loose files ===");
+
+        let output_dir = self.config.loose_output_dir.clone().unwrap_or_else(|| {
+            loose_files::default_output_dir(&self.config.fo4_dir, &self.plugin_name)
+        });
+
+        let mut sources: Vec<&Path> = Vec::new();
+        if let Some(staging) = self.config.mo2_data_dir.as_deref() {
+            sources.push(staging);
+        }
+        sources.push(&self.data_dir);
+
+        let export = loose_files::export(
+            &self.plugin_name,
+            &sources,
+            &output_dir,
+            self.config.build_mode == BuildMode::Clean,
         )?;
+        self.summary.loose_output = Some(export.output_dir);
+        Ok(())
+    }
 
-        let is_xbox = self.config.build_mode == BuildMode::Xbox;
-        let mo2_data_dir = self.config.mo2_data_dir.as_deref();
+    /// Directories the game sees as `Data`, highest priority first
+    ///
+    /// In MO2 mode this is the VFS (overwrite and enabled mod folders of the selected
+    /// profile) and the staging directory, followed by the real `Data` directory.
+    fn game_data_dirs(&self) -> Vec<PathBuf> {
+        let mut dirs = Vec::new();
+        if let Some(ref mo2_path) = self.config.mo2_path {
+            match mo2_helper::virtual_data_dirs(mo2_path) {
+                Ok(vfs_dirs) => dirs.extend(vfs_dirs),
+                Err(e) => warn!("Could not read the MO2 profile, only checking Data: {e:#}"),
+            }
+        }
+        if let Some(ref staging) = self.config.mo2_data_dir
+            && !dirs.contains(staging)
+        {
+            dirs.push(staging.clone());
+        }
+        dirs.push(self.data_dir.clone());
+        dirs
+    }
 
-        archive_manager.add_previs_to_archive(&archive_name, is_xbox, mo2_data_dir)?;
+    /// Find a plugin in the directories the game sees as `Data`
+    fn locate_plugin(&self, plugin_name: &str) -> Option<PathBuf> {
+        self.game_data_dirs()
+            .iter()
+            .map(|dir| dir.join(plugin_name))
+            .find(|path| path.is_file())
+    }
 
-        info!("Added previs data to archive: {archive_name}");
+    /// Check that every master of the plugin is installed
+    fn validate_masters(&self) -> Result<()> {
+        let search_dirs = self.game_data_dirs();
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            bail!("Plugin not found: {}", self.plugin_name);
+        };
+
+        let header = validation::check_masters(&plugin_path, &search_dirs)?;
+        info!(
+            "Plugin: {} ({}, form version {}, {} masters{})",
+            self.plugin_name,
+            header.kind(),
+            header.form_version,
+            header.masters.len(),
+            header
+                .author
+                .as_deref()
+                .map(|author| format!(", author {author}"))
+                .unwrap_or_default()
+        );
+        info!("All masters found: {}", header.masters.join(", "));
         Ok(())
     }
 
-    /// Automatically copy xPrevisPatch to target plugin if it doesn't exist
-    fn copy_xprevis_if_needed(&self) -> Result<()> {
-        let target_plugin = self.data_dir.join(&self.plugin_name);
+    /// Log how many cells the run will touch and their current precombine/previs state
+    fn report_cells(&self) {
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            return;
+        };
 
-        // Only copy if target plugin doesn't exist
-        if target_plugin.exists() {
+        match scanner::scan(&plugin_path) {
+            Ok(scan) => {
+                info!("Cells in {}: {}", self.plugin_name, scan.summary());
+                let attention = scan
+                    .cells
+                    .iter()
+                    .filter(|cell| cell.status().needs_attention())
+                    .count();
+                if attention > 0 {
+                    info!(
+                        "{attention} cells have stale or missing previs data (run `analyze` for the list)"
+                    );
+                }
+            }
+            Err(e) => warn!("Could not scan {} for cells: {e:#}", self.plugin_name),
+        }
+    }
+
+    /// Path of the plugin if it is a light plugin (`.esl` or ESL-flagged)
+    fn light_plugin_path(&self) -> Result<Option<PathBuf>> {
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            return Ok(None);
+        };
+        let header = PluginHeader::read(&plugin_path)?;
+        Ok(light::is_light_plugin(&plugin_path, &header).then_some(plugin_path))
+    }
+
+    /// Snapshot the plugin before a step changes it (see [`snapshots`])
+    ///
+    /// Returns the snapshot and the plugin's path, or `None` if the plugin does not exist
+    /// yet.
+    fn snapshot_plugin(&self, step: WorkflowStep) -> Result<Option<(Snapshot, PathBuf)>> {
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            return Ok(None);
+        };
+        let store = SnapshotStore::new(&self.config.fo4_dir, &self.plugin_name);
+        let snapshot = store
+            .take(&plugin_path, &format!("before-step-{}", step.number()))
+            .context("Failed to snapshot the plugin; it was not modified")?;
+        Ok(Some((snapshot, plugin_path)))
+    }
+
+    /// Run a merge step, restoring the plugin from a snapshot taken first if it fails
+    fn with_rollback(&self, step: WorkflowStep, merge: impl FnOnce() -> Result<()>) -> Result<()> {
+        let snapshot = self.snapshot_plugin(step)?;
+        let Err(e) = merge() else {
             return Ok(());
+        };
+
+        if let Some((snapshot, plugin_path)) = snapshot {
+            warn!(
+                "Step {} failed; restoring {} from its snapshot",
+                step.number(),
+                self.plugin_name
+            );
+            if let Err(restore_err) = snapshots::restore(&snapshot, &plugin_path) {
+                return Err(e.context(format!(
+                    "CRITICAL: Failed to restore the plugin ({restore_err:#}). The snapshot \
+                    is at: {}",
+                    snapshot.path.display()
+                )));
+            }
         }
+        Err(e)
+    }
 
-        let xprevis_plugins = filesystem::find_xprevis_patch_plugins(&self.data_dir)?;
+    /// Check that every precombined mesh the plugin references exists as a loose file
+    fn check_precombine_integrity(&self) -> Result<()> {
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            bail!("Plugin not found: {}", self.plugin_name);
+        };
+        let scan = scanner::scan(&plugin_path)?;
+        let ids = LoadOrderFormIds::read(&plugin_path, &self.game_data_dirs())?;
+
+        let mut dirs = vec![self.data_dir.as_path()];
+        if let Some(ref staging) = self.config.mo2_data_dir {
+            dirs.push(staging);
+        }
+        let report = precombine_check::check(&scan, &ids, &precombine_check::loose_meshes(&dirs)?);
+        report.log();
 
-        if xprevis_plugins.is_empty() {
-            // Neither target plugin nor xPrevisPatch exists - this is an error
+        if !report.is_ok() {
             bail!(
-                "Plugin '{}' does not exist in Data folder, and no xPrevisPatch plugin found to use as a seed.\n\
-                \n\
-                To proceed, you need either:\n\
-                1. A plugin file named '{}' in: {}\n\
-                2. An xPrevisPatch.esp file (or similar) in the Data folder to copy from\n\
-                \n\
-                Please create or copy one of these files before running the workflow.",
+                "{} references {} precombined meshes that do not exist (first: {}).\n\
+                Step 1 probably did not finish; run the workflow again from step 1.",
                 self.plugin_name,
-                self.plugin_name,
-                self.data_dir.display()
+                report.missing.len(),
+                report.missing[0].file
             );
         }
+        Ok(())
+    }
 
-        println!();
-        println!("INFO: Found xPrevisPatch plugin(s):");
-        for plugin in &xprevis_plugins {
-            println!("  - {plugin}");
+    /// Log which exterior cells have no previs file and which previs files match no cell
+    ///
+    /// Only warns; a failed check is logged too. Before the merge (`before_merge`), the
+    /// `RVIS` links are read from `Previs.esp` when it numbers its masters like the plugin.
+    fn check_previs_coverage(&self, before_merge: bool) {
+        if let Err(e) = self.previs_coverage(before_merge) {
+            warn!("Could not check previs coverage: {e:#}");
         }
+    }
 
-        // Use the first xPrevisPatch plugin found
-        let source_plugin = self.data_dir.join(&xprevis_plugins[0]);
+    fn previs_coverage(&self, before_merge: bool) -> Result<()> {
+        let Some(plugin_path) = self.locate_plugin(&self.plugin_name) else {
+            bail!("Plugin not found: {}", self.plugin_name);
+        };
+        let plugin = scanner::scan(&plugin_path)?;
+
+        let mut previs = None;
+        if before_merge && let Some(previs_path) = self.locate_plugin("Previs.esp") {
+            let masters = PluginHeader::read(&plugin_path)?.masters;
+            let previs_masters = PluginHeader::read(&previs_path)?.masters;
+            let same_numbering = previs_masters.len() == masters.len() + 1
+                && previs_masters
+                    .iter()
+                    .zip(masters.iter().chain([&self.plugin_name]))
+                    .all(|(a, b)| a.eq_ignore_ascii_case(b));
+            if same_numbering {
+                previs = Some(scanner::scan(&previs_path)?);
+            } else {
+                info!(
+                    "Previs.esp numbers its masters differently than {}; using the plugin's RVIS",
+                    self.plugin_name
+                );
+            }
+        }
+
+        let mut dirs = vec![self.data_dir.as_path()];
+        if let Some(ref staging) = self.config.mo2_data_dir {
+            dirs.push(staging);
+        }
+        let ids = LoadOrderFormIds::read(&plugin_path, &self.game_data_dirs())?;
+        let report = previs_check::check(
+            &plugin,
+            previs.as_ref(),
+            &ids,
+            &previs_check::previs_files(&dirs)?,
+        );
+        report.log();
+        Ok(())
+    }
+
+    /// Warn if merging `source_name` would push the light plugin past its limits
+    ///
+    /// Only the `FO4Edit` merge copies the source's new records into the plugin; the
+    /// native merge skips them (see [`merge::MergeReport`]), so it adds no new records
+    /// and there is nothing to project.
+    fn warn_light_merge(&self, plugin_path: &Path, source_name: &str) {
+        if self.config.merge_tool != MergeTool::FO4Edit {
+            return;
+        }
+        let Some(source) = self.locate_plugin(source_name) else {
+            return;
+        };
+        if let Err(e) = light::warn_if_merge_exceeds(plugin_path, &source) {
+            warn!("Could not check light plugin limits before merging {source_name}: {e:#}");
+        }
+    }
+
+    /// Copy a seed plugin to the target plugin if the target doesn't exist
+    ///
+    /// The seed is `--seed` if given. Otherwise it is the `xPrevisPatch` plugin in `Data`;
+    /// when there are several, the user picks one (interactive mode) or the run stops and
+    /// asks for `--seed`.
+    fn copy_seed_if_needed(&self) -> Result<()> {
+        let target_plugin = self.data_dir.join(&self.plugin_name);
+
+        // Only copy if target plugin doesn't exist
+        if target_plugin.exists() {
+            return Ok(());
+        }
+
+        let seed = if let Some(ref seed) = self.config.seed_plugin {
+            if !self.data_dir.join(seed).is_file() {
+                bail!(
+                    "Seed plugin '{seed}' not found in: {}",
+                    self.data_dir.display()
+                );
+            }
+            seed.clone()
+        } else {
+            let xprevis_plugins = filesystem::find_xprevis_patch_plugins(&self.data_dir)?;
+            match xprevis_plugins.as_slice() {
+                [] => {
+                    // Neither target plugin nor a seed exists - this is an error
+                    bail!(
+                        "Plugin '{}' does not exist in Data folder, and no xPrevisPatch plugin found to use as a seed.\n\
+                        \n\
+                        To proceed, you need either:\n\
+                        1. A plugin file named '{}' in: {}\n\
+                        2. An xPrevisPatch.esp file (or similar) in the Data folder to copy from,\n\
+                           or a seed plugin passed with --seed (see the create-seed command)\n\
+                        \n\
+                        Please create or copy one of these files before running the workflow.",
+                        self.plugin_name,
+                        self.plugin_name,
+                        self.data_dir.display()
+                    );
+                }
+                [only] => only.clone(),
+                _ if self.interactive => {
+                    let Some(index) = prompts::prompt_select_seed_plugin(&xprevis_plugins)? else {
+                        bail!("No seed plugin selected");
+                    };
+                    xprevis_plugins[index].clone()
+                }
+                _ => bail!(
+                    "Plugin '{}' does not exist and several xPrevisPatch plugins were found: {}.\n\
+                    Pass --seed <PLUGIN> to choose the one to copy.",
+                    self.plugin_name,
+                    xprevis_plugins.join(", ")
+                ),
+            }
+        };
+        let source_plugin = self.data_dir.join(&seed);
 
         println!();
-        println!("Copying {} to {} ...", xprevis_plugins[0], self.plugin_name);
+        println!("Copying seed plugin {seed} to {} ...", self.plugin_name);
 
         fs::copy(&source_plugin, &target_plugin).with_context(|| {
             format!(
@@ -722,9 +1661,10 @@ impl<'a> WorkflowExecutor<'a> {
                 target_plugin.display()
             )
         })?;
+        info!("Created {} from seed plugin {seed}", self.plugin_name);
 
-        println!("✓ Plugin created with cell data from xPrevisPatch");
-        println!("  The xPrevisPatch source file remains in your Data folder.");
+        println!("✓ Plugin created with cell data from {seed}");
+        println!("  The seed plugin remains in your Data folder.");
         println!();
 
         Ok(())
@@ -761,6 +1701,47 @@ impl<'a> WorkflowExecutor<'a> {
         info!("Plugin: {}", self.plugin_name);
         info!("Build Mode: {:?}", self.config.build_mode);
         info!("Completed in: {minutes}m {seconds}s");
+        if let (Some(profile), Some(packer)) = (
+            self.summary.precombine_compression,
+            &self.summary.precombine_packer,
+        ) {
+            info!(
+                "Archive compression (precombines): {} via {}",
+                profile.as_str(),
+                packer.tool
+            );
+        }
+        if let (Some(profile), Some(packer)) =
+            (self.summary.previs_compression, &self.summary.previs_packer)
+        {
+            info!(
+                "Archive compression (previs): {} via {}",
+                profile.as_str(),
+                packer.tool
+            );
+        }
+        if let Some(size) = self.summary.archive_size {
+            info!(
+                "Archive size: {} (limit {})",
+                archive_budget::format_size(size),
+                archive_budget::format_size(
+                    SizeBudget::for_build_mode(self.config.build_mode).limit
+                )
+            );
+        }
+        if let Some(ref output) = self.summary.loose_output {
+            info!("Loose files: {}", output.display());
+        }
+        if !self.summary.preserved_assets.is_empty() {
+            info!("");
+            info!(
+                "Preserved {} existing assets in the archive:",
+                self.summary.preserved_assets.len()
+            );
+            for asset in &self.summary.preserved_assets {
+                info!("  {asset}");
+            }
+        }
         info!("");
         info!(
             "Previsibines generated successfully for {}!",
@@ -807,6 +1788,245 @@ mod tests {
         assert_eq!(WorkflowStep::from_number(9), None);
     }
 
+    #[test]
+    fn test_step_range_validation() {
+        use crate::config::ArchiveTool;
+
+        let clean = Config::new(BuildMode::Clean, ArchiveTool::Archive2);
+        let mut filtered = Config::new(BuildMode::Filtered, ArchiveTool::Archive2);
+
+        let range = StepRange::new(
+            WorkflowStep::GeneratePrevis,
+            WorkflowStep::MergePrevis,
+            &[WorkflowStep::GeneratePrevis, WorkflowStep::MergePrevis],
+            &filtered,
+        )
+        .unwrap();
+        assert_eq!(
+            range.steps().collect::<Vec<_>>(),
+            [WorkflowStep::GeneratePrevis, WorkflowStep::MergePrevis]
+        );
+        assert!(!range.is_to_end());
+
+        // Backwards ranges and clean-mode-only steps outside clean mode are rejected
+        let only_psg = [WorkflowStep::CompressPSG];
+        assert!(
+            StepRange::new(
+                WorkflowStep::MergePrevis,
+                WorkflowStep::GeneratePrevis,
+                &[],
+                &clean
+            )
+            .is_err()
+        );
+        assert!(
+            StepRange::new(
+                WorkflowStep::CompressPSG,
+                WorkflowStep::CompressPSG,
+                &only_psg,
+                &clean
+            )
+            .is_ok()
+        );
+        assert!(
+            StepRange::new(
+                WorkflowStep::CompressPSG,
+                WorkflowStep::CompressPSG,
+                &only_psg,
+                &filtered
+            )
+            .is_err()
+        );
+        // A range may pass over skipped steps, but not consist of them only
+        assert!(
+            StepRange::new(
+                WorkflowStep::CreatePrecombinedArchive,
+                WorkflowStep::BuildCDX,
+                &[],
+                &filtered
+            )
+            .is_ok()
+        );
+        filtered.loose_files = true;
+        assert!(
+            StepRange::new(
+                WorkflowStep::CreatePrecombinedArchive,
+                WorkflowStep::BuildCDX,
+                &[],
+                &filtered
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_plan_steps_changes_nothing() {
+        use crate::config::ArchiveTool;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let fo4_dir = temp.path();
+        let vis_dir = fo4_dir.join("Data").join("vis");
+        fs::create_dir_all(&vis_dir).unwrap();
+        fs::write(vis_dir.join("0000003c.uvd"), b"previs").unwrap();
+        fs::write(fo4_dir.join("Data").join("MyMod.esp"), b"plugin").unwrap();
+        fs::write(fo4_dir.join("dxgi.dll"), b"reshade").unwrap();
+
+        let mut config = Config::new(BuildMode::Filtered, ArchiveTool::Native);
+        config.fo4_dir = fo4_dir.to_path_buf();
+        config.creation_kit_path = PathBuf::from("CreationKit.exe");
+        config.ck_log_path = Some(fo4_dir.join("CreationKit.log"));
+        config.hooks.dir = PathBuf::from("scripts");
+        config.hooks.hooks.push(crate::hooks::Hook {
+            when: HookTiming::Before,
+            step: Some(1),
+            command: "clean.cmd".to_string(),
+        });
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+
+        let plans = executor.plan_steps(StepRange::from_step(WorkflowStep::GeneratePrecombined));
+        assert_eq!(plans.len(), 8);
+
+        let step1 = &plans[0].actions;
+        assert_eq!(step1[0], "Run before-step-1 hook: clean.cmd (in scripts)");
+        assert!(step1[1].starts_with("Snapshot "));
+        assert!(step1.contains(&format!(
+            "Stop: {} is not empty (1 files); interactive runs ask to wipe it",
+            vis_dir.display()
+        )));
+        assert!(step1.contains(
+            &"Rename 1 DLL(s) to *-PJMdisabled while CreationKit runs: dxgi.dll".to_string()
+        ));
+        assert!(step1.contains(
+            &"Run: CreationKit.exe -GeneratePrecombined:MyMod.esp filtered all".to_string()
+        ));
+
+        assert_eq!(plans[3].skipped, Some("clean mode only"));
+        // Step 1 already wiped vis
+        assert!(
+            !plans[5]
+                .actions
+                .iter()
+                .any(|action| action.starts_with("Stop:"))
+        );
+
+        // Nothing was snapshotted, wiped, renamed or journaled
+        assert!(vis_dir.join("0000003c.uvd").exists());
+        assert!(fo4_dir.join("dxgi.dll").exists());
+        assert!(!fo4_dir.join("GeneratePrevisibines").exists());
+    }
+
+    #[test]
+    fn test_step2_restores_plugin_when_meshes_are_missing() {
+        use crate::config::ArchiveTool;
+        use crate::plugin::test_util::{group, record, subrecord, tes4};
+
+        fn plugin(masters: &[&str], xcri: Option<&[u8]>) -> Vec<u8> {
+            let mut cell = Vec::new();
+            subrecord(&mut cell, *b"EDID", b"Vault111\0");
+            if let Some(xcri) = xcri {
+                subrecord(&mut cell, *b"XCRI", xcri);
+            }
+            let cell = record(*b"CELL", 0, 0x0000_1234, &cell);
+            let block = group([4, 0, 0, 0], 2, &group([3, 0, 0, 0], 3, &cell));
+            [tes4(0, "", masters), group(*b"CELL", 0, &block)].concat()
+        }
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        let precombined = data_dir.join("meshes").join("precombined");
+        fs::create_dir_all(&precombined).unwrap();
+        fs::write(precombined.join("0000ABCD_00000001_OC.nif"), b"mesh").unwrap();
+        let original = plugin(&["Fallout4.esm"], None);
+        fs::write(data_dir.join("MyMod.esp"), &original).unwrap();
+        let xcri: Vec<u8> = [1u32, 0, 0xDEAD_BEEF]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        fs::write(
+            data_dir.join("CombinedObjects.esp"),
+            plugin(&["Fallout4.esm", "MyMod.esp"], Some(&xcri)),
+        )
+        .unwrap();
+
+        let mut config = Config::new(BuildMode::Clean, ArchiveTool::Native);
+        config.merge_tool = MergeTool::Native;
+        config.fo4_dir = temp.path().to_path_buf();
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+
+        // The merge succeeds, but the mesh it points at was never written
+        let err = executor.step2_merge_combined_objects().unwrap_err();
+        assert!(format!("{err:#}").contains("00001234_DEADBEEF_OC.nif"));
+        assert_eq!(fs::read(data_dir.join("MyMod.esp")).unwrap(), original);
+    }
+
+    #[test]
+    fn test_missing_plugin_created_from_seed() {
+        use crate::config::ArchiveTool;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        fs::create_dir_all(&data_dir).unwrap();
+        fs::write(data_dir.join("Seed.esp"), b"seed").unwrap();
+
+        let mut config = Config::new(BuildMode::Clean, ArchiveTool::Native);
+        config.fo4_dir = temp.path().to_path_buf();
+
+        // No plugin, no --seed and no xPrevisPatch plugin: nothing to start from
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+        assert!(!executor.has_seed());
+        assert!(executor.copy_seed_if_needed().is_err());
+
+        config.seed_plugin = Some("Seed.esp".to_string());
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+        assert!(executor.has_seed());
+        executor.copy_seed_if_needed().unwrap();
+        assert_eq!(fs::read(data_dir.join("MyMod.esp")).unwrap(), b"seed");
+        assert!(data_dir.join("Seed.esp").exists());
+    }
+
+    #[test]
+    fn test_plan_shows_seed_copy_for_missing_plugin() {
+        use crate::config::ArchiveTool;
+
+        let temp = tempfile::TempDir::new().unwrap();
+        let data_dir = temp.path().join("Data");
+        fs::create_dir_all(&data_dir).unwrap();
+        fs::write(data_dir.join("xPrevisPatch.esp"), b"seed").unwrap();
+
+        let mut config = Config::new(BuildMode::Clean, ArchiveTool::Native);
+        config.fo4_dir = temp.path().to_path_buf();
+        config.creation_kit_path = PathBuf::from("CreationKit.exe");
+        config.ck_log_path = Some(temp.path().join("CreationKit.log"));
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+
+        let plans = executor.plan_steps(StepRange::from_step(WorkflowStep::GeneratePrecombined));
+        let step1 = &plans[0].actions;
+        assert_eq!(
+            step1[0],
+            format!(
+                "Copy seed plugin {} to {}",
+                data_dir.join("xPrevisPatch.esp").display(),
+                data_dir.join("MyMod.esp").display()
+            )
+        );
+        assert!(step1[1].starts_with(&format!(
+            "Snapshot {}",
+            data_dir.join("MyMod.esp").display()
+        )));
+        assert!(
+            step1
+                .iter()
+                .any(|action| action.starts_with("Run: CreationKit.exe"))
+        );
+        assert!(!data_dir.join("MyMod.esp").exists());
+
+        // A --seed that does not exist stops the run
+        config.seed_plugin = Some("Missing.esp".to_string());
+        let executor = WorkflowExecutor::new(&config, "MyMod.esp".to_string(), false);
+        let plans = executor.plan_steps(StepRange::from_step(WorkflowStep::GeneratePrecombined));
+        assert!(plans[0].actions[0].starts_with("Stop: seed plugin 'Missing.esp' not found"));
+    }
+
     #[test]
     fn test_clean_mode_only_steps() {
         assert!(!WorkflowStep::GeneratePrecombined.is_clean_mode_only());
@@ -815,6 +2035,14 @@ mod tests {
         assert!(!WorkflowStep::GeneratePrevis.is_clean_mode_only());
     }
 
+    #[test]
+    fn test_archive_steps() {
+        assert!(WorkflowStep::CreatePrecombinedArchive.is_archive_step());
+        assert!(WorkflowStep::AddPrevisToArchive.is_archive_step());
+        assert!(!WorkflowStep::MergePrevis.is_archive_step());
+        assert!(!WorkflowStep::CompressPSG.is_archive_step());
+    }
+
     #[test]
     fn test_step_next() {
         assert_eq!(